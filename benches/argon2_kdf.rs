@@ -0,0 +1,65 @@
+//! Compare the time it takes to open a database with a single-lane and a four-lane Argon2 key
+//! derivation with the same memory and iteration count, i.e. the same amount of work. With the
+//! `argon2_threads` feature, the four lanes are computed in parallel.
+//!
+//! ```bash
+//! cargo bench --features save_kdbx4 --bench argon2_kdf
+//! ```
+
+use std::time::{Duration, Instant};
+
+use keepass::{
+    config::{DatabaseConfig, DatabaseSettings, KdfConfig},
+    Database, DatabaseKey,
+};
+
+const MEMORY: u64 = 256 * 1024 * 1024;
+const ITERATIONS: u64 = 2;
+const RUNS: usize = 3;
+
+fn key() -> DatabaseKey {
+    DatabaseKey::new().with_password("benchmark")
+}
+
+/// Median time of opening a database that uses Argon2 with the given number of lanes
+fn time_open(parallelism: u32) -> Duration {
+    let db = Database::new(DatabaseConfig {
+        settings: DatabaseSettings {
+            kdf: KdfConfig::Argon2 {
+                iterations: ITERATIONS,
+                memory: MEMORY,
+                parallelism,
+                version: argon2::Version::Version13,
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let mut data = Vec::new();
+    db.save(&mut data, key()).unwrap();
+
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            Database::open(&mut data.as_slice(), key()).unwrap();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn main() {
+    let single = time_open(1);
+    let parallel = time_open(4);
+
+    println!(
+        "Argon2d, {} MiB, {} iterations: 1 lane {:?}, 4 lanes {:?}, speedup {:.2}x",
+        MEMORY / 1024 / 1024,
+        ITERATIONS,
+        single,
+        parallel,
+        single.as_secs_f64() / parallel.as_secs_f64()
+    );
+}
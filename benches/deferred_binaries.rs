@@ -0,0 +1,175 @@
+//! Compare opening databases with large attachments with and without `OpenOptions::defer_binaries`:
+//! a KDBX4 database with 50 MiB of attachments, and a KDBX3 database written by KeePass with a
+//! compressed attachment. Reports the time, the allocated and peak memory of opening, and the
+//! memory that the open database holds.
+//!
+//! ```bash
+//! cargo bench --features save_kdbx4 --bench deferred_binaries
+//! ```
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use keepass::{
+    config::{DatabaseConfig, DatabaseSettings, KdfConfig, OpenOptions},
+    db::Entry,
+    Database, DatabaseKey,
+};
+
+const ATTACHMENT_SIZE: usize = 5 * 1024 * 1024;
+const ATTACHMENT_COUNT: usize = 10;
+const RUNS: usize = 5;
+
+/// Allocator that counts the allocations and the peak of the allocated memory
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        TOTAL.fetch_add(layout.size(), Ordering::Relaxed);
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    // growing and shrinking the large buffers of the payload is done in place where possible
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        TOTAL.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        let allocated = ALLOCATED.fetch_add(new_size, Ordering::Relaxed) + new_size;
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Memory use of opening a database once
+struct Usage {
+    allocations: usize,
+    total: usize,
+    peak: usize,
+    retained: usize,
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0
+}
+
+/// Pseudo-random content that does not compress, so that the payload is as large as the attachments
+fn content(seed: u64) -> Vec<u8> {
+    let mut state = seed * 2 + 1;
+    (0..ATTACHMENT_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn kdbx4_database(key: &DatabaseKey) -> Vec<u8> {
+    let mut db = Database::new(DatabaseConfig {
+        settings: DatabaseSettings {
+            kdf: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    for i in 0..ATTACHMENT_COUNT {
+        let mut entry = Entry::new();
+        entry.set("Title", &format!("Entry {}", i));
+        entry.add_attachment(&mut db, &format!("attachment_{}.bin", i), content(i as u64));
+        db.root.add_child(entry);
+    }
+
+    let mut data = Vec::new();
+    db.save(&mut data, key.clone()).unwrap();
+    data
+}
+
+/// Median time of opening a database
+fn time_open(data: &[u8], key: &DatabaseKey, options: &OpenOptions) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            Database::parse_with_options(data, key.clone(), options).unwrap();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+/// Allocations and peak memory of opening a database, and the memory held by the open database
+fn measure_open(data: &[u8], key: &DatabaseKey, options: &OpenOptions) -> Usage {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let total = TOTAL.load(Ordering::Relaxed);
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(allocated, Ordering::Relaxed);
+
+    let db = Database::parse_with_options(data, key.clone(), options).unwrap();
+
+    let usage = Usage {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        total: TOTAL.load(Ordering::Relaxed) - total,
+        peak: PEAK.load(Ordering::Relaxed) - allocated,
+        retained: ALLOCATED.load(Ordering::Relaxed) - allocated,
+    };
+    drop(db);
+    usage
+}
+
+fn compare(name: &str, data: &[u8], key: &DatabaseKey) {
+    println!("{}:", name);
+    for (mode, options) in [
+        ("loaded", OpenOptions::new()),
+        ("deferred", OpenOptions::new().defer_binaries(true)),
+    ] {
+        let time = time_open(data, key, &options);
+        let usage = measure_open(data, key, &options);
+        println!(
+            "  {:<8} {:?}, {} allocations of {:.1} MiB, {:.1} MiB peak, {:.1} MiB held by the database",
+            mode,
+            time,
+            usage.allocations,
+            mib(usage.total),
+            mib(usage.peak),
+            mib(usage.retained)
+        );
+    }
+}
+
+fn main() {
+    let key = DatabaseKey::new().with_password("benchmark");
+    compare(
+        &format!(
+            "KDBX4, {} MiB of attachments",
+            ATTACHMENT_SIZE * ATTACHMENT_COUNT / 1024 / 1024
+        ),
+        &kdbx4_database(&key),
+        &key,
+    );
+
+    let key = DatabaseKey::new().with_password("samplepassword");
+    let data = std::fs::read("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx").unwrap();
+    compare("KDBX3, compressed attachment", &data, &key);
+}
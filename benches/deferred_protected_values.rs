@@ -0,0 +1,76 @@
+//! Compare opening a database with many protected values with and without
+//! `OpenOptions::defer_protected_values`, and the time of accessing a single password afterwards.
+//!
+//! ```bash
+//! cargo bench --features save_kdbx4 --bench deferred_protected_values
+//! ```
+
+use std::time::{Duration, Instant};
+
+use keepass::{
+    config::{DatabaseConfig, DatabaseSettings, KdfConfig, OpenOptions},
+    db::Entry,
+    Database, DatabaseKey,
+};
+
+const ENTRY_COUNT: usize = 20_000;
+const RUNS: usize = 5;
+
+fn database(key: &DatabaseKey) -> Vec<u8> {
+    let mut db = Database::new(DatabaseConfig {
+        settings: DatabaseSettings {
+            kdf: KdfConfig::Aes { rounds: 10 },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    for i in 0..ENTRY_COUNT {
+        let mut entry = Entry::new();
+        entry.set("Title", &format!("Entry {}", i));
+        entry.set("UserName", &format!("user{}", i));
+        entry.set("Password", &format!("password of entry {}", i).repeat(4));
+        entry.set("PIN", &format!("{:06}", i));
+        entry.set_protected("PIN", true);
+        db.root.add_child(entry);
+    }
+
+    let mut data = Vec::new();
+    db.save(&mut data, key.clone()).unwrap();
+    data
+}
+
+/// Median time of opening the database and reading the password of its last entry
+fn time_open(data: &[u8], key: &DatabaseKey, options: &OpenOptions) -> (Duration, Duration) {
+    let mut times: Vec<(Duration, Duration)> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let db = Database::parse_with_options(data, key.clone(), options).unwrap();
+            let opened = start.elapsed();
+
+            let entry = db.root.iter_entries().last().unwrap();
+            let start = Instant::now();
+            assert!(entry.get_password().is_some());
+            (opened, start.elapsed())
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
+
+fn main() {
+    let key = DatabaseKey::new().with_password("benchmark");
+    let data = database(&key);
+
+    println!("KDBX4, {} entries with 2 protected values each:", ENTRY_COUNT);
+    for (mode, options) in [
+        ("decrypted", OpenOptions::new()),
+        ("deferred", OpenOptions::new().defer_protected_values(true)),
+    ] {
+        let (open, access) = time_open(&data, &key, &options);
+        println!(
+            "  {:<9} opened in {:?}, password read in {:?}",
+            mode, open, access
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Count the allocations of merging two versions of a database with 10 000 entries that differ in a
+//! single entry, which is the common case of synchronizing a database.
+//!
+//! ```bash
+//! cargo bench --features _merge --bench merge
+//! ```
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use chrono::Duration;
+use keepass::{
+    db::{Entry, Group, Value},
+    Database,
+};
+
+const GROUPS: usize = 100;
+const ENTRIES_PER_GROUP: usize = 100;
+const HISTORY_ENTRIES: usize = 3;
+
+/// Allocator that counts the allocations and the peak of the allocated memory
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn database() -> Database {
+    let mut db = Database::new(Default::default());
+    let start = db.now() - Duration::days(30);
+
+    for g in 0..GROUPS {
+        let mut group = Group::new(&format!("Group {}", g));
+        for e in 0..ENTRIES_PER_GROUP {
+            let mut entry = Entry::new();
+            for version in 0..=HISTORY_ENTRIES {
+                entry.fields.insert(
+                    "Title".to_string(),
+                    Value::Unprotected(format!("Entry {}-{}", g, e)),
+                );
+                entry.fields.insert(
+                    "Password".to_string(),
+                    Value::Protected(format!("password {}", version).as_str().into()),
+                );
+                entry.update_history_at(start + Duration::days(version as i64));
+            }
+            group.add_child(entry);
+        }
+        db.root.add_child(group);
+    }
+
+    db
+}
+
+fn main() {
+    let mut db = database();
+
+    let mut other = db.clone();
+    let entry = other
+        .root
+        .iter_entries_mut()
+        .nth(GROUPS * ENTRIES_PER_GROUP / 2)
+        .unwrap();
+    entry
+        .fields
+        .insert("Password".to_string(), Value::Protected("changed".into()));
+    entry.update_history_at(db.now());
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(allocated, Ordering::Relaxed);
+    let start = Instant::now();
+
+    let log = db.merge(&other).unwrap();
+
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let peak = PEAK.load(Ordering::Relaxed) - allocated;
+    assert_eq!(log.events.len(), 1);
+
+    println!(
+        "Merging {} entries with one change: {:?}, {} allocations, {:.1} MiB peak",
+        GROUPS * ENTRIES_PER_GROUP,
+        elapsed,
+        allocations,
+        peak as f64 / 1024.0 / 1024.0
+    );
+}
@@ -19,6 +19,10 @@ struct Args {
     /// Do not use a password to decrypt the database
     #[arg(short = 'n', long)]
     no_password: bool,
+
+    /// Include protected values such as passwords in plain text instead of redacting them
+    #[arg(long)]
+    secrets: bool,
 }
 
 pub fn main() -> Result<()> {
@@ -42,7 +46,11 @@ pub fn main() -> Result<()> {
     let db = Database::open(&mut source, key)?;
 
     let stdout = std::io::stdout().lock();
-    serde_json::ser::to_writer(stdout, &db)?;
+    if args.secrets {
+        serde_json::ser::to_writer(stdout, &db.serializable_with_secrets())?;
+    } else {
+        serde_json::ser::to_writer(stdout, &db)?;
+    }
 
     Ok(())
 }
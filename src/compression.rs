@@ -3,14 +3,25 @@ use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 #[cfg(feature = "save_kdbx4")]
 use flate2::Compression as Flate2Compression;
-use std::io::Read;
 #[cfg(feature = "save_kdbx4")]
 use std::io::Write;
+use std::{convert::TryFrom, io::Read};
 
 pub trait Compression {
     #[cfg(feature = "save_kdbx4")]
     fn compress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error>;
-    fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error>;
+
+    /// Decompress at most `max_size + 1` bytes, so that callers can detect data that exceeds
+    /// `max_size` without inflating all of it
+    fn decompress(&self, in_buffer: &[u8], max_size: u64) -> Result<Vec<u8>, std::io::Error> {
+        match self.decompress_partial(in_buffer, max_size) {
+            (_, Some(e)) => Err(e),
+            (res, None) => Ok(res),
+        }
+    }
+
+    /// Like `decompress`, but also returns the data that was decompressed before an error
+    fn decompress_partial(&self, in_buffer: &[u8], max_size: u64) -> (Vec<u8>, Option<std::io::Error>);
 }
 
 pub struct NoCompression;
@@ -20,8 +31,9 @@ impl Compression for NoCompression {
     fn compress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error> {
         Ok(in_buffer.to_vec())
     }
-    fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error> {
-        Ok(in_buffer.to_vec())
+    fn decompress_partial(&self, in_buffer: &[u8], max_size: u64) -> (Vec<u8>, Option<std::io::Error>) {
+        let len = usize::try_from(max_size.saturating_add(1)).unwrap_or(usize::MAX);
+        (in_buffer[..in_buffer.len().min(len)].to_vec(), None)
     }
 }
 
@@ -37,10 +49,51 @@ impl Compression for GZipCompression {
         encoder.finish()?;
         Ok(res)
     }
-    fn decompress(&self, in_buffer: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    fn decompress_partial(&self, in_buffer: &[u8], max_size: u64) -> (Vec<u8>, Option<std::io::Error>) {
         let mut res = Vec::new();
-        let mut decoder = GzDecoder::new(in_buffer);
-        decoder.read_to_end(&mut res)?;
-        Ok(res)
+        let mut decoder = GzDecoder::new(in_buffer).take(max_size.saturating_add(1));
+        // read_to_end keeps the data read before an error in the buffer
+        let err = decoder.read_to_end(&mut res).err();
+        (res, err)
+    }
+}
+
+/// Writer that compresses the data written to it. `finish` must be called to write the end of the
+/// compressed stream.
+#[cfg(feature = "save_kdbx4")]
+pub(crate) enum CompressionWriter<W: Write> {
+    None(W),
+    GZip(GzEncoder<W>),
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> CompressionWriter<W> {
+    pub(crate) fn gzip(writer: W) -> Self {
+        CompressionWriter::GZip(GzEncoder::new(writer, Flate2Compression::default()))
+    }
+
+    /// Write the end of the compressed stream, returning the inner writer
+    pub(crate) fn finish(self) -> Result<W, std::io::Error> {
+        match self {
+            CompressionWriter::None(writer) => Ok(writer),
+            CompressionWriter::GZip(encoder) => encoder.finish(),
+        }
+    }
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> Write for CompressionWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        match self {
+            CompressionWriter::None(writer) => writer.write(buf),
+            CompressionWriter::GZip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        match self {
+            CompressionWriter::None(writer) => writer.flush(),
+            CompressionWriter::GZip(encoder) => encoder.flush(),
+        }
     }
 }
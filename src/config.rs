@@ -1,7 +1,10 @@
 //! Configuration options for how to compress and encrypt databases
 use hex_literal::hex;
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, sync::Arc};
+
+use cipher::generic_array::GenericArray;
+use uuid::Uuid;
 
 pub use crate::format::DatabaseVersion;
 
@@ -14,7 +17,7 @@ use crate::{
         kdf,
     },
     error::{
-        CompressionConfigError, CryptographyError, InnerCipherConfigError, KdfConfigError,
+        CompressionConfigError, CryptographyError, InnerCipherConfigError, KdfConfigError, KdfError,
         OuterCipherConfigError,
     },
     format::KDBX4_CURRENT_MINOR_VERSION,
@@ -38,17 +41,9 @@ pub struct DatabaseConfig {
     /// Version of the outer database file
     pub version: DatabaseVersion,
 
-    /// What encryption to use for the outer encryption
-    pub outer_cipher_config: OuterCipherConfig,
-
-    /// What algorithm to use to compress the inner data
-    pub compression_config: CompressionConfig,
-
-    /// What encryption to use for protected fields inside the database
-    pub inner_cipher_config: InnerCipherConfig,
-
-    /// Settings for the Key Derivation Function (KDF)
-    pub kdf_config: KdfConfig,
+    /// Ciphers, compression and Key Derivation Function (KDF) to save the database with
+    #[cfg_attr(feature = "serialization", serde(flatten))]
+    pub settings: DatabaseSettings,
 
     /// Custom data of plugins/ports.
     pub public_custom_data: Option<VariantDictionary>,
@@ -59,20 +54,385 @@ impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
             version: DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION),
-            outer_cipher_config: OuterCipherConfig::AES256,
-            compression_config: CompressionConfig::GZip,
-            inner_cipher_config: InnerCipherConfig::ChaCha20,
-            kdf_config: KdfConfig::Argon2 {
+            settings: DatabaseSettings::default(),
+            public_custom_data: None,
+        }
+    }
+}
+
+/// The settings that a database is saved with, i.e. its `DatabaseConfig` without the version and
+/// the public custom data. See `Database::settings`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct DatabaseSettings {
+    /// What encryption to use for the outer encryption
+    #[cfg_attr(feature = "serialization", serde(rename = "outer_cipher_config"))]
+    pub cipher: OuterCipherConfig,
+
+    /// Settings for the Key Derivation Function (KDF)
+    #[cfg_attr(feature = "serialization", serde(rename = "kdf_config"))]
+    pub kdf: KdfConfig,
+
+    /// What algorithm to use to compress the inner data
+    #[cfg_attr(feature = "serialization", serde(rename = "compression_config"))]
+    pub compression: CompressionConfig,
+
+    /// What encryption to use for protected fields inside the database
+    #[cfg_attr(feature = "serialization", serde(rename = "inner_cipher_config"))]
+    pub inner_cipher: InnerCipherConfig,
+}
+
+/// Sensible default settings for new databases
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            cipher: OuterCipherConfig::AES256,
+            kdf: KdfConfig::Argon2 {
                 iterations: 50,
                 memory: 1024 * 1024,
                 parallelism: 4,
                 version: argon2::Version::Version13,
             },
-            public_custom_data: None,
+            compression: CompressionConfig::GZip,
+            inner_cipher: InnerCipherConfig::ChaCha20,
+        }
+    }
+}
+
+/// Options for how a database is read when opening it
+///
+/// ```
+/// use keepass::{config::OpenOptions, Database, DatabaseKey};
+/// use std::fs::File;
+///
+/// let options = OpenOptions::new().strict(true);
+/// let mut file = File::open("tests/resources/test_db_with_password.kdbx")?;
+/// let db = Database::open_with_options(&mut file, DatabaseKey::new().with_password("demopass"), &options)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpenOptions {
+    pub(crate) strict: bool,
+    pub(crate) limits: ParseLimits,
+    pub(crate) defer_binaries: bool,
+    pub(crate) skip_history: bool,
+    pub(crate) defer_protected_values: bool,
+    pub(crate) version: Option<KdbxVersion>,
+    pub(crate) key_derivation: Option<CustomKdf>,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail on malformed timestamps and empty elements, which are otherwise treated as missing
+    /// timestamps and empty strings
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Limits on the resources that opening the database may use
+    pub fn limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Keep KDBX4 attachments in the decrypted payload instead of copying them out, and decompress
+    /// KDBX3 attachments when they are first accessed, see `HeaderAttachment::data`.
+    ///
+    /// This saves allocating and copying the attachments when opening, see the `deferred_binaries`
+    /// benchmark. Deferred KDBX3 attachments that cannot be decompressed fail in `try_data`.
+    pub fn defer_binaries(mut self, defer_binaries: bool) -> Self {
+        self.defer_binaries = defer_binaries;
+        self
+    }
+
+    /// Do not parse the history of entries, for read-only lookups. The database cannot be saved
+    /// unless `Database::allow_lossy_save` is called first.
+    pub fn skip_history(mut self, skip_history: bool) -> Self {
+        self.skip_history = skip_history;
+        self
+    }
+
+    /// Decrypt protected values such as passwords when they are first accessed instead of when
+    /// opening the database.
+    ///
+    /// The protected values are read as `Value::Deferred`, which is decrypted transparently by
+    /// accessors such as `Entry::get_password` and `Value::expose_secret`, and written as usual when
+    /// saving. This only applies to KDBX databases with an inner stream cipher; other protected values
+    /// are read as `Value::Protected`.
+    ///
+    /// Values that are never accessed are never decrypted, so their plaintext is not kept in memory.
+    /// Decrypting a value is cheap compared to parsing the XML, so this does not make opening
+    /// noticeably faster, see the `deferred_protected_values` benchmark. Nor does it save memory:
+    /// a deferred value keeps its ciphertext, and once accessed also its plaintext.
+    pub fn defer_protected_values(mut self, defer_protected_values: bool) -> Self {
+        self.defer_protected_values = defer_protected_values;
+        self
+    }
+
+    /// Parse the database as the given KDBX version regardless of its header, for files with
+    /// incorrect version bytes, see `Database::open_with_version`
+    pub fn force_version(mut self, version: KdbxVersion) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Derive the key with another implementation of the key derivation function, see
+    /// `KeyDerivationFunction`
+    pub fn key_derivation(mut self, kdf: Arc<dyn KeyDerivationFunction>) -> Self {
+        self.key_derivation = Some(CustomKdf(kdf));
+        self
+    }
+
+    /// The key derivation function to open the database with
+    pub(crate) fn kdf(&self) -> &dyn KeyDerivationFunction {
+        match &self.key_derivation {
+            Some(CustomKdf(kdf)) => kdf.as_ref(),
+            None => &BuiltinKdf,
+        }
+    }
+}
+
+/// A key derivation function for opening databases with `OpenOptions::key_derivation` or
+/// `Database::open_with_kdf`, also with functions that this crate does not implement.
+///
+/// ```
+/// use keepass::config::{BuiltinKdf, KdfParameters, KeyDerivationFunction};
+/// use keepass::error::KdfError;
+///
+/// /// Log every key derivation
+/// struct LoggingKdf;
+///
+/// impl KeyDerivationFunction for LoggingKdf {
+///     fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError> {
+///         println!("Deriving a key with {}", params.uuid());
+///         BuiltinKdf.derive_key(composite_key, params)
+///     }
+/// }
+/// ```
+pub trait KeyDerivationFunction: Send + Sync {
+    /// Transform the 32-byte composite key with the parameters of the key derivation function
+    fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError>;
+}
+
+/// The key derivation functions of this crate for every `KdfConfig`, used unless
+/// `OpenOptions::key_derivation` sets another implementation
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinKdf;
+
+impl KeyDerivationFunction for BuiltinKdf {
+    fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError> {
+        let (config, seed) = params
+            .to_kdf_config()?
+            .ok_or(KdfError::Unsupported { uuid: params.uuid() })?;
+        if composite_key.len() != 32 {
+            return Err(CryptographyError::from(cipher::InvalidLength).into());
+        }
+
+        let key = config
+            .get_kdf_seeded(&seed)
+            .transform_key(GenericArray::from_slice(composite_key))?;
+        Ok(key.into())
+    }
+}
+
+/// The AES-KDF key derivation function of KeePass, which fails with `KdfError::Unsupported` for
+/// databases that use another function
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AesKdf;
+
+impl KeyDerivationFunction for AesKdf {
+    fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError> {
+        match params.uuid().as_bytes() {
+            &KDF_AES_KDBX3 | &KDF_AES_KDBX4 => BuiltinKdf.derive_key(composite_key, params),
+            _ => Err(KdfError::Unsupported { uuid: params.uuid() }),
+        }
+    }
+}
+
+/// The Argon2d and Argon2id key derivation functions of KeePass, which fail with
+/// `KdfError::Unsupported` for databases that use another function
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Kdf;
+
+impl KeyDerivationFunction for Argon2Kdf {
+    fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError> {
+        match params.uuid().as_bytes() {
+            &KDF_ARGON2 | &KDF_ARGON2ID => BuiltinKdf.derive_key(composite_key, params),
+            _ => Err(KdfError::Unsupported { uuid: params.uuid() }),
+        }
+    }
+}
+
+/// The UUID and the parameters of the key derivation function of a database, as stored in its
+/// header. KDBX3 and KDB databases have the parameters of AES-KDF: the rounds `R` and the seed `S`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParameters {
+    uuid: Uuid,
+    parameters: VariantDictionary,
+}
+
+impl KdfParameters {
+    pub(crate) fn new(parameters: VariantDictionary) -> Result<Self, KdfConfigError> {
+        let uuid = parameters.get::<Vec<u8>>(KDF_ID)?;
+        let uuid = Uuid::from_slice(uuid).map_err(|_| KdfConfigError::InvalidKDFUUID { uuid: uuid.clone() })?;
+
+        Ok(KdfParameters { uuid, parameters })
+    }
+
+    pub(crate) fn from_config(config: &KdfConfig, seed: &[u8]) -> Self {
+        let parameters = config.to_variant_dictionary(seed);
+        KdfParameters::new(parameters).expect("KDF UUID is set")
+    }
+
+    /// UUID of the key derivation function
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn get_u32(&self, name: &str) -> Option<u32> {
+        self.parameters.get(name).ok().copied()
+    }
+
+    pub fn get_u64(&self, name: &str) -> Option<u64> {
+        self.parameters.get(name).ok().copied()
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.parameters.get(name).ok().copied()
+    }
+
+    pub fn get_i32(&self, name: &str) -> Option<i32> {
+        self.parameters.get(name).ok().copied()
+    }
+
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        self.parameters.get(name).ok().copied()
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.parameters.get::<String>(name).ok().map(String::as_str)
+    }
+
+    pub fn get_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.parameters.get::<Vec<u8>>(name).ok().map(Vec::as_slice)
+    }
+
+    /// Seed or salt `S` of the function, which challenge-response keys are challenged with
+    pub(crate) fn seed(&self) -> &[u8] {
+        self.get_bytes(KDF_SEED).unwrap_or_default()
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn variant_dictionary(&self) -> &VariantDictionary {
+        &self.parameters
+    }
+
+    /// The `KdfConfig` and the seed of a function of this crate, or `None` for other functions
+    pub(crate) fn to_kdf_config(&self) -> Result<Option<(KdfConfig, Vec<u8>)>, KdfConfigError> {
+        match <(KdfConfig, Vec<u8>)>::try_from(&self.parameters) {
+            Ok(config) => Ok(Some(config)),
+            Err(KdfConfigError::InvalidKDFUUID { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Key derivation function set with `OpenOptions::key_derivation`. Options are equal if they use
+/// the same instance.
+#[derive(Clone)]
+pub(crate) struct CustomKdf(Arc<dyn KeyDerivationFunction>);
+
+impl PartialEq for CustomKdf {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomKdf {}
+
+impl std::fmt::Debug for CustomKdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomKdf")
+    }
+}
+
+/// KDBX version to parse a database as, see `OpenOptions::force_version`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdbxVersion {
+    Kdbx31,
+    Kdbx4,
+}
+
+/// Upper bounds for the contents of a database, to protect against malicious files such as
+/// decompression bombs. The defaults are generous enough for any realistic database.
+///
+/// ```
+/// use keepass::config::{OpenOptions, ParseLimits};
+///
+/// let options = OpenOptions::new().limits(ParseLimits {
+///     max_decompressed_size: 16 * 1024 * 1024,
+///     max_entries: 10_000,
+///     ..ParseLimits::default()
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum size of the decompressed payload, in bytes
+    pub max_decompressed_size: u64,
+
+    /// Maximum nesting depth of the XML document. Groups are parsed recursively, so this also
+    /// bounds the stack usage of the parser.
+    pub max_nesting_depth: usize,
+
+    /// Maximum number of entries, including history entries
+    pub max_entries: usize,
+
+    /// Maximum size of a single attachment, in bytes
+    pub max_binary_size: u64,
+
+    /// Maximum size of all attachments together, in bytes
+    pub max_total_binary_size: u64,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_decompressed_size: 4 * 1024 * 1024 * 1024,
+            max_nesting_depth: 128,
+            max_entries: 1_000_000,
+            max_binary_size: 1024 * 1024 * 1024,
+            max_total_binary_size: 2 * 1024 * 1024 * 1024,
         }
     }
 }
 
+/// One of the limits in `ParseLimits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseLimit {
+    DecompressedSize,
+    NestingDepth,
+    Entries,
+    BinarySize,
+    TotalBinarySize,
+}
+
+impl std::fmt::Display for ParseLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ParseLimit::DecompressedSize => "max_decompressed_size",
+            ParseLimit::NestingDepth => "max_nesting_depth",
+            ParseLimit::Entries => "max_entries",
+            ParseLimit::BinarySize => "max_binary_size",
+            ParseLimit::TotalBinarySize => "max_total_binary_size",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 /// Choices for outer encryption
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -95,6 +455,25 @@ impl OuterCipherConfig {
         }
     }
 
+    /// Get an encryptor for a payload that is written in parts, which produces the same ciphertext
+    /// as `get_cipher`
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn get_encryptor(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn ciphers::StreamEncryptor>, CryptographyError> {
+        match self {
+            OuterCipherConfig::AES256 => Ok(Box::new(ciphers::CbcStreamEncryptor::<aes::Aes256>::new(
+                key, iv,
+            )?)),
+            OuterCipherConfig::Twofish => Ok(Box::new(ciphers::CbcStreamEncryptor::<twofish::Twofish>::new(
+                key, iv,
+            )?)),
+            OuterCipherConfig::ChaCha20 => Ok(Box::new(ciphers::StreamCipherEncryptor::new_chacha20(key, iv)?)),
+        }
+    }
+
     #[cfg(feature = "save_kdbx4")]
     pub(crate) fn get_iv_size(&self) -> usize {
         match self {
@@ -147,6 +526,20 @@ impl InnerCipherConfig {
         }
     }
 
+    /// Create the cipher to parse protected values with. If `defer` is set, the cipher keeps a copy
+    /// of its key so that values can be decrypted after parsing, see `Cipher::defer`.
+    pub(crate) fn get_parsing_cipher(
+        &self,
+        key: &[u8],
+        defer: bool,
+    ) -> Result<Box<dyn ciphers::Cipher>, CryptographyError> {
+        match self {
+            InnerCipherConfig::Salsa20 if defer => Ok(Box::new(ciphers::Salsa20Cipher::new_deferring(key)?)),
+            InnerCipherConfig::ChaCha20 if defer => Ok(Box::new(ciphers::ChaCha20Cipher::new_deferring(key)?)),
+            _ => self.get_cipher(key),
+        }
+    }
+
     #[cfg(feature = "save_kdbx4")]
     pub(crate) fn dump(&self) -> u32 {
         match self {
@@ -225,7 +618,148 @@ fn serialize_argon2_version<S: serde::Serializer>(
     serializer.serialize_u32(version.as_u32())
 }
 
+/// Parameter of an Argon2 key derivation that is adjusted by `KdfConfig::benchmark`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdfScaling {
+    /// Adjust the number of iterations, keeping the memory fixed
+    Iterations,
+
+    /// Adjust the memory in steps of 1 MiB, keeping the number of iterations fixed. The memory is
+    /// limited to 1 GiB, beyond which the number of iterations is adjusted instead.
+    Memory,
+}
+
+/// Minimum share of the target duration that a key derivation has to take in
+/// `KdfConfig::benchmark` for its time to be extrapolated
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+const BENCHMARK_MIN_SHARE: u32 = 8;
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+const MIB: u64 = 1024 * 1024;
+
+/// Most memory that `KdfScaling::Memory` assigns to Argon2
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+const BENCHMARK_MAX_MEMORY: u64 = 1024 * MIB;
+
 impl KdfConfig {
+    /// Calibrate the parameter chosen by `scaling` so that deriving a key takes roughly `target` on
+    /// the current machine, like the one second delay button of KeePass. AES always scales rounds.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use keepass::config::{KdfConfig, KdfScaling};
+    ///
+    /// let template = KdfConfig::Argon2id {
+    ///     iterations: 1,
+    ///     memory: 1024 * 1024,
+    ///     parallelism: 2,
+    ///     version: argon2::Version::Version13,
+    /// };
+    /// let config = template.benchmark(Duration::from_millis(50), KdfScaling::Iterations).unwrap();
+    /// assert!(matches!(config, KdfConfig::Argon2id { memory: 1048576, parallelism: 2, .. }));
+    /// ```
+    ///
+    /// This is not available on wasm32-unknown-unknown, which has no `std::time::Instant`.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn benchmark(
+        &self,
+        target: std::time::Duration,
+        scaling: KdfScaling,
+    ) -> Result<KdfConfig, CryptographyError> {
+        let composite_key = Default::default();
+        let seed = [0; 32];
+
+        self.benchmark_with(target, scaling, |config| {
+            let kdf = config.get_kdf_seeded(&seed);
+            let start = std::time::Instant::now();
+            kdf.transform_key(&composite_key)?;
+            Ok(start.elapsed())
+        })
+    }
+
+    /// Calibrate the parameters like `benchmark`, taking the duration of a key derivation with a
+    /// configuration from `time`
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    fn benchmark_with<F>(
+        &self,
+        target: std::time::Duration,
+        scaling: KdfScaling,
+        mut time: F,
+    ) -> Result<KdfConfig, CryptographyError>
+    where
+        F: FnMut(&KdfConfig) -> Result<std::time::Duration, CryptographyError>,
+    {
+        let mut config = self.clone();
+        loop {
+            let elapsed = time(&config)?;
+
+            if elapsed >= target / BENCHMARK_MIN_SHARE {
+                let factor = target.as_secs_f64() / elapsed.as_secs_f64();
+                return Ok(config.scaled(scaling, factor));
+            }
+
+            config = config.scaled(scaling, 2.0);
+        }
+    }
+
+    /// Multiply the parameter given by `scaling` with `factor`, keeping it at a valid value
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    fn scaled(&self, scaling: KdfScaling, factor: f64) -> KdfConfig {
+        let scale = |value: u64| ((value as f64 * factor).round() as u64).max(1);
+
+        let scale_argon2 = |iterations: u64, memory: u64, parallelism: u32| match scaling {
+            KdfScaling::Iterations => (scale(iterations), memory),
+            KdfScaling::Memory => {
+                let mebibytes = ((memory as f64 * factor / MIB as f64).round() as u64).max(1);
+                if mebibytes.saturating_mul(MIB) > BENCHMARK_MAX_MEMORY {
+                    // the rest of the factor is applied to the iterations
+                    let factor = factor * memory as f64 / BENCHMARK_MAX_MEMORY as f64;
+                    let iterations = ((iterations as f64 * factor).round() as u64).max(1);
+                    return (iterations, BENCHMARK_MAX_MEMORY);
+                }
+                // Argon2 needs at least 8 KiB per lane
+                (
+                    iterations,
+                    (mebibytes * MIB).max(8 * 1024 * u64::from(parallelism)),
+                )
+            }
+        };
+
+        match self {
+            KdfConfig::Aes { rounds } => KdfConfig::Aes {
+                rounds: scale(*rounds),
+            },
+            KdfConfig::Argon2 {
+                iterations,
+                memory,
+                parallelism,
+                version,
+            } => {
+                let (iterations, memory) = scale_argon2(*iterations, *memory, *parallelism);
+                KdfConfig::Argon2 {
+                    iterations,
+                    memory,
+                    parallelism: *parallelism,
+                    version: *version,
+                }
+            }
+            KdfConfig::Argon2id {
+                iterations,
+                memory,
+                parallelism,
+                version,
+            } => {
+                let (iterations, memory) = scale_argon2(*iterations, *memory, *parallelism);
+                KdfConfig::Argon2id {
+                    iterations,
+                    memory,
+                    parallelism: *parallelism,
+                    version: *version,
+                }
+            }
+        }
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn seed_size(&self) -> usize {
         match self {
@@ -238,9 +772,12 @@ impl KdfConfig {
     /// For writing out a database, generate a new KDF seed from the config and return the KDF
     /// and the generated seed
     #[cfg(feature = "save_kdbx4")]
-    pub(crate) fn get_kdf_and_seed(&self) -> Result<(Box<dyn kdf::Kdf>, Vec<u8>), getrandom::Error> {
+    pub(crate) fn get_kdf_and_seed(
+        &self,
+        rng: &mut crate::format::SaveRng,
+    ) -> Result<(Box<dyn kdf::Kdf>, Vec<u8>), getrandom::Error> {
         let mut kdf_seed = vec![0; self.seed_size()];
-        getrandom::fill(&mut kdf_seed)?;
+        rng.fill(&mut kdf_seed)?;
 
         let kdf = self.get_kdf_seeded(&kdf_seed);
 
@@ -283,7 +820,6 @@ impl KdfConfig {
         }
     }
 
-    #[cfg(feature = "save_kdbx4")]
     pub(crate) fn to_variant_dictionary(&self, seed: &[u8]) -> VariantDictionary {
         let mut vd = VariantDictionary::new();
 
@@ -330,10 +866,10 @@ const KDF_AES_KDBX4: [u8; 16] = hex!("7c02bb8279a74ac0927d114a00648238");
 const KDF_ARGON2: [u8; 16] = hex!("ef636ddf8c29444b91f7a9a403e30a0c");
 const KDF_ARGON2ID: [u8; 16] = hex!("9e298b1956db4773b23dfc3ec6f0a1e6");
 
-impl TryFrom<VariantDictionary> for (KdfConfig, Vec<u8>) {
+impl TryFrom<&VariantDictionary> for (KdfConfig, Vec<u8>) {
     type Error = KdfConfigError;
 
-    fn try_from(vd: VariantDictionary) -> Result<(KdfConfig, Vec<u8>), Self::Error> {
+    fn try_from(vd: &VariantDictionary) -> Result<(KdfConfig, Vec<u8>), Self::Error> {
         let uuid = vd.get::<Vec<u8>>(KDF_ID)?;
 
         if uuid == &KDF_ARGON2ID {
@@ -407,6 +943,18 @@ impl CompressionConfig {
         }
     }
 
+    /// Get a writer that compresses the data written to it like `get_compression`
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn get_compression_writer<W: std::io::Write>(
+        &self,
+        writer: W,
+    ) -> compression::CompressionWriter<W> {
+        match self {
+            CompressionConfig::None => compression::CompressionWriter::None(writer),
+            CompressionConfig::GZip => compression::CompressionWriter::gzip(writer),
+        }
+    }
+
     #[cfg(feature = "save_kdbx4")]
     pub(crate) fn dump(&self) -> [u8; 4] {
         match self {
@@ -427,3 +975,121 @@ impl TryFrom<u32> for CompressionConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod config_tests {
+    use std::time::Duration;
+
+    use super::{AesKdf, Argon2Kdf, BuiltinKdf, KdfConfig, KdfParameters, KdfScaling, KeyDerivationFunction};
+    use crate::error::{CryptographyError, KdfError};
+
+    fn argon2(iterations: u64, memory: u64) -> KdfConfig {
+        KdfConfig::Argon2 {
+            iterations,
+            memory,
+            parallelism: 2,
+            version: argon2::Version::Version13,
+        }
+    }
+
+    /// Duration of a key derivation on a machine that takes a microsecond per AES round and a
+    /// millisecond per Argon2 iteration and MiB
+    fn fake_time(config: &KdfConfig) -> Result<Duration, CryptographyError> {
+        Ok(match config {
+            KdfConfig::Aes { rounds } => Duration::from_micros(*rounds),
+            KdfConfig::Argon2 {
+                iterations, memory, ..
+            }
+            | KdfConfig::Argon2id {
+                iterations, memory, ..
+            } => Duration::from_millis(iterations * memory / (1024 * 1024)),
+        })
+    }
+
+    #[test]
+    fn benchmark_kdf() {
+        let template = argon2(1, 1024 * 1024);
+        let benchmark = |target: u64, scaling| {
+            template
+                .benchmark_with(Duration::from_millis(target), scaling, fake_time)
+                .unwrap()
+        };
+
+        // doubled until the derivation takes 16 ms, then scaled by 5
+        assert_eq!(benchmark(80, KdfScaling::Iterations), argon2(80, 1024 * 1024));
+        assert_eq!(benchmark(40, KdfScaling::Memory), argon2(1, 40 * 1024 * 1024));
+
+        // the memory is limited, and the iterations are scaled instead
+        assert_eq!(
+            benchmark(10_000, KdfScaling::Memory),
+            argon2(10, 1024 * 1024 * 1024)
+        );
+
+        let config = KdfConfig::Aes { rounds: 1000 }
+            .benchmark_with(Duration::from_millis(10), KdfScaling::Memory, fake_time)
+            .unwrap();
+        assert_eq!(config, KdfConfig::Aes { rounds: 10_000 });
+
+        // the real benchmark keeps the parameters that are not scaled
+        let config = template
+            .benchmark(Duration::from_millis(10), KdfScaling::Iterations)
+            .unwrap();
+        assert!(matches!(
+            config,
+            KdfConfig::Argon2 {
+                memory: 1048576,
+                parallelism: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn scaled() {
+        assert_eq!(
+            argon2(3, 64 * 1024 * 1024).scaled(KdfScaling::Iterations, 0.1),
+            argon2(1, 64 * 1024 * 1024)
+        );
+        assert_eq!(
+            argon2(3, 64 * 1024 * 1024).scaled(KdfScaling::Memory, 0.5),
+            argon2(3, 32 * 1024 * 1024)
+        );
+        assert_eq!(
+            argon2(3, 64 * 1024).scaled(KdfScaling::Memory, 0.5),
+            argon2(3, 1024 * 1024)
+        );
+        assert_eq!(
+            KdfConfig::Aes { rounds: 10 }.scaled(KdfScaling::Iterations, 2.5),
+            KdfConfig::Aes { rounds: 25 }
+        );
+        assert_eq!(
+            argon2(3, 512 * 1024 * 1024).scaled(KdfScaling::Memory, 4.0),
+            argon2(6, 1024 * 1024 * 1024)
+        );
+    }
+
+    #[test]
+    fn standard_kdfs() {
+        let composite_key = [7; 32];
+        let aes = KdfParameters::from_config(&KdfConfig::Aes { rounds: 10 }, &[1; 32]);
+        let argon2 = KdfParameters::from_config(&argon2(1, 1024 * 1024), &[1; 32]);
+
+        let builtin = |params| BuiltinKdf.derive_key(&composite_key, params).unwrap();
+        assert_eq!(AesKdf.derive_key(&composite_key, &aes).unwrap(), builtin(&aes));
+        assert_eq!(
+            Argon2Kdf.derive_key(&composite_key, &argon2).unwrap(),
+            builtin(&argon2)
+        );
+
+        assert!(matches!(
+            AesKdf.derive_key(&composite_key, &argon2),
+            Err(KdfError::Unsupported { .. })
+        ));
+        assert!(matches!(
+            Argon2Kdf.derive_key(&composite_key, &aes),
+            Err(KdfError::Unsupported { .. })
+        ));
+        assert_eq!(aes.get_u64("R"), Some(10));
+        assert_eq!(aes.get_u32("R"), None);
+    }
+}
@@ -1,19 +1,45 @@
-use aes::Aes256;
 #[cfg(feature = "save_kdbx4")]
-use cipher::BlockEncryptMut;
+use std::io::Write;
+
+use aes::Aes256;
 use cipher::{block_padding::Pkcs7, generic_array::GenericArray, BlockDecryptMut};
+#[cfg(feature = "save_kdbx4")]
+use cipher::{BlockCipher, BlockEncryptMut};
+use std::sync::Arc;
+
 use salsa20::{
-    cipher::{KeyIvInit, StreamCipher},
+    cipher::{KeyIvInit, StreamCipher, StreamCipherSeek},
     Salsa20,
 };
 
-use crate::crypt::CryptographyError;
+use zeroize::Zeroize;
+
+use crate::{crypt::CryptographyError, db::DeferredValue};
 
 pub(crate) trait Cipher {
     #[cfg(feature = "save_kdbx4")]
     fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, CryptographyError>;
     fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, CryptographyError>;
 
+    /// Decrypt a buffer in place, for ciphers whose plaintext has the same length as the ciphertext
+    fn decrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<(), CryptographyError> {
+        let mut plaintext = self.decrypt(buffer)?;
+        if plaintext.len() != buffer.len() {
+            plaintext.zeroize();
+            return Err(cipher::InvalidLength.into());
+        }
+        buffer.copy_from_slice(&plaintext);
+        plaintext.zeroize();
+        Ok(())
+    }
+
+    /// Skip over a ciphertext instead of decrypting it, returning a value that can decrypt it
+    /// later on. Only stream ciphers that can seek support this; others return `None` without
+    /// consuming the ciphertext.
+    fn defer(&mut self, _ciphertext: &[u8]) -> Option<DeferredValue> {
+        None
+    }
+
     #[cfg(feature = "save_kdbx4")]
     /// The number of bytes expected by the cipher as an initialization vector.
     fn iv_size() -> usize
@@ -27,6 +53,149 @@ pub(crate) trait Cipher {
         Self: Sized;
 }
 
+/// Encryptor for a payload that is not available all at once
+#[cfg(feature = "save_kdbx4")]
+pub(crate) trait StreamEncryptor {
+    /// Encrypt the next part of the plaintext, appending the ciphertext that is ready to `out`
+    fn update(&mut self, plaintext: &[u8], out: &mut Vec<u8>);
+
+    /// Encrypt the rest of the plaintext including any padding, appending it to `out`
+    fn finish(&mut self, out: &mut Vec<u8>);
+}
+
+/// CBC mode encryption with PKCS#7 padding, as in `AES256Cipher` and `TwofishCipher`
+#[cfg(feature = "save_kdbx4")]
+pub(crate) struct CbcStreamEncryptor<C: BlockEncryptMut + BlockCipher> {
+    encryptor: cbc::Encryptor<C>,
+
+    /// Plaintext that does not fill a complete block yet
+    pending: Vec<u8>,
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<C: BlockEncryptMut + BlockCipher> CbcStreamEncryptor<C>
+where
+    cbc::Encryptor<C>: KeyIvInit,
+{
+    pub(crate) fn new(key: &[u8], iv: &[u8]) -> Result<Self, CryptographyError> {
+        Ok(CbcStreamEncryptor {
+            encryptor: cbc::Encryptor::<C>::new_from_slices(key, iv)?,
+            pending: Vec::new(),
+        })
+    }
+
+    fn encrypt_block(&mut self, block: &[u8], out: &mut Vec<u8>) {
+        let mut block = GenericArray::clone_from_slice(block);
+        self.encryptor.encrypt_block_mut(&mut block);
+        out.extend_from_slice(&block);
+    }
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<C: BlockEncryptMut + BlockCipher> StreamEncryptor for CbcStreamEncryptor<C>
+where
+    cbc::Encryptor<C>: KeyIvInit,
+{
+    fn update(&mut self, plaintext: &[u8], out: &mut Vec<u8>) {
+        let block_size = C::block_size();
+
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(plaintext);
+
+        let complete = pending.len() - pending.len() % block_size;
+        for block in pending[..complete].chunks_exact(block_size) {
+            self.encrypt_block(block, out);
+        }
+
+        pending.drain(..complete);
+        self.pending = pending;
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        let block_size = C::block_size();
+
+        // PKCS#7 always pads, with a full block if the plaintext ends on a block boundary
+        let padding = block_size - self.pending.len();
+        let mut block = std::mem::take(&mut self.pending);
+        block.resize(block_size, padding as u8);
+        self.encrypt_block(&block, out);
+    }
+}
+
+/// Encryption with a stream cipher, as in `ChaCha20Cipher`
+#[cfg(feature = "save_kdbx4")]
+pub(crate) struct StreamCipherEncryptor<C: StreamCipher> {
+    cipher: C,
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl StreamCipherEncryptor<chacha20::ChaCha20> {
+    pub(crate) fn new_chacha20(key: &[u8], iv: &[u8]) -> Result<Self, CryptographyError> {
+        Ok(StreamCipherEncryptor {
+            cipher: chacha20::ChaCha20::new_from_slices(key, iv)?,
+        })
+    }
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<C: StreamCipher> StreamEncryptor for StreamCipherEncryptor<C> {
+    fn update(&mut self, plaintext: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(plaintext);
+        self.cipher.apply_keystream(&mut out[start..]);
+    }
+
+    fn finish(&mut self, _out: &mut Vec<u8>) {}
+}
+
+/// Writer that encrypts the data written to it. `finish` must be called to write the padding.
+#[cfg(feature = "save_kdbx4")]
+pub(crate) struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: Box<dyn StreamEncryptor>,
+    buffer: Vec<u8>,
+}
+
+/// Maximum amount of data that an `EncryptWriter` encrypts at once
+#[cfg(feature = "save_kdbx4")]
+const ENCRYPT_CHUNK_SIZE: usize = 64 * 1024;
+
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> EncryptWriter<W> {
+    pub(crate) fn new(inner: W, encryptor: Box<dyn StreamEncryptor>) -> Self {
+        EncryptWriter {
+            inner,
+            encryptor,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Write the end of the ciphertext, returning the inner writer
+    pub(crate) fn finish(mut self) -> std::io::Result<W> {
+        self.buffer.clear();
+        self.encryptor.finish(&mut self.buffer);
+        self.inner.write_all(&self.buffer)?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = usize::min(buf.len(), ENCRYPT_CHUNK_SIZE);
+
+        self.buffer.clear();
+        self.encryptor.update(&buf[..size], &mut self.buffer);
+        self.inner.write_all(&self.buffer)?;
+
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 #[cfg(feature = "save_kdbx4")]
 type Aes256CbcEncryptor = cbc::Encryptor<Aes256>;
 type Aes256CbcDecryptor = cbc::Decryptor<Aes256>;
@@ -124,19 +293,76 @@ impl Cipher for TwofishCipher {
     }
 }
 
+/// Key and nonce of an inner stream cipher, to decrypt protected values at their position in the
+/// keystream after parsing, see `Cipher::defer`
+pub(crate) enum Keystream {
+    Salsa20 { key: Vec<u8>, iv: Vec<u8> },
+    ChaCha20 { key: Vec<u8>, nonce: Vec<u8> },
+}
+
+impl Keystream {
+    /// XOR the keystream starting at `offset` into `buffer`
+    pub(crate) fn apply_at(&self, offset: u64, buffer: &mut [u8]) {
+        match self {
+            Keystream::Salsa20 { key, iv } => {
+                let mut cipher = Salsa20::new(GenericArray::from_slice(key), GenericArray::from_slice(iv));
+                cipher.seek(offset);
+                cipher.apply_keystream(buffer);
+            }
+            Keystream::ChaCha20 { key, nonce } => {
+                let mut cipher =
+                    chacha20::ChaCha20::new(GenericArray::from_slice(key), GenericArray::from_slice(nonce));
+                cipher.seek(offset);
+                cipher.apply_keystream(buffer);
+            }
+        }
+    }
+}
+
+impl Drop for Keystream {
+    fn drop(&mut self) {
+        match self {
+            Keystream::Salsa20 { key, .. } | Keystream::ChaCha20 { key, .. } => zeroize::Zeroize::zeroize(key),
+        }
+    }
+}
+
+/// Skip over a ciphertext in a seekable stream cipher, see `Cipher::defer`
+fn defer_in<C: StreamCipherSeek>(
+    cipher: &mut C,
+    keystream: &Arc<Keystream>,
+    ciphertext: &[u8],
+) -> Option<DeferredValue> {
+    let offset: u64 = cipher.try_current_pos().ok()?;
+    cipher.try_seek(offset + ciphertext.len() as u64).ok()?;
+    Some(DeferredValue::new(ciphertext.to_vec(), keystream.clone(), offset))
+}
+
+const SALSA20_IV: [u8; 8] = [0xE8, 0x30, 0x09, 0x4B, 0x97, 0x20, 0x5D, 0x2A];
+
 pub(crate) struct Salsa20Cipher {
     cipher: salsa20::Salsa20,
+    keystream: Option<Arc<Keystream>>,
 }
 
 impl Salsa20Cipher {
     pub(crate) fn new(key: &[u8]) -> Result<Self, CryptographyError> {
-        let key = GenericArray::from_slice(key);
-        let iv = GenericArray::from([0xE8, 0x30, 0x09, 0x4B, 0x97, 0x20, 0x5D, 0x2A]);
-
         Ok(Salsa20Cipher {
-            cipher: Salsa20::new(key, &iv),
+            cipher: Salsa20::new(GenericArray::from_slice(key), &GenericArray::from(SALSA20_IV)),
+            keystream: None,
         })
     }
+
+    /// Create a cipher like `new` that keeps a copy of the key to defer the decryption of values,
+    /// see `Cipher::defer`
+    pub(crate) fn new_deferring(key: &[u8]) -> Result<Self, CryptographyError> {
+        let mut cipher = Self::new(key)?;
+        cipher.keystream = Some(Arc::new(Keystream::Salsa20 {
+            key: key.to_vec(),
+            iv: SALSA20_IV.to_vec(),
+        }));
+        Ok(cipher)
+    }
 }
 
 impl Cipher for Salsa20Cipher {
@@ -152,6 +378,15 @@ impl Cipher for Salsa20Cipher {
         Ok(buffer)
     }
 
+    fn decrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<(), CryptographyError> {
+        self.cipher.apply_keystream(buffer);
+        Ok(())
+    }
+
+    fn defer(&mut self, ciphertext: &[u8]) -> Option<DeferredValue> {
+        defer_in(&mut self.cipher, self.keystream.as_ref()?, ciphertext)
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn iv_size() -> usize {
         // or 16
@@ -166,6 +401,7 @@ impl Cipher for Salsa20Cipher {
 
 pub(crate) struct ChaCha20Cipher {
     cipher: chacha20::ChaCha20,
+    keystream: Option<Arc<Keystream>>,
 }
 
 impl ChaCha20Cipher {
@@ -173,18 +409,27 @@ impl ChaCha20Cipher {
     pub(crate) fn new(key: &[u8]) -> Result<Self, CryptographyError> {
         let iv = crate::crypt::calculate_sha512(&[key])?;
 
-        let key = GenericArray::from_slice(&iv[0..32]);
-        let nonce = GenericArray::from_slice(&iv[32..44]);
+        Self::new_key_iv(&iv[0..32], &iv[32..44])
+    }
 
-        Ok(ChaCha20Cipher {
-            cipher: chacha20::ChaCha20::new(key, nonce),
-        })
+    /// Create as an inner cipher like `new` that keeps a copy of the key to defer the decryption
+    /// of values, see `Cipher::defer`
+    pub(crate) fn new_deferring(key: &[u8]) -> Result<Self, CryptographyError> {
+        let iv = crate::crypt::calculate_sha512(&[key])?;
+
+        let mut cipher = Self::new_key_iv(&iv[0..32], &iv[32..44])?;
+        cipher.keystream = Some(Arc::new(Keystream::ChaCha20 {
+            key: iv[0..32].to_vec(),
+            nonce: iv[32..44].to_vec(),
+        }));
+        Ok(cipher)
     }
 
     /// Create as an outer cipher by separately-specified key and iv
     pub(crate) fn new_key_iv(key: &[u8], iv: &[u8]) -> Result<Self, CryptographyError> {
         Ok(ChaCha20Cipher {
             cipher: chacha20::ChaCha20::new_from_slices(key, iv)?,
+            keystream: None,
         })
     }
 }
@@ -202,6 +447,15 @@ impl Cipher for ChaCha20Cipher {
         Ok(buffer)
     }
 
+    fn decrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<(), CryptographyError> {
+        self.cipher.apply_keystream(buffer);
+        Ok(())
+    }
+
+    fn defer(&mut self, ciphertext: &[u8]) -> Option<DeferredValue> {
+        defer_in(&mut self.cipher, self.keystream.as_ref()?, ciphertext)
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn iv_size() -> usize {
         12
@@ -228,6 +482,10 @@ impl Cipher for PlainCipher {
         Ok(Vec::from(ciphertext))
     }
 
+    fn decrypt_in_place(&mut self, _buffer: &mut [u8]) -> Result<(), CryptographyError> {
+        Ok(())
+    }
+
     #[cfg(feature = "save_kdbx4")]
     fn iv_size() -> usize {
         1
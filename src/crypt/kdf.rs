@@ -50,13 +50,14 @@ pub struct Argon2Kdf {
     pub variant: argon2::Variant,
 }
 
-impl Kdf for Argon2Kdf {
-    fn transform_key(
+impl Argon2Kdf {
+    fn transform_key_with(
         &self,
         composite_key: &GenericArray<u8, U32>,
+        thread_mode: argon2::ThreadMode,
     ) -> Result<GenericArray<u8, U32>, CryptographyError> {
         let config = argon2::Config {
-            thread_mode: argon2::ThreadMode::Parallel,
+            thread_mode,
             ad: &[],
             hash_length: 32,
             lanes: self.parallelism,
@@ -73,6 +74,27 @@ impl Kdf for Argon2Kdf {
     }
 }
 
+impl Kdf for Argon2Kdf {
+    fn transform_key(
+        &self,
+        composite_key: &GenericArray<u8, U32>,
+    ) -> Result<GenericArray<u8, U32>, CryptographyError> {
+        self.transform_key_with(composite_key, thread_mode(self.parallelism))
+    }
+}
+
+/// Compute the lanes of Argon2 in one thread each, if there are several lanes
+#[cfg(all(feature = "argon2_threads", not(target_family = "wasm")))]
+fn thread_mode(lanes: u32) -> argon2::ThreadMode {
+    argon2::ThreadMode::from_threads(lanes)
+}
+
+/// Compute the lanes of Argon2 one after another, since threads are disabled or unavailable
+#[cfg(not(all(feature = "argon2_threads", not(target_family = "wasm"))))]
+fn thread_mode(_lanes: u32) -> argon2::ThreadMode {
+    argon2::ThreadMode::Sequential
+}
+
 /*
 pub(crate) fn transform_key_argon2(
     composite_key: &GenericArray<u8, U32>,
@@ -84,3 +106,39 @@ pub(crate) fn transform_key_argon2(
     };
 }
 */
+
+#[cfg(all(test, feature = "argon2_threads"))]
+mod kdf_tests {
+    use cipher::generic_array::GenericArray;
+
+    use super::{Argon2Kdf, Kdf};
+
+    /// The lanes of Argon2 are computed in one thread each, which must not change the derived key
+    #[test]
+    fn argon2_threads_derive_same_key() {
+        let composite_key = GenericArray::clone_from_slice(&[42; 32]);
+
+        for variant in [argon2::Variant::Argon2d, argon2::Variant::Argon2id] {
+            for parallelism in [2, 3, 4, 8, 16] {
+                let kdf = Argon2Kdf {
+                    memory: 1024 * 1024,
+                    salt: vec![7; 32],
+                    iterations: 3,
+                    parallelism,
+                    version: argon2::Version::Version13,
+                    variant,
+                };
+
+                let sequential = kdf
+                    .transform_key_with(&composite_key, argon2::ThreadMode::Sequential)
+                    .unwrap();
+                let parallel = kdf
+                    .transform_key_with(&composite_key, argon2::ThreadMode::Parallel)
+                    .unwrap();
+
+                assert_eq!(sequential, parallel, "{:?} with {} lanes", variant, parallelism);
+                assert_eq!(kdf.transform_key(&composite_key).unwrap(), sequential);
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    alloc::Layout,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+use cipher::generic_array::{ArrayLength, GenericArray};
+use zeroize::Zeroize;
+
+/// Buffer for keys, zeroed when dropped. With the `mlock` feature,
+/// it is kept on locked memory pages of its own where the operating system allows it.
+pub(crate) struct LockedBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+
+    #[cfg(all(feature = "mlock", any(unix, windows)))]
+    locked: bool,
+}
+
+// the buffer owns its memory like a Box<[u8]>
+unsafe impl Send for LockedBuffer {}
+unsafe impl Sync for LockedBuffer {}
+
+impl LockedBuffer {
+    /// Allocate a zeroed buffer of `len` bytes
+    pub(crate) fn new(len: usize) -> Self {
+        let layout = buffer_layout(len);
+        let ptr = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) })
+            .unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+
+        #[cfg(all(feature = "mlock", any(unix, windows)))]
+        let locked = unsafe { memsec::mlock(ptr.as_ptr(), layout.size()) };
+
+        LockedBuffer {
+            ptr,
+            len,
+            layout,
+
+            #[cfg(all(feature = "mlock", any(unix, windows)))]
+            locked,
+        }
+    }
+
+    /// Copy data into a new buffer
+    pub(crate) fn from_slice(data: &[u8]) -> Self {
+        let mut buffer = LockedBuffer::new(data.len());
+        buffer.copy_from_slice(data);
+        buffer
+    }
+
+    /// Whether the memory of the buffer is locked
+    #[cfg(feature = "mlock")]
+    pub(crate) fn is_locked(&self) -> bool {
+        #[cfg(any(unix, windows))]
+        return self.locked;
+
+        #[cfg(not(any(unix, windows)))]
+        false
+    }
+}
+
+/// Layout of the memory of a buffer: whole pages with the `mlock` feature, so that no other data
+/// shares a locked page
+fn buffer_layout(len: usize) -> Layout {
+    #[cfg(all(feature = "mlock", any(unix, windows)))]
+    {
+        let page_size = page_size();
+        let size = len.max(1).div_ceil(page_size) * page_size;
+        Layout::from_size_align(size, page_size).expect("page-aligned layout")
+    }
+
+    #[cfg(not(all(feature = "mlock", any(unix, windows))))]
+    Layout::array::<u8>(len.max(1)).expect("buffer layout")
+}
+
+#[cfg(all(feature = "mlock", unix))]
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+// all Windows architectures use 4 KiB pages
+#[cfg(all(feature = "mlock", windows))]
+fn page_size() -> usize {
+    4096
+}
+
+impl Deref for LockedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for LockedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()).zeroize();
+
+            #[cfg(all(feature = "mlock", any(unix, windows)))]
+            if self.locked {
+                memsec::munlock(self.ptr.as_ptr(), self.layout.size());
+            }
+
+            std::alloc::dealloc(self.ptr.as_ptr(), self.layout);
+        }
+    }
+}
+
+/// Key material derived while opening or saving a database, such as the transformed key and the
+/// master key, as well as the session keys of locked databases, kept in a `LockedBuffer`
+pub(crate) struct LockedKey<N: ArrayLength<u8>> {
+    buffer: LockedBuffer,
+    length: PhantomData<N>,
+}
+
+impl<N: ArrayLength<u8>> LockedKey<N> {
+    /// Move a key into locked memory, zeroing the original
+    pub(crate) fn new(mut key: GenericArray<u8, N>) -> Self {
+        let buffer = LockedBuffer::from_slice(&key);
+        key.as_mut_slice().zeroize();
+
+        LockedKey {
+            buffer,
+            length: PhantomData,
+        }
+    }
+}
+
+impl<N: ArrayLength<u8>> Deref for LockedKey<N> {
+    type Target = GenericArray<u8, N>;
+
+    fn deref(&self) -> &Self::Target {
+        GenericArray::from_slice(&self.buffer)
+    }
+}
+
+/// Check whether the operating system allows this process to lock memory, see the `mlock` feature
+#[cfg(feature = "mlock")]
+pub fn memory_locking_available() -> bool {
+    LockedBuffer::new(1).is_locked()
+}
+
+#[cfg(test)]
+mod locked_tests {
+    use cipher::generic_array::{typenum::U32, GenericArray};
+
+    use super::{LockedBuffer, LockedKey};
+
+    #[test]
+    fn keeps_key() {
+        let key: GenericArray<u8, U32> = GenericArray::clone_from_slice(&[7; 32]);
+        let locked = LockedKey::new(key);
+        assert_eq!(&locked[..], &[7; 32]);
+    }
+
+    #[test]
+    fn keeps_data() {
+        let buffer = LockedBuffer::from_slice(b"secret");
+        assert_eq!(&buffer[..], b"secret");
+        assert!(LockedBuffer::new(0).is_empty());
+    }
+
+    #[cfg(all(feature = "mlock", any(unix, windows)))]
+    #[test]
+    fn buffers_do_not_share_pages() {
+        let page_size = super::page_size();
+        let first = LockedKey::new(GenericArray::<u8, U32>::clone_from_slice(&[1; 32]));
+        let second = LockedKey::new(*first);
+
+        // each key starts on a page of its own, so unlocking one cannot unlock the other
+        let page = |key: &LockedKey<U32>| key.buffer.as_ptr() as usize / page_size;
+        assert_eq!(first.buffer.as_ptr() as usize % page_size, 0);
+        assert_ne!(page(&first), page(&second));
+
+        drop(first);
+        assert_eq!(&second[..], &[1; 32]);
+    }
+
+    #[cfg(feature = "mlock")]
+    #[test]
+    fn probe_matches_locking() {
+        let locked = LockedKey::new(GenericArray::<u8, U32>::default());
+
+        // the probe and the key lock memory the same way, so they succeed or fail together
+        #[cfg(any(unix, windows))]
+        assert_eq!(locked.buffer.is_locked(), super::memory_locking_available());
+        #[cfg(not(any(unix, windows)))]
+        assert!(!super::memory_locking_available());
+        drop(locked);
+    }
+}
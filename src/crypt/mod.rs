@@ -14,6 +14,7 @@ use crate::error::CryptographyError;
 
 pub(crate) mod ciphers;
 pub(crate) mod kdf;
+pub(crate) mod locked;
 
 pub(crate) fn calculate_hmac(
     elements: &[&[u8]],
@@ -0,0 +1,88 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+#[cfg(feature = "save_kdbx4")]
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{db::Database, error::DatabaseOpenError, key::DatabaseKey};
+
+/// Run a CPU-heavy closure on the blocking thread pool of the runtime, passing on panics
+async fn run_blocking<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+impl Database {
+    /// Parse a database from a `tokio::io::AsyncRead`.
+    ///
+    /// The source is read asynchronously, and the key derivation, decryption and parsing run on the
+    /// blocking thread pool of the runtime with `tokio::task::spawn_blocking`, so that other tasks
+    /// keep running while a slow key derivation function such as Argon2 is computed.
+    pub async fn open_async<R: AsyncRead + Unpin>(
+        mut source: R,
+        key: DatabaseKey,
+    ) -> Result<Database, DatabaseOpenError> {
+        let mut data = Vec::new();
+        source.read_to_end(&mut data).await?;
+
+        run_blocking(move || Database::parse(&data, key)).await
+    }
+
+    /// Save a database to a `tokio::io::AsyncWrite`.
+    ///
+    /// The database is serialized and encrypted with `tokio::task::block_in_place`, which lets the
+    /// other tasks of the runtime move to other worker threads, and streamed into the destination
+    /// as it is written. This needs the multi-threaded runtime, on a current-thread runtime an error
+    /// of the kind `std::io::ErrorKind::Unsupported` is returned.
+    #[cfg(feature = "save_kdbx4")]
+    pub async fn save_async<W: AsyncWrite + Unpin>(
+        &self,
+        mut destination: W,
+        key: DatabaseKey,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        let handle = tokio::runtime::Handle::current();
+        if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::CurrentThread {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "save_async needs the multi-threaded runtime",
+            )
+            .into());
+        }
+
+        tokio::task::block_in_place(|| {
+            let mut writer = std::io::BufWriter::new(BlockingWriter {
+                handle: &handle,
+                destination: &mut destination,
+            });
+            self.save(&mut writer, key)?;
+            std::io::Write::flush(&mut writer)?;
+            Ok::<_, crate::error::DatabaseSaveError>(())
+        })?;
+
+        destination.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Writes to an `AsyncWrite` from blocking code, see `Database::save_async`
+#[cfg(feature = "save_kdbx4")]
+struct BlockingWriter<'a, W> {
+    handle: &'a tokio::runtime::Handle,
+    destination: &'a mut W,
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<W: AsyncWrite + Unpin> std::io::Write for BlockingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.handle.block_on(self.destination.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.handle.block_on(self.destination.flush())
+    }
+}
@@ -0,0 +1,183 @@
+use crate::db::{AutoTypeAssociation, Entry};
+
+impl AutoTypeAssociation {
+    /// Whether the window pattern of this association matches a window title.
+    ///
+    /// As in KeePass, the pattern has to match the whole title, ignoring case, where `*` matches
+    /// any number of characters and `?` matches a single character. Associations without a
+    /// window pattern match no window.
+    pub fn matches_window(&self, window_title: &str) -> bool {
+        self.window
+            .as_deref()
+            .is_some_and(|pattern| glob_matches(pattern, window_title))
+    }
+}
+
+impl Entry {
+    /// Whether auto-type is enabled for this entry and one of its window associations matches a
+    /// window title, see `AutoTypeAssociation::matches_window`
+    pub fn matches_autotype_window(&self, window_title: &str) -> bool {
+        self.autotype_association_for_window(window_title).is_some()
+    }
+
+    /// Get the auto-type sequence of the first window association that matches a window title, or
+    /// the default sequence of the entry if the association does not have its own.
+    ///
+    /// Returns `None` if auto-type is disabled, no association matches, or neither the
+    /// association nor the entry has a sequence, in which case clients use the default sequence
+    /// of the group, see `Group::get_default_autotype_sequence`.
+    pub fn get_autotype_sequence_for_window(&self, window_title: &str) -> Option<&str> {
+        let association = self.autotype_association_for_window(window_title)?;
+        association
+            .sequence
+            .as_deref()
+            .filter(|sequence| !sequence.is_empty())
+            .or_else(|| self.autotype.as_ref()?.sequence.as_deref())
+            .filter(|sequence| !sequence.is_empty())
+    }
+
+    fn autotype_association_for_window(&self, window_title: &str) -> Option<&AutoTypeAssociation> {
+        let autotype = self.autotype.as_ref().filter(|autotype| autotype.enabled)?;
+        autotype
+            .associations
+            .iter()
+            .find(|association| association.matches_window(window_title))
+    }
+}
+
+/// Match a KeePass window pattern against a whole text, ignoring case
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // the position after the last `*` in the pattern, and the position in the text it was tried at
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            // let the last `*` match one more character and try again
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod autotype_tests {
+    use proptest::prelude::*;
+
+    use super::glob_matches;
+    use crate::db::{AutoType, AutoTypeAssociation, Entry};
+
+    /// Straightforward recursive matcher to compare `glob_matches` against
+    fn reference_matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => (0..=text.len()).any(|skip| reference_matches(rest, &text[skip..])),
+            Some((&c, rest)) => match text.split_first() {
+                Some((&t, text_rest)) => (c == '?' || c == t) && reference_matches(rest, text_rest),
+                None => false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*Firefox*", "Login - Mozilla Firefox"));
+        assert!(glob_matches("login - *", "LOGIN - Mozilla Firefox"));
+        assert!(glob_matches("Pu?TY", "PuTTY"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("", ""));
+        assert!(glob_matches("a**b", "ab"));
+        assert!(glob_matches("*a*b", "xaxxab"));
+        assert!(!glob_matches("Firefox", "Mozilla Firefox"));
+        assert!(!glob_matches("Pu?TY", "PuTY"));
+        assert!(!glob_matches("*a", "ab"));
+        assert!(!glob_matches("", "a"));
+    }
+
+    #[test]
+    fn test_sequence_for_window() {
+        let mut entry = Entry::new();
+        assert_eq!(entry.get_autotype_sequence_for_window("Firefox"), None);
+
+        entry.autotype = Some(AutoType {
+            enabled: true,
+            sequence: Some("{USERNAME}{TAB}{PASSWORD}{ENTER}".to_string()),
+            associations: vec![
+                AutoTypeAssociation {
+                    window: Some("*PuTTY*".to_string()),
+                    sequence: Some("{PASSWORD}{ENTER}".to_string()),
+                },
+                AutoTypeAssociation {
+                    window: Some("* - Mozilla Firefox".to_string()),
+                    sequence: None,
+                },
+                AutoTypeAssociation {
+                    window: Some("*".to_string()),
+                    sequence: Some("{USERNAME}".to_string()),
+                },
+            ],
+        });
+
+        assert_eq!(
+            entry.get_autotype_sequence_for_window("server - putty"),
+            Some("{PASSWORD}{ENTER}")
+        );
+        assert_eq!(
+            entry.get_autotype_sequence_for_window("Login - Mozilla Firefox"),
+            Some("{USERNAME}{TAB}{PASSWORD}{ENTER}")
+        );
+        assert_eq!(
+            entry.get_autotype_sequence_for_window("Terminal"),
+            Some("{USERNAME}")
+        );
+        assert!(entry.matches_autotype_window("Terminal"));
+
+        entry.autotype.as_mut().unwrap().enabled = false;
+        assert!(!entry.matches_autotype_window("Terminal"));
+        assert_eq!(entry.get_autotype_sequence_for_window("Terminal"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_like_reference(pattern in "[aB*?]{0,8}", text in "[abAB]{0,8}") {
+            let lower = |s: &str| s.to_lowercase().chars().collect::<Vec<_>>();
+            prop_assert_eq!(
+                glob_matches(&pattern, &text),
+                reference_matches(&lower(&pattern), &lower(&text))
+            );
+        }
+
+        #[test]
+        fn wildcards_match_replaced_characters(text in "[a-zA-Z0-9 ]{0,16}", replace in any::<Vec<bool>>()) {
+            let pattern: String = text
+                .chars()
+                .zip(replace.iter().chain(std::iter::repeat(&false)))
+                .map(|(c, &replace)| if replace { '?' } else { c })
+                .collect();
+            let surrounded = format!("*{}*", pattern);
+            let longer = format!("{}x", text);
+            prop_assert!(glob_matches(&pattern, &text.to_uppercase()));
+            prop_assert!(glob_matches(&surrounded, &text));
+            prop_assert!(!glob_matches(&pattern, &longer));
+        }
+    }
+}
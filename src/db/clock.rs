@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+
+use crate::db::Times;
+
+/// Source of the current time for the timestamps that a `Database` sets on its own
+pub trait TimeProvider: Send + Sync {
+    /// Get the current time in UTC
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// Time provider using the system clock, truncated to whole seconds like all KeePass timestamps
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemTimeProvider;
+
+impl TimeProvider for SystemTimeProvider {
+    fn now(&self) -> NaiveDateTime {
+        Times::now()
+    }
+}
+
+/// Time provider that always returns the same time, e.g. for tests or reproducible batch jobs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedTimeProvider(pub NaiveDateTime);
+
+impl TimeProvider for FixedTimeProvider {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
+
+/// Shared handle to the time provider of a database, together with whether changes update
+/// timestamps, see `Database::set_update_times`.
+///
+/// The database and all of its entries and groups share the time provider, and nodes compare equal
+/// regardless of it.
+#[derive(Clone)]
+pub(crate) struct Clock {
+    provider: Arc<dyn TimeProvider>,
+    pub(crate) update_times: bool,
+}
+
+impl Clock {
+    pub(crate) fn new(provider: Box<dyn TimeProvider>, update_times: bool) -> Self {
+        Clock {
+            provider: Arc::from(provider),
+            update_times,
+        }
+    }
+
+    pub(crate) fn now(&self) -> NaiveDateTime {
+        self.provider.now()
+    }
+
+    /// The time to record a change with, or `None` if changes do not update timestamps
+    pub(crate) fn change_time(&self) -> Option<NaiveDateTime> {
+        self.update_times.then(|| self.now())
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock {
+            provider: Arc::new(SystemTimeProvider),
+            update_times: true,
+        }
+    }
+}
+
+impl std::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+impl PartialEq for Clock {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Clock {}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, OnceLock};
+
+use secstr::SecStr;
+
+use crate::crypt::ciphers::Keystream;
+
+/// A protected value that is decrypted when it is first accessed, see
+/// `OpenOptions::defer_protected_values`.
+///
+/// The value holds the ciphertext from the file and its position in the keystream of the inner
+/// cipher. Once decrypted, the plaintext is kept like the content of `Value::Protected`, in addition
+/// to the ciphertext.
+#[derive(Clone)]
+pub struct DeferredValue {
+    ciphertext: Vec<u8>,
+    keystream: Arc<Keystream>,
+    offset: u64,
+    plaintext: OnceLock<SecStr>,
+}
+
+impl DeferredValue {
+    pub(crate) fn new(ciphertext: Vec<u8>, keystream: Arc<Keystream>, offset: u64) -> Self {
+        DeferredValue {
+            ciphertext,
+            keystream,
+            offset,
+            plaintext: OnceLock::new(),
+        }
+    }
+
+    /// Get the plaintext, decrypting it if this has not happened yet
+    pub fn get(&self) -> &SecStr {
+        self.plaintext.get_or_init(|| {
+            // decrypt in the memory of the SecStr, so that there is no other copy of the plaintext.
+            // Unlike in a regular parse, invalid UTF-8 is only noticed by accessors such as
+            // `Value::expose_secret`, which return `None` for it.
+            let mut value = SecStr::new(self.ciphertext.clone());
+            self.keystream.apply_at(self.offset, value.unsecure_mut());
+            value
+        })
+    }
+
+    /// Whether the value has been decrypted already
+    pub fn is_decrypted(&self) -> bool {
+        self.plaintext.get().is_some()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ciphertext.is_empty()
+    }
+}
+
+impl PartialEq for DeferredValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl Eq for DeferredValue {}
+
+impl std::fmt::Debug for DeferredValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeferredValue")
+            .field("decrypted", &self.is_decrypted())
+            .finish()
+    }
+}
@@ -0,0 +1,152 @@
+use uuid::Uuid;
+
+use crate::db::{Database, Group, Node, NodeRef};
+
+/// Name of the recycle bin when it is created by `Database::delete_where`, as in KeePass
+const RECYCLE_BIN_NAME: &str = "Recycle Bin";
+
+/// Icon of the recycle bin when it is created by `Database::delete_where`, as in KeePass
+const RECYCLE_BIN_ICON_ID: usize = 43;
+
+/// How `Database::delete_where` deletes nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Remove the nodes and log their deletion in `deleted_objects`, so that merging removes them
+    /// from other copies of the database
+    Permanent,
+
+    /// Remove the nodes without logging their deletion
+    PermanentUnlogged,
+
+    /// Move the nodes to the recycle bin, which is created if the database does not have one.
+    ///
+    /// As in KeePass, nodes that already are in the recycle bin are removed and logged like with
+    /// `Permanent`, and so are all nodes if the recycle bin is disabled in the `Meta` of the database.
+    Recycle,
+}
+
+impl Database {
+    /// Delete all groups and entries that match a predicate, e.g. to empty old entries from the
+    /// recycle bin, and return the deleted nodes.
+    ///
+    /// The nodes below a matching group are deleted along with it, without calling the predicate
+    /// for them. The root group is never deleted, and with `DeleteMode::Recycle`, neither are the
+    /// recycle bin and the groups containing it. Recycled nodes are returned as they were moved to
+    /// the recycle bin.
+    ///
+    /// ```
+    /// use keepass::{db::{DeleteMode, Entry, NodeRef}, Database};
+    ///
+    /// let mut db = Database::new(Default::default());
+    /// db.root.add_child(Entry::new());
+    ///
+    /// let deleted = db.delete_where(|node| matches!(node, NodeRef::Entry(_)), DeleteMode::Recycle);
+    /// assert_eq!(deleted.len(), 1);
+    /// assert!(db.meta.recyclebin_uuid.is_some());
+    /// ```
+    pub fn delete_where(&mut self, predicate: impl Fn(&NodeRef) -> bool, mode: DeleteMode) -> Vec<Node> {
+        let recycle = mode == DeleteMode::Recycle && self.meta.recyclebin_enabled != Some(false);
+        let recycle_bin = self
+            .meta
+            .recyclebin_uuid
+            .filter(|uuid| matches!(self.get_by_uuid(uuid), Some(NodeRef::Group(_))));
+
+        // recycling the recycle bin or a group containing it would leave nowhere to move it to
+        let mut protected = Vec::new();
+        if let (true, Some(uuid)) = (recycle, recycle_bin) {
+            protected = self.root.find_node_location(uuid).unwrap_or_default();
+            protected.push(uuid);
+        }
+
+        let mut matches = Vec::new();
+        for child in &self.root.children {
+            collect_matches(
+                child.as_ref(),
+                &predicate,
+                &protected,
+                recycle_bin,
+                false,
+                &mut matches,
+            );
+        }
+
+        let mut deleted = Vec::new();
+        for (uuid, in_recycle_bin) in matches {
+            if recycle && !in_recycle_bin {
+                let recycle_bin = self.recycle_bin();
+                let Some(mut node) = self.remove_by_uuid(&uuid) else {
+                    continue;
+                };
+
+                if let Some(now) = self.clock.change_time() {
+                    match &mut node {
+                        Node::Group(g) => g.times.set_location_changed(now),
+                        Node::Entry(e) => e.times.set_location_changed(now),
+                    }
+                }
+                deleted.push(node.clone());
+                self.add_to_group(&recycle_bin, node);
+            } else if let Some(node) = self.delete_by_uuid(&uuid, mode != DeleteMode::PermanentUnlogged) {
+                deleted.push(node);
+            }
+        }
+
+        deleted
+    }
+
+    /// Get the UUID of the recycle bin, creating it in the root group if it does not exist
+    fn recycle_bin(&mut self) -> Uuid {
+        if let Some(uuid) = self.meta.recyclebin_uuid {
+            if matches!(self.get_by_uuid(&uuid), Some(NodeRef::Group(_))) {
+                return uuid;
+            }
+        }
+
+        let recycle_bin = Group {
+            icon_id: Some(RECYCLE_BIN_ICON_ID),
+            enable_autotype: Some("false".to_string()),
+            enable_searching: Some("false".to_string()),
+            ..self.new_group(RECYCLE_BIN_NAME)
+        };
+        let uuid = recycle_bin.uuid;
+        let root = self.root.uuid;
+        self.add_to_group(&root, recycle_bin);
+
+        self.meta.recyclebin_uuid = Some(uuid);
+        if let Some(now) = self.clock.change_time() {
+            self.meta.recyclebin_changed = Some(now);
+        }
+        uuid
+    }
+}
+
+/// Collect the UUIDs of the nodes that match the predicate, and whether they are in the recycle bin.
+/// Matching nodes are not descended into, and protected nodes never match.
+fn collect_matches(
+    node: NodeRef<'_>,
+    predicate: &impl Fn(&NodeRef) -> bool,
+    protected: &[Uuid],
+    recycle_bin: Option<Uuid>,
+    in_recycle_bin: bool,
+    matches: &mut Vec<(Uuid, bool)>,
+) {
+    if !protected.contains(&node.uuid()) && predicate(&node) {
+        matches.push((node.uuid(), in_recycle_bin));
+        return;
+    }
+
+    let is_recycle_bin = recycle_bin == Some(node.uuid());
+
+    if let NodeRef::Group(group) = node {
+        for child in &group.children {
+            collect_matches(
+                child.as_ref(),
+                predicate,
+                protected,
+                recycle_bin,
+                in_recycle_bin || is_recycle_bin,
+                matches,
+            );
+        }
+    }
+}
@@ -1,24 +1,67 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, io::Read, path::Path};
 
+use indexmap::IndexMap;
 use secstr::SecStr;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 #[cfg(feature = "_merge")]
-use crate::db::merge::{MergeError, MergeLog};
+use crate::db::{
+    merge::{MergeError, MergeLog, MergeStrategy},
+    HeaderAttachment,
+};
 #[cfg(all(test, feature = "_merge"))]
 use std::{thread, time};
 
-use crate::db::{Color, CustomData, Times};
+use crate::{
+    db::{
+        clock::Clock,
+        deferred::DeferredValue,
+        lock::LockedValue,
+        otp_uri::{base32_encode, is_otp_uri, OtpAlgorithm, OtpParameters, TotpOptions},
+        Color, CustomData, Database, Times, UnknownElement,
+    },
+    error::OtpParseError,
+};
 
 #[cfg(feature = "totp")]
 use crate::db::otp::{TOTPError, TOTP};
 
+/// Fields that may contain an `otpauth://` URI, in order of precedence
+const OTP_URI_FIELDS: [&str; 3] = ["otp", "TOTP Seed", "_TOTP_Secret"];
+
+/// Fields that KeePass and the KeeOtp2 plugin store TOTP settings in, instead of an URI
+const TIME_OTP_SECRET_FIELD: &str = "TimeOtp-Secret-Base32";
+const TIME_OTP_LENGTH_FIELD: &str = "TimeOtp-Length";
+const TIME_OTP_PERIOD_FIELD: &str = "TimeOtp-Period";
+const TIME_OTP_ALGORITHM_FIELD: &str = "TimeOtp-Algorithm";
+
+/// Values of the `TimeOtp-Algorithm` field
+const TIME_OTP_ALGORITHMS: [(&str, OtpAlgorithm); 3] = [
+    ("HMAC-SHA-1", OtpAlgorithm::Sha1),
+    ("HMAC-SHA-256", OtpAlgorithm::Sha256),
+    ("HMAC-SHA-512", OtpAlgorithm::Sha512),
+];
+
+/// Fields that `Entry::set` protects when it creates them
+const PROTECTED_BY_DEFAULT: [&str; 2] = ["Password", "otp"];
+
+/// Size of the chunks that files are read in by `Entry::add_attachment_from_path`
+const ATTACHMENT_CHUNK_SIZE: usize = 64 * 1024;
+
 /// A database entry containing several key-value fields.
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub struct Entry {
     pub uuid: Uuid,
-    pub fields: HashMap<String, Value>,
+
+    /// Fields of the entry in the order they were added, which is kept when the database is saved
+    pub fields: IndexMap<String, Value>,
+
+    /// Attachments of the entry, mapping the attachment name to an index into
+    /// `Database::header_attachments`
+    pub attachments: HashMap<String, usize>,
+
     pub autotype: Option<AutoType>,
     pub tags: Vec<String>,
 
@@ -32,10 +75,26 @@ pub struct Entry {
     pub foreground_color: Option<Color>,
     pub background_color: Option<Color>,
 
+    /// Color values as read from a file, if they could not be parsed as `#RRGGBB` or are written
+    /// differently than `Color` formats them, e.g. with uppercase hex digits. These are written back
+    /// unchanged on save unless the corresponding color was changed.
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) foreground_color_text: Option<String>,
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) background_color_text: Option<String>,
+
     pub override_url: Option<String>,
     pub quality_check: Option<bool>,
 
     pub history: Option<History>,
+
+    /// XML elements of the entry that are not known to this crate
+    pub unknown_elements: Vec<UnknownElement>,
+
+    /// Source of the current time for the timestamps that the entry updates, shared with the
+    /// database that the entry was added to
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) clock: Clock,
 }
 impl Entry {
     pub fn new() -> Entry {
@@ -46,47 +105,166 @@ impl Entry {
         }
     }
 
-    #[cfg(feature = "_merge")]
-    pub(crate) fn merge(&self, other: &Entry) -> Result<(Option<Entry>, MergeLog), MergeError> {
-        let mut log = MergeLog::default();
-
-        let source_last_modification = match other.times.get_last_modification() {
-            Some(t) => *t,
-            None => {
-                log.warnings.push(format!(
-                    "Entry {} did not have a last modification timestamp",
-                    other.uuid
-                ));
-                Times::epoch()
+    /// Use the time provider of a database for the timestamps of this entry and its history
+    pub(crate) fn set_clock(&mut self, clock: &Clock) {
+        self.clock = clock.clone();
+        if let Some(history) = self.history.as_mut() {
+            for entry in history.entries.iter_mut() {
+                entry.clock = clock.clone();
             }
-        };
-        let destination_last_modification = match self.times.get_last_modification() {
-            Some(t) => *t,
-            None => {
-                log.warnings.push(format!(
-                    "Entry {} did not have a last modification timestamp",
-                    self.uuid
-                ));
-                Times::now()
+        }
+    }
+
+    /// Update the last modification time after a change, unless the database does not update
+    /// timestamps on changes, see `Database::set_update_times`
+    fn record_change(&mut self) {
+        if let Some(now) = self.clock.change_time() {
+            self.times.touch(true, now);
+        }
+    }
+
+    /// Assign a new UUID to this entry and the entries in its history
+    pub(crate) fn regenerate_uuid(&mut self) {
+        self.uuid = Uuid::new_v4();
+        if let Some(history) = self.history.as_mut() {
+            for entry in history.entries.iter_mut() {
+                entry.uuid = self.uuid;
             }
+        }
+    }
+
+    /// Compare the contents of two entries, ignoring their UUIDs, timestamps and history.
+    ///
+    /// This tells whether an entry was actually edited, as opposed to only accessed or moved.
+    /// Attachments are compared by name and by their index in the attachment pool, so both entries
+    /// need to belong to the same database. See `content_eq_with_attachments` for entries of
+    /// different databases.
+    pub fn content_eq(&self, other: &Entry) -> bool {
+        self.content_eq_by(other, &|a, b| a == b)
+    }
+
+    /// Compare the contents of two entries like `content_eq`, but compare attachments by their
+    /// content, which is looked up in the attachment pool of `db` for this entry and of `other_db`
+    /// for `other`.
+    pub fn content_eq_with_attachments(&self, db: &Database, other: &Entry, other_db: &Database) -> bool {
+        self.content_eq_by(other, &|a, b| {
+            db.header_attachments.get(a).map(|a| a.data())
+                == other_db.header_attachments.get(b).map(|b| b.data())
+        })
+    }
+
+    /// Compare the contents of two entries like `content_eq`, with `same_attachment` telling whether
+    /// the attachments at two indices have the same content
+    fn content_eq_by(&self, other: &Entry, same_attachment: &dyn Fn(usize, usize) -> bool) -> bool {
+        // destructure to not miss any fields that are added in the future
+        let Entry {
+            uuid: _,
+            fields,
+            attachments,
+            autotype,
+            tags,
+            times: _,
+            custom_data,
+            icon_id,
+            custom_icon_uuid,
+            foreground_color,
+            background_color,
+            foreground_color_text,
+            background_color_text,
+            override_url,
+            quality_check,
+            history: _,
+            unknown_elements,
+            clock: _,
+        } = self;
+
+        *fields == other.fields
+            && attachments.len() == other.attachments.len()
+            && attachments.iter().all(|(name, index)| {
+                other
+                    .attachments
+                    .get(name)
+                    .is_some_and(|other_index| same_attachment(*index, *other_index))
+            })
+            && *autotype == other.autotype
+            && *tags == other.tags
+            && *custom_data == other.custom_data
+            && *icon_id == other.icon_id
+            && *custom_icon_uuid == other.custom_icon_uuid
+            && *foreground_color == other.foreground_color
+            && *background_color == other.background_color
+            && *foreground_color_text == other.foreground_color_text
+            && *background_color_text == other.background_color_text
+            && *override_url == other.override_url
+            && *quality_check == other.quality_check
+            && *unknown_elements == other.unknown_elements
+    }
+
+    /// Whether this entry is in the recycle bin of a database, either directly or in one of its
+    /// subgroups. This is false if the database has no recycle bin or does not contain the entry.
+    pub fn is_in_recycle_bin(&self, db: &Database) -> bool {
+        let Some(recycle_bin) = db.meta.recyclebin_uuid else {
+            return false;
         };
 
-        if destination_last_modification == source_last_modification {
-            if !self.has_diverged_from(other) {
-                // This should never happen.
-                // This means that an entry was updated without updating the last modification
-                // timestamp.
-                return Err(MergeError::EntryModificationTimeNotUpdated(
-                    other.uuid.to_string(),
-                ));
+        db.root
+            .find_node_location(self.uuid)
+            .is_some_and(|location| location.contains(&recycle_bin))
+    }
+
+    #[cfg(feature = "_merge")]
+    pub(crate) fn merge(
+        &self,
+        other: &Entry,
+        strategy: MergeStrategy,
+        attachments: &[HeaderAttachment],
+    ) -> Result<(Option<Entry>, MergeLog), MergeError> {
+        let mut log = MergeLog::default();
+
+        let keep_destination = match strategy {
+            MergeStrategy::PreferLocal => true,
+            MergeStrategy::PreferRemote => false,
+            MergeStrategy::Timestamp => {
+                let source_last_modification = match other.times.get_last_modification() {
+                    Some(t) => *t,
+                    None => {
+                        log.warnings.push(format!(
+                            "Entry {} did not have a last modification timestamp",
+                            other.uuid
+                        ));
+                        Times::epoch()
+                    }
+                };
+                let destination_last_modification = match self.times.get_last_modification() {
+                    Some(t) => *t,
+                    None => {
+                        log.warnings.push(format!(
+                            "Entry {} did not have a last modification timestamp",
+                            self.uuid
+                        ));
+                        self.clock.now()
+                    }
+                };
+
+                if destination_last_modification == source_last_modification {
+                    if !self.has_diverged_from(other, attachments) {
+                        // This should never happen.
+                        // This means that an entry was updated without updating the last modification
+                        // timestamp.
+                        return Err(MergeError::EntryModificationTimeNotUpdated(
+                            other.uuid.to_string(),
+                        ));
+                    }
+                    return Ok((None, log));
+                }
+
+                destination_last_modification > source_last_modification
             }
-            return Ok((None, log));
-        }
+        };
 
-        let (mut merged_entry, entry_merge_log) = match destination_last_modification > source_last_modification
-        {
-            true => self.merge_history(other)?,
-            false => other.clone().merge_history(self)?,
+        let (mut merged_entry, entry_merge_log) = match keep_destination {
+            true => self.merge_history(other, attachments)?,
+            false => other.clone().merge_history(self, attachments)?,
         };
 
         // The location changed timestamp is handled separately when merging two databases.
@@ -100,7 +278,11 @@ impl Entry {
     }
 
     #[cfg(feature = "_merge")]
-    pub(crate) fn merge_history(&self, other: &Entry) -> Result<(Entry, MergeLog), MergeError> {
+    pub(crate) fn merge_history(
+        &self,
+        other: &Entry,
+        attachments: &[HeaderAttachment],
+    ) -> Result<(Entry, MergeLog), MergeError> {
         let mut log = MergeLog::default();
 
         let mut source_history = match &other.history {
@@ -136,7 +318,7 @@ impl Entry {
         // TODO we should probably check for uncommitted changes in the destination
         // database here too for consistency.
 
-        let history_merge_log = destination_history.merge_with(&source_history)?;
+        let history_merge_log = destination_history.merge_with(&source_history, attachments)?;
         response.history = Some(destination_history);
 
         Ok((response, log.merge_with(&history_merge_log)))
@@ -157,30 +339,91 @@ impl Entry {
         self.update_history();
     }
 
+    /// Whether two entries differ in anything but their timestamps, including their history. The
+    /// attachments of both entries refer to `attachments` and are compared by content, since the
+    /// pool may contain the same content more than once.
     #[cfg(feature = "_merge")]
-    // Convenience function used in when merging two entries
-    pub(crate) fn has_diverged_from(&self, other_entry: &Entry) -> bool {
-        let new_times = Times::default();
-
-        let mut self_without_times = self.clone();
-        self_without_times.times = new_times.clone();
-
-        let mut other_without_times = other_entry.clone();
-        other_without_times.times = new_times.clone();
+    pub(crate) fn has_diverged_from(&self, other_entry: &Entry, attachments: &[HeaderAttachment]) -> bool {
+        let same_attachment =
+            |a: usize, b: usize| a == b || attachments.get(a).is_some_and(|a| attachments.get(b) == Some(a));
+
+        let same_history = match (&self.history, &other_entry.history) {
+            (Some(history), Some(other_history)) => {
+                history.entries.len() == other_history.entries.len()
+                    && history
+                        .entries
+                        .iter()
+                        .zip(&other_history.entries)
+                        .all(|(a, b)| a.times == b.times && !a.has_diverged_from(b, attachments))
+            }
+            (history, other_history) => history.is_none() && other_history.is_none(),
+        };
 
-        !self_without_times.eq(&other_without_times)
+        self.uuid != other_entry.uuid || !same_history || !self.content_eq_by(other_entry, &same_attachment)
     }
 }
 
 impl<'a> Entry {
     /// Get a field by name, taking care of unprotecting Protected values automatically
     pub fn get(&'a self, key: &str) -> Option<&'a str> {
-        match self.fields.get(key) {
-            Some(&Value::Bytes(_)) => None,
-            Some(Value::Protected(pv)) => std::str::from_utf8(pv.unsecure()).ok(),
-            Some(Value::Unprotected(uv)) => Some(uv),
-            None => None,
-        }
+        self.fields.get(key)?.expose_secret()
+    }
+
+    /// Get the names of all fields of this entry, including standard fields such as 'Title' and
+    /// 'Password', in the order in which they are stored
+    pub fn field_names(&self) -> impl Iterator<Item = &str> {
+        self.fields.keys().map(|name| &name[..])
+    }
+
+    /// Whether the entry has a field with the given name, even if it is empty
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.contains_key(name)
+    }
+
+    /// Get the number of fields of this entry, including standard fields
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Set the text of a field and update the last modification time. An existing field keeps
+    /// whether it is protected, new 'Password' and 'otp' fields are protected and other new fields
+    /// are not, see `set_protected`.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let protected = match self.fields.get(key) {
+            Some(existing) => existing.is_protected(),
+            None => PROTECTED_BY_DEFAULT.contains(&key),
+        };
+        let value = if protected {
+            Value::Protected(SecStr::from(value))
+        } else {
+            Value::Unprotected(value.to_string())
+        };
+        self.fields.insert(key.to_string(), value);
+        self.record_change();
+    }
+
+    /// Whether a field is protected, i.e. encrypted with the inner cipher when the database is
+    /// saved and marked with `Protected="True"` in the XML. Returns `false` for missing fields.
+    pub fn is_protected(&self, key: &str) -> bool {
+        self.fields.get(key).is_some_and(Value::is_protected)
+    }
+
+    /// Protect or unprotect a field, keeping its content, and update the last modification time if
+    /// it changed. Missing fields, binary fields and locked fields are left as they are.
+    pub fn set_protected(&mut self, key: &str, protected: bool) {
+        let Some(value) = self.fields.get_mut(key) else {
+            return;
+        };
+
+        let changed = match value {
+            Value::Unprotected(text) if protected => Value::Protected(SecStr::from(std::mem::take(text))),
+            Value::Protected(_) | Value::Deferred(_) if !protected => Value::Unprotected(
+                String::from_utf8_lossy(value.protected().expect("protected").unsecure()).into_owned(),
+            ),
+            _ => return,
+        };
+        *value = changed;
+        self.record_change();
     }
 
     /// Get a bytes field by name
@@ -211,6 +454,12 @@ impl<'a> Entry {
         self.times.get_expiry()
     }
 
+    /// Whether the entry expires and its expiry time has passed at the current time of its
+    /// database, see `Times::is_expired_at`
+    pub fn is_expired(&self) -> bool {
+        self.times.is_expired_at(self.clock.now())
+    }
+
     /// Convenience method for getting a TOTP from this entry
     #[cfg(feature = "totp")]
     pub fn get_otp(&'a self) -> Result<TOTP, TOTPError> {
@@ -222,6 +471,96 @@ impl<'a> Entry {
         self.get("otp")
     }
 
+    /// Get the `otpauth://` URI stored in the entry, looking at the fields that common password
+    /// managers and plugins use for it
+    pub fn get_otp_uri(&'a self) -> Option<&'a str> {
+        OTP_URI_FIELDS
+            .iter()
+            .filter_map(|field| self.get(field))
+            .find(|value| is_otp_uri(value))
+            .map(|value| value.trim())
+    }
+
+    /// Decode the components of the `otpauth://` URI stored in the entry
+    pub fn parse_otp_uri(&'a self) -> Result<OtpParameters, OtpParseError> {
+        self.get_otp_uri().ok_or(OtpParseError::NoUri)?.parse()
+    }
+
+    /// Get an `otpauth://` URI for the TOTP secret of the entry, e.g. to display it as a QR code.
+    /// This is the URI stored in the entry, see `get_otp_uri`, or else an URI built from the
+    /// `TimeOtp-*` fields of KeePass, with the title as the name of the account.
+    pub fn totp_uri(&self) -> Option<String> {
+        if let Some(uri) = self.get_otp_uri() {
+            return Some(uri.to_string());
+        }
+
+        let algorithm = match self.get(TIME_OTP_ALGORITHM_FIELD) {
+            Some(name) => TIME_OTP_ALGORITHMS.iter().find(|(n, _)| *n == name)?.1,
+            None => OtpAlgorithm::Sha1,
+        };
+        let defaults = TotpOptions::default();
+        let params = OtpParameters {
+            secret: self.get(TIME_OTP_SECRET_FIELD)?.replace(' ', ""),
+            issuer: None,
+            account: self.get_title().map(str::to_string),
+            algorithm,
+            digits: match self.get(TIME_OTP_LENGTH_FIELD) {
+                Some(digits) => digits.parse().ok()?,
+                None => defaults.digits,
+            },
+            period: match self.get(TIME_OTP_PERIOD_FIELD) {
+                Some(period) => period.parse().ok()?,
+                None => defaults.period,
+            },
+        };
+        Some(params.to_string())
+    }
+
+    /// Store a TOTP secret as an `otpauth://` URI in the protected `otp` field, and update the last
+    /// modification time. With `TotpOptions::split_fields`, the secret and its settings are also
+    /// stored in the `TimeOtp-*` fields for KeePass and older plugins; otherwise, these fields are
+    /// removed so that they cannot disagree with the URI.
+    pub fn set_totp(&mut self, secret: &[u8], options: TotpOptions) {
+        let params = OtpParameters {
+            secret: base32_encode(secret),
+            issuer: options.issuer,
+            account: options.account,
+            algorithm: options.algorithm,
+            digits: options.digits,
+            period: options.period,
+        };
+        self.fields.insert(
+            "otp".to_string(),
+            Value::Protected(SecStr::from(params.to_string())),
+        );
+
+        let split_fields = [
+            (TIME_OTP_SECRET_FIELD, params.secret.clone()),
+            (TIME_OTP_LENGTH_FIELD, params.digits.to_string()),
+            (TIME_OTP_PERIOD_FIELD, params.period.to_string()),
+            (
+                TIME_OTP_ALGORITHM_FIELD,
+                TIME_OTP_ALGORITHMS
+                    .iter()
+                    .find(|(_, a)| *a == params.algorithm)
+                    .map(|(name, _)| name.to_string())
+                    .unwrap_or_default(),
+            ),
+        ];
+        for (field, value) in split_fields {
+            if !options.split_fields {
+                self.fields.shift_remove(field);
+            } else if field == TIME_OTP_SECRET_FIELD {
+                self.fields
+                    .insert(field.to_string(), Value::Protected(SecStr::from(value)));
+            } else {
+                self.fields.insert(field.to_string(), Value::Unprotected(value));
+            }
+        }
+
+        self.record_change();
+    }
+
     /// Convenience method for getting the value of the 'Title' field
     pub fn get_title(&'a self) -> Option<&'a str> {
         self.get("Title")
@@ -242,6 +581,144 @@ impl<'a> Entry {
         self.get("URL")
     }
 
+    /// Update the last access time, and the last modification time if `modified` is set, to the
+    /// current time of the database of the entry
+    pub fn touch(&mut self, modified: bool) {
+        self.touch_at(modified, self.clock.now());
+    }
+
+    /// Update the last access time, and the last modification time if `modified` is set, to `now`
+    pub fn touch_at(&mut self, modified: bool, now: chrono::NaiveDateTime) {
+        self.times.touch(modified, now);
+    }
+
+    /// Record that the entry has been used, e.g. by copying its password, by incrementing the usage
+    /// count and updating the last access time
+    pub fn increment_usage(&mut self) {
+        self.increment_usage_at(self.clock.now());
+    }
+
+    /// Like `increment_usage`, but with an explicit timestamp for the last access time
+    pub fn increment_usage_at(&mut self, now: chrono::NaiveDateTime) {
+        self.times.usage_count += 1;
+        self.times.touch(false, now);
+    }
+
+    /// Get the foreground color used to display the entry
+    pub fn get_foreground_color(&'a self) -> Option<&'a Color> {
+        self.foreground_color.as_ref()
+    }
+
+    /// Set the foreground color used to display the entry and update the last modification time
+    pub fn set_foreground_color(&mut self, color: Option<Color>) {
+        self.foreground_color = color;
+        self.foreground_color_text = None;
+        self.record_change();
+    }
+
+    /// Get the background color used to display the entry
+    pub fn get_background_color(&'a self) -> Option<&'a Color> {
+        self.background_color.as_ref()
+    }
+
+    /// Set the background color used to display the entry and update the last modification time
+    pub fn set_background_color(&mut self, color: Option<Color>) {
+        self.background_color = color;
+        self.background_color_text = None;
+        self.record_change();
+    }
+
+    /// Get the URL override, i.e. the command that clients run instead of opening the 'URL' field
+    pub fn get_override_url(&'a self) -> Option<&'a str> {
+        self.override_url.as_deref()
+    }
+
+    /// Set the URL override and update the last modification time. An empty string removes the
+    /// override.
+    pub fn set_override_url(&mut self, url: &str) {
+        self.override_url = if url.is_empty() {
+            None
+        } else {
+            Some(url.to_string())
+        };
+        self.record_change();
+    }
+
+    /// Add data to the attachment pool of `db` and attach it to this entry under `name`, replacing
+    /// any attachment with the same name. Updates the last modification time.
+    ///
+    /// Since this borrows the database mutably, attach files before adding a new entry to the tree.
+    pub fn add_attachment(&mut self, db: &mut Database, name: &str, content: Vec<u8>) {
+        let index = db.add_attachment(content);
+        self.attachments.insert(name.to_string(), index);
+        self.record_change();
+    }
+
+    /// Read a file and attach it to this entry, using the file name as the attachment name. See
+    /// `add_attachment`.
+    pub fn add_attachment_from_path(&mut self, db: &mut Database, path: &Path) -> Result<(), std::io::Error> {
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Path does not end in a valid UTF-8 file name",
+            )
+        })?;
+
+        // the file is hashed while it is read in chunks, to find the same content in the pool
+        let mut file = std::fs::File::open(path)?;
+        let mut content = Vec::with_capacity(file.metadata().map_or(0, |m| m.len() as usize));
+        let mut hasher = Sha256::new();
+        let mut chunk = vec![0; ATTACHMENT_CHUNK_SIZE];
+        loop {
+            let read = match file.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            hasher.update(&chunk[..read]);
+            content.extend_from_slice(&chunk[..read]);
+        }
+
+        let index = db.add_hashed_attachment(content, hasher.finalize().into());
+        self.attachments.insert(name.to_string(), index);
+        self.record_change();
+
+        Ok(())
+    }
+
+    /// Get the names of the attachments of this entry, in alphabetical order
+    pub fn attachment_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.attachments.keys().map(|name| &name[..]).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Get the content of the attachment with the given name from the attachment pool of `db`.
+    ///
+    /// Attachments that were not loaded when opening the database are read from the decrypted
+    /// payload, see `OpenOptions::defer_binaries`.
+    pub fn get_attachment<'db>(&self, db: &'db Database, name: &str) -> Option<&'db [u8]> {
+        let index = self.attachments.get(name)?;
+        db.header_attachments
+            .get(*index)
+            .map(|attachment| attachment.data())
+    }
+
+    /// Remove the attachment with the given name from this entry and return whether it existed.
+    /// Updates the last modification time if an attachment was removed.
+    ///
+    /// The data stays in the attachment pool of the database. Data that no entry refers to anymore
+    /// is left out when the database is saved.
+    pub fn remove_attachment(&mut self, name: &str) -> bool {
+        if self.attachments.remove(name).is_none() {
+            return false;
+        }
+
+        self.record_change();
+        true
+    }
+
     /// Adds the current version of the entry to the entry's history
     /// and updates the last modification timestamp.
     /// The history will only be updated if the entry has
@@ -249,6 +726,12 @@ impl<'a> Entry {
     ///
     /// Returns whether or not a new history entry was added.
     pub fn update_history(&mut self) -> bool {
+        self.update_history_at(self.clock.now())
+    }
+
+    /// Like `update_history`, but use `now` as the new last modification time instead of the
+    /// current time of the database of the entry.
+    pub fn update_history_at(&mut self, now: chrono::NaiveDateTime) -> bool {
         if self.history.is_none() {
             self.history = Some(History::default());
         }
@@ -257,7 +740,7 @@ impl<'a> Entry {
             return false;
         }
 
-        self.times.set_last_modification(Times::now());
+        self.times.set_last_modification(now);
 
         let mut new_history_entry = self.clone();
         new_history_entry.history.take().unwrap();
@@ -295,22 +778,97 @@ impl<'a> Entry {
     }
 }
 
-/// A value that can be a raw string, byte array, or protected memory region
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// A value that can be a raw string, byte array, or protected memory region.
+///
+/// New representations of protected values may be added, so prefer accessors such as
+/// `Value::is_protected` and `Value::expose_secret` over matching on the variants.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Value {
     Bytes(Vec<u8>),
     Unprotected(String),
+
+    /// A protected value, kept in a `SecStr` rather than a `secrecy::SecretString` because `SecStr`
+    /// also locks its memory on Unix and compares in constant time
     Protected(SecStr),
+
+    /// A protected value that is encrypted in memory while the database is locked
+    Locked(LockedValue),
+
+    /// A protected value that is decrypted when it is first accessed. It is equal to a
+    /// `Value::Protected` with the same content.
+    Deferred(DeferredValue),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Unprotected(a), Value::Unprotected(b)) => a == b,
+            (Value::Locked(a), Value::Locked(b)) => a == b,
+            (Value::Protected(_) | Value::Deferred(_), Value::Protected(_) | Value::Deferred(_)) => {
+                self.protected() == other.protected()
+            }
+            _ => false,
+        }
+    }
 }
 
+impl Eq for Value {}
+
 impl Value {
+    /// Whether the value is protected, including values that are locked or not yet decrypted
+    pub fn is_protected(&self) -> bool {
+        matches!(self, Value::Protected(_) | Value::Locked(_) | Value::Deferred(_))
+    }
+
     pub fn is_empty(&self) -> bool {
         match self {
             Value::Bytes(b) => b.is_empty(),
             Value::Unprotected(u) => u.is_empty(),
             Value::Protected(p) => p.unsecure().is_empty(),
+            Value::Locked(l) => l.is_empty(),
+            Value::Deferred(d) => d.is_empty(),
+        }
+    }
+
+    /// Content of a protected value, decrypting a deferred value if needed
+    pub(crate) fn protected(&self) -> Option<&SecStr> {
+        match self {
+            Value::Protected(p) => Some(p),
+            Value::Deferred(d) => Some(d.get()),
+            Value::Bytes(_) | Value::Unprotected(_) | Value::Locked(_) => None,
         }
     }
+
+    /// Get the text of a protected or unprotected value, named after `secrecy::ExposeSecret`.
+    ///
+    /// Protected values are redacted when formatted with `Debug` or `Display` and zeroed when they
+    /// are dropped, so this is the way to access their content. Returns `None` for binary values,
+    /// locked values and protected values that are not valid UTF-8.
+    pub fn expose_secret(&self) -> Option<&str> {
+        match self {
+            Value::Unprotected(u) => Some(u),
+            Value::Protected(_) | Value::Deferred(_) => std::str::from_utf8(self.protected()?.unsecure()).ok(),
+            Value::Bytes(_) | Value::Locked(_) => None,
+        }
+    }
+
+    /// Copy the text of a protected or unprotected value into a `secrecy::SecretString`, see
+    /// `expose_secret`
+    #[cfg(feature = "secrecy")]
+    pub fn to_secret_string(&self) -> Option<secrecy::SecretString> {
+        self.expose_secret().map(secrecy::SecretString::from)
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<secrecy::SecretString> for Value {
+    fn from(secret: secrecy::SecretString) -> Self {
+        use secrecy::ExposeSecret;
+
+        Value::Protected(SecStr::new(secret.expose_secret().as_bytes().to_vec()))
+    }
 }
 
 #[cfg(feature = "serialization")]
@@ -322,11 +880,101 @@ impl serde::Serialize for Value {
         match self {
             Value::Bytes(b) => serializer.serialize_bytes(b),
             Value::Unprotected(u) => serializer.serialize_str(u),
-            Value::Protected(p) => serializer.serialize_str(String::from_utf8_lossy(p.unsecure()).as_ref()),
+            Value::Protected(_) | Value::Deferred(_) if crate::db::serialization::exposes_secrets() => {
+                serializer.serialize_str(
+                    String::from_utf8_lossy(self.protected().expect("protected").unsecure()).as_ref(),
+                )
+            }
+            Value::Protected(_) | Value::Deferred(_) => serializer.serialize_str(crate::db::REDACTED),
+            Value::Locked(_) => serializer.serialize_none(),
         }
     }
 }
 
+#[cfg(feature = "json")]
+impl Entry {
+    /// Get a JSON representation of the entry, e.g. for scripts. Unlike the `serialization` feature,
+    /// which follows the structure of `Entry`, the shape of the object is stable across versions:
+    ///
+    /// ```json
+    /// {
+    ///   "uuid": "0b5bc1a4-7ec0-4c11-9cc4-0d5ee21aa0f5",
+    ///   "fields": { "Title": "Example", "Password": "s3cr3t", "Binary": "AAEC" },
+    ///   "tags": ["web"],
+    ///   "times": {
+    ///     "creation": "2024-01-01T12:00:00Z",
+    ///     "last_modification": "2024-01-01T12:00:00Z",
+    ///     "last_access": "2024-01-01T12:00:00Z",
+    ///     "location_changed": "2024-01-01T12:00:00Z",
+    ///     "expiry": null,
+    ///     "expires": false,
+    ///     "usage_count": 0
+    ///   },
+    ///   "custom_data": { "KPXC_DECRYPTION_KEY": "..." }
+    /// }
+    /// ```
+    ///
+    /// Text values are strings and binary values are Base64 strings. Protected values are `null`
+    /// unless `include_protected` is set, and locked values are always `null`, since unlocking them
+    /// requires the database. Missing times and custom data items without a value are `null`.
+    /// History, attachments and display settings such as icons and colors are not included.
+    pub fn to_json_value(&self, include_protected: bool) -> serde_json::Value {
+        use serde_json::{json, Map};
+
+        let fields: Map<String, serde_json::Value> = self
+            .fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value_to_json(value, include_protected)))
+            .collect();
+
+        let custom_data: Map<String, serde_json::Value> = self
+            .custom_data
+            .items
+            .iter()
+            .map(|(key, item)| {
+                let value = item
+                    .value
+                    .as_ref()
+                    .map_or(serde_json::Value::Null, |v| value_to_json(v, include_protected));
+                (key.clone(), value)
+            })
+            .collect();
+
+        let time =
+            |time: Option<&chrono::NaiveDateTime>| time.map(|t| t.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+
+        json!({
+            "uuid": self.uuid.to_string(),
+            "fields": fields,
+            "tags": self.tags,
+            "times": {
+                "creation": time(self.times.get_creation()),
+                "last_modification": time(self.times.get_last_modification()),
+                "last_access": time(self.times.get_last_access()),
+                "location_changed": time(self.times.get_location_changed()),
+                "expiry": time(self.times.get_expiry()),
+                "expires": self.times.expires,
+                "usage_count": self.times.usage_count,
+            },
+            "custom_data": custom_data,
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+fn value_to_json(value: &Value, include_protected: bool) -> serde_json::Value {
+    use base64::{engine::general_purpose as base64_engine, Engine as _};
+
+    match value {
+        Value::Bytes(b) => serde_json::Value::String(base64_engine::STANDARD.encode(b)),
+        Value::Unprotected(u) => serde_json::Value::String(u.clone()),
+        Value::Protected(_) | Value::Deferred(_) if include_protected => serde_json::Value::String(
+            String::from_utf8_lossy(value.protected().expect("protected").unsecure()).into_owned(),
+        ),
+        Value::Protected(_) | Value::Deferred(_) | Value::Locked(_) => serde_json::Value::Null,
+    }
+}
+
 /// An AutoType setting associated with an Entry
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -388,7 +1036,11 @@ impl History {
 
     // Merge both histories together.
     #[cfg(feature = "_merge")]
-    pub(crate) fn merge_with(&mut self, other: &History) -> Result<MergeLog, MergeError> {
+    pub(crate) fn merge_with(
+        &mut self,
+        other: &History,
+        attachments: &[HeaderAttachment],
+    ) -> Result<MergeLog, MergeError> {
         let mut log = MergeLog::default();
         let mut new_history_entries: HashMap<chrono::NaiveDateTime, Entry> = HashMap::new();
 
@@ -407,7 +1059,7 @@ impl History {
             let modification_time = history_entry.times.get_last_modification().unwrap();
             let existing_history_entry = new_history_entries.get(modification_time);
             if let Some(existing_history_entry) = existing_history_entry {
-                if existing_history_entry.has_diverged_from(history_entry) {
+                if existing_history_entry.has_diverged_from(history_entry, attachments) {
                     log.warnings.push(format!(
                         "History entries for {} have the same modification timestamp but were not the same.",
                         existing_history_entry.uuid
@@ -433,11 +1085,111 @@ impl History {
 
 #[cfg(test)]
 mod entry_tests {
-    use std::{thread, time};
-
     use secstr::SecStr;
 
     use super::{Entry, Value};
+    use crate::{
+        db::{Color, Database, Group, Times},
+        error::OtpParseError,
+    };
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn field_order() {
+        use crate::DatabaseKey;
+
+        let names = [
+            "Title", "zeta", "UserName", "alpha", "Password", "URL", "middle", "Notes",
+        ];
+
+        let mut db = Database::new(Default::default());
+        let mut entry = Entry::new();
+        for name in names {
+            entry
+                .fields
+                .insert(name.to_string(), Value::Unprotected(name.to_lowercase()));
+        }
+        db.root.add_child(entry);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("test"))
+            .unwrap();
+        let reopened =
+            Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("test")).unwrap();
+
+        let fields: Vec<&str> = reopened.root.entries()[0]
+            .fields
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(fields, names);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn protection_round_trip() {
+        use crate::DatabaseKey;
+
+        let mut db = Database::new(Default::default());
+        let mut entry = Entry::new();
+        entry.set("Title", "Mail");
+        entry.set("Password", "hunter2");
+        entry.set("PIN", "1234");
+        entry.set_protected("PIN", true);
+        entry.set_protected("Password", false);
+        db.root.add_child(entry);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("test"))
+            .unwrap();
+        let reopened =
+            Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("test")).unwrap();
+
+        let entry = reopened.root.entries()[0];
+        assert!(!entry.is_protected("Title"));
+        assert!(!entry.is_protected("Password"));
+        assert!(entry.is_protected("PIN"));
+        assert_eq!(entry.get("PIN"), Some("1234"));
+    }
+
+    #[test]
+    fn protection() {
+        let mut entry = Entry::new();
+        entry.set("Title", "Mail");
+        entry.set("Password", "hunter2");
+        entry.set("otp", "otpauth://totp/Mail?secret=JBSWY3DPEHPK3PXP");
+        assert!(!entry.is_protected("Title"));
+        assert!(entry.is_protected("Password"));
+        assert!(entry.is_protected("otp"));
+        assert!(!entry.is_protected("Missing"));
+
+        entry.set_protected("Title", true);
+        entry.set_protected("Password", false);
+        assert!(matches!(entry.fields["Title"], Value::Protected(_)));
+        assert_eq!(
+            entry.fields["Password"],
+            Value::Unprotected("hunter2".to_string())
+        );
+        assert_eq!(entry.get_title(), Some("Mail"));
+
+        // existing fields keep their protection
+        entry.set("Password", "correct horse");
+        assert_eq!(
+            entry.fields["Password"],
+            Value::Unprotected("correct horse".to_string())
+        );
+        entry.set("Title", "Webmail");
+        assert!(entry.is_protected("Title"));
+        assert_eq!(entry.get_title(), Some("Webmail"));
+
+        entry
+            .fields
+            .insert("Binary".to_string(), Value::Bytes(vec![0xff]));
+        entry.set_protected("Binary", true);
+        entry.set_protected("Missing", true);
+        assert_eq!(entry.fields["Binary"], Value::Bytes(vec![0xff]));
+        assert!(!entry.fields.contains_key("Missing"));
+    }
 
     #[test]
     fn byte_values() {
@@ -467,89 +1219,261 @@ mod entry_tests {
 
     #[test]
     fn update_history() {
-        let mut entry = Entry::new();
-        let mut last_modification_time = *entry.times.get_last_modification().unwrap();
+        let t0 = Times::epoch();
+        let t1 = t0 + chrono::Duration::seconds(1);
+        let t2 = t1 + chrono::Duration::seconds(1);
+        let t3 = t2 + chrono::Duration::seconds(1);
+
+        let mut entry = Entry {
+            times: Times::new_at(t0),
+            ..Entry::new()
+        };
 
         entry
             .fields
             .insert("Username".to_string(), Value::Unprotected("user".to_string()));
-        // Making sure to wait 1 sec before update the history, to make
-        // sure that we get a different modification timestamp.
-        thread::sleep(time::Duration::from_secs(1));
 
-        assert!(entry.update_history());
+        assert!(entry.update_history_at(t1));
         assert!(entry.history.is_some());
         assert_eq!(entry.history.as_ref().unwrap().entries.len(), 1);
-        assert_ne!(
-            entry.times.get_last_modification().unwrap(),
-            &last_modification_time
-        );
-        last_modification_time = *entry.times.get_last_modification().unwrap();
-        thread::sleep(time::Duration::from_secs(1));
+        assert_eq!(entry.times.get_last_modification(), Some(&t1));
 
         // Updating the history without making any changes
         // should not do anything.
-        assert!(!entry.update_history());
+        assert!(!entry.update_history_at(t2));
         assert!(entry.history.is_some());
         assert_eq!(entry.history.as_ref().unwrap().entries.len(), 1);
-        assert_eq!(
-            entry.times.get_last_modification().unwrap(),
-            &last_modification_time
-        );
+        assert_eq!(entry.times.get_last_modification(), Some(&t1));
 
         entry
             .fields
             .insert("Title".to_string(), Value::Unprotected("first title".to_string()));
 
-        assert!(entry.update_history());
+        assert!(entry.update_history_at(t2));
         assert!(entry.history.is_some());
         assert_eq!(entry.history.as_ref().unwrap().entries.len(), 2);
-        assert_ne!(
-            entry.times.get_last_modification().unwrap(),
-            &last_modification_time
-        );
-        last_modification_time = *entry.times.get_last_modification().unwrap();
-        thread::sleep(time::Duration::from_secs(1));
+        assert_eq!(entry.times.get_last_modification(), Some(&t2));
 
-        assert!(!entry.update_history());
+        assert!(!entry.update_history_at(t3));
         assert!(entry.history.is_some());
         assert_eq!(entry.history.as_ref().unwrap().entries.len(), 2);
-        assert_eq!(
-            entry.times.get_last_modification().unwrap(),
-            &last_modification_time
-        );
+        assert_eq!(entry.times.get_last_modification(), Some(&t2));
 
         entry.fields.insert(
             "Title".to_string(),
             Value::Unprotected("second title".to_string()),
         );
 
-        assert!(entry.update_history());
+        assert!(entry.update_history_at(t3));
         assert!(entry.history.is_some());
         assert_eq!(entry.history.as_ref().unwrap().entries.len(), 3);
-        assert_ne!(
-            entry.times.get_last_modification().unwrap(),
-            &last_modification_time
-        );
-        last_modification_time = *entry.times.get_last_modification().unwrap();
-        thread::sleep(time::Duration::from_secs(1));
+        assert_eq!(entry.times.get_last_modification(), Some(&t3));
 
         assert!(!entry.update_history());
         assert!(entry.history.is_some());
         assert_eq!(entry.history.as_ref().unwrap().entries.len(), 3);
-        assert_eq!(
-            entry.times.get_last_modification().unwrap(),
-            &last_modification_time
-        );
+        assert_eq!(entry.times.get_last_modification(), Some(&t3));
 
         let last_history_entry = entry.history.as_ref().unwrap().entries.first().unwrap();
         assert_eq!(last_history_entry.get_title().unwrap(), "second title");
+        assert_eq!(last_history_entry.times.get_last_modification(), Some(&t3));
 
         for history_entry in &entry.history.unwrap().entries {
             assert!(history_entry.history.is_none());
         }
     }
 
+    #[test]
+    fn add_attachment_from_path() {
+        let mut db = Database::new(Default::default());
+        let path = std::path::Path::new("tests/resources/test_key.key");
+
+        let mut entry = Entry::new();
+        entry.add_attachment_from_path(&mut db, path).unwrap();
+        assert_eq!(entry.attachments.get("test_key.key"), Some(&0));
+        assert_eq!(db.header_attachments.len(), 1);
+        assert_eq!(db.header_attachments[0].data(), std::fs::read(path).unwrap());
+
+        // the same data is only stored once
+        let mut other = Entry::new();
+        other.add_attachment_from_path(&mut db, path).unwrap();
+        other.add_attachment(&mut db, "copy.key", std::fs::read(path).unwrap());
+        assert_eq!(other.attachments.get("test_key.key"), Some(&0));
+        assert_eq!(other.attachments.get("copy.key"), Some(&0));
+        assert_eq!(db.header_attachments.len(), 1);
+
+        other.add_attachment(&mut db, "other.txt", b"other data".to_vec());
+        assert_eq!(other.attachments.get("other.txt"), Some(&1));
+
+        let missing = std::path::Path::new("tests/resources/does_not_exist");
+        assert!(entry.add_attachment_from_path(&mut db, missing).is_err());
+        assert_eq!(entry.attachments.len(), 1);
+    }
+
+    #[test]
+    fn remove_attachment() {
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry.add_attachment(&mut db, "b.txt", b"b".to_vec());
+        entry.add_attachment(&mut db, "a.txt", b"a".to_vec());
+        assert_eq!(entry.attachment_names(), vec!["a.txt", "b.txt"]);
+
+        assert!(entry.remove_attachment("b.txt"));
+        assert!(!entry.remove_attachment("b.txt"));
+        assert!(!entry.remove_attachment("does-not-exist"));
+        assert_eq!(entry.attachment_names(), vec!["a.txt"]);
+
+        // the data is only dropped from the pool when saving
+        assert_eq!(db.header_attachments.len(), 2);
+    }
+
+    #[test]
+    fn protected_value_is_redacted() {
+        let value = Value::Protected(SecStr::from("hunter2"));
+        assert!(!format!("{:?}", value).contains("hunter2"));
+        assert_eq!(value.expose_secret(), Some("hunter2"));
+
+        let mut entry = Entry::new();
+        entry.fields.insert("Password".to_string(), value);
+        assert!(!format!("{:?}", entry).contains("hunter2"));
+
+        assert_eq!(
+            Value::Unprotected("visible".to_string()).expose_secret(),
+            Some("visible")
+        );
+        assert_eq!(Value::Bytes(b"bytes".to_vec()).expose_secret(), None);
+    }
+
+    #[cfg(feature = "secrecy")]
+    #[test]
+    fn secrecy_conversions() {
+        use secrecy::{ExposeSecret, SecretString};
+
+        let value = Value::from(SecretString::from("hunter2"));
+        assert!(matches!(value, Value::Protected(_)));
+        assert_eq!(value.expose_secret(), Some("hunter2"));
+
+        let secret = value.to_secret_string().unwrap();
+        assert_eq!(secret.expose_secret(), "hunter2");
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn to_json_value() {
+        let time = chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+
+        let mut entry = Entry::new();
+        entry.uuid = uuid::uuid!("0b5bc1a4-7ec0-4c11-9cc4-0d5ee21aa0f5");
+        entry.times = Times::new_at(time);
+        entry.times.usage_count = 3;
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Example".to_string()));
+        entry
+            .fields
+            .insert("Password".to_string(), Value::Protected(SecStr::from("s3cr3t")));
+        entry
+            .fields
+            .insert("Key".to_string(), Value::Bytes(vec![0, 1, 2]));
+        entry.tags = vec!["web".to_string()];
+        entry.custom_data.set("plugin".to_string(), "enabled".to_string());
+
+        assert_eq!(
+            entry.to_json_value(true),
+            serde_json::json!({
+                "uuid": "0b5bc1a4-7ec0-4c11-9cc4-0d5ee21aa0f5",
+                "fields": { "Title": "Example", "Password": "s3cr3t", "Key": "AAEC" },
+                "tags": ["web"],
+                "times": {
+                    "creation": "2024-01-01T12:00:00Z",
+                    "last_modification": "2024-01-01T12:00:00Z",
+                    "last_access": "2024-01-01T12:00:00Z",
+                    "location_changed": "2024-01-01T12:00:00Z",
+                    "expiry": "2024-01-01T12:00:00Z",
+                    "expires": false,
+                    "usage_count": 3,
+                },
+                "custom_data": { "plugin": "enabled" },
+            })
+        );
+
+        let redacted = entry.to_json_value(false);
+        assert_eq!(redacted["fields"]["Password"], serde_json::Value::Null);
+        assert_eq!(redacted["fields"]["Title"], "Example");
+    }
+
+    #[test]
+    fn get_attachment() {
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry.add_attachment(&mut db, "a.txt", b"a".to_vec());
+        entry.attachments.insert("dangling.txt".to_string(), 5);
+
+        assert_eq!(entry.get_attachment(&db, "a.txt"), Some(&b"a"[..]));
+        assert_eq!(entry.get_attachment(&db, "dangling.txt"), None);
+        assert_eq!(entry.get_attachment(&db, "does-not-exist"), None);
+    }
+
+    #[test]
+    fn touch_and_usage() {
+        let mut entry = Entry::new();
+        let creation = *entry.times.get_creation().unwrap();
+        let modification = *entry.times.get_last_modification().unwrap();
+
+        let t1 = chrono::NaiveDate::from_ymd_opt(2030, 1, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let t2 = t1 + chrono::Duration::hours(1);
+        let t3 = t2 + chrono::Duration::hours(1);
+
+        entry.touch_at(false, t1);
+        assert_eq!(entry.times.get_last_access(), Some(&t1));
+        assert_eq!(entry.times.get_last_modification(), Some(&modification));
+
+        entry.touch_at(true, t2);
+        assert_eq!(entry.times.get_last_access(), Some(&t2));
+        assert_eq!(entry.times.get_last_modification(), Some(&t2));
+
+        assert_eq!(entry.times.usage_count, 0);
+        entry.increment_usage_at(t3);
+        entry.increment_usage_at(t3);
+        assert_eq!(entry.times.usage_count, 2);
+        assert_eq!(entry.times.get_last_access(), Some(&t3));
+        assert_eq!(entry.times.get_last_modification(), Some(&t2));
+
+        assert_eq!(entry.times.get_creation(), Some(&creation));
+    }
+
+    #[test]
+    fn color_and_override_url() {
+        let mut entry = Entry::new();
+        entry.times.set_last_modification(Times::epoch());
+
+        let red: Color = "#FF0000".parse().unwrap();
+        entry.set_background_color(Some(red.clone()));
+        assert_eq!(entry.get_background_color(), Some(&red));
+        assert_eq!(entry.get_foreground_color(), None);
+        assert_ne!(entry.times.get_last_modification(), Some(&Times::epoch()));
+
+        entry.times.set_last_modification(Times::epoch());
+        entry.set_override_url("cmd://firefox {URL}");
+        assert_eq!(entry.get_override_url(), Some("cmd://firefox {URL}"));
+        assert_ne!(entry.times.get_last_modification(), Some(&Times::epoch()));
+
+        entry.set_override_url("");
+        assert_eq!(entry.get_override_url(), None);
+
+        entry.set_background_color(None);
+        assert_eq!(entry.get_background_color(), None);
+    }
+
     #[cfg(feature = "totp")]
     #[test]
     fn totp() {
@@ -559,6 +1483,261 @@ mod entry_tests {
         assert!(entry.get_otp().is_ok());
     }
 
+    #[test]
+    fn content_eq() {
+        let mut db = Database::new(Default::default());
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Sample".to_string()));
+        entry.tags.push("work".to_string());
+
+        let mut other = entry.clone();
+        other.regenerate_uuid();
+        other
+            .times
+            .set_last_access(Times::now() + chrono::Duration::seconds(10));
+        other.update_history();
+        assert_ne!(entry, other);
+        assert!(entry.content_eq(&other));
+
+        other.tags.push("personal".to_string());
+        assert!(!entry.content_eq(&other));
+
+        let mut other = entry.clone();
+        other
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Changed".to_string()));
+        assert!(!entry.content_eq(&other));
+
+        let mut other = entry.clone();
+        let mut other_db = Database::new(Default::default());
+        other.add_attachment(&mut other_db, "file.txt", b"hello".to_vec());
+        assert!(!entry.content_eq_with_attachments(&db, &other, &other_db));
+
+        // across databases, attachments are compared by content, not by their index in the pool
+        db.add_attachment(b"unrelated".to_vec());
+        entry.add_attachment(&mut db, "file.txt", b"hello".to_vec());
+        assert!(entry.content_eq_with_attachments(&db, &other, &other_db));
+        assert!(!entry.content_eq(&other));
+
+        let mut other = entry.clone();
+        other.add_attachment(&mut db, "file.txt", b"changed".to_vec());
+        assert!(!entry.content_eq(&other));
+    }
+
+    #[test]
+    fn is_in_recycle_bin() {
+        let mut db = Database::new(Default::default());
+
+        let entry = Entry::new();
+        let entry_uuid = entry.uuid;
+        db.root.add_child(entry.clone());
+        assert!(!entry.is_in_recycle_bin(&db));
+
+        let mut recycle_bin = Group::new("Recycle Bin");
+        let mut subgroup = Group::new("Deleted group");
+        subgroup.add_child(Entry::new());
+        recycle_bin.add_child(subgroup);
+        db.meta.recyclebin_uuid = Some(recycle_bin.uuid);
+        db.root.add_child(recycle_bin.clone());
+        assert!(!entry.is_in_recycle_bin(&db));
+
+        let removed = db.delete_by_uuid(&entry_uuid, false).unwrap();
+        assert!(db.add_to_group(&recycle_bin.uuid, removed));
+        assert!(entry.is_in_recycle_bin(&db));
+
+        // entries in subgroups of the recycle bin are recycled as well
+        let nested = recycle_bin.groups()[0].entries()[0].clone();
+        assert!(nested.is_in_recycle_bin(&db));
+
+        db.meta.recyclebin_uuid = None;
+        assert!(!entry.is_in_recycle_bin(&db));
+    }
+
+    #[test]
+    fn otp_uri() {
+        let mut entry = Entry::new();
+        assert_eq!(entry.get_otp_uri(), None);
+        assert!(matches!(entry.parse_otp_uri(), Err(OtpParseError::NoUri)));
+
+        // KeeOtp stores a plain base32 secret in "TOTP Seed", which is not an URI
+        entry.fields.insert(
+            "TOTP Seed".to_string(),
+            Value::Unprotected("JBSWY3DPEHPK3PXP".to_string()),
+        );
+        assert_eq!(entry.get_otp_uri(), None);
+
+        let uri = "otpauth://totp/ACME%20Co:alice?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co";
+        entry.fields.insert(
+            "_TOTP_Secret".to_string(),
+            Value::Protected(SecStr::new(uri.as_bytes().to_vec())),
+        );
+        assert_eq!(entry.get_otp_uri(), Some(uri));
+
+        let params = entry.parse_otp_uri().unwrap();
+        assert_eq!(params.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(params.issuer, Some("ACME Co".to_string()));
+        assert_eq!(params.account, Some("alice".to_string()));
+
+        // the "otp" field takes precedence
+        entry.fields.insert(
+            "otp".to_string(),
+            Value::Unprotected("otpauth://totp/bob?secret=GEZDGNBVGY".to_string()),
+        );
+        assert_eq!(entry.parse_otp_uri().unwrap().account, Some("bob".to_string()));
+    }
+
+    #[test]
+    fn field_names() {
+        let mut entry = Entry::new();
+        assert_eq!(entry.field_count(), 0);
+        assert!(!entry.has_field("Title"));
+
+        for name in [
+            "Title",
+            "UserName",
+            "Password",
+            "URL",
+            "Notes",
+            "PIN",
+            "Recovery Code",
+        ] {
+            entry.set(name, "");
+        }
+        entry.set("Security Question", "First pet");
+
+        assert_eq!(entry.field_count(), 8);
+        assert_eq!(
+            entry.field_names().collect::<Vec<_>>(),
+            [
+                "Title",
+                "UserName",
+                "Password",
+                "URL",
+                "Notes",
+                "PIN",
+                "Recovery Code",
+                "Security Question"
+            ]
+        );
+        assert!(entry.has_field("Notes"));
+        assert!(entry.has_field("PIN"));
+        assert!(!entry.has_field("pin"));
+    }
+
+    #[test]
+    fn set_totp() {
+        use super::{
+            TIME_OTP_ALGORITHM_FIELD, TIME_OTP_LENGTH_FIELD, TIME_OTP_PERIOD_FIELD, TIME_OTP_SECRET_FIELD,
+        };
+        use crate::db::{OtpAlgorithm, OtpParameters, TotpOptions};
+
+        let mut entry = Entry::new();
+        entry.set_totp(
+            b"12345678901234567890",
+            TotpOptions {
+                issuer: Some("ACME Co".to_string()),
+                account: Some("jöhn@example.com".to_string()),
+                digits: 8,
+                ..Default::default()
+            },
+        );
+        assert!(entry.is_protected("otp"));
+        assert_eq!(
+            entry.get("otp"),
+            Some(
+                "otpauth://totp/ACME%20Co:j%C3%B6hn%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+                 &issuer=ACME%20Co&algorithm=SHA1&digits=8&period=30"
+            )
+        );
+        assert!(entry.get(TIME_OTP_SECRET_FIELD).is_none());
+        assert_eq!(entry.totp_uri().as_deref(), entry.get("otp"));
+
+        let params = entry.parse_otp_uri().unwrap();
+        assert_eq!(params.issuer, Some("ACME Co".to_string()));
+        assert_eq!(params.account, Some("jöhn@example.com".to_string()));
+        assert_eq!(params.digits, 8);
+
+        // the split fields describe the same secret, and an URI can be built from them alone
+        let mut entry = Entry::new();
+        entry.set("Title", "Mail");
+        entry.set_totp(
+            b"12345678901234567890",
+            TotpOptions {
+                algorithm: OtpAlgorithm::Sha256,
+                period: 60,
+                split_fields: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            entry.get(TIME_OTP_SECRET_FIELD),
+            Some("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ")
+        );
+        assert!(entry.is_protected(TIME_OTP_SECRET_FIELD));
+        assert_eq!(entry.get(TIME_OTP_LENGTH_FIELD), Some("6"));
+        assert_eq!(entry.get(TIME_OTP_PERIOD_FIELD), Some("60"));
+        assert_eq!(entry.get(TIME_OTP_ALGORITHM_FIELD), Some("HMAC-SHA-256"));
+
+        let uri: OtpParameters = entry.get("otp").unwrap().parse().unwrap();
+        entry.fields.shift_remove("otp");
+        let params: OtpParameters = entry.totp_uri().unwrap().parse().unwrap();
+        assert_eq!(
+            params,
+            OtpParameters {
+                account: Some("Mail".to_string()),
+                ..uri
+            }
+        );
+
+        // storing a secret without split fields removes them
+        entry.set_totp(b"12345678901234567890", TotpOptions::default());
+        assert!(entry.get(TIME_OTP_ALGORITHM_FIELD).is_none());
+    }
+
+    #[cfg(feature = "totp")]
+    #[test]
+    fn set_totp_codes() {
+        use crate::db::{OtpAlgorithm, TotpOptions};
+
+        // test vectors of RFC 6238
+        let vectors: [(&[u8], OtpAlgorithm, [&str; 2]); 3] = [
+            (
+                b"12345678901234567890",
+                OtpAlgorithm::Sha1,
+                ["94287082", "07081804"],
+            ),
+            (
+                b"12345678901234567890123456789012",
+                OtpAlgorithm::Sha256,
+                ["46119246", "68084774"],
+            ),
+            (
+                b"1234567890123456789012345678901234567890123456789012345678901234",
+                OtpAlgorithm::Sha512,
+                ["90693936", "25091201"],
+            ),
+        ];
+
+        for (secret, algorithm, codes) in vectors {
+            let mut entry = Entry::new();
+            entry.set_totp(
+                secret,
+                TotpOptions {
+                    issuer: Some("ACME Co".to_string()),
+                    algorithm,
+                    digits: 8,
+                    ..Default::default()
+                },
+            );
+
+            let totp = entry.get_otp().unwrap();
+            assert_eq!(totp.value_at(59).code, codes[0]);
+            assert_eq!(totp.value_at(1111111109).code, codes[1]);
+        }
+    }
+
     #[cfg(feature = "serialization")]
     #[test]
     fn serialization() {
@@ -574,6 +1753,13 @@ mod entry_tests {
 
         assert_eq!(
             serde_json::to_string(&Value::Protected(SecStr::new("ABC".as_bytes().to_vec()))).unwrap(),
+            "\"[redacted]\"".to_string()
+        );
+        assert_eq!(
+            serde_json::to_string(&crate::db::WithSecrets(&Value::Protected(SecStr::new(
+                "ABC".as_bytes().to_vec()
+            ))))
+            .unwrap(),
             "\"ABC\"".to_string()
         );
     }
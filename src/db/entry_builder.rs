@@ -0,0 +1,168 @@
+use secstr::SecStr;
+use uuid::Uuid;
+
+use crate::{
+    db::{Database, Entry, NodeRef, Value},
+    error::GroupNotFoundError,
+};
+
+/// Builder for a new entry in a group of a database, see `Database::new_entry_in_group`
+#[must_use = "the entry is only added to the database by calling `build`"]
+pub struct EntryBuilder<'db> {
+    database: &'db mut Database,
+    group_uuid: Uuid,
+    entry: Entry,
+}
+
+impl<'db> EntryBuilder<'db> {
+    fn field(mut self, name: &str, value: Value) -> Self {
+        self.entry.fields.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn title(self, title: &str) -> Self {
+        self.field("Title", Value::Unprotected(title.to_string()))
+    }
+
+    pub fn username(self, username: &str) -> Self {
+        self.field("UserName", Value::Unprotected(username.to_string()))
+    }
+
+    /// Set the password, which is stored as a protected value
+    pub fn password(self, password: &str) -> Self {
+        self.field("Password", Value::Protected(SecStr::from(password)))
+    }
+
+    pub fn url(self, url: &str) -> Self {
+        self.field("URL", Value::Unprotected(url.to_string()))
+    }
+
+    pub fn notes(self, notes: &str) -> Self {
+        self.field("Notes", Value::Unprotected(notes.to_string()))
+    }
+
+    /// Add a tag, unless the entry already has it
+    pub fn tag(mut self, tag: &str) -> Self {
+        if !self.entry.tags.iter().any(|t| t == tag) {
+            self.entry.tags.push(tag.to_string());
+        }
+        self
+    }
+
+    /// Let the entry expire after the given duration, counted from the current time of the database
+    pub fn expires_in(mut self, duration: chrono::Duration) -> Self {
+        let expiry = self.database.now() + duration;
+        self.entry.times.set_expiry(expiry);
+        self.entry.times.expires = true;
+        self
+    }
+
+    /// Add the entry to the group and return its UUID
+    pub fn build(self) -> Uuid {
+        let uuid = self.entry.uuid;
+        let added = self.database.add_to_group(&self.group_uuid, self.entry);
+        assert!(added, "the group was found when creating the builder");
+        uuid
+    }
+}
+
+impl Database {
+    /// Start building a new entry in the group with the given UUID. The entry is created with
+    /// `Database::new_entry` and only added to the group by `EntryBuilder::build`.
+    ///
+    /// ```
+    /// use keepass::{db::Group, Database};
+    ///
+    /// let mut db = Database::new(Default::default());
+    /// let group = Group::new("Email");
+    /// let group_uuid = group.uuid;
+    /// db.root.add_child(group);
+    ///
+    /// let entry_uuid = db
+    ///     .new_entry_in_group(&group_uuid)
+    ///     .unwrap()
+    ///     .title("Mail")
+    ///     .username("jdoe")
+    ///     .password("hunter2")
+    ///     .url("https://mail.example.com")
+    ///     .tag("work")
+    ///     .expires_in(chrono::Duration::days(90))
+    ///     .build();
+    /// ```
+    pub fn new_entry_in_group(&mut self, group_uuid: &Uuid) -> Result<EntryBuilder<'_>, GroupNotFoundError> {
+        if !matches!(self.get_by_uuid(group_uuid), Some(NodeRef::Group(_))) {
+            return Err(GroupNotFoundError(*group_uuid));
+        }
+
+        Ok(EntryBuilder {
+            entry: self.new_entry(),
+            database: self,
+            group_uuid: *group_uuid,
+        })
+    }
+}
+
+#[cfg(test)]
+mod entry_builder_tests {
+    use chrono::{Duration, NaiveDateTime};
+
+    use crate::{
+        db::{FixedTimeProvider, Group, NodeRef, Value},
+        Database,
+    };
+
+    #[test]
+    fn build_entry_in_group() {
+        let now = NaiveDateTime::parse_from_str("2024-01-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(now)));
+
+        let mut parent = Group::new("Internet");
+        let child = Group::new("Email");
+        let group_uuid = child.uuid;
+        parent.add_child(child);
+        db.root.add_child(parent);
+
+        let entry_uuid = db
+            .new_entry_in_group(&group_uuid)
+            .unwrap()
+            .title("Mail")
+            .username("jdoe")
+            .password("hunter2")
+            .url("https://mail.example.com")
+            .notes("Main account")
+            .tag("work")
+            .tag("mail")
+            .tag("work")
+            .expires_in(Duration::days(90))
+            .build();
+
+        let entry = match db.root.get(&["Internet", "Email", "Mail"]) {
+            Some(NodeRef::Entry(e)) => e,
+            _ => panic!("Expected the entry at Internet/Email/Mail"),
+        };
+
+        assert_eq!(entry.uuid, entry_uuid);
+        assert_eq!(entry.get_username(), Some("jdoe"));
+        assert!(matches!(entry.fields.get("Password"), Some(Value::Protected(_))));
+        assert_eq!(entry.get_password(), Some("hunter2"));
+        assert_eq!(entry.get_url(), Some("https://mail.example.com"));
+        assert_eq!(entry.get("Notes"), Some("Main account"));
+        assert_eq!(entry.tags, vec!["work".to_string(), "mail".to_string()]);
+
+        assert!(entry.times.expires);
+        assert_eq!(entry.times.get_expiry(), Some(&(now + Duration::days(90))));
+        assert_eq!(entry.times.get_creation(), Some(&now));
+    }
+
+    #[test]
+    fn group_not_found() {
+        let mut db = Database::new(Default::default());
+        let missing = uuid::Uuid::new_v4();
+
+        let err = db.new_entry_in_group(&missing).err().unwrap();
+        assert_eq!(err.0, missing);
+        assert_eq!(db.root.iter_entries().count(), 0);
+    }
+}
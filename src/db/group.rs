@@ -1,50 +1,140 @@
-use std::collections::VecDeque;
+use std::{cmp::Ordering, collections::VecDeque};
 
 use uuid::Uuid;
 
-use crate::db::{
-    entry::Entry,
-    node::{Node, NodeIter, NodeRef, NodeRefMut},
-    CustomData, Times,
+use crate::{
+    db::{
+        clock::Clock,
+        entry::Entry,
+        node::{EntryIterMut, IterOrder, Node, NodeIter, NodeRef, NodeRefMut, PathIter},
+        CustomData, Database, Times, UnknownElement,
+    },
+    error::IndexOutOfBounds,
 };
 
 #[cfg(feature = "_merge")]
 use crate::db::merge::{MergeError, MergeEvent, MergeEventType, MergeLog};
 
-#[cfg(feature = "_merge")]
+/// UUIDs of the groups on the path to a node, starting with the group the search started from
 pub(crate) type NodeLocation = Vec<Uuid>;
 
-pub(crate) enum SearchField {
-    #[cfg(any(test, feature = "_merge"))]
-    Uuid,
+/// Options for looking up nodes by their path, see `Group::get_with_options`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GetOptions {
+    /// Compare the names of groups and the titles of entries to the path regardless of case
+    pub case_insensitive: bool,
+}
+
+/// What `Group::sort_children` sorts the children of a group by
+#[non_exhaustive]
+pub enum SortKey {
+    /// The name of groups and the title of entries, ignoring case
     Title,
+
+    /// The title of entries, ignoring case. The groups are placed before the entries and keep their
+    /// order.
+    EntryTitle,
+
+    /// The name of groups, ignoring case. The groups are placed before the entries, which keep their
+    /// order.
+    GroupName,
+
+    /// The creation time, oldest first
+    Created,
+
+    /// The last modification time, oldest first
+    Modified,
+
+    /// A custom comparator
+    Custom(Box<dyn Fn(NodeRef, NodeRef) -> Ordering>),
 }
 
-impl SearchField {
-    pub(crate) fn matches(&self, node: &Node, field_value: &str) -> bool {
+impl SortKey {
+    fn compare(&self, a: NodeRef, b: NodeRef) -> Ordering {
         match self {
-            #[cfg(any(test, feature = "_merge"))]
-            SearchField::Uuid => {
-                let uuid = match node {
-                    Node::Entry(e) => e.uuid,
-                    Node::Group(g) => g.uuid,
-                };
-                uuid.to_string() == field_value
-            }
-            SearchField::Title => {
-                let title = match node {
-                    Node::Entry(e) => e.get_title(),
-                    Node::Group(g) => Some(g.get_name()),
-                };
-                match title {
-                    Some(t) => t == field_value,
-                    None => false,
-                }
+            SortKey::Title => compare_ignoring_case(a.title(), b.title()),
+            SortKey::EntryTitle | SortKey::GroupName if a.is_entry() != b.is_entry() => {
+                a.is_entry().cmp(&b.is_entry())
             }
+            SortKey::EntryTitle if a.is_entry() => compare_ignoring_case(a.title(), b.title()),
+            SortKey::GroupName if a.is_group() => compare_ignoring_case(a.title(), b.title()),
+            SortKey::EntryTitle | SortKey::GroupName => Ordering::Equal,
+            SortKey::Created => a.times().get_creation().cmp(&b.times().get_creation()),
+            SortKey::Modified => a
+                .times()
+                .get_last_modification()
+                .cmp(&b.times().get_last_modification()),
+            SortKey::Custom(compare) => compare(a, b),
+        }
+    }
+}
+
+impl std::fmt::Debug for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortKey::Title => write!(f, "Title"),
+            SortKey::EntryTitle => write!(f, "EntryTitle"),
+            SortKey::GroupName => write!(f, "GroupName"),
+            SortKey::Created => write!(f, "Created"),
+            SortKey::Modified => write!(f, "Modified"),
+            SortKey::Custom(_) => write!(f, "Custom(..)"),
         }
     }
 }
 
+/// A segment of a path, which matches a node by its name or title, or by its UUID
+struct PathSegment<'p> {
+    name: &'p str,
+    lowercase_name: Option<String>,
+    uuid: Option<Uuid>,
+}
+
+impl<'p> PathSegment<'p> {
+    fn new(segment: &'p str, options: GetOptions) -> Self {
+        PathSegment {
+            name: segment,
+            lowercase_name: options.case_insensitive.then(|| segment.to_lowercase()),
+            uuid: Uuid::parse_str(segment).ok(),
+        }
+    }
+
+    fn matches(&self, node: &Node) -> bool {
+        let (uuid, name) = match node {
+            Node::Entry(e) => (e.uuid, e.get_title()),
+            Node::Group(g) => (g.uuid, Some(g.get_name())),
+        };
+
+        if self.uuid == Some(uuid) {
+            return true;
+        }
+
+        match (name, &self.lowercase_name) {
+            (Some(name), Some(lowercase_name)) => name.to_lowercase() == *lowercase_name,
+            (Some(name), None) => name == self.name,
+            (None, _) => false,
+        }
+    }
+}
+
+/// The settings of a group, i.e. everything except for its UUID, children, timestamps and custom
+/// data. See `Group::new_with_settings` and `Group::settings`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GroupSettings {
+    pub name: String,
+    pub notes: Option<String>,
+    pub icon_id: Option<usize>,
+    pub custom_icon_uuid: Option<Uuid>,
+
+    /// Whether autotype is enabled, `None` to inherit the setting from the parent group
+    pub enable_autotype: Option<bool>,
+
+    /// Whether searching is enabled, `None` to inherit the setting from the parent group
+    pub enable_searching: Option<bool>,
+
+    pub default_autotype_sequence: Option<String>,
+    pub is_expanded: bool,
+}
+
 /// A database group with child groups and entries
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -79,18 +169,26 @@ pub struct Group {
     /// Default autotype sequence
     pub default_autotype_sequence: Option<String>,
 
-    /// Whether autotype is enabled
-    // TODO: in example XML files, this is "null" - what should the type be?
+    /// Whether autotype is enabled, as written in the XML ("true", "false" or "null" to inherit from
+    /// the parent group). Use `get_enable_autotype` and `set_enable_autotype` for a typed view.
     pub enable_autotype: Option<String>,
 
-    /// Whether searching is enabled
-    // TODO: in example XML files, this is "null" - what should the type be?
+    /// Whether searching is enabled, as written in the XML ("true", "false" or "null" to inherit from
+    /// the parent group). Use `get_enable_searching` and `set_enable_searching` for a typed view.
     pub enable_searching: Option<String>,
 
     /// UUID for the last top visible entry
     // TODO figure out what that is supposed to mean. According to the KeePass sourcecode, it has
     // something to do with restoring selected items when re-opening a database.
     pub last_top_visible_entry: Option<Uuid>,
+
+    /// XML elements of the group that are not known to this crate
+    pub unknown_elements: Vec<UnknownElement>,
+
+    /// Source of the current time for the timestamps that the group updates, shared with the
+    /// database that the group was added to
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) clock: Clock,
 }
 
 impl Group {
@@ -103,12 +201,102 @@ impl Group {
         }
     }
 
-    /// Add a child node (an entry or a group) to this group.
+    /// Create a group with the given settings, instead of setting the fields of a group created
+    /// with `Group::new` one by one
+    pub fn new_with_settings(settings: GroupSettings) -> Group {
+        let GroupSettings {
+            name,
+            notes,
+            icon_id,
+            custom_icon_uuid,
+            enable_autotype,
+            enable_searching,
+            default_autotype_sequence,
+            is_expanded,
+        } = settings;
+
+        Group {
+            notes,
+            icon_id,
+            custom_icon_uuid,
+            enable_autotype: Some(format_inherited_bool(enable_autotype)),
+            enable_searching: Some(format_inherited_bool(enable_searching)),
+            default_autotype_sequence,
+            is_expanded,
+            ..Group::new(&name)
+        }
+    }
+
+    /// Get a copy of the settings of this group
+    pub fn settings(&self) -> GroupSettings {
+        GroupSettings {
+            name: self.name.clone(),
+            notes: self.notes.clone(),
+            icon_id: self.icon_id,
+            custom_icon_uuid: self.custom_icon_uuid,
+            enable_autotype: self.get_enable_autotype(),
+            enable_searching: self.get_enable_searching(),
+            default_autotype_sequence: self.default_autotype_sequence.clone(),
+            is_expanded: self.is_expanded,
+        }
+    }
+
+    /// Add a child node (an entry or a group) to this group, and update the last modification time
+    /// of this group unless the database does not update timestamps on changes, see
+    /// `Database::set_update_times`.
     pub fn add_child(&mut self, node: impl Into<Node>) {
-        self.children.push(node.into());
+        self.push_child(node);
+        self.record_change();
     }
 
-    /// Recursively get a Group or Entry reference by specifying a path relative to the current Group
+    /// Add a child node without updating the timestamps of this group, e.g. when reading or merging
+    /// a database
+    pub(crate) fn push_child(&mut self, node: impl Into<Node>) {
+        let mut node = node.into();
+        node.set_clock(&self.clock);
+        self.children.push(node);
+    }
+
+    /// Insert a child node at a position among the children of this group, shifting the children
+    /// after it, e.g. to add an entry after the last subgroup. Like `add_child`, this updates the
+    /// last modification time of this group.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of children, like `Vec::insert`. See
+    /// `try_add_child_at` for a version that returns an error instead.
+    pub fn add_child_at(&mut self, index: usize, node: impl Into<Node>) {
+        let mut node = node.into();
+        node.set_clock(&self.clock);
+        self.children.insert(index, node);
+        self.record_change();
+    }
+
+    /// Insert a child node at a position among the children of this group, see `add_child_at`
+    pub fn try_add_child_at(&mut self, index: usize, node: impl Into<Node>) -> Result<(), IndexOutOfBounds> {
+        if index > self.children.len() {
+            return Err(IndexOutOfBounds {
+                index,
+                len: self.children.len(),
+            });
+        }
+        self.add_child_at(index, node);
+        Ok(())
+    }
+
+    /// Get the position of a direct child among the children of this group
+    pub fn index_of_child(&self, uuid: &Uuid) -> Option<usize> {
+        self.children
+            .iter()
+            .position(|child| child.as_ref().uuid() == *uuid)
+    }
+
+    /// Recursively get a Group or Entry reference by specifying a path relative to the current Group.
+    ///
+    /// Each segment of the path is the name of a group or the title of an entry, or the UUID of a
+    /// group or entry as a string. If several nodes match a segment, the first one is used, see
+    /// `get_all` to get all matching nodes. Names are case-sensitive, see `get_with_options` to
+    /// ignore case.
     /// ```
     /// use keepass::{Database, DatabaseKey, db::NodeRef};
     /// use std::fs::File;
@@ -128,84 +316,191 @@ impl Group {
     /// }
     /// ```
     pub fn get<'a>(&'a self, path: &[&str]) -> Option<NodeRef<'a>> {
-        self.get_internal(path, SearchField::Title)
+        self.get_internal(path, GetOptions::default())
     }
 
-    #[cfg(any(test, feature = "_merge"))]
+    /// Like `get`, with options for matching the segments of the path
+    /// ```
+    /// use keepass::db::{GetOptions, Group, NodeRef};
+    ///
+    /// let mut root = Group::new("Root");
+    /// root.add_child(Group::new("Email"));
+    ///
+    /// let options = GetOptions { case_insensitive: true };
+    /// assert!(matches!(root.get_with_options(&["EMAIL"], options), Some(NodeRef::Group(_))));
+    /// ```
+    pub fn get_with_options<'a>(&'a self, path: &[&str], options: GetOptions) -> Option<NodeRef<'a>> {
+        self.get_internal(path, options)
+    }
+
+    #[cfg(test)]
     pub(crate) fn get_by_uuid<'a, T: AsRef<str>>(&'a self, path: &[T]) -> Option<NodeRef<'a>> {
-        self.get_internal(path, SearchField::Uuid)
+        self.get_internal(path, GetOptions::default())
     }
 
-    fn get_internal<'a, T: AsRef<str>>(&'a self, path: &[T], search_field: SearchField) -> Option<NodeRef<'a>> {
-        if path.is_empty() {
-            Some(NodeRef::Group(self))
-        } else if path.len() == 1 {
-            let head = &path[0];
-            self.children.iter().find_map(|n| {
-                if search_field.matches(n, head.as_ref()) {
-                    return Some(n.as_ref());
-                }
-                None
-            })
-        } else {
-            let head = &path[0];
-            let tail = &path[1..path.len()];
-
-            let head_group = self.children.iter().find_map(|n| match n {
-                Node::Group(g) if search_field.matches(n, head.as_ref()) => Some(g),
+    fn get_internal<'a, T: AsRef<str>>(&'a self, path: &[T], options: GetOptions) -> Option<NodeRef<'a>> {
+        let Some((last, groups)) = path.split_last() else {
+            return Some(NodeRef::Group(self));
+        };
+
+        let mut group = self;
+        for segment in groups {
+            let segment = PathSegment::new(segment.as_ref(), options);
+            group = group.children.iter().find_map(|n| match n {
+                Node::Group(g) if segment.matches(n) => Some(g),
                 _ => None,
             })?;
+        }
+
+        let last = PathSegment::new(last.as_ref(), options);
+        group.children.iter().find(|n| last.matches(n)).map(Node::as_ref)
+    }
+
+    /// Get all groups and entries at a path relative to this group. Unlike `get`, this follows all
+    /// groups that match a segment of the path, and returns all matching nodes in the order in which
+    /// they are stored, e.g. all entries with the same title.
+    pub fn get_all<'a>(&'a self, path: &[&str]) -> Vec<NodeRef<'a>> {
+        self.get_all_with_options(path, GetOptions::default())
+    }
+
+    /// Like `get_all`, with options for matching the segments of the path
+    pub fn get_all_with_options<'a>(&'a self, path: &[&str], options: GetOptions) -> Vec<NodeRef<'a>> {
+        let Some((last, groups)) = path.split_last() else {
+            return vec![NodeRef::Group(self)];
+        };
 
-            head_group.get_internal(tail, search_field)
+        let mut parents = vec![self];
+        for segment in groups {
+            let segment = PathSegment::new(segment, options);
+            parents = parents
+                .into_iter()
+                .flat_map(|parent| parent.children.iter())
+                .filter_map(|n| match n {
+                    Node::Group(g) if segment.matches(n) => Some(g),
+                    _ => None,
+                })
+                .collect();
         }
+
+        let last = PathSegment::new(last, options);
+        parents
+            .into_iter()
+            .flat_map(|parent| parent.children.iter())
+            .filter(|n| last.matches(n))
+            .map(Node::as_ref)
+            .collect()
     }
 
     /// Recursively get a mutable reference to a Group or Entry by specifying a path relative to
-    /// the current Group
+    /// the current Group. The path is matched like in `get`.
+    /// ```
+    /// use keepass::{Database, DatabaseKey, db::NodeRefMut};
+    /// use std::fs::File;
+    ///
+    /// let mut file = File::open("tests/resources/test_db_with_password.kdbx").unwrap();
+    /// let mut db = Database::open(
+    ///     &mut file,
+    ///     DatabaseKey::new().with_password("demopass")
+    /// ).unwrap();
+    ///
+    /// if let Some(NodeRefMut::Group(g)) = db.root.get_mut(&["General"]) {
+    ///     g.notes = Some("Updated".to_string());
+    /// }
+    /// ```
     pub fn get_mut<'a>(&'a mut self, path: &[&str]) -> Option<NodeRefMut<'a>> {
-        self.get_mut_internal(path, SearchField::Title)
+        self.get_mut_internal(path, GetOptions::default())
+    }
+
+    /// Like `get_mut`, with options for matching the segments of the path
+    pub fn get_mut_with_options<'a>(
+        &'a mut self,
+        path: &[&str],
+        options: GetOptions,
+    ) -> Option<NodeRefMut<'a>> {
+        self.get_mut_internal(path, options)
     }
 
-    #[cfg(any(test, feature = "_merge"))]
+    #[cfg(test)]
     pub(crate) fn get_by_uuid_mut<'a, T: AsRef<str>>(&'a mut self, path: &[T]) -> Option<NodeRefMut<'a>> {
-        self.get_mut_internal(path, SearchField::Uuid)
+        self.get_mut_internal(path, GetOptions::default())
     }
 
     fn get_mut_internal<'a, T: AsRef<str>>(
         &'a mut self,
         path: &[T],
-        search_field: SearchField,
+        options: GetOptions,
     ) -> Option<NodeRefMut<'a>> {
-        if path.is_empty() {
-            Some(NodeRefMut::Group(self))
-        } else if path.len() == 1 {
-            let head = &path[0];
-            self.children
-                .iter_mut()
-                .filter(|n| search_field.matches(n, head.as_ref()))
-                .map(|t| t.as_mut())
-                .next()
-        } else {
-            let head = &path[0];
-            let tail = &path[1..path.len()];
-
-            let head_group: &mut Group = self.children.iter_mut().find_map(|n| {
-                let node_matches = search_field.matches(n, head.as_ref());
+        let Some((last, groups)) = path.split_last() else {
+            return Some(NodeRefMut::Group(self));
+        };
+
+        let mut group = self;
+        for segment in groups {
+            let segment = PathSegment::new(segment.as_ref(), options);
+            group = group.children.iter_mut().find_map(|n| {
+                let node_matches = segment.matches(n);
                 match n {
                     Node::Group(g) if node_matches => Some(g),
                     _ => None,
                 }
             })?;
+        }
+
+        let last = PathSegment::new(last.as_ref(), options);
+        group
+            .children
+            .iter_mut()
+            .find(|n| last.matches(n))
+            .map(Node::as_mut)
+    }
+
+    /// Get a node by the UUIDs of the groups on the way to it, followed by its own UUID. Unlike
+    /// `get_by_uuid`, this does not format the UUIDs as strings.
+    #[cfg(feature = "_merge")]
+    pub(crate) fn find_node(&self, path: &[Uuid]) -> Option<NodeRef<'_>> {
+        let Some((last, groups)) = path.split_last() else {
+            return Some(NodeRef::Group(self));
+        };
+
+        let mut group = self;
+        for uuid in groups {
+            group = group.children.iter().find_map(|n| match n {
+                Node::Group(g) if &g.uuid == uuid => Some(g),
+                _ => None,
+            })?;
+        }
 
-            head_group.get_mut_internal(tail, search_field)
+        group
+            .children
+            .iter()
+            .find(|n| &n.uuid() == last)
+            .map(Node::as_ref)
+    }
+
+    #[cfg(feature = "_merge")]
+    pub(crate) fn find_node_mut(&mut self, path: &[Uuid]) -> Option<NodeRefMut<'_>> {
+        let Some((last, groups)) = path.split_last() else {
+            return Some(NodeRefMut::Group(self));
+        };
+
+        let mut group = self;
+        for uuid in groups {
+            group = group.children.iter_mut().find_map(|n| match n {
+                Node::Group(g) if &g.uuid == uuid => Some(g),
+                _ => None,
+            })?;
         }
+
+        group
+            .children
+            .iter_mut()
+            .find(|n| &n.uuid() == last)
+            .map(Node::as_mut)
     }
 
     #[cfg(feature = "_merge")]
     pub(crate) fn find_group(&self, path: &[Uuid]) -> Option<&Group> {
-        let path: Vec<String> = path.iter().map(|p| p.to_string()).collect();
-        let node_ref = self.get_by_uuid(&path)?;
-        match node_ref {
+        match self.find_node(path)? {
             NodeRef::Group(g) => Some(g),
             NodeRef::Entry(_) => None,
         }
@@ -213,9 +508,7 @@ impl Group {
 
     #[cfg(feature = "_merge")]
     pub(crate) fn find_entry(&self, path: &[Uuid]) -> Option<&Entry> {
-        let path: Vec<String> = path.iter().map(|p| p.to_string()).collect();
-        let node_ref = self.get_by_uuid(&path)?;
-        match node_ref {
+        match self.find_node(path)? {
             NodeRef::Entry(e) => Some(e),
             NodeRef::Group(_) => None,
         }
@@ -223,9 +516,7 @@ impl Group {
 
     #[cfg(feature = "_merge")]
     pub(crate) fn find_entry_mut(&mut self, path: &[Uuid]) -> Option<&mut Entry> {
-        let path: Vec<String> = path.iter().map(|p| p.to_string()).collect();
-        let node_ref = self.get_by_uuid_mut(&path)?;
-        match node_ref {
+        match self.find_node_mut(path)? {
             NodeRefMut::Entry(e) => Some(e),
             NodeRefMut::Group(_) => None,
         }
@@ -233,14 +524,23 @@ impl Group {
 
     #[cfg(feature = "_merge")]
     pub(crate) fn find_group_mut(&mut self, path: &[Uuid]) -> Option<&mut Group> {
-        let path: Vec<String> = path.iter().map(|p| p.to_string()).collect();
-        let node_ref = self.get_by_uuid_mut(&path)?;
-        match node_ref {
+        match self.find_node_mut(path)? {
             NodeRefMut::Group(g) => Some(g),
             NodeRefMut::Entry(_) => None,
         }
     }
 
+    /// Assign new UUIDs to this group and everything below it
+    pub(crate) fn regenerate_uuids(&mut self) {
+        self.uuid = Uuid::new_v4();
+        for child in &mut self.children {
+            match child {
+                Node::Group(g) => g.regenerate_uuids(),
+                Node::Entry(e) => e.regenerate_uuid(),
+            }
+        }
+    }
+
     /// Recursively remove a node from this group or its children
     pub fn remove_node_by_uuid(&mut self, uuid: &Uuid) -> Option<Node> {
         // First, check direct children
@@ -283,6 +583,133 @@ impl Group {
         self.times.get_expiry()
     }
 
+    /// Whether the group expires and its expiry time has passed at the current time of its
+    /// database, see `Times::is_expired_at`
+    pub fn is_expired(&self) -> bool {
+        self.times.is_expired_at(self.clock.now())
+    }
+
+    /// Update the last access time, and the last modification time if `modified` is set, to the
+    /// current time of the database of the group
+    pub fn touch(&mut self, modified: bool) {
+        self.touch_at(modified, self.clock.now());
+    }
+
+    /// Use the time provider of a database for the timestamps of this group and all nodes below it
+    pub(crate) fn set_clock(&mut self, clock: &Clock) {
+        self.clock = clock.clone();
+        for child in self.children.iter_mut() {
+            child.set_clock(clock);
+        }
+    }
+
+    /// Update the last modification time after a change, unless the database does not update
+    /// timestamps on changes, see `Database::set_update_times`
+    fn record_change(&mut self) {
+        if let Some(now) = self.clock.change_time() {
+            self.times.touch(true, now);
+        }
+    }
+
+    /// Update the last access time, and the last modification time if `modified` is set, to `now`
+    pub fn touch_at(&mut self, modified: bool, now: chrono::NaiveDateTime) {
+        self.times.touch(modified, now);
+    }
+
+    /// Get the notes of the group
+    pub fn get_notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// Set the notes of the group and update the last modification time
+    pub fn set_notes(&mut self, notes: Option<&str>) {
+        self.notes = notes.map(|n| n.to_string());
+        self.record_change();
+    }
+
+    /// Set whether the group is expanded in the user interface and update the last modification time
+    pub fn set_expanded(&mut self, is_expanded: bool) {
+        self.is_expanded = is_expanded;
+        self.record_change();
+    }
+
+    /// Get the default autotype sequence for entries in this group
+    pub fn get_default_autotype_sequence(&self) -> Option<&str> {
+        self.default_autotype_sequence.as_deref()
+    }
+
+    /// Set the default autotype sequence and update the last modification time
+    pub fn set_default_autotype_sequence(&mut self, sequence: Option<&str>) {
+        self.default_autotype_sequence = sequence.map(|s| s.to_string());
+        self.record_change();
+    }
+
+    /// Whether autotype is enabled for this group. `None` means that the setting is inherited from
+    /// the parent group.
+    pub fn get_enable_autotype(&self) -> Option<bool> {
+        parse_inherited_bool(self.enable_autotype.as_deref())
+    }
+
+    /// Set whether autotype is enabled for this group (`None` to inherit from the parent group) and
+    /// update the last modification time
+    pub fn set_enable_autotype(&mut self, enabled: Option<bool>) {
+        self.enable_autotype = Some(format_inherited_bool(enabled));
+        self.record_change();
+    }
+
+    /// Whether searching is enabled for this group. `None` means that the setting is inherited from
+    /// the parent group.
+    pub fn get_enable_searching(&self) -> Option<bool> {
+        parse_inherited_bool(self.enable_searching.as_deref())
+    }
+
+    /// Set whether searching is enabled for this group (`None` to inherit from the parent group) and
+    /// update the last modification time
+    pub fn set_enable_searching(&mut self, enabled: Option<bool>) {
+        self.enable_searching = Some(format_inherited_bool(enabled));
+        self.record_change();
+    }
+
+    /// Get the UUID of the entry that was last shown at the top of the entry list for this group
+    pub fn get_last_top_visible_entry(&self) -> Option<Uuid> {
+        self.last_top_visible_entry
+    }
+
+    /// Set the UUID of the entry that was last shown at the top of the entry list and update the
+    /// last modification time
+    pub fn set_last_top_visible_entry(&mut self, uuid: Option<Uuid>) {
+        self.last_top_visible_entry = uuid;
+        self.record_change();
+    }
+
+    /// Recursively collect all entries that are visible to searches.
+    ///
+    /// Groups with searching disabled are skipped together with their subgroups, unless a subgroup
+    /// explicitly enables searching again. Setting `include_disabled` returns all entries regardless of
+    /// the group settings.
+    pub fn searchable_entries(&self, include_disabled: bool) -> Vec<&Entry> {
+        let mut response = Vec::new();
+        self.collect_searchable_entries(true, include_disabled, &mut response);
+        response
+    }
+
+    fn collect_searchable_entries<'a>(
+        &'a self,
+        parent_enabled: bool,
+        include_disabled: bool,
+        response: &mut Vec<&'a Entry>,
+    ) {
+        let enabled = self.get_enable_searching().unwrap_or(parent_enabled);
+
+        for node in &self.children {
+            match node {
+                Node::Entry(e) if enabled || include_disabled => response.push(e),
+                Node::Entry(_) => {}
+                Node::Group(g) => g.collect_searchable_entries(enabled, include_disabled, response),
+            }
+        }
+    }
+
     pub fn entries(&self) -> Vec<&Entry> {
         let mut response: Vec<&Entry> = vec![];
         for node in &self.children {
@@ -323,6 +750,70 @@ impl Group {
         response
     }
 
+    /// Sort the children of this group with a comparator. The sort is stable, so children that
+    /// compare equal keep their order, and the order is kept when saving the database.
+    ///
+    /// Like the other methods that reorder children, this only changes the presentation and does not
+    /// update the modification time of the group. Call `touch(true)` to count it as a modification.
+    pub fn sort_children_by(&mut self, mut compare: impl FnMut(NodeRef, NodeRef) -> Ordering) {
+        self.children.sort_by(|a, b| compare(a.as_ref(), b.as_ref()));
+    }
+
+    /// Sort the children of this group by `by`, e.g. to sort them A-Z. With `groups_first`, the
+    /// subgroups are placed before the entries, and with `recursive`, the children of all subgroups
+    /// are sorted as well. Children without the timestamp that is sorted by come first.
+    ///
+    /// The sort is stable and only changes the order of the children, see `sort_children_by`.
+    pub fn sort_children(&mut self, by: SortKey, groups_first: bool, recursive: bool) {
+        self.sort_children_with(&by, groups_first, recursive);
+    }
+
+    fn sort_children_with(&mut self, by: &SortKey, groups_first: bool, recursive: bool) {
+        self.sort_children_by(|a, b| {
+            let groups = match groups_first {
+                true => a.is_entry().cmp(&b.is_entry()),
+                false => Ordering::Equal,
+            };
+            groups.then_with(|| by.compare(a, b))
+        });
+
+        if recursive {
+            for group in self.groups_mut() {
+                group.sort_children_with(by, groups_first, recursive);
+            }
+        }
+    }
+
+    /// Sort the entries by title, ignoring case, and place them after the groups, as KeePass
+    /// displays them. The groups keep their order, see `sort_children_by`.
+    pub fn sort_entries_by_title(&mut self) {
+        self.sort_children(SortKey::EntryTitle, false, false);
+    }
+
+    /// Sort the subgroups by name, ignoring case, and place them before the entries, as KeePass
+    /// displays them. The entries keep their order, see `sort_children_by`.
+    pub fn sort_groups_by_name(&mut self) {
+        self.sort_children(SortKey::GroupName, false, false);
+    }
+
+    /// Move the child at `from_index` to `to_index`, shifting the children in between, e.g. to
+    /// reorder entries manually. See `sort_children_by` for how this affects timestamps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is not less than the number of children.
+    pub fn move_child(&mut self, from_index: usize, to_index: usize) {
+        assert!(
+            to_index < self.children.len(),
+            "to_index {} out of bounds for {} children",
+            to_index,
+            self.children.len()
+        );
+
+        let child = self.children.remove(from_index);
+        self.children.insert(to_index, child);
+    }
+
     #[cfg(feature = "_merge")]
     pub(crate) fn remove_node(&mut self, uuid: &Uuid) -> Result<Node, MergeError> {
         let mut removed_node: Option<Node> = None;
@@ -357,7 +848,7 @@ impl Group {
         )))
     }
 
-    #[cfg(feature = "_merge")]
+    /// Path from this group to the parent group of the node with the given UUID
     pub(crate) fn find_node_location(&self, id: Uuid) -> Option<NodeLocation> {
         let mut current_location = vec![self.uuid];
         for node in &self.children {
@@ -402,7 +893,7 @@ impl Group {
                     "Group {} did not have a last modification timestamp",
                     self.uuid
                 ));
-                Times::now()
+                self.clock.now()
             }
         };
 
@@ -449,24 +940,220 @@ impl Group {
         Ok(log)
     }
 
+    /// Compare the contents of two groups and all of their descendants, ignoring UUIDs, timestamps
+    /// and the history of entries. Children are compared in order, and their attachments by their
+    /// index in the attachment pool, see `Entry::content_eq`.
+    pub fn content_eq(&self, other: &Group) -> bool {
+        self.content_eq_by(other, &|a, b| a.content_eq(b))
+    }
+
+    /// Compare the contents of two groups like `content_eq`, but compare attachments by their
+    /// content, see `Entry::content_eq_with_attachments`.
+    pub fn content_eq_with_attachments(&self, db: &Database, other: &Group, other_db: &Database) -> bool {
+        self.content_eq_by(other, &|a, b| a.content_eq_with_attachments(db, b, other_db))
+    }
+
+    /// Compare the contents of two groups like `content_eq`, with `entry_eq` comparing the entries
+    fn content_eq_by(&self, other: &Group, entry_eq: &dyn Fn(&Entry, &Entry) -> bool) -> bool {
+        self.properties_eq(other)
+            && self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(other.children.iter())
+                .all(|pair| match pair {
+                    (Node::Group(a), Node::Group(b)) => a.content_eq_by(b, entry_eq),
+                    (Node::Entry(a), Node::Entry(b)) => entry_eq(a, b),
+                    _ => false,
+                })
+    }
+
+    /// Compare the properties of two groups, ignoring UUIDs, timestamps and children
+    fn properties_eq(&self, other: &Group) -> bool {
+        // destructure to not miss any fields that are added in the future
+        let Group {
+            uuid: _,
+            name,
+            notes,
+            icon_id,
+            custom_icon_uuid,
+            children: _,
+            times: _,
+            custom_data,
+            is_expanded,
+            default_autotype_sequence,
+            enable_autotype,
+            enable_searching,
+            last_top_visible_entry,
+            unknown_elements,
+            clock: _,
+        } = self;
+
+        *name == other.name
+            && *notes == other.notes
+            && *icon_id == other.icon_id
+            && *custom_icon_uuid == other.custom_icon_uuid
+            && *custom_data == other.custom_data
+            && *is_expanded == other.is_expanded
+            && *default_autotype_sequence == other.default_autotype_sequence
+            && *enable_autotype == other.enable_autotype
+            && *enable_searching == other.enable_searching
+            && *last_top_visible_entry == other.last_top_visible_entry
+            && *unknown_elements == other.unknown_elements
+    }
+
     #[cfg(feature = "_merge")]
     pub(crate) fn has_diverged_from(&self, other: &Group) -> bool {
-        let new_times = Times::new();
-        let mut self_purged = self.clone();
-        self_purged.times = new_times.clone();
-        self_purged.children = vec![];
+        self.uuid != other.uuid || !self.properties_eq(other)
+    }
 
-        let mut other_purged = other.clone();
-        other_purged.times = new_times.clone();
-        other_purged.children = vec![];
-        !self_purged.eq(&other_purged)
+    /// Copy this group without its children
+    #[cfg(feature = "_merge")]
+    pub(crate) fn clone_without_children(&self) -> Group {
+        Group {
+            uuid: self.uuid,
+            name: self.name.clone(),
+            notes: self.notes.clone(),
+            icon_id: self.icon_id,
+            custom_icon_uuid: self.custom_icon_uuid,
+            children: Vec::new(),
+            times: self.times.clone(),
+            custom_data: self.custom_data.clone(),
+            is_expanded: self.is_expanded,
+            default_autotype_sequence: self.default_autotype_sequence.clone(),
+            enable_autotype: self.enable_autotype.clone(),
+            enable_searching: self.enable_searching.clone(),
+            last_top_visible_entry: self.last_top_visible_entry,
+            unknown_elements: self.unknown_elements.clone(),
+            clock: self.clock.clone(),
+        }
     }
 }
 
 impl<'a> Group {
+    /// Recursively iterate over this group and all groups and entries below it, breadth-first.
+    ///
+    /// The nodes are visited level by level: this group first, then its children, then their
+    /// children, and so on. Within a level, the children of a group are visited in the order in
+    /// which they are stored, and before the children of the groups that come after it. Use
+    /// `iter_with_path` with `IterOrder::DepthFirst` to visit the tree depth-first instead.
+    ///
+    /// ```
+    /// use keepass::db::{Group, NodeRef};
+    ///
+    /// let mut root = Group::new("Root");
+    /// let mut email = Group::new("Email");
+    /// email.add_child(Group::new("Work"));
+    /// root.add_child(email);
+    /// root.add_child(Group::new("Banking"));
+    ///
+    /// let names: Vec<&str> = root
+    ///     .iter()
+    ///     .filter_map(|node| match node {
+    ///         NodeRef::Group(g) => Some(g.name.as_str()),
+    ///         NodeRef::Entry(_) => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(names, ["Root", "Email", "Banking", "Work"]);
+    /// ```
     pub fn iter(&'a self) -> NodeIter<'a> {
         self.into_iter()
     }
+
+    /// Recursively iterate over this group and all groups and entries below it, together with the
+    /// names of the groups that contain them, starting with the name of this group. The path of
+    /// this group itself is empty.
+    ///
+    /// In both orders, a group is visited before its children, and the children of a group are
+    /// visited in the order in which they are stored.
+    ///
+    /// ```
+    /// use keepass::db::{Entry, Group, IterOrder, NodeRef};
+    ///
+    /// let mut root = Group::new("Root");
+    /// let mut email = Group::new("Email");
+    /// email.add_child(Entry::new());
+    /// root.add_child(email);
+    ///
+    /// for (path, node) in root.iter_with_path(IterOrder::DepthFirst) {
+    ///     if let NodeRef::Entry(_) = node {
+    ///         assert_eq!(path, vec!["Root", "Email"]);
+    ///     }
+    /// }
+    /// ```
+    pub fn iter_with_path(&'a self, order: IterOrder) -> PathIter<'a> {
+        PathIter::new(self, order)
+    }
+
+    /// Recursively iterate over all entries in this group and its subgroups.
+    ///
+    /// All entries are returned, including the ones in groups with searching disabled. Use
+    /// `searchable_entries` to skip those.
+    pub fn iter_entries(&'a self) -> impl Iterator<Item = &'a Entry> {
+        self.iter().filter_map(|node| match node {
+            NodeRef::Entry(e) => Some(e),
+            NodeRef::Group(_) => None,
+        })
+    }
+
+    /// Recursively iterate over all subgroups of this group, not including the group itself
+    pub fn iter_groups(&'a self) -> impl Iterator<Item = &'a Group> {
+        self.iter().skip(1).filter_map(|node| match node {
+            NodeRef::Group(g) => Some(g),
+            NodeRef::Entry(_) => None,
+        })
+    }
+
+    /// Recursively iterate over mutable references to all entries in this group and its subgroups.
+    ///
+    /// Unlike `iter_entries`, the tree is traversed depth-first. There is no mutable counterpart to
+    /// `iter_groups`, since a mutable reference to a group would alias the mutable references to its
+    /// subgroups that the iterator yields later on.
+    pub fn iter_entries_mut(&'a mut self) -> impl Iterator<Item = &'a mut Entry> {
+        EntryIterMut::new(self)
+    }
+
+    /// Call a closure with a mutable reference to this group and to every group and entry below it,
+    /// e.g. to change a field of all entries.
+    ///
+    /// The tree is traversed depth-first, and a group is visited before its children, starting with
+    /// this group itself. Since the children of a group are only visited after the closure returns,
+    /// the closure may add or remove children, and only the children that remain are visited.
+    pub fn visit_mut<F: FnMut(NodeRefMut<'_>)>(&mut self, mut visitor: F) {
+        self.visit_mut_with(&mut visitor)
+    }
+
+    fn visit_mut_with<F: FnMut(NodeRefMut<'_>)>(&mut self, visitor: &mut F) {
+        visitor(NodeRefMut::Group(self));
+        for child in self.children.iter_mut() {
+            match child {
+                Node::Group(g) => g.visit_mut_with(visitor),
+                Node::Entry(e) => visitor(NodeRefMut::Entry(e)),
+            }
+        }
+    }
+
+    /// Recursively remove all groups and entries below this group for which the predicate returns
+    /// `false`, e.g. to delete all expired entries.
+    ///
+    /// The tree is traversed depth-first, and the predicate is called for a group before its
+    /// children. If a group is removed, everything below it is removed as well, without calling the
+    /// predicate for it. Removed nodes are not recorded as deleted objects of a database.
+    pub fn retain_recursive<F: FnMut(NodeRef<'_>) -> bool>(&mut self, mut predicate: F) {
+        self.retain_recursive_with(&mut predicate)
+    }
+
+    fn retain_recursive_with<F: FnMut(NodeRef<'_>) -> bool>(&mut self, predicate: &mut F) {
+        self.children.retain_mut(|child| {
+            if !predicate(child.as_ref()) {
+                return false;
+            }
+            if let Node::Group(g) = child {
+                g.retain_recursive_with(predicate);
+            }
+            true
+        });
+    }
 }
 
 impl<'a> IntoIterator for &'a Group {
@@ -481,10 +1168,35 @@ impl<'a> IntoIterator for &'a Group {
     }
 }
 
+/// Compare names or titles for sorting them like KeePass
+fn compare_ignoring_case(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+/// Parse a KeePass tri-state boolean where "null" means that the value is inherited
+fn parse_inherited_bool(value: Option<&str>) -> Option<bool> {
+    match value?.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn format_inherited_bool(value: Option<bool>) -> String {
+    match value {
+        Some(true) => "true",
+        Some(false) => "false",
+        None => "null",
+    }
+    .to_string()
+}
+
 #[cfg(test)]
 mod group_tests {
+    use uuid::Uuid;
+
     use super::Group;
-    use crate::db::Entry;
+    use crate::db::{Entry, NodeRef, NodeRefMut, Times, Value};
     use crate::Database;
 
     #[test]
@@ -523,6 +1235,117 @@ mod group_tests {
         assert!(db.root.get_mut(&["General"]).is_some());
         assert!(db.root.get_mut(&["Invalid Group"]).is_none());
         assert!(db.root.get_mut(&[]).is_some());
+
+        match db.root.get_mut(&["General"]) {
+            Some(NodeRefMut::Group(g)) => g.notes = Some("Updated".to_string()),
+            _ => panic!("Expected a group"),
+        }
+        match db.root.get_mut(&["General", "Sample Entry #2"]) {
+            Some(NodeRefMut::Entry(e)) => {
+                e.fields
+                    .insert("UserName".to_string(), Value::Unprotected("new user".to_string()));
+            }
+            _ => panic!("Expected an entry"),
+        }
+
+        match db.root.get(&["General"]) {
+            Some(NodeRef::Group(g)) => assert_eq!(g.notes.as_deref(), Some("Updated")),
+            _ => panic!("Expected a group"),
+        }
+        match db.root.get(&["General", "Sample Entry #2"]) {
+            Some(NodeRef::Entry(e)) => assert_eq!(e.get_username(), Some("new user")),
+            _ => panic!("Expected an entry"),
+        }
+    }
+
+    #[test]
+    fn get_ambiguous_and_mixed_case() {
+        use crate::db::GetOptions;
+
+        let entry = |title: &str, username: &str| {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+            entry
+                .fields
+                .insert("UserName".to_string(), Value::Unprotected(username.to_string()));
+            entry
+        };
+
+        let mut root = Group::new("Root");
+        let mut work = Group::new("Work");
+        work.add_child(entry("Mail", "alice"));
+        work.add_child(entry("Mail", "bob"));
+        let second_bob = entry("mail", "bob2");
+        let second_bob_uuid = second_bob.uuid;
+        let work_uuid = work.uuid;
+        root.add_child(work);
+        let mut other_work = Group::new("work");
+        other_work.add_child(second_bob);
+        root.add_child(other_work);
+
+        let usernames = |nodes: Vec<NodeRef>| -> Vec<String> {
+            nodes
+                .into_iter()
+                .map(|node| match node {
+                    NodeRef::Entry(e) => e.get_username().unwrap().to_string(),
+                    NodeRef::Group(g) => g.name.clone(),
+                })
+                .collect()
+        };
+
+        // the first of the duplicate titles is returned by get, all of them by get_all
+        match root.get(&["Work", "Mail"]) {
+            Some(NodeRef::Entry(e)) => assert_eq!(e.get_username(), Some("alice")),
+            _ => panic!("Expected an entry"),
+        }
+        assert_eq!(usernames(root.get_all(&["Work", "Mail"])), vec!["alice", "bob"]);
+        assert!(root.get(&["WORK", "MAIL"]).is_none());
+        assert!(root.get_all(&["WORK", "MAIL"]).is_empty());
+        assert_eq!(usernames(root.get_all(&[])), vec!["Root"]);
+
+        let options = GetOptions {
+            case_insensitive: true,
+        };
+        match root.get_with_options(&["WORK", "MAIL"], options) {
+            Some(NodeRef::Entry(e)) => assert_eq!(e.get_username(), Some("alice")),
+            _ => panic!("Expected an entry"),
+        }
+        assert_eq!(
+            usernames(root.get_all_with_options(&["WORK", "MAIL"], options)),
+            vec!["alice", "bob", "bob2"]
+        );
+        assert_eq!(
+            usernames(root.get_all_with_options(&["Work"], options)),
+            vec!["Work", "work"]
+        );
+
+        // UUIDs can be mixed with names
+        let work_uuid = work_uuid.to_string();
+        let second_bob_uuid = second_bob_uuid.to_string();
+        assert_eq!(
+            usernames(root.get_all(&[&work_uuid, "Mail"])),
+            vec!["alice", "bob"]
+        );
+        match root.get(&["work", &second_bob_uuid]) {
+            Some(NodeRef::Entry(e)) => assert_eq!(e.get_username(), Some("bob2")),
+            _ => panic!("Expected an entry"),
+        }
+        assert!(root.get(&[&work_uuid, &second_bob_uuid]).is_none());
+
+        match root.get_mut_with_options(&["work", "MAIL"], options) {
+            Some(NodeRefMut::Entry(e)) => {
+                e.fields
+                    .insert("UserName".to_string(), Value::Unprotected("carol".to_string()));
+            }
+            _ => panic!("Expected an entry"),
+        }
+        assert_eq!(usernames(root.get_all(&["Work", "Mail"])), vec!["carol", "bob"]);
+        assert!(matches!(
+            root.get_mut(&[&work_uuid]),
+            Some(NodeRefMut::Group(g)) if g.name == "Work"
+        ));
     }
 
     #[test]
@@ -604,4 +1427,607 @@ mod group_tests {
         assert!(db.root.get_by_uuid_mut(&invalid_path).is_none());
         assert!(db.root.get_by_uuid_mut(&empty_path).is_some());
     }
+
+    #[test]
+    fn metadata_setters() {
+        let mut group = Group::new("Group");
+        let creation = *group.times.get_creation().unwrap();
+        group.times.set_last_modification(Times::epoch());
+
+        group.set_notes(Some("some notes"));
+        assert_eq!(group.get_notes(), Some("some notes"));
+        assert_ne!(group.times.get_last_modification(), Some(&Times::epoch()));
+
+        group.set_expanded(true);
+        assert!(group.is_expanded);
+
+        group.set_default_autotype_sequence(Some("{USERNAME}{TAB}{PASSWORD}{ENTER}"));
+        assert_eq!(
+            group.get_default_autotype_sequence(),
+            Some("{USERNAME}{TAB}{PASSWORD}{ENTER}")
+        );
+
+        let entry_uuid = Uuid::new_v4();
+        group.set_last_top_visible_entry(Some(entry_uuid));
+        assert_eq!(group.get_last_top_visible_entry(), Some(entry_uuid));
+
+        assert_eq!(group.get_enable_searching(), None);
+        group.set_enable_searching(Some(false));
+        assert_eq!(group.get_enable_searching(), Some(false));
+        assert_eq!(group.enable_searching.as_deref(), Some("false"));
+        group.set_enable_searching(None);
+        assert_eq!(group.get_enable_searching(), None);
+        assert_eq!(group.enable_searching.as_deref(), Some("null"));
+
+        group.enable_autotype = Some("True".to_string());
+        assert_eq!(group.get_enable_autotype(), Some(true));
+        group.set_enable_autotype(Some(false));
+        assert_eq!(group.get_enable_autotype(), Some(false));
+
+        assert_eq!(group.times.get_creation(), Some(&creation));
+    }
+
+    #[test]
+    fn touch() {
+        let mut group = Group::new("Group");
+        let creation = *group.times.get_creation().unwrap();
+        let modification = *group.times.get_last_modification().unwrap();
+
+        let t1 = chrono::NaiveDate::from_ymd_opt(2030, 1, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let t2 = t1 + chrono::Duration::hours(1);
+
+        group.touch_at(false, t1);
+        assert_eq!(group.times.get_last_access(), Some(&t1));
+        assert_eq!(group.times.get_last_modification(), Some(&modification));
+
+        group.touch_at(true, t2);
+        assert_eq!(group.times.get_last_access(), Some(&t2));
+        assert_eq!(group.times.get_last_modification(), Some(&t2));
+
+        assert_eq!(group.times.get_creation(), Some(&creation));
+    }
+
+    #[test]
+    fn adding_children_touches_parent() {
+        let mut group = Group::new("Group");
+        group.times.set_last_modification(Times::epoch());
+
+        group.add_child(Entry::new());
+        assert!(group.times.get_last_modification() > Some(&Times::epoch()));
+
+        // nodes added while reading a database keep the timestamps as stored
+        group.times.set_last_modification(Times::epoch());
+        group.push_child(Entry::new());
+        assert_eq!(group.times.get_last_modification(), Some(&Times::epoch()));
+    }
+
+    #[test]
+    fn searchable_entries() {
+        let mut root = Group::new("Root");
+        root.add_child(Entry::new());
+
+        let mut hidden = Group::new("Hidden");
+        hidden.set_enable_searching(Some(false));
+        hidden.add_child(Entry::new());
+
+        let mut inherits = Group::new("Inherits");
+        inherits.set_enable_searching(None);
+        inherits.add_child(Entry::new());
+        hidden.add_child(inherits);
+
+        let mut reenabled = Group::new("Re-enabled");
+        reenabled.set_enable_searching(Some(true));
+        let reenabled_entry = Entry::new();
+        let reenabled_uuid = reenabled_entry.uuid;
+        reenabled.add_child(reenabled_entry);
+        hidden.add_child(reenabled);
+
+        root.add_child(hidden);
+
+        let searchable = root.searchable_entries(false);
+        assert_eq!(searchable.len(), 2);
+        assert!(searchable.iter().any(|e| e.uuid == reenabled_uuid));
+
+        assert_eq!(root.searchable_entries(true).len(), 4);
+    }
+
+    #[test]
+    fn iter_entries_and_groups() {
+        let mut root = Group::new("Root");
+        root.add_child(Entry::new());
+        root.add_child(Entry::new());
+
+        let mut child = Group::new("Child");
+        child.add_child(Entry::new());
+
+        let mut grandchild = Group::new("Grandchild");
+        grandchild.add_child(Entry::new());
+        child.add_child(grandchild);
+
+        root.add_child(child);
+        root.add_child(Group::new("Empty"));
+
+        assert_eq!(root.iter_entries().count(), 4);
+        assert_eq!(root.iter_groups().count(), 3);
+        assert_eq!(
+            root.iter_groups().map(|g| g.name.as_str()).collect::<Vec<_>>(),
+            vec!["Child", "Empty", "Grandchild"]
+        );
+
+        for (i, entry) in root.iter_entries_mut().enumerate() {
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(format!("Entry {i}")));
+        }
+        assert_eq!(root.iter_entries_mut().count(), 4);
+        assert!(root.get(&["Child", "Grandchild", "Entry 3"]).is_some());
+    }
+
+    #[test]
+    fn iter_with_path() {
+        use crate::db::IterOrder;
+
+        let entry = |title: &str| {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+            entry
+        };
+
+        let mut a1 = Group::new("A1");
+        a1.add_child(entry("a11"));
+        let mut a = Group::new("A");
+        a.add_child(entry("a1"));
+        a.add_child(a1);
+        let mut b = Group::new("B");
+        b.add_child(entry("b1"));
+        let mut root = Group::new("Root");
+        root.add_child(entry("r1"));
+        root.add_child(a);
+        root.add_child(b);
+
+        let visit = |order: IterOrder| -> Vec<(String, String)> {
+            root.iter_with_path(order)
+                .map(|(path, node)| {
+                    let name = match node {
+                        NodeRef::Group(g) => g.name.clone(),
+                        NodeRef::Entry(e) => e.get_title().unwrap().to_string(),
+                    };
+                    (path.join("/"), name)
+                })
+                .collect()
+        };
+        let expected = |nodes: &[(&str, &str)]| -> Vec<(String, String)> {
+            nodes
+                .iter()
+                .map(|(path, name)| (path.to_string(), name.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            visit(IterOrder::DepthFirst),
+            expected(&[
+                ("", "Root"),
+                ("Root", "r1"),
+                ("Root", "A"),
+                ("Root/A", "a1"),
+                ("Root/A", "A1"),
+                ("Root/A/A1", "a11"),
+                ("Root", "B"),
+                ("Root/B", "b1"),
+            ])
+        );
+        assert_eq!(
+            visit(IterOrder::BreadthFirst),
+            expected(&[
+                ("", "Root"),
+                ("Root", "r1"),
+                ("Root", "A"),
+                ("Root", "B"),
+                ("Root/A", "a1"),
+                ("Root/A", "A1"),
+                ("Root/B", "b1"),
+                ("Root/A/A1", "a11"),
+            ])
+        );
+
+        // the path and name of every node lead to it
+        for order in [IterOrder::DepthFirst, IterOrder::BreadthFirst] {
+            for (path, node) in root.iter_with_path(order).skip(1) {
+                let name = match node {
+                    NodeRef::Group(g) => g.name.as_str(),
+                    NodeRef::Entry(e) => e.get_title().unwrap(),
+                };
+                let mut relative: Vec<&str> = path[1..].to_vec();
+                relative.push(name);
+                assert_eq!(root.get(&relative), Some(node));
+            }
+        }
+    }
+
+    /// A tree with an entry in the root group and an entry in each of three nested groups
+    fn nested_tree() -> Group {
+        let mut root = Group::new("Root");
+        root.add_child(Entry::new());
+
+        let mut child = Group::new("Child");
+        child.add_child(Entry::new());
+        let mut grandchild = Group::new("Grandchild");
+        grandchild.add_child(Entry::new());
+        let mut great_grandchild = Group::new("Great-grandchild");
+        great_grandchild.add_child(Entry::new());
+
+        grandchild.add_child(great_grandchild);
+        child.add_child(grandchild);
+        root.add_child(child);
+        root
+    }
+
+    #[test]
+    fn visit_mut() {
+        let mut root = nested_tree();
+
+        let mut visited = Vec::new();
+        root.visit_mut(|node| match node {
+            NodeRefMut::Group(g) => visited.push(g.name.clone()),
+            NodeRefMut::Entry(e) => {
+                let title = format!("Entry {}", visited.len());
+                e.fields
+                    .insert("Title".to_string(), Value::Unprotected(title.clone()));
+                visited.push(title);
+            }
+        });
+
+        assert_eq!(
+            visited,
+            vec![
+                "Root",
+                "Entry 1",
+                "Child",
+                "Entry 3",
+                "Grandchild",
+                "Entry 5",
+                "Great-grandchild",
+                "Entry 7"
+            ]
+        );
+        assert!(root
+            .get(&["Child", "Grandchild", "Great-grandchild", "Entry 7"])
+            .is_some());
+        assert!(root.iter_entries().all(|e| e.get_title().is_some()));
+    }
+
+    #[test]
+    fn retain_recursive() {
+        let now = Times::now();
+        let mut root = nested_tree();
+
+        let expire = |entry: &mut Entry| {
+            entry.times.expires = true;
+            entry.times.set_expiry(now - chrono::Duration::days(1));
+        };
+        expire(root.entries_mut()[0]);
+        root.visit_mut(|node| {
+            if let NodeRefMut::Group(g) = node {
+                if g.name == "Great-grandchild" {
+                    expire(g.entries_mut()[0]);
+                }
+            }
+        });
+
+        root.retain_recursive(|node| match node {
+            NodeRef::Entry(e) => !(e.times.expires && e.times.get_expiry().is_some_and(|t| *t < now)),
+            NodeRef::Group(_) => true,
+        });
+
+        assert_eq!(root.iter_entries().count(), 2);
+        assert_eq!(root.iter_groups().count(), 3);
+        assert!(root.entries().is_empty());
+        assert!(root
+            .get(&["Child", "Grandchild", "Great-grandchild"])
+            .is_some_and(|node| matches!(node, NodeRef::Group(g) if g.entries().is_empty())));
+
+        // removing a group removes everything below it
+        let mut seen = Vec::new();
+        root.retain_recursive(|node| match node {
+            NodeRef::Group(g) => {
+                seen.push(g.name.clone());
+                g.name != "Grandchild"
+            }
+            NodeRef::Entry(_) => true,
+        });
+        assert_eq!(seen, vec!["Child", "Grandchild"]);
+        assert_eq!(root.iter_groups().count(), 1);
+        assert_eq!(root.iter_entries().count(), 1);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn sort_children() {
+        use crate::DatabaseKey;
+
+        fn titles(group: &Group) -> Vec<&str> {
+            group.children.iter().map(|c| c.as_ref().title()).collect()
+        }
+
+        let mut db = Database::new(Default::default());
+        for title in ["b", "Group b", "A", "Group a", "c"] {
+            if let Some(name) = title.strip_prefix("Group ") {
+                db.root.add_child(Group::new(name));
+            } else {
+                let mut entry = Entry::new();
+                entry
+                    .fields
+                    .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+                db.root.add_child(entry);
+            }
+        }
+        let modified = db.root.times.get_last_modification().cloned();
+
+        db.root.sort_groups_by_name();
+        assert_eq!(titles(&db.root), ["a", "b", "b", "A", "c"]);
+        db.root.sort_entries_by_title();
+        assert_eq!(titles(&db.root), ["a", "b", "A", "b", "c"]);
+        db.root.move_child(4, 0);
+        assert_eq!(titles(&db.root), ["c", "a", "b", "A", "b"]);
+        db.root.sort_children_by(|a, b| b.title().cmp(a.title()));
+        assert_eq!(titles(&db.root), ["c", "b", "b", "a", "A"]);
+        assert_eq!(db.root.times.get_last_modification().cloned(), modified);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("test"))
+            .unwrap();
+        let db_loaded =
+            Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("test")).unwrap();
+        assert_eq!(db_loaded.root.children, db.root.children);
+    }
+
+    #[test]
+    fn sort_children_by_key() {
+        use super::SortKey;
+        use chrono::Duration;
+
+        fn titles(group: &Group) -> Vec<&str> {
+            group.children.iter().map(|c| c.as_ref().title()).collect()
+        }
+
+        let mut root = Group::new("Root");
+        for (title, age) in [("b", 1), ("Group b", 3), ("A", 2), ("Group a", 4), ("c", 2)] {
+            let created = Times::epoch() + Duration::days(10 - age);
+            if let Some(name) = title.strip_prefix("Group ") {
+                let mut group = Group::new(name);
+                for title in ["y", "x"] {
+                    let mut entry = Entry::new();
+                    entry.set("Title", title);
+                    group.add_child(entry);
+                }
+                group.times.set_creation(created);
+                group
+                    .times
+                    .set_last_modification(created + Duration::days(age * 2));
+                root.add_child(group);
+            } else {
+                let mut entry = Entry::new();
+                entry.set("Title", title);
+                entry.times.set_creation(created);
+                entry
+                    .times
+                    .set_last_modification(created + Duration::days(age * 2));
+                root.add_child(entry);
+            }
+        }
+        let modified = root.times.get_last_modification().cloned();
+
+        root.sort_children(SortKey::Title, false, false);
+        assert_eq!(titles(&root), ["A", "a", "b", "b", "c"]);
+        assert_eq!(titles(root.groups()[0]), ["y", "x"]);
+
+        root.sort_children(SortKey::Title, true, true);
+        assert_eq!(titles(&root), ["a", "b", "A", "b", "c"]);
+        assert_eq!(titles(root.groups()[0]), ["x", "y"]);
+
+        // ties keep their order
+        root.sort_children(SortKey::Created, false, false);
+        assert_eq!(titles(&root), ["a", "b", "A", "c", "b"]);
+        root.sort_children(SortKey::Modified, true, false);
+        assert_eq!(titles(&root), ["b", "a", "b", "A", "c"]);
+
+        root.sort_children(
+            SortKey::Custom(Box::new(|a, b| b.title().cmp(a.title()))),
+            false,
+            true,
+        );
+        assert_eq!(titles(&root), ["c", "b", "b", "a", "A"]);
+        assert_eq!(titles(root.groups()[0]), ["y", "x"]);
+        assert_eq!(root.times.get_last_modification().cloned(), modified);
+    }
+
+    #[test]
+    #[should_panic]
+    fn move_child_out_of_bounds() {
+        let mut group = Group::new("Group");
+        group.add_child(Entry::new());
+        group.move_child(0, 1);
+    }
+
+    #[test]
+    fn add_child_at() {
+        fn titles(group: &Group) -> Vec<&str> {
+            group.children.iter().map(|c| c.as_ref().title()).collect()
+        }
+
+        let mut group = Group::new("Group");
+        group.add_child(Group::new("b"));
+        group.add_child(Group::new("d"));
+
+        group.add_child_at(0, Group::new("a"));
+        group.add_child_at(2, Group::new("c"));
+        let last = Group::new("e");
+        let last_uuid = last.uuid;
+        group.try_add_child_at(4, last).unwrap();
+        assert_eq!(titles(&group), ["a", "b", "c", "d", "e"]);
+        assert_eq!(group.index_of_child(&last_uuid), Some(4));
+        assert_eq!(group.index_of_child(&group.uuid), None);
+
+        let error = group.try_add_child_at(6, Entry::new()).unwrap_err();
+        assert_eq!((error.index, error.len), (6, 5));
+        assert_eq!(group.children.len(), 5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_child_at_out_of_bounds() {
+        Group::new("Group").add_child_at(1, Entry::new());
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn settings_roundtrip() {
+        use crate::{
+            db::{GroupSettings, NodeRef},
+            DatabaseKey,
+        };
+
+        let settings = GroupSettings {
+            name: "Servers".to_string(),
+            notes: Some("Production only".to_string()),
+            icon_id: Some(27),
+            custom_icon_uuid: Some(Uuid::new_v4()),
+            enable_autotype: Some(false),
+            enable_searching: Some(true),
+            default_autotype_sequence: Some("{USERNAME}{ENTER}".to_string()),
+            is_expanded: true,
+        };
+        let group = Group::new_with_settings(settings.clone());
+        assert_eq!(group.settings(), settings);
+
+        let mut db = Database::new(Default::default());
+        db.root.add_child(group);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("test"))
+            .unwrap();
+        let db_loaded =
+            Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("test")).unwrap();
+
+        match db_loaded.root.get(&["Servers"]) {
+            Some(NodeRef::Group(g)) => assert_eq!(g.settings(), settings),
+            _ => panic!("Expected the new group"),
+        }
+
+        // inherited settings are kept as well
+        let settings = GroupSettings {
+            name: "Inheriting".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(Group::new_with_settings(settings.clone()).settings(), settings);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn enable_flags_roundtrip() {
+        use crate::{db::NodeRef, DatabaseKey};
+        use std::fs::File;
+
+        // written by KeePassXC, with all groups inheriting their settings
+        let mut db = Database::open(
+            &mut File::open("tests/resources/test_db_kdbx4_with_password_argon2.kdbx").unwrap(),
+            DatabaseKey::new().with_password("demopass"),
+        )
+        .unwrap();
+
+        assert_eq!(db.root.enable_searching.as_deref(), Some("null"));
+        assert_eq!(db.root.get_enable_searching(), None);
+        assert_eq!(db.root.get_enable_autotype(), None);
+
+        let mut child = Group::new("Not searchable");
+        child.set_enable_searching(Some(false));
+        child.set_enable_autotype(Some(true));
+        db.root.add_child(child);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("demopass"))
+            .unwrap();
+
+        let xml = Database::get_xml(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("demopass"),
+        )
+        .unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("<EnableSearching>null</EnableSearching>"));
+        assert!(xml.contains("<EnableSearching>false</EnableSearching>"));
+        assert!(xml.contains("<EnableAutoType>true</EnableAutoType>"));
+
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("demopass"),
+        )
+        .unwrap();
+
+        assert_eq!(db_loaded.root.get_enable_searching(), None);
+        match db_loaded.root.get(&["Not searchable"]) {
+            Some(NodeRef::Group(g)) => {
+                assert_eq!(g.get_enable_searching(), Some(false));
+                assert_eq!(g.get_enable_autotype(), Some(true));
+            }
+            _ => panic!("Expected the new group"),
+        }
+    }
+
+    #[test]
+    fn content_eq() {
+        let mut group = Group::new("Root");
+        let mut child = Group::new("Child");
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Sample".to_string()));
+        child.add_child(entry);
+        group.add_child(child);
+
+        let mut other = group.clone();
+        other.uuid = Uuid::new_v4();
+        other.times = Times::new();
+        if let Some(NodeRefMut::Group(child)) = other.get_mut(&["Child"]) {
+            child.uuid = Uuid::new_v4();
+        }
+        if let Some(NodeRefMut::Entry(entry)) = other.get_mut(&["Child", "Sample"]) {
+            entry.uuid = Uuid::new_v4();
+            entry.times.set_last_access(Times::now());
+        }
+        assert_ne!(group, other);
+        assert!(group.content_eq(&other));
+
+        // changes deep in the tree are detected
+        if let Some(NodeRefMut::Entry(entry)) = other.get_mut(&["Child", "Sample"]) {
+            entry
+                .fields
+                .insert("UserName".to_string(), Value::Unprotected("alice".to_string()));
+        }
+        assert!(!group.content_eq(&other));
+
+        let mut other = group.clone();
+        other.add_child(Group::new("Another child"));
+        assert!(!group.content_eq(&other));
+
+        // attachments are compared by content across databases
+        let mut db = Database::new(Default::default());
+        let mut other_db = Database::new(Default::default());
+        other_db.add_attachment(b"unrelated".to_vec());
+        let mut entry = Entry::new();
+        let mut other_entry = Entry::new();
+        entry.add_attachment(&mut db, "file.txt", b"hello".to_vec());
+        other_entry.add_attachment(&mut other_db, "file.txt", b"hello".to_vec());
+        let mut group = Group::new("Root");
+        let mut other = group.clone();
+        group.add_child(entry);
+        other.add_child(other_entry);
+        assert!(group.content_eq_with_attachments(&db, &other, &other_db));
+        assert!(!group.content_eq(&other));
+    }
 }
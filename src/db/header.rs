@@ -0,0 +1,204 @@
+use std::io::Read;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::{
+    config::{CompressionConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    crypt,
+    db::Database,
+    error::{DatabaseIntegrityError, DatabaseOpenError},
+    format::{kdbx3, kdbx4, DatabaseVersion},
+    variant_dictionary::VariantDictionary,
+};
+
+/// Settings and seeds from the unencrypted outer header of a KDBX database, see
+/// `Database::read_header`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderInfo {
+    /// Version of the database file
+    pub version: DatabaseVersion,
+
+    /// Cipher that the payload is encrypted with
+    pub outer_cipher_config: OuterCipherConfig,
+
+    /// Compression of the payload
+    pub compression_config: CompressionConfig,
+
+    /// Key derivation function, without its seed
+    pub kdf_config: KdfConfig,
+
+    /// Seed that is hashed with the transformed key into the master key
+    pub master_seed: Vec<u8>,
+
+    /// Initialization vector of the outer cipher
+    pub encryption_iv: Vec<u8>,
+
+    /// Seed of the key derivation function: the transform seed in KDBX3, and the seed or salt in the
+    /// KDF parameters in KDBX4
+    pub kdf_seed: Vec<u8>,
+
+    /// Cipher of the protected values. Only KDBX3 stores it in the outer header, KDBX4 stores it in
+    /// the encrypted inner header.
+    pub inner_cipher_config: Option<InnerCipherConfig>,
+
+    /// Key of the cipher of the protected values, in KDBX3
+    pub protected_stream_key: Option<Vec<u8>>,
+
+    /// The first bytes of the decrypted payload, to check that it was decrypted correctly, in KDBX3
+    pub stream_start_bytes: Option<Vec<u8>>,
+
+    /// Custom data of plugins, in KDBX4
+    pub public_custom_data: Option<VariantDictionary>,
+}
+
+/// Raw bytes of the outer header of a KDBX database, see `Database::read_header`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderBytes {
+    /// The outer header, from the start of the file up to and including its end field. In KDBX4, the
+    /// hash and the HMAC of the header are computed over these bytes.
+    pub header: Vec<u8>,
+
+    /// The SHA-256 hash of the header that follows it in KDBX4
+    pub sha256: Option<Vec<u8>>,
+
+    /// The HMAC-SHA-256 of the header that follows the hash in KDBX4
+    pub hmac: Option<Vec<u8>>,
+}
+
+impl Database {
+    /// Read the outer header of a KDBX database without decrypting it, for applications that
+    /// decrypt or inspect the payload themselves. `Database::get_version` only reads the version.
+    ///
+    /// The reader is expected at the start of the file. Afterwards, it is positioned at the start of
+    /// the encrypted payload:
+    ///
+    /// * For KDBX3, right after the end field of the header, at the encrypted payload that starts
+    ///   with the stream start bytes once it is decrypted.
+    /// * For KDBX4, after the SHA-256 hash and the HMAC that follow the header, at the first block of
+    ///   the HMAC block stream. The hash is verified, the HMAC needs the key and is not.
+    ///
+    /// If an error is returned, the position of the reader is unspecified. KDB databases are not
+    /// supported.
+    pub fn read_header(source: &mut dyn Read) -> Result<(HeaderInfo, HeaderBytes), DatabaseOpenError> {
+        let mut header = vec![0; DatabaseVersion::get_version_header_size()];
+        source.read_exact(&mut header)?;
+
+        // fields consist of a type, a length with the given size and the data
+        let length_size = match DatabaseVersion::parse(&header)? {
+            DatabaseVersion::KDB3(_) => 2,
+            DatabaseVersion::KDB4(_) => 4,
+            _ => return Err(DatabaseOpenError::UnsupportedVersion),
+        };
+        loop {
+            let start = header.len();
+            header.resize(start + 1 + length_size, 0);
+            source.read_exact(&mut header[start..])?;
+
+            let entry_type = header[start];
+            let entry_length = LittleEndian::read_uint(&header[start + 1..], length_size);
+            let read = source.take(entry_length).read_to_end(&mut header)?;
+            if (read as u64) < entry_length {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+
+            // the end field has the same type in both versions
+            if entry_type == kdbx4::HEADER_END {
+                break;
+            }
+        }
+
+        if length_size == 2 {
+            let info = kdbx3::parse_header_info(&header)?;
+            return Ok((
+                info,
+                HeaderBytes {
+                    header,
+                    sha256: None,
+                    hmac: None,
+                },
+            ));
+        }
+
+        let info = kdbx4::parse_header_info(&header)?;
+        let mut sha256 = vec![0; 32];
+        source.read_exact(&mut sha256)?;
+        let mut hmac = vec![0; 32];
+        source.read_exact(&mut hmac)?;
+
+        if sha256 != crypt::calculate_sha256(&[&header])?.as_slice() {
+            return Err(DatabaseIntegrityError::HeaderHashMismatch.into());
+        }
+
+        Ok((
+            info,
+            HeaderBytes {
+                header,
+                sha256: Some(sha256),
+                hmac: Some(hmac),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod header_tests {
+    use std::{fs::File, io::Read};
+
+    use crate::{
+        config::{InnerCipherConfig, KdfConfig},
+        db::Database,
+        format::DatabaseVersion,
+    };
+
+    #[test]
+    fn kdbx3() {
+        let mut file = File::open("tests/resources/test_db_with_password.kdbx").unwrap();
+        let (info, bytes) = Database::read_header(&mut file).unwrap();
+
+        assert_eq!(info.version, DatabaseVersion::KDB3(1));
+        assert!(matches!(info.kdf_config, KdfConfig::Aes { .. }));
+        assert_eq!(info.inner_cipher_config, Some(InnerCipherConfig::Salsa20));
+        assert_eq!(info.stream_start_bytes.map(|b| b.len()), Some(32));
+        assert!(bytes.sha256.is_none());
+
+        // the header is followed by the payload
+        let mut payload = Vec::new();
+        file.read_to_end(&mut payload).unwrap();
+        let data = std::fs::read("tests/resources/test_db_with_password.kdbx").unwrap();
+        assert_eq!([bytes.header, payload].concat(), data);
+    }
+
+    #[test]
+    fn kdbx4() {
+        let mut file = File::open("tests/resources/test_db_kdbx4_with_password_argon2.kdbx").unwrap();
+        let (info, bytes) = Database::read_header(&mut file).unwrap();
+
+        assert!(matches!(info.version, DatabaseVersion::KDB4(_)));
+        assert!(matches!(info.kdf_config, KdfConfig::Argon2 { .. }));
+        assert_eq!(info.inner_cipher_config, None);
+
+        let mut payload = Vec::new();
+        file.read_to_end(&mut payload).unwrap();
+        let data = std::fs::read("tests/resources/test_db_kdbx4_with_password_argon2.kdbx").unwrap();
+        let header_end = bytes.header.len();
+        assert_eq!(&data[..header_end], bytes.header.as_slice());
+        assert_eq!(
+            &data[header_end..header_end + 32],
+            bytes.sha256.unwrap().as_slice()
+        );
+        assert_eq!(
+            &data[header_end + 32..header_end + 64],
+            bytes.hmac.unwrap().as_slice()
+        );
+        assert_eq!(&data[header_end + 64..], payload.as_slice());
+    }
+
+    #[test]
+    fn truncated() {
+        let data = std::fs::read("tests/resources/test_db_kdbx4_with_password_argon2.kdbx").unwrap();
+        assert!(Database::read_header(&mut &data[..100]).is_err());
+
+        let mut kdb = File::open("tests/resources/test_db_kdb_with_password.kdb").unwrap();
+        assert!(Database::read_header(&mut kdb).is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::db::{Database, Group, Node, NodeRef, NodeRefMut};
+
+/// Cached positions of the nodes of a database, see `Database::build_index`.
+///
+/// The position of a node consists of the indices in `children` of the groups between the root group
+/// and the node, followed by the index of the node itself, so that a node is found without comparing
+/// the UUIDs of its siblings. The position of the root group is empty.
+#[derive(Clone, Default)]
+pub(crate) struct UuidIndex(Option<HashMap<Uuid, Vec<usize>>>);
+
+impl UuidIndex {
+    fn build(root: &Group) -> Self {
+        let mut positions = HashMap::new();
+        positions.insert(root.uuid, Vec::new());
+        for (index, child) in root.children.iter().enumerate() {
+            insert_positions(&[], index, child, &mut positions);
+        }
+        UuidIndex(Some(positions))
+    }
+
+    pub(crate) fn is_built(&self) -> bool {
+        self.0.is_some()
+    }
+
+    fn position(&self, uuid: &Uuid) -> Option<&[usize]> {
+        self.0.as_ref()?.get(uuid).map(Vec::as_slice)
+    }
+
+    /// Add a node and everything below it, after it was added at `index` to the group at
+    /// `parent_position`
+    fn insert(&mut self, parent_position: &[usize], index: usize, node: &Node) {
+        if let Some(positions) = self.0.as_mut() {
+            insert_positions(parent_position, index, node, positions);
+        }
+    }
+
+    /// Remove a node and everything below it, after it was removed at `index` from `parent`, the
+    /// group at `parent_position`. The siblings that followed it, and everything below them, move up
+    /// by one.
+    fn remove(&mut self, parent: &Group, parent_position: &[usize], index: usize, node: &Node) {
+        let Some(positions) = self.0.as_mut() else {
+            return;
+        };
+
+        positions.remove(&node.uuid());
+        if let Node::Group(group) = node {
+            for descendant in group.iter() {
+                positions.remove(&descendant.uuid());
+            }
+        }
+
+        let depth = parent_position.len();
+        for (new_index, sibling) in parent.children.iter().enumerate().skip(index) {
+            let moved: Vec<Uuid> = match sibling {
+                Node::Group(g) => g.iter().map(|n| n.uuid()).collect(),
+                Node::Entry(e) => vec![e.uuid],
+            };
+            for uuid in moved {
+                // nodes with the same UUID elsewhere in the tree keep their position
+                if let Some(position) = positions.get_mut(&uuid) {
+                    if position.len() > depth
+                        && position[..depth] == *parent_position
+                        && position[depth] == new_index + 1
+                    {
+                        position[depth] = new_index;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn insert_positions(
+    parent_position: &[usize],
+    index: usize,
+    node: &Node,
+    positions: &mut HashMap<Uuid, Vec<usize>>,
+) {
+    let mut position = parent_position.to_vec();
+    position.push(index);
+
+    // like a search of the tree, the first of several nodes with the same UUID wins
+    positions.entry(node.uuid()).or_insert_with(|| position.clone());
+
+    if let Node::Group(group) = node {
+        for (index, child) in group.children.iter().enumerate() {
+            insert_positions(&position, index, child, positions);
+        }
+    }
+}
+
+/// Get the node at a position in the format of the index
+fn node_at<'a>(root: &'a Group, position: &[usize]) -> Option<NodeRef<'a>> {
+    let Some((last, groups)) = position.split_last() else {
+        return Some(NodeRef::Group(root));
+    };
+
+    let mut group = root;
+    for index in groups {
+        group = match group.children.get(*index)? {
+            Node::Group(g) => g,
+            Node::Entry(_) => return None,
+        };
+    }
+    group.children.get(*last).map(Node::as_ref)
+}
+
+/// Get the group at a position in the format of the index, for modification
+fn group_at_mut<'a>(root: &'a mut Group, position: &[usize]) -> Option<&'a mut Group> {
+    let mut group = root;
+    for index in position {
+        group = match group.children.get_mut(*index)? {
+            Node::Group(g) => g,
+            Node::Entry(_) => return None,
+        };
+    }
+    Some(group)
+}
+
+/// Search the tree for the position of a node below `group`, visiting the nodes in the same order
+/// as `Group::find_node_location`
+fn search(group: &Group, uuid: &Uuid) -> Option<Vec<usize>> {
+    for (index, child) in group.children.iter().enumerate() {
+        if child.uuid() == *uuid {
+            return Some(vec![index]);
+        }
+        if let Node::Group(g) = child {
+            if let Some(mut position) = search(g, uuid) {
+                position.insert(0, index);
+                return Some(position);
+            }
+        }
+    }
+    None
+}
+
+impl std::fmt::Debug for UuidIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("UuidIndex")
+    }
+}
+
+impl PartialEq for UuidIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for UuidIndex {}
+
+impl Database {
+    /// Build an index of the locations of all groups and entries, so that looking them up by UUID
+    /// does not search the whole tree. This speeds up bulk operations such as deleting or copying
+    /// many nodes by UUID.
+    ///
+    /// The index is used by `get_by_uuid`, `get_by_uuid_mut`, `delete_by_uuid`, `copy_entry`,
+    /// `copy_group`, `mark_used`, `export_group`, `import_group` and `new_entry_in_group`, which
+    /// also keep it up to date. `root_mut` and `with_root_mut` drop the index, since they allow
+    /// arbitrary changes to the tree, and merging rebuilds it. After changing the `children` of a
+    /// group through the `root` field directly, the index must be rebuilt with this method: an
+    /// outdated index never returns the wrong node, but nodes that were added or moved are found by
+    /// searching the tree again.
+    pub fn build_index(&mut self) {
+        self.index = UuidIndex::build(&self.root);
+    }
+
+    /// Drop the index built by `build_index`
+    pub fn drop_index(&mut self) {
+        self.index = UuidIndex::default();
+    }
+
+    /// Get whether the database has an index built by `build_index`
+    pub fn is_indexed(&self) -> bool {
+        self.index.is_built()
+    }
+
+    /// Get a group or entry by its UUID, including the root group
+    pub fn get_by_uuid(&self, uuid: &Uuid) -> Option<NodeRef<'_>> {
+        let position = self.position_of(uuid)?;
+        node_at(&self.root, &position)
+    }
+
+    /// Get a group or entry by its UUID, including the root group, for modification
+    pub fn get_by_uuid_mut(&mut self, uuid: &Uuid) -> Option<NodeRefMut<'_>> {
+        let position = self.position_of(uuid)?;
+        let Some((last, parent_position)) = position.split_last() else {
+            return Some(NodeRefMut::Group(&mut self.root));
+        };
+        group_at_mut(&mut self.root, parent_position)?
+            .children
+            .get_mut(*last)
+            .map(Node::as_mut)
+    }
+
+    /// Position of a node in the format of the index, taken from the index if it is up to date for
+    /// the node and searched for otherwise
+    fn position_of(&self, uuid: &Uuid) -> Option<Vec<usize>> {
+        if let Some(position) = self.index.position(uuid) {
+            if node_at(&self.root, position).is_some_and(|node| node.uuid() == *uuid) {
+                return Some(position.to_vec());
+            }
+        }
+
+        if self.root.uuid == *uuid {
+            return Some(Vec::new());
+        }
+        search(&self.root, uuid)
+    }
+
+    /// Add a node to the group with the given UUID, returning `false` if there is no such group
+    pub(crate) fn add_to_group(&mut self, group_uuid: &Uuid, node: impl Into<Node>) -> bool {
+        let Some(position) = self.position_of(group_uuid) else {
+            return false;
+        };
+        let Some(group) = group_at_mut(&mut self.root, &position) else {
+            return false;
+        };
+
+        let node = node.into();
+        self.index.insert(&position, group.children.len(), &node);
+        group.add_child(node);
+        true
+    }
+
+    /// Remove a node below the root group from the tree
+    pub(crate) fn remove_by_uuid(&mut self, uuid: &Uuid) -> Option<Node> {
+        self.remove_by_uuid_with_parent(uuid).map(|(node, _)| node)
+    }
+
+    /// Remove a node below the root group from the tree, together with the UUID of the group it
+    /// was in
+    pub(crate) fn remove_by_uuid_with_parent(&mut self, uuid: &Uuid) -> Option<(Node, Uuid)> {
+        let position = self.position_of(uuid)?;
+        let (index, parent_position) = position.split_last()?;
+        let parent = group_at_mut(&mut self.root, parent_position)?;
+
+        let node = parent.children.remove(*index);
+        self.index.remove(parent, parent_position, *index, &node);
+        Some((node, parent.uuid))
+    }
+}
+
+#[cfg(test)]
+mod index_tests {
+    use uuid::Uuid;
+
+    use crate::db::{Database, Entry, Group, NodeRef};
+
+    /// A database with a group containing a subgroup with an entry. Returns the UUIDs of the group,
+    /// the subgroup and the entry.
+    fn make_db() -> (Database, [Uuid; 3]) {
+        let mut db = Database::new(Default::default());
+
+        let entry = Entry::new();
+        let mut subgroup = Group::new("Subgroup");
+        let mut group = Group::new("Group");
+        let uuids = [group.uuid, subgroup.uuid, entry.uuid];
+
+        subgroup.add_child(entry);
+        group.add_child(subgroup);
+        db.root.add_child(group);
+
+        (db, uuids)
+    }
+
+    fn indexed(db: &Database, uuid: &Uuid) -> bool {
+        db.index.position(uuid).is_some()
+    }
+
+    #[test]
+    fn lookup() {
+        let (mut db, [group, subgroup, entry]) = make_db();
+        let root = db.root.uuid;
+
+        for with_index in [false, true] {
+            if with_index {
+                db.build_index();
+            }
+            assert_eq!(db.is_indexed(), with_index);
+
+            assert!(matches!(db.get_by_uuid(&root), Some(NodeRef::Group(g)) if g.uuid == root));
+            assert!(matches!(db.get_by_uuid(&group), Some(NodeRef::Group(g)) if g.name == "Group"));
+            assert!(matches!(db.get_by_uuid(&subgroup), Some(NodeRef::Group(g)) if g.name == "Subgroup"));
+            assert!(matches!(db.get_by_uuid(&entry), Some(NodeRef::Entry(e)) if e.uuid == entry));
+            assert!(db.get_by_uuid(&Uuid::new_v4()).is_none());
+        }
+
+        assert_eq!(db.index.position(&entry), Some(&[0, 0, 0][..]));
+        assert_eq!(db.index.position(&root), Some(&[][..]));
+    }
+
+    #[test]
+    fn updated_by_operations() {
+        let (mut db, [group, subgroup, entry]) = make_db();
+        let root = db.root.uuid;
+        db.root.add_child(Entry::new());
+        db.build_index();
+
+        let copy = db.copy_group(&subgroup, &root).unwrap();
+        assert!(indexed(&db, &copy));
+        let copied_entry = match db.get_by_uuid(&copy) {
+            Some(NodeRef::Group(g)) => g.entries()[0].uuid,
+            _ => panic!("Expected the copied group"),
+        };
+        assert_eq!(db.index.position(&copy), Some(&[2][..]));
+        assert_eq!(db.index.position(&copied_entry), Some(&[2, 0][..]));
+
+        let new_entry = db.new_entry_in_group(&group).unwrap().title("New").build();
+        assert_eq!(db.index.position(&new_entry), Some(&[0, 1][..]));
+
+        assert!(db.delete_by_uuid(&subgroup, false).is_some());
+        assert!(!indexed(&db, &subgroup));
+        assert!(!indexed(&db, &entry));
+        assert!(db.get_by_uuid(&entry).is_none());
+        assert!(db.delete_by_uuid(&subgroup, false).is_none());
+
+        // the following siblings move up, including the nodes below them
+        assert_eq!(db.index.position(&new_entry), Some(&[0, 0][..]));
+        assert!(db.delete_by_uuid(&group, false).is_some());
+        assert_eq!(db.index.position(&copy), Some(&[1][..]));
+        assert_eq!(db.index.position(&copied_entry), Some(&[1, 0][..]));
+        assert!(matches!(db.get_by_uuid(&copied_entry), Some(NodeRef::Entry(e)) if e.uuid == copied_entry));
+
+        // the root group cannot be deleted
+        assert!(db.delete_by_uuid(&root, false).is_none());
+
+        db.root_mut();
+        assert!(!db.is_indexed());
+    }
+
+    #[test]
+    fn outdated_index() {
+        let (mut db, [_, _, entry]) = make_db();
+        db.build_index();
+
+        // move the entry to the root group without updating the index
+        let moved = db.root.remove_node_by_uuid(&entry).unwrap();
+        db.root.add_child(moved);
+        let added = Entry::new();
+        let added_uuid = added.uuid;
+        db.root.add_child(added);
+
+        assert_eq!(db.index.position(&entry), Some(&[0, 0, 0][..]));
+        assert!(matches!(db.get_by_uuid(&entry), Some(NodeRef::Entry(e)) if e.uuid == entry));
+        assert!(matches!(db.get_by_uuid(&added_uuid), Some(NodeRef::Entry(_))));
+        assert!(db.delete_by_uuid(&entry, false).is_some());
+        assert!(db.get_by_uuid(&entry).is_none());
+
+        db.build_index();
+        assert_eq!(db.index.position(&added_uuid), Some(&[1][..]));
+    }
+}
@@ -0,0 +1,303 @@
+use chacha20::ChaCha20;
+use cipher::{
+    generic_array::{typenum::U32, GenericArray},
+    KeyIvInit, StreamCipher,
+};
+use secstr::SecStr;
+use uuid::Uuid;
+
+use crate::{
+    crypt::locked::LockedKey,
+    db::{Database, Entry, Group, Node, Value},
+    error::LockError,
+};
+
+const NONCE_SIZE: usize = 12;
+
+/// Key used to encrypt protected values while a database is locked, zeroed when dropped
+pub struct SessionKey {
+    uuid: Uuid,
+    key: LockedKey<U32>,
+}
+
+impl SessionKey {
+    fn generate() -> Result<Self, LockError> {
+        let mut key = GenericArray::<u8, U32>::default();
+        getrandom::fill(&mut key[..])?;
+        Ok(SessionKey {
+            uuid: Uuid::new_v4(),
+            key: LockedKey::new(key),
+        })
+    }
+
+    /// Identifier of the key, stored alongside every value that was locked with it
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    fn cipher(&self, nonce: &[u8; NONCE_SIZE]) -> ChaCha20 {
+        ChaCha20::new(&self.key, GenericArray::from_slice(&nonce[..]))
+    }
+}
+
+impl Clone for SessionKey {
+    fn clone(&self) -> Self {
+        SessionKey {
+            uuid: self.uuid,
+            key: LockedKey::new(*self.key),
+        }
+    }
+}
+
+impl std::fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionKey").field("uuid", &self.uuid).finish()
+    }
+}
+
+/// A protected value that has been encrypted with a session key by `Database::lock`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct LockedValue {
+    session_key_uuid: Uuid,
+    nonce: [u8; NONCE_SIZE],
+    ciphertext: Vec<u8>,
+}
+
+impl LockedValue {
+    fn encrypt(session_key: &SessionKey, nonce: [u8; NONCE_SIZE], plaintext: &[u8]) -> Self {
+        let mut ciphertext = Vec::from(plaintext);
+        session_key.cipher(&nonce).apply_keystream(&mut ciphertext);
+
+        LockedValue {
+            session_key_uuid: session_key.uuid,
+            nonce,
+            ciphertext,
+        }
+    }
+
+    fn decrypt(&self, session_key: &SessionKey) -> SecStr {
+        let mut plaintext = self.ciphertext.clone();
+        session_key.cipher(&self.nonce).apply_keystream(&mut plaintext);
+        SecStr::new(plaintext)
+    }
+
+    /// Identifier of the session key that this value was locked with
+    pub fn session_key_uuid(&self) -> Uuid {
+        self.session_key_uuid
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ciphertext.is_empty()
+    }
+}
+
+/// All values of an entry that may hold protected data
+fn entry_values_mut(entry: &mut Entry) -> impl Iterator<Item = &mut Value> {
+    entry.fields.values_mut().chain(
+        entry
+            .custom_data
+            .items
+            .values_mut()
+            .filter_map(|item| item.value.as_mut()),
+    )
+}
+
+/// Call `f` on every entry below `group`, including the entries in their history
+fn for_each_entry_mut<F>(group: &mut Group, f: &mut F) -> Result<(), LockError>
+where
+    F: FnMut(&mut Entry) -> Result<(), LockError>,
+{
+    for node in group.children.iter_mut() {
+        match node {
+            Node::Group(g) => for_each_entry_mut(g, f)?,
+            Node::Entry(e) => {
+                f(e)?;
+                if let Some(history) = e.history.as_mut() {
+                    for history_entry in history.entries.iter_mut() {
+                        f(history_entry)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Encrypt all protected entry values in memory with a new session key, which is returned.
+    /// Returns `None` without changing anything if the database is already locked.
+    pub fn lock(&mut self) -> Result<Option<SessionKey>, LockError> {
+        if self.is_locked() {
+            return Ok(None);
+        }
+
+        let session_key = SessionKey::generate()?;
+
+        // draw all nonces before changing any value, so that a failing random number generator
+        // leaves the database untouched
+        let mut protected_values = 0;
+        for_each_entry_mut(&mut self.root, &mut |entry| {
+            protected_values += entry_values_mut(entry)
+                .filter(|v| matches!(v, Value::Protected(_) | Value::Deferred(_)))
+                .count();
+            Ok(())
+        })?;
+        let mut nonces = vec![[0u8; NONCE_SIZE]; protected_values];
+        getrandom::fill(nonces.as_flattened_mut())?;
+
+        let mut nonces = nonces.into_iter();
+        for_each_entry_mut(&mut self.root, &mut |entry| {
+            for value in entry_values_mut(entry) {
+                if let Some(p) = value.protected() {
+                    let nonce = nonces.next().expect("a nonce for every protected value");
+                    *value = Value::Locked(LockedValue::encrypt(&session_key, nonce, p.unsecure()));
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(Some(session_key))
+    }
+
+    /// Decrypt all protected values that were encrypted by `Database::lock`
+    pub fn unlock(&mut self, session_key: &SessionKey) -> Result<(), LockError> {
+        // check all values before changing any of them so that a wrong key leaves the database untouched
+        for_each_entry_mut(&mut self.root, &mut |entry| {
+            for value in entry_values_mut(entry) {
+                if let Value::Locked(l) = value {
+                    if l.session_key_uuid != session_key.uuid {
+                        return Err(LockError::WrongSessionKey);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+        for_each_entry_mut(&mut self.root, &mut |entry| {
+            for value in entry_values_mut(entry) {
+                if let Value::Locked(l) = value {
+                    *value = Value::Protected(l.decrypt(session_key));
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Whether any protected values are currently encrypted by `Database::lock`
+    pub fn is_locked(&self) -> bool {
+        fn group_is_locked(group: &Group) -> bool {
+            group.children.iter().any(|node| match node {
+                Node::Group(g) => group_is_locked(g),
+                Node::Entry(e) => std::iter::once(e)
+                    .chain(e.history.iter().flat_map(|h| h.get_entries()))
+                    .any(|e| {
+                        e.fields
+                            .values()
+                            .chain(e.custom_data.items.values().filter_map(|i| i.value.as_ref()))
+                            .any(|v| matches!(v, Value::Locked(_)))
+                    }),
+            })
+        }
+
+        group_is_locked(&self.root)
+    }
+}
+
+#[cfg(test)]
+mod lock_tests {
+    use secstr::SecStr;
+
+    use crate::{
+        db::{Entry, Node, Value},
+        error::LockError,
+        Database,
+    };
+
+    fn make_db() -> Database {
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Demo".to_string()));
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected(SecStr::new("s3cr3t".as_bytes().to_vec())),
+        );
+        db.root.add_child(entry);
+        db
+    }
+
+    fn first_entry(db: &Database) -> &Entry {
+        match &db.root.children[0] {
+            Node::Entry(e) => e,
+            Node::Group(_) => panic!("Expected an entry"),
+        }
+    }
+
+    #[test]
+    fn lock_unlock() -> Result<(), LockError> {
+        let mut db = make_db();
+        let original = db.clone();
+
+        let session_key = db.lock()?.expect("database was not locked yet");
+        assert!(db.is_locked());
+
+        let entry = first_entry(&db);
+        assert!(
+            matches!(entry.fields["Password"], Value::Locked(ref l) if l.session_key_uuid() == session_key.uuid())
+        );
+        assert_eq!(entry.get_password(), None);
+        assert_eq!(entry.get_title(), Some("Demo"));
+
+        // locking again does not change anything
+        let locked = db.clone();
+        assert!(db.lock()?.is_none());
+        assert_eq!(db, locked);
+
+        db.unlock(&session_key)?;
+        assert!(!db.is_locked());
+        assert_eq!(first_entry(&db).get_password(), Some("s3cr3t"));
+        assert_eq!(db, original);
+
+        // unlocking an unlocked database is fine as well
+        db.unlock(&session_key)?;
+        assert_eq!(db, original);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unlock_with_wrong_key() -> Result<(), LockError> {
+        let mut db = make_db();
+        let _session_key = db.lock()?.unwrap();
+
+        let mut other_db = make_db();
+        let other_key = other_db.lock()?.unwrap();
+
+        let locked = db.clone();
+        assert!(matches!(db.unlock(&other_key), Err(LockError::WrongSessionKey)));
+        assert_eq!(db, locked);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn save_locked() -> Result<(), LockError> {
+        use crate::{error::DatabaseSaveError, DatabaseKey};
+
+        let mut db = make_db();
+        let session_key = db.lock()?.unwrap();
+
+        let mut buffer = Vec::new();
+        let res = db.save(&mut buffer, DatabaseKey::new().with_password("testing"));
+        assert!(matches!(res, Err(DatabaseSaveError::Locked)));
+
+        db.unlock(&session_key)?;
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+
+        Ok(())
+    }
+}
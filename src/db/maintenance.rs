@@ -0,0 +1,206 @@
+use std::convert::TryFrom;
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::db::{Database, History};
+
+/// What `Database::run_maintenance` removed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MaintenanceSummary {
+    /// Number of history items that were removed because an entry had too many or they were too old
+    pub history_items_removed: usize,
+
+    /// Number of deletion records that were removed because they were too old
+    pub deleted_objects_removed: usize,
+
+    /// Number of merge records that were removed because they were too old, see
+    /// `Database::merge_history`
+    pub merge_records_removed: usize,
+}
+
+impl Database {
+    /// Get the number of days that history items and deletion records are kept for, see
+    /// `run_maintenance`. `None` means that they are kept forever.
+    pub fn maintenance_history_days(&self) -> Option<usize> {
+        limit(self.meta.maintenance_history_days)
+    }
+
+    /// Set the number of days that history items and deletion records are kept for, or `None` to
+    /// keep them forever, and update the time the settings were last changed
+    pub fn set_maintenance_history_days(&mut self, days: Option<usize>) {
+        self.meta.maintenance_history_days = days.map(|days| isize::try_from(days).unwrap_or(isize::MAX));
+        if let Some(now) = self.clock.change_time() {
+            self.meta.settings_changed = Some(now);
+        }
+    }
+
+    /// Trim the history of the entries and the deletion records according to the settings in `Meta`:
+    ///
+    /// * History items older than `maintenance_history_days` are removed, as are the oldest items
+    ///   of entries with more than `history_max_items` items. The size limit of the history is not
+    ///   enforced.
+    /// * Deletion records older than `maintenance_history_days` are removed. Merging with a copy
+    ///   of the database that still has the deleted nodes restores them afterwards.
+    /// * Merge records older than `maintenance_history_days` are removed.
+    ///
+    /// Absent or negative settings mean that nothing is removed for them. Times are compared to
+    /// `Database::now`.
+    pub fn run_maintenance(&mut self) -> MaintenanceSummary {
+        let horizon = self
+            .maintenance_history_days()
+            .and_then(|days| i64::try_from(days).ok())
+            .and_then(Duration::try_days)
+            .and_then(|days| self.now().checked_sub_signed(days));
+        let max_items = limit(self.meta.history_max_items);
+
+        let mut summary = MaintenanceSummary::default();
+        for entry in self.root.iter_entries_mut() {
+            if let Some(history) = entry.history.as_mut() {
+                summary.history_items_removed += trim_history(history, horizon, max_items);
+            }
+        }
+
+        if let Some(horizon) = horizon {
+            let objects = &mut self.deleted_objects.objects;
+            let before = objects.len();
+            objects.retain(|object| object.deletion_time >= horizon);
+            summary.deleted_objects_removed = before - objects.len();
+
+            let records = &mut self.meta.merge_history;
+            let before = records.len();
+            records.retain(|record| record.merged_at >= horizon);
+            summary.merge_records_removed = before - records.len();
+        }
+
+        summary
+    }
+}
+
+/// Interpret a setting where negative values mean no limit
+fn limit(value: Option<isize>) -> Option<usize> {
+    value.and_then(|value| usize::try_from(value).ok())
+}
+
+/// Remove history items modified before `horizon`, then the oldest items beyond `max_items`, and
+/// return the number of removed items
+fn trim_history(history: &mut History, horizon: Option<NaiveDateTime>, max_items: Option<usize>) -> usize {
+    let before = history.entries.len();
+
+    if let Some(horizon) = horizon {
+        history
+            .entries
+            .retain(|item| item.times.get_last_modification().is_none_or(|t| *t >= horizon));
+    }
+
+    if let Some(max_items) = max_items {
+        while history.entries.len() > max_items {
+            let oldest = (0..history.entries.len())
+                .min_by_key(|&i| history.entries[i].times.get_last_modification())
+                .unwrap_or_default();
+            history.entries.remove(oldest);
+        }
+    }
+
+    before - history.entries.len()
+}
+
+#[cfg(test)]
+mod maintenance_tests {
+    use chrono::Duration;
+
+    use super::MaintenanceSummary;
+    use crate::db::{Database, DeletedObject, Entry, FixedTimeProvider, History, MergeRecord, Times};
+
+    fn database() -> Database {
+        let now = Times::epoch() + Duration::days(1000);
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(now)));
+
+        let mut entry = Entry::new();
+        let mut history = History::default();
+        for days_ago in [400, 100, 20, 10, 1] {
+            let mut item = entry.clone();
+            item.times.set_last_modification(now - Duration::days(days_ago));
+            history.add_entry(item);
+        }
+        entry.history = Some(history);
+        db.root.add_child(entry);
+
+        for days_ago in [500, 30, 5] {
+            db.deleted_objects.objects.push(DeletedObject {
+                uuid: uuid::Uuid::new_v4(),
+                deletion_time: now - Duration::days(days_ago),
+            });
+        }
+
+        for days_ago in [60, 2] {
+            db.meta.merge_history.push(MergeRecord {
+                merged_at: now - Duration::days(days_ago),
+                ..Default::default()
+            });
+        }
+
+        db
+    }
+
+    fn history_ages(db: &Database) -> Vec<i64> {
+        db.root.entries()[0]
+            .history
+            .as_ref()
+            .unwrap()
+            .get_entries()
+            .iter()
+            .map(|item| (db.now() - *item.times.get_last_modification().unwrap()).num_days())
+            .collect()
+    }
+
+    #[test]
+    fn keep_forever() {
+        let mut db = database();
+        assert_eq!(db.maintenance_history_days(), None);
+        assert_eq!(db.run_maintenance(), MaintenanceSummary::default());
+
+        db.meta.maintenance_history_days = Some(-1);
+        db.meta.history_max_items = Some(-1);
+        assert_eq!(db.maintenance_history_days(), None);
+        assert_eq!(db.run_maintenance(), MaintenanceSummary::default());
+        assert_eq!(history_ages(&db), [1, 10, 20, 100, 400]);
+    }
+
+    #[test]
+    fn trim_by_age() {
+        let mut db = database();
+        db.set_maintenance_history_days(Some(30));
+        assert_eq!(db.maintenance_history_days(), Some(30));
+        assert_eq!(db.meta.settings_changed, Some(db.now()));
+
+        assert_eq!(
+            db.run_maintenance(),
+            MaintenanceSummary {
+                history_items_removed: 2,
+                deleted_objects_removed: 1,
+                merge_records_removed: 1,
+            }
+        );
+        assert_eq!(history_ages(&db), [1, 10, 20]);
+        assert_eq!(db.deleted_objects.objects.len(), 2);
+        assert_eq!(db.merge_history().len(), 1);
+    }
+
+    #[test]
+    fn trim_by_count() {
+        let mut db = database();
+        db.set_maintenance_history_days(Some(365));
+        db.meta.history_max_items = Some(2);
+
+        assert_eq!(
+            db.run_maintenance(),
+            MaintenanceSummary {
+                history_items_removed: 3,
+                deleted_objects_removed: 1,
+                merge_records_removed: 0,
+            }
+        );
+        assert_eq!(history_ages(&db), [1, 10]);
+    }
+}
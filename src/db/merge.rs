@@ -1,4 +1,6 @@
+use crate::config::OpenOptions;
 use crate::db::NodeLocation;
+use crate::error::DatabaseOpenError;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -7,14 +9,77 @@ pub enum MergeEventType {
     EntryCreated,
     EntryDeleted,
     EntryLocationUpdated,
+    /// The entry was moved to different groups in both databases since they were last merged into
+    /// each other, according to their merge histories. It ends up in the group it was moved to most
+    /// recently, which is reported with `EntryLocationUpdated` if that is the group of the other
+    /// database.
+    EntryLocationConflict,
     EntryUpdated,
 
     GroupCreated,
     GroupDeleted,
     GroupLocationUpdated,
+    /// The group was moved to different groups in both databases, see `EntryLocationConflict`
+    GroupLocationConflict,
     GroupUpdated,
 }
 
+/// How `Database::merge_with_strategy` resolves conflicting entries.
+///
+/// An entry is conflicting if it exists in both databases with the same UUID, and differs between
+/// them in anything other than its timestamps, including its history. The databases have no common
+/// ancestor to compare to, so an entry that was edited in only one of them is conflicting as well.
+///
+/// The strategy only decides which version of a conflicting entry is kept. The histories of both
+/// versions are combined, and the location of entries and groups, the properties of groups and the
+/// deletion of nodes are always resolved by their timestamps.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep the version that was modified most recently
+    #[default]
+    Timestamp,
+
+    /// Keep the version of the database that is merged into, regardless of timestamps
+    PreferLocal,
+
+    /// Keep the version of the other database, regardless of timestamps
+    PreferRemote,
+}
+
+/// Options for `Database::merge_from_reader` and `Database::merge_from_path`
+///
+/// ```
+/// use keepass::{config::OpenOptions, db::{MergeOptions, MergeStrategy}};
+///
+/// let options = MergeOptions::new()
+///     .strategy(MergeStrategy::PreferRemote)
+///     .open_options(OpenOptions::new().strict(true));
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MergeOptions {
+    pub(crate) strategy: MergeStrategy,
+    pub(crate) open_options: OpenOptions,
+}
+
+impl MergeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How conflicting entries are resolved, see `MergeStrategy`
+    pub fn strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Options for opening the other database
+    pub fn open_options(mut self, open_options: OpenOptions) -> Self {
+        self.open_options = open_options;
+        self
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MergeEvent {
     /// The uuid of the node (entry or group) affected by
@@ -53,6 +118,20 @@ pub enum MergeError {
     DuplicateHistoryEntries(String, String),
 }
 
+/// Errors while merging a database that is read from a file into another database, see
+/// `Database::merge_from_reader`
+#[derive(Error)]
+#[derive(Debug)]
+pub enum MergeFromError {
+    /// The other database could not be opened, e.g. because the key is incorrect
+    #[error("Could not open the database to merge: {0}")]
+    Open(#[from] DatabaseOpenError),
+
+    /// The databases could not be merged
+    #[error(transparent)]
+    Merge(#[from] MergeError),
+}
+
 impl MergeLog {
     pub fn merge_with(&self, other: &MergeLog) -> MergeLog {
         let mut response = MergeLog::default();
@@ -74,6 +153,7 @@ mod merge_tests {
     use std::{thread, time};
     use uuid::Uuid;
 
+    use super::{MergeEventType, MergeStrategy};
     use crate::db::{Entry, Group, Node, Times};
     use crate::Database;
 
@@ -682,11 +762,18 @@ mod merge_tests {
         let mut new_entry = Entry::new();
         let new_entry_uuid = new_entry.uuid;
         new_entry.set_field_and_commit("Title", "new_entry");
+        let source_sub_group_uuid = source_sub_group.uuid;
         source_sub_group.add_child(new_entry);
 
+        // adding the entry also modified the group
         let merge_result = destination_db.merge(&source_db).unwrap();
         assert_eq!(merge_result.warnings.len(), 0);
-        assert_eq!(merge_result.events.len(), 1);
+        assert_eq!(merge_result.events.len(), 2);
+        assert!(merge_result
+            .events
+            .iter()
+            .any(|e| e.node_uuid == source_sub_group_uuid
+                && matches!(e.event_type, MergeEventType::GroupUpdated)));
 
         let entry_count_after = get_all_entries(&destination_db.root).len();
         let group_count_after = get_all_groups(&destination_db.root).len();
@@ -968,11 +1055,16 @@ mod merge_tests {
             .set_location_changed(new_location_changed_timestamp);
 
         let source_group_2 = get_group_mut(&mut source_db, &["group2"]);
+        let source_group_2_uuid = source_group_2.uuid;
         source_group_2.add_child(source_sub_group_1);
 
+        // adding the group also modified its new parent
         let merge_result = destination_db.merge(&source_db).unwrap();
         assert_eq!(merge_result.warnings.len(), 0);
-        assert_eq!(merge_result.events.len(), 1);
+        assert_eq!(merge_result.events.len(), 2);
+        assert!(merge_result.events.iter().any(
+            |e| e.node_uuid == source_group_2_uuid && matches!(e.event_type, MergeEventType::GroupUpdated)
+        ));
 
         let entry_count_after = get_all_entries(&destination_db.root).len();
         let group_count_after = get_all_groups(&destination_db.root).len();
@@ -1057,6 +1149,51 @@ mod merge_tests {
         assert_eq!(entry.get_title(), Some("entry1_updated"));
     }
 
+    #[test]
+    fn test_attachments_added_on_both_sides() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+
+        // both attachment pools start with different content at the same index
+        let mut local_entry = Entry::new();
+        local_entry.set("Title", "local");
+        local_entry.add_attachment(&mut destination_db, "local.txt", b"LOCAL SECRET".to_vec());
+        destination_db.root.add_child(local_entry);
+
+        let mut remote_entry = Entry::new();
+        remote_entry.set("Title", "remote");
+        remote_entry.add_attachment(&mut source_db, "remote.txt", b"remote".to_vec());
+        source_db.root.add_child(remote_entry);
+
+        let index = source_db.add_attachment(b"updated".to_vec());
+        let entry = &mut source_db.root.entries_mut()[0];
+        entry.attachments.insert("updated.txt".to_string(), index);
+        entry.set_field_and_commit("Title", "entry1_updated");
+
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 2);
+
+        let attachment = |title: &str, name: &str| {
+            let entry = get_all_entries(&destination_db.root)
+                .into_iter()
+                .find(|e| e.get_title() == Some(title))
+                .unwrap();
+            entry.get_attachment(&destination_db, name).map(<[u8]>::to_vec)
+        };
+        assert_eq!(attachment("local", "local.txt"), Some(b"LOCAL SECRET".to_vec()));
+        assert_eq!(attachment("remote", "remote.txt"), Some(b"remote".to_vec()));
+        assert_eq!(
+            attachment("entry1_updated", "updated.txt"),
+            Some(b"updated".to_vec())
+        );
+        assert_eq!(destination_db.header_attachments.len(), 3);
+
+        // the attachments are compared by content, so merging again does not change anything
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 0);
+        assert_eq!(destination_db.header_attachments.len(), 3);
+    }
+
     #[test]
     fn test_update_with_conflicts() {
         let mut destination_db = create_test_database();
@@ -1250,4 +1387,554 @@ mod merge_tests {
             Some(new_location_changed_timestamp).as_ref(),
         );
     }
+
+    #[test]
+    fn test_deleted_objects_union() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+
+        let only_in_destination = Uuid::new_v4();
+        let only_in_source = Uuid::new_v4();
+        let in_both = Uuid::new_v4();
+
+        let earlier = Times::now();
+        let later = earlier + chrono::Duration::seconds(10);
+
+        destination_db.deleted_objects.objects = vec![
+            crate::db::DeletedObject {
+                uuid: only_in_destination,
+                deletion_time: earlier,
+            },
+            crate::db::DeletedObject {
+                uuid: in_both,
+                deletion_time: earlier,
+            },
+        ];
+        source_db.deleted_objects.objects = vec![
+            crate::db::DeletedObject {
+                uuid: in_both,
+                deletion_time: later,
+            },
+            crate::db::DeletedObject {
+                uuid: only_in_source,
+                deletion_time: earlier,
+            },
+        ];
+
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 0);
+
+        let deleted_objects = &destination_db.deleted_objects;
+        assert_eq!(deleted_objects.objects.len(), 3);
+        assert!(deleted_objects.contains(only_in_destination));
+        assert!(deleted_objects.contains(only_in_source));
+        assert_eq!(deleted_objects.get(in_both).unwrap().deletion_time, later);
+
+        // merging again does not duplicate the deletions
+        destination_db.merge(&source_db).unwrap();
+        assert_eq!(destination_db.deleted_objects.objects.len(), 3);
+    }
+
+    #[test]
+    fn test_deleted_entry_not_resurrected_by_stale_replica() {
+        let base_db = create_test_database();
+
+        // the entry is created and deleted in one replica, while another replica never sees it
+        let mut replica_db = base_db.clone();
+        let mut other_replica_db = base_db.clone();
+
+        let mut entry = Entry::new();
+        let entry_uuid = entry.uuid;
+        entry.set_field_and_commit("Title", "resurrected");
+        replica_db.root.add_child(entry);
+
+        // a stale copy still has the entry
+        let stale_db = replica_db.clone();
+
+        thread::sleep(time::Duration::from_secs(1));
+        replica_db.delete_by_uuid(&entry_uuid, true).unwrap();
+
+        // the deletion is recorded even though the entry never existed in the other replica
+        let merge_result = other_replica_db.merge(&replica_db).unwrap();
+        assert_eq!(merge_result.events.len(), 0);
+        assert!(other_replica_db.deleted_objects.contains(entry_uuid));
+
+        // so that the stale copy cannot bring the entry back
+        let merge_result = other_replica_db.merge(&stale_db).unwrap();
+        assert_eq!(merge_result.events.len(), 0);
+        assert!(other_replica_db.root.find_node_location(entry_uuid).is_none());
+
+        let merge_result = replica_db.merge(&stale_db).unwrap();
+        assert_eq!(merge_result.events.len(), 0);
+        assert!(replica_db.root.find_node_location(entry_uuid).is_none());
+
+        // and the stale copy learns about the deletion
+        let mut stale_db = stale_db;
+        let merge_result = stale_db.merge(&other_replica_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert!(stale_db.root.find_node_location(entry_uuid).is_none());
+        assert!(stale_db.deleted_objects.contains(entry_uuid));
+    }
+
+    fn uuid(id: &str) -> Uuid {
+        Uuid::parse_str(id).unwrap()
+    }
+
+    #[test]
+    fn test_entry_moved_between_groups_on_replica() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+        let entry_count_before = get_all_entries(&destination_db.root).len();
+
+        let moved_at = Times::now() + chrono::Duration::seconds(1);
+        source_db
+            .relocate_node(
+                &uuid(ENTRY2_ID),
+                &vec![uuid(GROUP1_ID), uuid(SUBGROUP1_ID)],
+                &vec![uuid(GROUP2_ID), uuid(SUBGROUP2_ID)],
+                moved_at,
+            )
+            .unwrap();
+
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert!(matches!(
+            merge_result.events[0].event_type,
+            MergeEventType::EntryLocationUpdated
+        ));
+
+        // the entry was moved, not copied
+        assert_eq!(get_all_entries(&destination_db.root).len(), entry_count_before);
+        assert!(get_group(&destination_db, &["group1", "subgroup1"])
+            .entries()
+            .is_empty());
+        let moved_entry = get_entry(&destination_db, &["group2", "subgroup2", "entry2"]);
+        assert_eq!(moved_entry.times.get_location_changed(), Some(&moved_at));
+
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 0);
+    }
+
+    #[test]
+    fn test_entry_moved_on_both_replicas() {
+        let original_db = create_test_database();
+        let moved_at = Times::now() + chrono::Duration::seconds(1);
+
+        let mut first_db = original_db.clone();
+        first_db
+            .relocate_node(
+                &uuid(ENTRY2_ID),
+                &vec![uuid(GROUP1_ID), uuid(SUBGROUP1_ID)],
+                &vec![uuid(GROUP2_ID)],
+                moved_at,
+            )
+            .unwrap();
+
+        let mut second_db = original_db.clone();
+        second_db
+            .relocate_node(
+                &uuid(ENTRY2_ID),
+                &vec![uuid(GROUP1_ID), uuid(SUBGROUP1_ID)],
+                &vec![uuid(GROUP2_ID), uuid(SUBGROUP2_ID)],
+                moved_at + chrono::Duration::seconds(1),
+            )
+            .unwrap();
+
+        // the more recent move of the other database wins
+        let mut destination_db = first_db.clone();
+        let merge_result = destination_db.merge(&second_db).unwrap();
+        assert_eq!(merge_result.events.len(), 2);
+        assert!(matches!(
+            merge_result.events[0].event_type,
+            MergeEventType::EntryLocationConflict
+        ));
+        assert!(matches!(
+            merge_result.events[1].event_type,
+            MergeEventType::EntryLocationUpdated
+        ));
+        get_entry(&destination_db, &["group2", "subgroup2", "entry2"]);
+
+        // the more recent move of the destination database wins
+        let mut destination_db = second_db.clone();
+        let merge_result = destination_db.merge(&first_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert!(matches!(
+            merge_result.events[0].event_type,
+            MergeEventType::EntryLocationConflict
+        ));
+        get_entry(&destination_db, &["group2", "subgroup2", "entry2"]);
+        assert_eq!(
+            get_all_entries(&destination_db.root).len(),
+            get_all_entries(&original_db.root).len()
+        );
+    }
+
+    #[test]
+    fn test_entry_moved_again_after_merge() {
+        use crate::db::FixedTimeProvider;
+
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+        destination_db.meta.replica_id = Some(Uuid::new_v4());
+        source_db.meta.replica_id = Some(Uuid::new_v4());
+        let moved_at = Times::now() + chrono::Duration::seconds(1);
+
+        destination_db
+            .relocate_node(
+                &uuid(ENTRY2_ID),
+                &vec![uuid(GROUP1_ID), uuid(SUBGROUP1_ID)],
+                &vec![uuid(GROUP2_ID)],
+                moved_at,
+            )
+            .unwrap();
+        source_db.set_time_provider(Box::new(FixedTimeProvider(
+            moved_at + chrono::Duration::seconds(1),
+        )));
+        source_db.merge(&destination_db).unwrap();
+        get_entry(&source_db, &["group2", "entry2"]);
+
+        // the source database knew about the move of the destination database when moving the entry
+        source_db
+            .relocate_node(
+                &uuid(ENTRY2_ID),
+                &vec![uuid(GROUP2_ID)],
+                &vec![uuid(GROUP2_ID), uuid(SUBGROUP2_ID)],
+                moved_at + chrono::Duration::seconds(2),
+            )
+            .unwrap();
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert!(matches!(
+            merge_result.events[0].event_type,
+            MergeEventType::EntryLocationUpdated
+        ));
+        get_entry(&destination_db, &["group2", "subgroup2", "entry2"]);
+    }
+
+    #[test]
+    fn test_entry_moved_into_group_moved_in_destination() {
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+        let moved_at = Times::now() + chrono::Duration::seconds(1);
+
+        destination_db
+            .relocate_node(
+                &uuid(SUBGROUP2_ID),
+                &vec![uuid(GROUP2_ID)],
+                &vec![uuid(GROUP1_ID)],
+                moved_at,
+            )
+            .unwrap();
+        source_db
+            .relocate_node(
+                &uuid(ENTRY2_ID),
+                &vec![uuid(GROUP1_ID), uuid(SUBGROUP1_ID)],
+                &vec![uuid(GROUP2_ID), uuid(SUBGROUP2_ID)],
+                moved_at,
+            )
+            .unwrap();
+
+        // the entry follows the group to where it is in the destination
+        let merge_result = destination_db.merge(&source_db).unwrap();
+        assert_eq!(merge_result.events.len(), 1);
+        assert!(matches!(
+            merge_result.events[0].event_type,
+            MergeEventType::EntryLocationUpdated
+        ));
+        get_entry(&destination_db, &["group1", "subgroup2", "entry2"]);
+    }
+
+    #[test]
+    fn test_merge_strategies() {
+        let mut older_db = create_test_database();
+        let mut newer_db = older_db.clone();
+
+        older_db.root.entries_mut()[0].set_field_and_commit("Title", "older");
+        newer_db.root.entries_mut()[0].set_field_and_commit("Title", "newer");
+
+        let mut older_entry = Entry::new();
+        older_entry.set_field_and_commit("Title", "added to older");
+        older_db.root.add_child(older_entry);
+        let mut newer_entry = Entry::new();
+        newer_entry.set_field_and_commit("Title", "added to newer");
+        newer_db.root.add_child(newer_entry);
+
+        newer_db.delete_by_uuid(&uuid(ENTRY2_ID), true).unwrap();
+
+        fn entry1(db: &Database) -> &Entry {
+            db.root
+                .iter_entries()
+                .find(|e| e.uuid == uuid(ENTRY1_ID))
+                .unwrap()
+        }
+        let title = |db: &Database| entry1(db).get_title().unwrap().to_string();
+        let check_rest = |db: &Database| {
+            assert!(db.root.get(&["added to older"]).is_some());
+            assert!(db.root.get(&["added to newer"]).is_some());
+            assert!(db.find_node_location(uuid(ENTRY2_ID)).is_none());
+        };
+
+        let mut merged_db = older_db.clone();
+        merged_db
+            .merge_with_strategy(&newer_db, MergeStrategy::Timestamp)
+            .unwrap();
+        assert_eq!(title(&merged_db), "newer");
+        check_rest(&merged_db);
+
+        // the older local version is kept even though the other version is newer
+        let mut merged_db = older_db.clone();
+        let merge_result = merged_db
+            .merge_with_strategy(&newer_db, MergeStrategy::PreferLocal)
+            .unwrap();
+        assert_eq!(title(&merged_db), "older");
+        check_rest(&merged_db);
+        // the newer version is still in the history
+        let history = entry1(&merged_db).history.as_ref().unwrap();
+        assert!(history
+            .get_entries()
+            .iter()
+            .any(|e| e.get_title() == Some("newer")));
+        assert!(merge_result
+            .events
+            .iter()
+            .any(|e| e.node_uuid == uuid(ENTRY1_ID) && matches!(e.event_type, MergeEventType::EntryUpdated)));
+
+        // the older remote version replaces the newer local version
+        let mut merged_db = newer_db.clone();
+        merged_db
+            .merge_with_strategy(&older_db, MergeStrategy::PreferRemote)
+            .unwrap();
+        assert_eq!(title(&merged_db), "older");
+        check_rest(&merged_db);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn merge_kdbx3_and_kdbx4() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::{
+            config::DatabaseVersion,
+            db::{NodeRef, Value},
+            DatabaseKey,
+        };
+
+        let key = || DatabaseKey::new().with_password("demopass");
+        let save_and_reopen = |db: &Database| -> Result<Database, Box<dyn std::error::Error>> {
+            let mut buffer = Vec::new();
+            db.save(&mut buffer, key())?;
+            Ok(Database::open(&mut buffer.as_slice(), key())?)
+        };
+        // KDBX3 keeps attachments in the metadata and KDBX4 in the inner header, both are moved into
+        // the attachment pool when opening
+        let add_entry = |db: &mut Database, title: &str| {
+            let mut entry = db.new_entry();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+            entry.add_attachment(db, "shared.txt", b"shared".to_vec());
+            entry.add_attachment(db, "own.txt", title.as_bytes().to_vec());
+            db.root.add_child(entry);
+        };
+
+        let mut kdbx3 = Database::open(
+            &mut std::fs::File::open("tests/resources/test_db_with_password.kdbx")?,
+            key(),
+        )?;
+        assert_eq!(kdbx3.format_version(), &DatabaseVersion::KDB3(1));
+
+        // the same database, upgraded on another device
+        let mut kdbx4 = kdbx3.clone();
+        kdbx4.set_format_version(DatabaseVersion::KDB4(0))?;
+        add_entry(&mut kdbx4, "Added to KDBX 4");
+        let kdbx4 = save_and_reopen(&kdbx4)?;
+        assert_eq!(kdbx4.format_version(), &DatabaseVersion::KDB4(0));
+
+        add_entry(&mut kdbx3, "Added to KDBX 3.1");
+        let kdbx3 = save_and_reopen(&kdbx3)?;
+
+        for (mut destination, source) in [(kdbx4.clone(), &kdbx3), (kdbx3.clone(), &kdbx4)] {
+            let version = destination.format_version().clone();
+            destination.merge(source)?;
+
+            let merged = save_and_reopen(&destination)?;
+            assert_eq!(merged.format_version(), &version);
+            for title in ["Added to KDBX 4", "Added to KDBX 3.1"] {
+                let Some(NodeRef::Entry(entry)) = merged.root.get(&[title]) else {
+                    panic!("missing entry {}", title);
+                };
+                assert_eq!(entry.get_attachment(&merged, "shared.txt"), Some(&b"shared"[..]));
+                assert_eq!(entry.get_attachment(&merged, "own.txt"), Some(title.as_bytes()));
+            }
+            assert_eq!(merged.header_attachments.len(), 3);
+            assert_eq!(
+                merged.root.iter_entries().count(),
+                kdbx3.root.iter_entries().count() + 1
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_history() {
+        use crate::db::FixedTimeProvider;
+        #[cfg(feature = "save_kdbx4")]
+        use crate::DatabaseKey;
+
+        let mut destination_db = create_test_database();
+        let mut source_db = destination_db.clone();
+        let first_merge = Times::epoch() + chrono::Duration::days(365);
+        destination_db.set_time_provider(Box::new(FixedTimeProvider(first_merge)));
+
+        let new_entry = Entry::new();
+        let new_entry_uuid = new_entry.uuid;
+        source_db.root.add_child(new_entry);
+        let modified_entry = &mut source_db.root.entries_mut()[0];
+        modified_entry.set_field_and_commit("Title", "entry1_updated");
+        modified_entry
+            .times
+            .set_last_modification(Times::now() + chrono::Duration::days(1));
+        destination_db.merge(&source_db).unwrap();
+
+        // merging again without changes is not recorded
+        destination_db.merge(&source_db).unwrap();
+        assert_eq!(destination_db.merge_history().len(), 1);
+
+        let second_merge = first_merge + chrono::Duration::days(1);
+        destination_db.set_time_provider(Box::new(FixedTimeProvider(second_merge)));
+        let entry2_uuid = Uuid::parse_str(ENTRY2_ID).unwrap();
+        source_db.delete_by_uuid(&entry2_uuid, true);
+        destination_db.merge(&source_db).unwrap();
+
+        let history = destination_db.merge_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].merged_at, first_merge);
+        assert_eq!(history[0].remote_db_uuid, Uuid::nil());
+        assert!(destination_db.meta.replica_id.is_some());
+        assert_eq!(history[0].added_entry_uuids, [new_entry_uuid]);
+        assert_eq!(
+            history[0].modified_entry_uuids,
+            [Uuid::parse_str(ENTRY1_ID).unwrap()]
+        );
+        assert!(history[0].deleted_entry_uuids.is_empty());
+        assert_eq!(history[1].merged_at, second_merge);
+        assert_eq!(history[1].deleted_entry_uuids, [entry2_uuid]);
+        assert!(history[1].added_entry_uuids.is_empty());
+
+        #[cfg(feature = "save_kdbx4")]
+        {
+            let mut buffer = Vec::new();
+            let key = || DatabaseKey::new().with_password("test");
+            destination_db.save(&mut buffer, key()).unwrap();
+            let reopened = Database::open(&mut buffer.as_slice(), key()).unwrap();
+            assert_eq!(reopened.merge_history(), destination_db.merge_history());
+            assert_eq!(reopened.meta.replica_id, destination_db.meta.replica_id);
+            assert!(reopened.meta.custom_data.is_empty());
+        }
+
+        // only the most recent records are kept
+        let old_records = vec![destination_db.merge_history()[0].clone(); 200];
+        destination_db.meta.merge_history = old_records;
+        let third_merge = second_merge + chrono::Duration::days(1);
+        destination_db.set_time_provider(Box::new(FixedTimeProvider(third_merge)));
+        source_db.root.add_child(Entry::new());
+        destination_db.merge(&source_db).unwrap();
+
+        let history = destination_db.merge_history();
+        assert_eq!(history.len(), 100);
+        assert_eq!(history[99].merged_at, third_merge);
+
+        // a copy takes a replica ID of its own when it is merged with the database it was copied from
+        let mut copy_db = destination_db.clone();
+        copy_db.root.add_child(Entry::new());
+        destination_db.merge(&copy_db).unwrap();
+        assert_ne!(destination_db.meta.replica_id, copy_db.meta.replica_id);
+        assert_eq!(
+            destination_db.merge_history().last().unwrap().remote_db_uuid,
+            copy_db.meta.replica_id.unwrap()
+        );
+    }
+
+    #[test]
+    fn merge_meta() {
+        use crate::db::FixedTimeProvider;
+
+        let earlier = Times::epoch() + chrono::Duration::days(365);
+        let later = earlier + chrono::Duration::days(1);
+
+        let mut destination = Database::new(Default::default());
+        let mut source = destination.clone();
+
+        destination.set_time_provider(Box::new(FixedTimeProvider(earlier)));
+        source.set_time_provider(Box::new(FixedTimeProvider(later)));
+
+        let templates = Uuid::new_v4();
+        destination.set_name("Local");
+        destination.set_entry_templates_group(Some(Uuid::new_v4()));
+        source.set_entry_templates_group(Some(templates));
+        destination.set_time_provider(Box::new(FixedTimeProvider(later + chrono::Duration::days(1))));
+        destination.set_description(Some("Local description"));
+        source.set_description(Some("Remote description"));
+
+        destination.merge(&source).unwrap();
+        assert_eq!(destination.name(), "Local");
+        assert_eq!(destination.description(), Some("Local description"));
+        assert_eq!(destination.entry_templates_group(), Some(templates));
+        assert_eq!(destination.meta.entry_templates_group_changed, Some(later));
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn merge_from_path() {
+        use super::MergeOptions;
+        use crate::{
+            config::{DatabaseConfig, DatabaseSettings, KdfConfig},
+            db::MergeFromError,
+            error::{DatabaseKeyError, DatabaseOpenError},
+            DatabaseKey,
+        };
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let mut other = db.clone();
+        let mut entry = Entry::new();
+        entry.set("Title", "From a colleague");
+        other.root.add_child(entry);
+
+        // the copy is saved with a key of its own
+        let path = std::env::temp_dir().join(format!("keepass-merge-{}.kdbx", Uuid::new_v4()));
+        let key = || DatabaseKey::new().with_password("colleague");
+        other
+            .save(&mut std::fs::File::create(&path).unwrap(), key())
+            .unwrap();
+
+        let res = db.merge_from_path(
+            &path,
+            DatabaseKey::new().with_password("wrong"),
+            MergeOptions::new(),
+        );
+        assert!(matches!(
+            res,
+            Err(MergeFromError::Open(DatabaseOpenError::Key(
+                DatabaseKeyError::IncorrectKey
+            )))
+        ));
+        assert!(db.root.entries().is_empty());
+
+        let res = db.merge_from_path(path.with_extension("missing"), key(), MergeOptions::new());
+        assert!(matches!(res, Err(MergeFromError::Open(DatabaseOpenError::Io(_)))));
+
+        let log = db.merge_from_path(
+            &path,
+            key(),
+            MergeOptions::new().strategy(MergeStrategy::PreferRemote),
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(log.unwrap().events.len(), 1);
+        assert_eq!(db.root.entries()[0].get_title(), Some("From a colleague"));
+    }
 }
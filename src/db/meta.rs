@@ -1,7 +1,10 @@
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
-use crate::db::{Color, CustomData};
+use crate::{
+    db::{Color, CustomData, DeferredBinary, UnknownElement},
+    error::DecompressionError,
+};
 
 /// Database metadata
 #[derive(Debug, Default, Eq, PartialEq, Clone)]
@@ -28,8 +31,8 @@ pub struct Meta {
     /// time the default username was last changed
     pub default_username_changed: Option<NaiveDateTime>,
 
-    /// number of days of maintenance history to keep
-    pub maintenance_history_days: Option<usize>,
+    /// number of days of maintenance history to keep, where a negative value means forever
+    pub maintenance_history_days: Option<isize>,
 
     /// color code for the database
     pub color: Option<Color>,
@@ -68,11 +71,11 @@ pub struct Meta {
     /// UUID of the last top-visible group
     pub last_top_visible_group: Option<Uuid>,
 
-    /// Maximum number of items of history to keep
-    pub history_max_items: Option<usize>,
+    /// Maximum number of items of history to keep, where a negative value means no limit
+    pub history_max_items: Option<isize>,
 
-    /// Maximum size of the history to keep
-    pub history_max_size: Option<usize>,
+    /// Maximum size of the history to keep in bytes, where a negative value means no limit
+    pub history_max_size: Option<isize>,
 
     /// Last time the settings were changed
     pub settings_changed: Option<NaiveDateTime>,
@@ -82,6 +85,146 @@ pub struct Meta {
 
     /// Additional custom data fields
     pub custom_data: CustomData,
+
+    /// XML elements of the metadata that are not known to this crate, e.g. settings of plugins
+    pub unknown_elements: Vec<UnknownElement>,
+
+    /// Records of the merges into this database, see `Database::merge_history`. They are stored in
+    /// a custom data item of the metadata.
+    pub merge_history: Vec<MergeRecord>,
+
+    /// Random identifier of this copy of the database, which `Database::merge` sets to tell copies
+    /// apart in the merge history. It is stored in a custom data item of the metadata.
+    pub replica_id: Option<Uuid>,
+}
+
+/// Key of the custom data item of the metadata that `Meta::merge_history` is stored in
+pub(crate) const MERGE_HISTORY_KEY: &str = "KeePassRs.MergeHistory";
+
+/// Key of the custom data item of the metadata that `Meta::replica_id` is stored in
+pub(crate) const REPLICA_ID_KEY: &str = "KeePassRs.ReplicaId";
+
+/// Number of the most recent records that `Database::merge` keeps in `Meta::merge_history`
+#[cfg(feature = "_merge")]
+pub(crate) const MERGE_HISTORY_MAX_RECORDS: usize = 100;
+
+/// What a call to `Database::merge` changed, see `Database::merge_history`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct MergeRecord {
+    /// Time of the merge, according to the time provider of the database
+    pub merged_at: NaiveDateTime,
+
+    /// Replica ID of the other database, see `Meta::replica_id`, or the nil UUID if it did not
+    /// have one
+    pub remote_db_uuid: Uuid,
+
+    /// Entries that were added from the other database
+    pub added_entry_uuids: Vec<Uuid>,
+
+    /// Entries that were removed because they were deleted in the other database
+    pub deleted_entry_uuids: Vec<Uuid>,
+
+    /// Entries that were updated or moved to the version of the other database
+    pub modified_entry_uuids: Vec<Uuid>,
+}
+
+impl MergeRecord {
+    const TIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S";
+
+    /// Write merge records with one line per record, consisting of the time, the UUID of the other
+    /// database and comma-separated lists of added, deleted and modified entries, separated by `;`
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn format_history(records: &[MergeRecord]) -> String {
+        let uuids = |uuids: &[Uuid]| uuids.iter().map(Uuid::to_string).collect::<Vec<_>>().join(",");
+
+        records
+            .iter()
+            .map(|r| {
+                format!(
+                    "{};{};{};{};{}",
+                    r.merged_at.format(Self::TIME_FORMAT),
+                    r.remote_db_uuid,
+                    uuids(&r.added_entry_uuids),
+                    uuids(&r.deleted_entry_uuids),
+                    uuids(&r.modified_entry_uuids)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Read merge records written by `format_history`, returning `None` if any of them is malformed
+    pub(crate) fn parse_history(history: &str) -> Option<Vec<MergeRecord>> {
+        let uuids = |uuids: &str| -> Option<Vec<Uuid>> {
+            uuids
+                .split(',')
+                .filter(|uuid| !uuid.is_empty())
+                .map(|uuid| Uuid::parse_str(uuid).ok())
+                .collect()
+        };
+
+        history
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.trim().split(';').collect();
+                let [merged_at, remote_db_uuid, added, deleted, modified] = fields[..] else {
+                    return None;
+                };
+                Some(MergeRecord {
+                    merged_at: NaiveDateTime::parse_from_str(merged_at, Self::TIME_FORMAT).ok()?,
+                    remote_db_uuid: Uuid::parse_str(remote_db_uuid).ok()?,
+                    added_entry_uuids: uuids(added)?,
+                    deleted_entry_uuids: uuids(deleted)?,
+                    modified_entry_uuids: uuids(modified)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "_merge")]
+impl Meta {
+    /// Take the settings that have a time they were last changed from `other` where they were
+    /// changed more recently
+    pub(crate) fn merge_with(&mut self, other: &Meta) {
+        fn is_newer(ours: Option<NaiveDateTime>, theirs: Option<NaiveDateTime>) -> bool {
+            match (ours, theirs) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(ours), Some(theirs)) => theirs > ours,
+            }
+        }
+
+        if is_newer(self.database_name_changed, other.database_name_changed) {
+            self.database_name = other.database_name.clone();
+            self.database_name_changed = other.database_name_changed;
+        }
+        if is_newer(
+            self.database_description_changed,
+            other.database_description_changed,
+        ) {
+            self.database_description = other.database_description.clone();
+            self.database_description_changed = other.database_description_changed;
+        }
+        if is_newer(self.default_username_changed, other.default_username_changed) {
+            self.default_username = other.default_username.clone();
+            self.default_username_changed = other.default_username_changed;
+        }
+        if is_newer(self.recyclebin_changed, other.recyclebin_changed) {
+            self.recyclebin_enabled = other.recyclebin_enabled;
+            self.recyclebin_uuid = other.recyclebin_uuid;
+            self.recyclebin_changed = other.recyclebin_changed;
+        }
+        if is_newer(
+            self.entry_templates_group_changed,
+            other.entry_templates_group_changed,
+        ) {
+            self.entry_templates_group = other.entry_templates_group;
+            self.entry_templates_group_changed = other.entry_templates_group_changed;
+        }
+    }
 }
 
 /// Database memory protection settings
@@ -142,10 +285,85 @@ pub struct BinaryAttachments {
 }
 
 /// Binary attachment in the metadata of a XML database
-#[derive(Debug, Default, PartialEq, Eq, Clone)]
-#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[derive(Debug, Default, Clone)]
 pub struct BinaryAttachment {
     pub identifier: Option<String>,
     pub compressed: bool,
-    pub content: Vec<u8>,
+
+    /// Content of the attachment, which is empty for compressed attachments that were not
+    /// decompressed when opening the database, see `OpenOptions::defer_binaries`. It is only
+    /// accessed through `data` and `set_data`, so that setting it cannot be overridden by the
+    /// deferred content.
+    pub(crate) content: Vec<u8>,
+
+    /// Compressed content, if it has not been decompressed yet
+    pub(crate) deferred: Option<DeferredBinary>,
+}
+
+impl BinaryAttachment {
+    pub fn new(identifier: Option<String>, compressed: bool, content: Vec<u8>) -> Self {
+        BinaryAttachment {
+            identifier,
+            compressed,
+            content,
+            deferred: None,
+        }
+    }
+
+    /// Get the content of the attachment, decompressing it if this has not happened yet. An
+    /// attachment that cannot be decompressed reads as empty, use `try_data` to tell it apart from
+    /// an empty attachment.
+    pub fn data(&self) -> &[u8] {
+        self.try_data().unwrap_or_default()
+    }
+
+    /// Get the content of the attachment, or the error decompressing a deferred attachment
+    pub fn try_data(&self) -> Result<&[u8], DecompressionError> {
+        match &self.deferred {
+            Some(deferred) => deferred.data(),
+            None => Ok(&self.content),
+        }
+    }
+
+    /// Replace the content of the attachment, including content that has not been decompressed yet
+    pub fn set_data(&mut self, content: Vec<u8>) {
+        self.content = content;
+        self.deferred = None;
+    }
+
+    /// Whether the content of the attachment has not been decompressed yet
+    pub fn is_deferred(&self) -> bool {
+        self.deferred.is_some()
+    }
+
+    /// Take the content of the attachment, decompressing it if this has not happened yet
+    pub fn into_content(self) -> Result<Vec<u8>, DecompressionError> {
+        match self.deferred {
+            Some(deferred) => Ok(deferred.data()?.to_vec()),
+            None => Ok(self.content),
+        }
+    }
+}
+
+impl PartialEq for BinaryAttachment {
+    fn eq(&self, other: &Self) -> bool {
+        self.identifier == other.identifier
+            && self.compressed == other.compressed
+            && self.data() == other.data()
+    }
+}
+
+impl Eq for BinaryAttachment {}
+
+#[cfg(feature = "serialization")]
+impl serde::Serialize for BinaryAttachment {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("BinaryAttachment", 3)?;
+        state.serialize_field("identifier", &self.identifier)?;
+        state.serialize_field("compressed", &self.compressed)?;
+        state.serialize_field("content", self.data())?;
+        state.end()
+    }
 }
@@ -1,9 +1,24 @@
 //! Types for representing data contained in a KeePass database
 
+pub(crate) mod autotype;
+pub(crate) mod clock;
+pub(crate) mod deferred;
+pub(crate) mod delete;
 pub(crate) mod entry;
+pub(crate) mod entry_builder;
 pub(crate) mod group;
+pub(crate) mod header;
+pub(crate) mod index;
+pub(crate) mod lock;
+pub(crate) mod maintenance;
 pub(crate) mod meta;
 pub(crate) mod node;
+pub(crate) mod otp_uri;
+pub(crate) mod placeholder;
+pub(crate) mod recovery;
+pub(crate) mod url_match;
+pub(crate) mod usage;
+pub(crate) mod validate;
 
 #[cfg(feature = "_merge")]
 pub(crate) mod merge;
@@ -11,31 +26,71 @@ pub(crate) mod merge;
 #[cfg(feature = "totp")]
 pub(crate) mod otp;
 
+#[cfg(feature = "tokio")]
+pub(crate) mod async_io;
+
+#[cfg(feature = "serialization")]
+pub(crate) mod serialization;
+
+#[cfg(feature = "watch")]
+pub(crate) mod watch;
+
 #[cfg(feature = "_merge")]
-use std::collections::VecDeque;
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::VecDeque, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
 use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 pub use crate::db::{
+    clock::{FixedTimeProvider, SystemTimeProvider, TimeProvider},
+    deferred::DeferredValue,
+    delete::DeleteMode,
     entry::{AutoType, AutoTypeAssociation, Entry, History, Value},
-    group::Group,
-    meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-    node::{Node, NodeIter, NodeRef, NodeRefMut},
+    entry_builder::EntryBuilder,
+    group::{GetOptions, Group, GroupSettings, SortKey},
+    header::{HeaderBytes, HeaderInfo},
+    lock::{LockedValue, SessionKey},
+    maintenance::MaintenanceSummary,
+    meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, MergeRecord, Meta},
+    node::{IterOrder, Node, NodeIter, NodeRef, NodeRefMut, PathIter},
+    otp_uri::{OtpAlgorithm, OtpParameters, TotpOptions},
+    recovery::RecoveryWarning,
+    url_match::{PublicSuffixList, PublicSuffixRules, UrlMatchMode},
+    validate::{Severity, ValidationIssue},
 };
 
 #[cfg(feature = "_merge")]
 use crate::db::merge::{MergeError, MergeEvent, MergeEventType, MergeLog};
+#[cfg(feature = "_merge")]
+pub use crate::db::merge::{MergeFromError, MergeOptions, MergeStrategy};
+#[cfg(feature = "_merge")]
+use std::borrow::Cow;
 
 #[cfg(feature = "totp")]
 pub use crate::db::otp::{TOTPAlgorithm, TOTP};
 
+#[cfg(feature = "serialization")]
+pub use crate::db::serialization::{WithSecrets, REDACTED};
+
+#[cfg(feature = "watch")]
+pub use crate::db::watch::DatabaseWatcher;
+
 #[cfg(feature = "_merge")]
 use crate::db::group::NodeLocation;
 use crate::{
-    config::DatabaseConfig,
-    error::{DatabaseIntegrityError, DatabaseOpenError, ParseColorError},
+    config::{
+        BuiltinKdf, DatabaseConfig, DatabaseSettings, KdbxVersion, KdfConfig, KeyDerivationFunction,
+        OpenOptions, OuterCipherConfig,
+    },
+    error::{
+        CopyError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, DecompressionError,
+        ParseColorError, TreeError,
+    },
     format::{
         kdb::parse_kdb,
         kdbx3::{decrypt_kdbx3, parse_kdbx3},
@@ -52,10 +107,14 @@ pub struct Database {
     /// Configuration settings of the database such as encryption and compression algorithms
     pub config: DatabaseConfig,
 
-    /// Binary attachments in the inner header
+    /// Binary attachments in the inner header. This is the attachment pool that entries refer to,
+    /// also for KDBX3 databases that store attachments in the metadata.
     pub header_attachments: Vec<HeaderAttachment>,
 
-    /// Root node of the KeePass database
+    /// Root node of the KeePass database.
+    ///
+    /// Prefer `root`, `root_mut` and `with_root_mut` over accessing this field directly. The field is
+    /// kept public for compatibility, but may become private in a future release.
     pub root: Group,
 
     /// References to previously-deleted objects
@@ -63,8 +122,41 @@ pub struct Database {
 
     /// Metadata of the KeePass database
     pub meta: Meta,
+
+    /// Source of the current time for timestamps set by the database
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) clock: clock::Clock,
+
+    /// Public suffix list for matching URLs by their registered domain, see `UrlMatchMode::Domain`
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) public_suffixes: url_match::PublicSuffixes,
+
+    /// Whether the history of entries was not parsed, see `OpenOptions::skip_history`
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) history_skipped: bool,
+
+    /// Locations of the nodes by UUID, see `Database::build_index`
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) index: index::UuidIndex,
+
+    /// Configuration read from the file header, if the database was read from a file
+    #[cfg_attr(feature = "serialization", serde(skip))]
+    pub(crate) read_config: ReadConfig,
+}
+
+/// Configuration read from the file header, which does not affect the equality of databases, so that a
+/// saved and reopened database equals the original
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ReadConfig(pub(crate) Option<DatabaseConfig>);
+
+impl PartialEq for ReadConfig {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
+impl Eq for ReadConfig {}
+
 impl Database {
     /// Parse a database from a std::io::Read
     pub fn open(source: &mut dyn std::io::Read, key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
@@ -74,35 +166,213 @@ impl Database {
         Database::parse(data.as_ref(), key)
     }
 
+    /// Parse a database from a reader, asking for a new key each time the key turns out to be incorrect.
+    ///
+    /// `key_fn` is called with the attempt number, starting at 1, and returns the key to try next. The
+    /// reader is rewound to its initial position before every attempt. If `key_fn` returns `None` or
+    /// `max_attempts` keys have been rejected, `DatabaseOpenError::TooManyAttempts` is returned. Errors
+    /// other than an incorrect key are returned immediately.
+    pub fn open_with_retry<R, F>(
+        reader: &mut R,
+        mut key_fn: F,
+        max_attempts: usize,
+    ) -> Result<Database, DatabaseOpenError>
+    where
+        R: std::io::Read + std::io::Seek,
+        F: FnMut(usize) -> Option<DatabaseKey>,
+    {
+        let start = reader.stream_position()?;
+
+        for attempt in 1..=max_attempts {
+            let key = key_fn(attempt).ok_or(DatabaseOpenError::TooManyAttempts)?;
+
+            reader.seek(std::io::SeekFrom::Start(start))?;
+
+            match Database::open(reader, key) {
+                Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey)) => continue,
+                res => return res,
+            }
+        }
+
+        Err(DatabaseOpenError::TooManyAttempts)
+    }
+
+    /// Parse a database from a std::io::Read, with options for how the database is read
+    pub fn open_with_options(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        options: &OpenOptions,
+    ) -> Result<Database, DatabaseOpenError> {
+        let mut data = Vec::new();
+        source.read_to_end(&mut data)?;
+
+        Database::parse_with_options(data.as_ref(), key, options)
+    }
+
+    /// Parse a database from a std::io::Read as the given KDBX version, bypassing the detection of
+    /// the version from the header of the file.
+    ///
+    /// This is a last resort for databases with incorrect version bytes, as written by some
+    /// third-party implementations: `Database::get_version` reports the version in the header, and
+    /// opening a database with the wrong version fails or produces garbage. See
+    /// `OpenOptions::force_version`, which can be combined with the other options.
+    pub fn open_with_version(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        version: KdbxVersion,
+    ) -> Result<Database, DatabaseOpenError> {
+        Database::open_with_options(source, key, &OpenOptions::new().force_version(version))
+    }
+
+    /// Parse a database from a std::io::Read, decrypting protected values only when they are first
+    /// accessed, see `OpenOptions::defer_protected_values`
+    pub fn open_lazy(source: &mut dyn std::io::Read, key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
+        Database::open_with_options(source, key, &OpenOptions::new().defer_protected_values(true))
+    }
+
+    /// Parse a database from a reader, transforming the composite key with another implementation
+    /// of the key derivation function, see `KeyDerivationFunction`
+    pub fn open_with_kdf<R, K>(reader: &mut R, key: DatabaseKey, kdf: &K) -> Result<Database, DatabaseOpenError>
+    where
+        R: std::io::Read,
+        K: KeyDerivationFunction,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        Database::parse_with_kdf(data.as_ref(), key, &OpenOptions::default(), kdf)
+    }
+
     pub fn parse(data: &[u8], key: DatabaseKey) -> Result<Database, DatabaseOpenError> {
-        let database_version = DatabaseVersion::parse(data)?;
+        Database::parse_with_options(data, key, &OpenOptions::default())
+    }
+
+    /// Parse a database from bytes, with options for how the database is read
+    pub fn parse_with_options(
+        data: &[u8],
+        key: DatabaseKey,
+        options: &OpenOptions,
+    ) -> Result<Database, DatabaseOpenError> {
+        Database::parse_with_kdf(data, key, options, options.kdf())
+    }
 
-        match database_version {
-            DatabaseVersion::KDB(_) => parse_kdb(data, &key),
+    fn parse_with_kdf(
+        data: &[u8],
+        key: DatabaseKey,
+        options: &OpenOptions,
+        kdf: &dyn KeyDerivationFunction,
+    ) -> Result<Database, DatabaseOpenError> {
+        match crate::format::parse_kdbx_version(data, options.version)? {
+            DatabaseVersion::KDB(_) => parse_kdb(data, &key, kdf),
             DatabaseVersion::KDB2(_) => Err(DatabaseOpenError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => parse_kdbx3(data, &key),
-            DatabaseVersion::KDB4(_) => parse_kdbx4(data, &key),
+            DatabaseVersion::KDB3(_) => parse_kdbx3(data, &key, options, kdf),
+            DatabaseVersion::KDB4(_) => parse_kdbx4(data, &key, options, kdf),
         }
     }
 
     /// Save a database to a std::io::Write
+    ///
+    /// Timestamps are written exactly as they are in memory, saving never updates any of them. See
+    /// `set_update_times` to also keep changes from updating them.
     #[cfg(feature = "save_kdbx4")]
     pub fn save(
         &self,
         destination: &mut dyn std::io::Write,
         key: DatabaseKey,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        self.save_with_progress(destination, key, |_| {})
+    }
+
+    /// Save a database to a std::io::Write, reporting the progress to a callback.
+    ///
+    /// The phases happen in the order of `SavePhase`, and every phase reports a fraction of 0.0 when
+    /// it starts and 1.0 when it is done.
+    ///
+    /// KDBX3 databases are compressed and encrypted in memory, and only the writing phase reports
+    /// intermediate progress, which is exact in the number of bytes written. KDBX4 databases are
+    /// streamed into the writer: the payload is compressed, encrypted and written in one pass, so
+    /// that saving does not need to hold a copy of the payload in memory. The progress of that pass
+    /// is reported by the compression phase as the fraction of the inner header and its attachments
+    /// that has been processed, and the XML document after it, whose size is not known in advance,
+    /// completes the phase. The encryption and writing phases complete as the remaining data is
+    /// flushed.
+    ///
+    /// ```
+    /// use keepass::{Database, DatabaseKey};
+    ///
+    /// let db = Database::new(Default::default());
+    /// let mut buffer = Vec::new();
+    /// db.save_with_progress(&mut buffer, DatabaseKey::new().with_password("demopass"), |progress| {
+    ///     println!("{:?}: {:.0}%", progress.phase, progress.fraction_complete * 100.0);
+    /// })?;
+    /// # Ok::<(), keepass::error::DatabaseSaveError>(())
+    /// ```
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_with_progress<W: std::io::Write + ?Sized, F: FnMut(SaveProgress)>(
+        &self,
+        writer: &mut W,
+        key: DatabaseKey,
+        progress_fn: F,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        self.save_with_rng(writer, key, &mut crate::format::SaveRng::System, progress_fn)
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    fn save_with_rng<W: std::io::Write + ?Sized, F: FnMut(SaveProgress)>(
+        &self,
+        writer: &mut W,
+        key: DatabaseKey,
+        rng: &mut crate::format::SaveRng,
+        mut progress_fn: F,
     ) -> Result<(), crate::error::DatabaseSaveError> {
         use crate::error::DatabaseSaveError;
-        use crate::format::kdbx4::dump_kdbx4;
+        use crate::format::{kdbx3::dump_kdbx3, kdbx4::dump_kdbx4};
+
+        if self.is_locked() {
+            return Err(DatabaseSaveError::Locked);
+        }
+        if self.history_skipped {
+            return Err(DatabaseSaveError::HistorySkipped);
+        }
 
+        // `&mut W` implements Write even for unsized writers, and can be passed on as `dyn Write`
+        let mut writer = writer;
         match self.config.version {
             DatabaseVersion::KDB(_) => Err(DatabaseSaveError::UnsupportedVersion),
             DatabaseVersion::KDB2(_) => Err(DatabaseSaveError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => Err(DatabaseSaveError::UnsupportedVersion),
-            DatabaseVersion::KDB4(_) => dump_kdbx4(self, &key, destination),
+            DatabaseVersion::KDB3(_) => dump_kdbx3(self, &key, &mut writer, rng, &mut progress_fn),
+            DatabaseVersion::KDB4(_) => dump_kdbx4(self, &key, &mut writer, rng, &mut progress_fn),
         }
     }
 
+    /// Save a database like `save`, but generate the seeds, keys and IVs from `seed` instead of
+    /// the random number generator of the operating system, so that saving the same database with
+    /// the same key and seed always produces the same bytes, e.g. for snapshot tests.
+    ///
+    /// Anyone who knows the seed can reproduce the encryption parameters, which weakens the
+    /// protection of the file, so this must not be used for databases that hold real secrets.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn save_deterministic<W: std::io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        key: DatabaseKey,
+        seed: u64,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        self.save_with_rng(writer, key, &mut crate::format::SaveRng::seeded(seed), |_| {})
+    }
+
+    /// Whether the history of entries was not parsed when opening the database, see
+    /// `OpenOptions::skip_history`
+    pub fn is_history_skipped(&self) -> bool {
+        self.history_skipped
+    }
+
+    /// Allow saving a database that was opened without the history of its entries. Saving it over
+    /// the original file permanently removes the history from the file.
+    pub fn allow_lossy_save(&mut self) {
+        self.history_skipped = false;
+    }
+
     /// Helper function to load a database into its internal XML chunks
     pub fn get_xml(source: &mut dyn std::io::Read, key: DatabaseKey) -> Result<Vec<u8>, DatabaseOpenError> {
         let mut data = Vec::new();
@@ -113,13 +383,100 @@ impl Database {
         let data = match database_version {
             DatabaseVersion::KDB(_) => return Err(DatabaseOpenError::UnsupportedVersion),
             DatabaseVersion::KDB2(_) => return Err(DatabaseOpenError::UnsupportedVersion),
-            DatabaseVersion::KDB3(_) => decrypt_kdbx3(data.as_ref(), &key)?.2,
-            DatabaseVersion::KDB4(_) => decrypt_kdbx4(data.as_ref(), &key)?.3,
+            DatabaseVersion::KDB3(_) => {
+                decrypt_kdbx3(data.as_ref(), &key, &OpenOptions::default(), &BuiltinKdf)?.2
+            }
+            DatabaseVersion::KDB4(_) => {
+                decrypt_kdbx4(data.as_ref(), &key, &OpenOptions::default(), &BuiltinKdf)?.3
+            }
         };
 
         Ok(data)
     }
 
+    /// Find the entries with a URL that matches `url`, e.g. for autofill.
+    ///
+    /// Both the `URL` field and additional URL fields (`KP2A_URL`, `KP2A_URL_1`, ...) of the entries
+    /// are checked. URLs without a scheme are treated as `https://` URLs. If `url` cannot be parsed,
+    /// no entries are returned. Entries in groups with searching disabled are not returned, see
+    /// `Group::searchable_entries`.
+    ///
+    /// ```
+    /// use keepass::{db::{Entry, UrlMatchMode, Value}, Database};
+    ///
+    /// let mut db = Database::new(Default::default());
+    /// let mut entry = Entry::new();
+    /// entry.fields.insert("URL".to_string(), Value::Unprotected("https://example.com".to_string()));
+    /// db.root.add_child(entry);
+    ///
+    /// assert_eq!(db.find_by_url("https://login.example.com/path", UrlMatchMode::Domain).len(), 1);
+    /// assert_eq!(db.find_by_url("https://login.example.com/path", UrlMatchMode::Exact).len(), 0);
+    /// ```
+    pub fn find_by_url(&self, url: &str, mode: UrlMatchMode) -> Vec<&Entry> {
+        let query = match url_match::parse_url(url) {
+            Some(query) => query,
+            None => return Vec::new(),
+        };
+
+        self.root
+            .searchable_entries(false)
+            .into_iter()
+            .filter(|entry| url_match::entry_matches(entry, &query, mode, &self.public_suffixes))
+            .collect()
+    }
+
+    /// Find the entries to offer to a browser extension for a URL, following the rules of
+    /// KeePassXC.
+    ///
+    /// Like `find_by_url`, the `URL` field and additional URL fields are checked, and also the URL
+    /// override of the entries. Entries in the recycle bin, entries in groups with searching
+    /// disabled and entries that KeePassXC hides from the browser are not returned. Matching by
+    /// domain needs a public suffix list, see `UrlMatchMode::Domain`.
+    pub fn find_entries_for_url(&self, url: &str, mode: UrlMatchMode) -> Vec<&Entry> {
+        let Some(query) = url_match::parse_url(url) else {
+            return Vec::new();
+        };
+
+        // recycle bins of other clients do not always have searching disabled
+        let recycled: HashSet<Uuid> = match self.meta.recyclebin_uuid.and_then(|uuid| self.get_by_uuid(&uuid)) {
+            Some(NodeRef::Group(recycle_bin)) => recycle_bin.iter_entries().map(|e| e.uuid).collect(),
+            _ => HashSet::new(),
+        };
+
+        self.root
+            .searchable_entries(false)
+            .into_iter()
+            .filter(|entry| !recycled.contains(&entry.uuid))
+            .filter(|entry| url_match::entry_matches_for_browser(entry, &query, mode, &self.public_suffixes))
+            .collect()
+    }
+
+    /// Get all groups, including the root group, that are expired at `Database::now`, see
+    /// `Times::is_expired_at`
+    pub fn expired_groups(&self) -> Vec<&Group> {
+        let now = self.now();
+        self.root
+            .iter()
+            .filter_map(|node| match node {
+                NodeRef::Group(g) if g.times.is_expired_at(now) => Some(g),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get all groups and entries that are expired at `Database::now`, e.g. for a view of expired
+    /// items. The nodes below an expired group are only included if they are expired themselves.
+    pub fn expired_nodes(&self) -> Vec<NodeRef<'_>> {
+        let now = self.now();
+        self.root
+            .iter()
+            .filter(|node| match node {
+                NodeRef::Group(g) => g.times.is_expired_at(now),
+                NodeRef::Entry(e) => e.times.is_expired_at(now),
+            })
+            .collect()
+    }
+
     /// Get the version of a database without decrypting it
     pub fn get_version(source: &mut dyn std::io::Read) -> Result<DatabaseVersion, DatabaseIntegrityError> {
         let mut data = vec![0; DatabaseVersion::get_version_header_size()];
@@ -135,191 +492,1011 @@ impl Database {
             root: Group::new("Root"),
             deleted_objects: Default::default(),
             meta: Default::default(),
+            clock: Default::default(),
+            public_suffixes: Default::default(),
+            history_skipped: false,
+            index: Default::default(),
+            read_config: Default::default(),
         }
     }
 
-    /// Deletes a node (entry or group) from the database by its UUID.
-    ///
-    /// # Arguments
+    /// Create a new, empty database with the default settings and a name, see `set_name`
     ///
-    /// * `uuid` - The UUID of the node to delete.
-    /// * `log_deletion` - If true, the deletion will be logged in the `deleted_objects` list,
-    ///   which is important for merging databases.
-    ///
-    /// # Returns
+    /// ```
+    /// use keepass::Database;
     ///
-    /// `Some(Node)` containing the deleted node if it was found, otherwise `None`.
-    pub fn delete_by_uuid(&mut self, uuid: &Uuid, log_deletion: bool) -> Option<Node> {
-        if let Some(removed_node) = self.root.remove_node_by_uuid(uuid) {
-            if log_deletion {
-                let uuid_to_log = match &removed_node {
-                    Node::Group(g) => g.uuid,
-                    Node::Entry(e) => e.uuid,
-                };
-                self.deleted_objects.objects.push(DeletedObject {
-                    uuid: uuid_to_log,
-                    deletion_time: Times::now(),
-                });
-            }
-            Some(removed_node)
-        } else {
-            None
-        }
+    /// let db = Database::new_named("Passwords");
+    /// assert_eq!(db.name(), "Passwords");
+    /// ```
+    pub fn new_named(name: impl Into<String>) -> Database {
+        let mut db = Database::new(Default::default());
+        db.set_name(name);
+        db
     }
 
-    /// Merge this database with another version of this same database.
-    /// This function will use the UUIDs to detect that entries and groups are
-    /// the same.
-    #[cfg(feature = "_merge")]
-    pub fn merge(&mut self, other: &Database) -> Result<MergeLog, MergeError> {
-        let mut log = MergeLog::default();
-        log.append(&self.merge_group(vec![], &other.root, false)?);
-        log.append(&self.merge_deletions(other)?);
-        Ok(log)
+    /// Create a new, empty database with the default settings, a name and a description, see
+    /// `set_name` and `set_description`
+    pub fn new_with_description(name: impl Into<String>, description: impl Into<String>) -> Database {
+        let mut db = Database::new_named(name);
+        db.set_description(Some(&description.into()));
+        db
     }
 
-    #[cfg(feature = "_merge")]
-    fn merge_deletions(&mut self, other: &Database) -> Result<MergeLog, MergeError> {
-        // Utility function to search for a UUID in the VecDeque of deleted objects.
-        let is_in_deleted_queue = |uuid: Uuid, deleted_groups_queue: &VecDeque<DeletedObject>| -> bool {
-            for deleted_object in deleted_groups_queue {
-                // This group still has a child group, but it is not going to be deleted.
-                if deleted_object.uuid == uuid {
-                    return true;
-                }
-            }
-            false
-        };
+    /// Replace the source of the current time that is used for timestamps set by the database, such as
+    /// deletion records, and by its entries and groups, such as `Entry::touch`. By default, the
+    /// system clock is used.
+    ///
+    /// Entries and groups use the time provider of the database once they are added to one of its
+    /// groups with `Group::add_child` or created with `new_entry` and `new_group`.
+    pub fn set_time_provider(&mut self, provider: Box<dyn TimeProvider>) {
+        self.clock = clock::Clock::new(provider, self.clock.update_times);
+        self.root.set_clock(&self.clock);
+    }
 
-        let mut log = MergeLog::default();
+    /// Set whether changes update timestamps, which is the default: the last modification time of
+    /// entries and groups changed by setters such as `Entry::set`, the location changed time of
+    /// moved nodes and the times that settings in `Meta` were changed.
+    ///
+    /// Without these updates, the timestamps are only changed explicitly, e.g. by `Entry::touch` or
+    /// `Entry::update_history`, so that a database can be modified and saved while keeping every
+    /// timestamp that the caller did not set. Saving never updates timestamps either way.
+    pub fn set_update_times(&mut self, update_times: bool) {
+        self.clock.update_times = update_times;
+        self.root.set_clock(&self.clock);
+    }
 
-        let mut new_deleted_objects = self.deleted_objects.clone();
+    /// Set the public suffix list that determines the registered domains of URLs for
+    /// `UrlMatchMode::Domain`, e.g. a `PublicSuffixRules` parsed from the list at
+    /// <https://publicsuffix.org/list/>. Without a list, the last two labels of a host name are taken
+    /// as its registered domain, which is wrong for public suffixes such as `co.uk`.
+    pub fn set_public_suffix_list(&mut self, list: Box<dyn PublicSuffixList>) {
+        self.public_suffixes = url_match::PublicSuffixes::new(list);
+    }
 
-        // We start by deleting the entries, since we will only remove groups if they are empty.
-        for deleted_object in &other.deleted_objects.objects {
-            if new_deleted_objects.contains(deleted_object.uuid) {
-                continue;
-            }
-            let entry_location = match self.find_node_location(deleted_object.uuid) {
-                Some(l) => l,
-                None => continue,
-            };
+    /// Get the current time according to the time provider of the database
+    pub fn now(&self) -> NaiveDateTime {
+        self.clock.now()
+    }
 
-            let parent_group = match self.root.find_group_mut(&entry_location) {
-                Some(g) => g,
-                None => return Err(MergeError::FindGroupError(entry_location)),
-            };
+    /// Create a new entry with all timestamps set to the current time of the database. The username
+    /// is set to the default username of the database, if there is one.
+    pub fn new_entry(&self) -> Entry {
+        let mut entry = Entry {
+            times: Times::new_at(self.now()),
+            clock: self.clock.clone(),
+            ..Entry::new()
+        };
+        if let Some(username) = self.default_username() {
+            entry
+                .fields
+                .insert("UserName".to_string(), Value::Unprotected(username.to_string()));
+        }
+        entry
+    }
 
-            let entry = match parent_group.find_entry(&[deleted_object.uuid]) {
-                Some(e) => e,
-                // This uuid might refer to a group, which will be handled later.
-                None => continue,
-            };
+    /// Create a new group with all timestamps set to the current time of the database
+    pub fn new_group(&self, name: &str) -> Group {
+        Group {
+            times: Times::new_at(self.now()),
+            clock: self.clock.clone(),
+            ..Group::new(name)
+        }
+    }
 
-            let entry_last_modification = match entry.times.get_last_modification() {
-                Some(t) => *t,
-                None => {
-                    log.warnings.push(format!(
-                        "Entry {} did not have a last modification timestamp",
-                        entry.uuid
-                    ));
-                    Times::now()
-                }
-            };
+    /// Get the name of the database, which is empty if no name was set
+    pub fn name(&self) -> &str {
+        self.meta.database_name.as_deref().unwrap_or_default()
+    }
 
-            if entry_last_modification < deleted_object.deletion_time {
-                parent_group.remove_node(&deleted_object.uuid)?;
-                log.events.push(MergeEvent {
-                    event_type: MergeEventType::EntryDeleted,
-                    node_uuid: deleted_object.uuid,
-                });
+    /// Set the name of the database and update the time it was last changed
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.meta.database_name = Some(name.into());
+        if let Some(now) = self.clock.change_time() {
+            self.meta.database_name_changed = Some(now);
+        }
+    }
 
-                new_deleted_objects.objects.push(deleted_object.clone());
-            }
+    /// Get the description of the database
+    pub fn description(&self) -> Option<&str> {
+        self.meta.database_description.as_deref()
+    }
+
+    /// Set the description of the database and update the time it was last changed
+    pub fn set_description(&mut self, description: Option<&str>) {
+        self.meta.database_description = description.map(|d| d.to_string());
+        if let Some(now) = self.clock.change_time() {
+            self.meta.database_description_changed = Some(now);
         }
+    }
 
-        let mut deleted_groups_queue: VecDeque<DeletedObject> = vec![].into();
-        for deleted_object in &other.deleted_objects.objects {
-            if new_deleted_objects.contains(deleted_object.uuid) {
-                continue;
-            }
-            deleted_groups_queue.push_back(deleted_object.clone());
+    /// Get the username that new entries are created with, see `new_entry`
+    pub fn default_username(&self) -> Option<&str> {
+        self.meta.default_username.as_deref()
+    }
+
+    /// Set the username that new entries are created with and update the time it was last changed
+    pub fn set_default_username(&mut self, username: Option<&str>) {
+        self.meta.default_username = username.map(|u| u.to_string());
+        if let Some(now) = self.clock.change_time() {
+            self.meta.default_username_changed = Some(now);
         }
+    }
 
-        while !deleted_groups_queue.is_empty() {
-            let deleted_object = deleted_groups_queue.pop_front().unwrap();
-            if new_deleted_objects.contains(deleted_object.uuid) {
-                continue;
-            }
-            let group_location = match self.find_node_location(deleted_object.uuid) {
-                Some(l) => l,
-                None => continue,
-            };
+    /// Get the UUID of the group that a client last selected, to restore its view. The group may
+    /// no longer exist.
+    pub fn last_selected_group(&self) -> Option<Uuid> {
+        self.meta.last_selected_group.filter(|uuid| !uuid.is_nil())
+    }
 
-            let parent_group = match self.root.find_group_mut(&group_location) {
-                Some(g) => g,
-                None => return Err(MergeError::FindGroupError(group_location)),
-            };
+    /// Set the UUID of the group that was last selected in a client
+    pub fn set_last_selected_group(&mut self, uuid: Option<Uuid>) {
+        self.meta.last_selected_group = uuid;
+    }
 
-            let group = match parent_group.find_group(&[deleted_object.uuid]) {
-                Some(e) => e,
-                None => {
-                    // The node might be an entry, since we didn't necessarily removed all the
-                    // entries that were in the deleted objects of the source database.
-                    continue;
-                }
-            };
+    /// Get the UUID of the group that a client last showed at the top of its group tree, to restore
+    /// its scroll position. The group may no longer exist.
+    pub fn last_top_visible_group(&self) -> Option<Uuid> {
+        self.meta.last_top_visible_group.filter(|uuid| !uuid.is_nil())
+    }
 
-            // Not deleting a group if it still has entries.
-            if !group.entries().is_empty() {
-                continue;
-            }
+    /// Set the UUID of the group that was last shown at the top of the group tree in a client
+    pub fn set_last_top_visible_group(&mut self, uuid: Option<Uuid>) {
+        self.meta.last_top_visible_group = uuid;
+    }
 
-            // This group still has a child group that might get deleted in the future, so we delay
-            // decision to delete it or not.
-            if !group
-                .groups()
-                .iter()
-                .filter(|g| !is_in_deleted_queue(g.uuid, &deleted_groups_queue))
-                .collect::<Vec<_>>()
-                .is_empty()
-            {
-                deleted_groups_queue.push_back(deleted_object.clone());
-                continue;
-            }
+    /// Get the UUID of the group whose entries clients offer as templates for new entries, see
+    /// `new_entry_from_template`
+    pub fn entry_templates_group(&self) -> Option<Uuid> {
+        self.meta.entry_templates_group
+    }
 
-            // This group still a groups that won't be deleted, so we don't delete it.
-            if !group.groups().is_empty() {
-                continue;
-            }
+    /// Set the group whose entries are templates for new entries and update the time it was last
+    /// changed
+    pub fn set_entry_templates_group(&mut self, uuid: Option<Uuid>) {
+        self.meta.entry_templates_group = uuid;
+        if let Some(now) = self.clock.change_time() {
+            self.meta.entry_templates_group_changed = Some(now);
+        }
+    }
 
-            let group_last_modification = match group.times.get_last_modification() {
-                Some(t) => *t,
-                None => {
-                    log.warnings.push(format!(
-                        "Group {} did not have a last modification timestamp",
-                        group.uuid
-                    ));
-                    Times::now()
-                }
-            };
+    /// Create a new entry from a template entry: a copy of the template with its fields, icon,
+    /// colors, tags and auto-type settings, but with a new UUID, all timestamps set to the current
+    /// time of the database and without history. Returns `None` if there is no entry with the UUID.
+    ///
+    /// The template does not have to be in the `entry_templates_group`. The new entry is not added
+    /// to the database.
+    pub fn new_entry_from_template(&self, template_uuid: &Uuid) -> Option<Entry> {
+        let Some(NodeRef::Entry(template)) = self.get_by_uuid(template_uuid) else {
+            return None;
+        };
 
-            if group_last_modification < deleted_object.deletion_time {
-                parent_group.remove_node(&deleted_object.uuid)?;
-                log.events.push(MergeEvent {
-                    event_type: MergeEventType::GroupDeleted,
-                    node_uuid: deleted_object.uuid,
-                });
+        Some(Entry {
+            uuid: Uuid::new_v4(),
+            times: Times::new_at(self.now()),
+            history: None,
+            ..template.clone()
+        })
+    }
 
-                new_deleted_objects.objects.push(deleted_object.clone());
-            }
+    /// Add a custom icon, usually a PNG image, and return the UUID to reference it with from
+    /// `Entry::custom_icon_uuid` and `Group::custom_icon_uuid`. If the database already has an icon
+    /// with the same image data, that icon is reused.
+    pub fn add_custom_icon(&mut self, data: Vec<u8>) -> Uuid {
+        if let Some(icon) = self.meta.custom_icons.icons.iter().find(|i| i.data == data) {
+            return icon.uuid;
         }
 
-        self.deleted_objects = new_deleted_objects;
-        Ok(log)
+        let uuid = Uuid::new_v4();
+        self.meta.custom_icons.icons.push(Icon { uuid, data });
+        uuid
     }
 
-    #[cfg(feature = "_merge")]
+    /// Get the image data of a custom icon by its UUID
+    pub fn get_custom_icon(&self, uuid: &Uuid) -> Option<&[u8]> {
+        self.meta
+            .custom_icons
+            .icons
+            .iter()
+            .find(|i| i.uuid == *uuid)
+            .map(|i| i.data.as_slice())
+    }
+
+    /// Get the settings that the database is saved with: the outer cipher, the key derivation
+    /// function and its parameters, the compression and the inner cipher for protected values. For an
+    /// opened database, these start out as the settings read from the file, but reflect any changes
+    /// made since. Use `cipher`, `kdf` and `format_version` for the settings as read.
+    pub fn settings(&self) -> &DatabaseSettings {
+        &self.config.settings
+    }
+
+    /// Change the settings of the database, see `settings`. The changed settings are used the next
+    /// time the database is saved. The format version is changed with `set_format_version` instead.
+    pub fn settings_mut(&mut self) -> &mut DatabaseSettings {
+        &mut self.config.settings
+    }
+
+    /// Get the outer cipher that the database was read with. This is the cipher from the file header,
+    /// even if `config` has been changed since. For a database that was not read from a file, this is
+    /// the cipher in `config`.
+    pub fn cipher(&self) -> &OuterCipherConfig {
+        &self.read_config().settings.cipher
+    }
+
+    /// Get the key derivation function that the database was read with, including its parameters,
+    /// see `cipher`
+    pub fn kdf(&self) -> &KdfConfig {
+        &self.read_config().settings.kdf
+    }
+
+    /// Get the file format version that the database was read with, see `cipher`
+    pub fn format_version(&self) -> &DatabaseVersion {
+        &self.read_config().version
+    }
+
+    fn read_config(&self) -> &DatabaseConfig {
+        self.read_config.0.as_ref().unwrap_or(&self.config)
+    }
+
+    /// Change the file format version that `save` writes.
+    ///
+    /// Downgrading to KDBX3 switches a ChaCha20 inner stream cipher to Salsa20 and fails if the database
+    /// uses features that KDBX3 cannot store, such as the Argon2 key derivation functions. Attachments are
+    /// written to the metadata of a KDBX3 file, since it has no inner header.
+    ///
+    /// Upgrading to KDBX4 moves the attachments from the metadata into the inner header and switches a
+    /// Salsa20 inner stream cipher to ChaCha20. The AES key derivation function of KDBX3 is replaced by
+    /// the Argon2 parameters of `DatabaseSettings::default`; change `settings_mut().kdf` afterwards to use
+    /// other parameters.
+    #[cfg(feature = "save_kdbx4")]
+    pub fn set_format_version(
+        &mut self,
+        version: DatabaseVersion,
+    ) -> Result<(), crate::error::DatabaseSaveError> {
+        use crate::config::InnerCipherConfig;
+        use crate::error::DatabaseSaveError;
+        use crate::format::kdbx3::{check_kdbx3_config, move_meta_binaries_to_pool};
+
+        match version {
+            DatabaseVersion::KDB3(_) => {
+                let mut config = self.config.clone();
+                if config.settings.inner_cipher == InnerCipherConfig::ChaCha20 {
+                    config.settings.inner_cipher = InnerCipherConfig::Salsa20;
+                }
+                check_kdbx3_config(&config)?;
+
+                config.version = version;
+                self.config = config;
+            }
+            DatabaseVersion::KDB4(_) => {
+                if self.config.settings.inner_cipher == InnerCipherConfig::Salsa20 {
+                    self.config.settings.inner_cipher = InnerCipherConfig::ChaCha20;
+                }
+
+                let upgrading = matches!(self.config.version, DatabaseVersion::KDB3(_));
+                if upgrading && matches!(self.config.settings.kdf, KdfConfig::Aes { .. }) {
+                    self.config.settings.kdf = DatabaseSettings::default().kdf;
+                }
+
+                move_meta_binaries_to_pool(self);
+                self.config.version = version;
+            }
+            DatabaseVersion::KDB(_) | DatabaseVersion::KDB2(_) => {
+                return Err(DatabaseSaveError::UnsupportedVersion)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the root group of the database
+    pub fn root(&self) -> &Group {
+        &self.root
+    }
+
+    /// Get a mutable reference to the root group of the database.
+    ///
+    /// Changes made through this reference are not checked, see `with_root_mut` for a checked
+    /// alternative. The index built by `build_index` is dropped.
+    pub fn root_mut(&mut self) -> &mut Group {
+        self.drop_index();
+        &mut self.root
+    }
+
+    /// Change the group tree in a closure and check that the tree is still consistent afterwards.
+    ///
+    /// If the closure changes the UUID of the root group, gives two nodes the same UUID, or makes an
+    /// entry refer to an attachment that does not exist, all changes of the closure are rolled back and
+    /// an error is returned. To make this possible, the tree is cloned before running the closure.
+    /// The index built by `build_index` is dropped.
+    pub fn with_root_mut<F, R>(&mut self, f: F) -> Result<R, TreeError>
+    where
+        F: FnOnce(&mut Group) -> R,
+    {
+        self.drop_index();
+        let backup = self.root.clone();
+        let result = f(&mut self.root);
+
+        if let Err(e) = self.check_tree(backup.uuid) {
+            self.root = backup;
+            return Err(e);
+        }
+
+        Ok(result)
+    }
+
+    fn check_tree(&self, root_uuid: Uuid) -> Result<(), TreeError> {
+        if self.root.uuid != root_uuid {
+            return Err(TreeError::RootUuidChanged {
+                old: root_uuid,
+                new: self.root.uuid,
+            });
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for node in self.root.iter() {
+            let uuid = match node {
+                NodeRef::Group(g) => g.uuid,
+                NodeRef::Entry(e) => e.uuid,
+            };
+            if !seen.insert(uuid) {
+                return Err(TreeError::DuplicateUuid(uuid));
+            }
+        }
+
+        for entry in self.root.iter_entries() {
+            if let Some(index) = entry
+                .attachments
+                .values()
+                .find(|index| **index >= self.header_attachments.len())
+            {
+                return Err(TreeError::MissingAttachment {
+                    entry: entry.uuid,
+                    index: *index,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add data to the attachment pool and return its index in `header_attachments`. Data that is
+    /// already in the pool is not added again, and the index of the existing attachment is returned.
+    pub fn add_attachment(&mut self, content: Vec<u8>) -> usize {
+        let hash = Sha256::digest(&content).into();
+        self.add_hashed_attachment(content, hash)
+    }
+
+    /// Add data with the given SHA-256 hash to the attachment pool, see `add_attachment`. The
+    /// attachments in the pool are told apart by the hashes of their content.
+    pub(crate) fn add_hashed_attachment(&mut self, content: Vec<u8>, hash: [u8; 32]) -> usize {
+        if let Some(index) = self
+            .header_attachments
+            .iter()
+            .position(|a| a.content_hash() == hash)
+        {
+            return index;
+        }
+
+        self.header_attachments.push(HeaderAttachment {
+            hash: hash.into(),
+            ..HeaderAttachment::new(0, content)
+        });
+        self.header_attachments.len() - 1
+    }
+
+    /// Get whether each attachment in the pool is referred to by an entry or one of its history
+    /// entries
+    #[cfg(feature = "save_kdbx4")]
+    fn used_attachments(&self) -> Vec<bool> {
+        let mut used = vec![false; self.header_attachments.len()];
+
+        for entry in self.root.iter_entries() {
+            let history = entry.history.iter().flat_map(|h| h.get_entries());
+            for e in std::iter::once(entry).chain(history) {
+                for index in e.attachments.values() {
+                    if let Some(u) = used.get_mut(*index) {
+                        *u = true;
+                    }
+                }
+            }
+        }
+
+        used
+    }
+
+    /// Get the index each attachment in the pool is written at, or `None` for the attachments that
+    /// no entry refers to, which are not written to the file
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn attachment_indices(&self) -> Vec<Option<usize>> {
+        let mut next = 0;
+        self.used_attachments()
+            .into_iter()
+            .map(|used| {
+                used.then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect()
+    }
+
+    /// Insert a deep copy of an entry into the target group, leaving the original in place.
+    ///
+    /// The copy and its history receive a new UUID, which is returned.
+    pub fn copy_entry(&mut self, entry_uuid: &Uuid, target_group_uuid: &Uuid) -> Result<Uuid, CopyError> {
+        let mut copy = match self.get_by_uuid(entry_uuid) {
+            Some(NodeRef::Entry(e)) => e.clone(),
+            _ => return Err(CopyError::EntryNotFound(*entry_uuid)),
+        };
+        copy.regenerate_uuid();
+
+        let uuid = copy.uuid;
+        if !self.add_to_group(target_group_uuid, copy) {
+            return Err(CopyError::GroupNotFound(*target_group_uuid));
+        }
+
+        Ok(uuid)
+    }
+
+    /// Insert a deep copy of a group into the target group, leaving the original in place.
+    ///
+    /// The copied group and all of its descendant groups and entries receive new UUIDs. The new UUID
+    /// of the copied group is returned.
+    pub fn copy_group(&mut self, group_uuid: &Uuid, target_group_uuid: &Uuid) -> Result<Uuid, CopyError> {
+        let source = match self.get_by_uuid(group_uuid) {
+            Some(NodeRef::Group(g)) => g,
+            _ => return Err(CopyError::GroupNotFound(*group_uuid)),
+        };
+
+        if group_uuid == target_group_uuid || source.iter_groups().any(|g| &g.uuid == target_group_uuid) {
+            return Err(CopyError::Cycle(*group_uuid));
+        }
+
+        let mut copy = source.clone();
+        copy.regenerate_uuids();
+
+        let uuid = copy.uuid;
+        if !self.add_to_group(target_group_uuid, copy) {
+            return Err(CopyError::GroupNotFound(*target_group_uuid));
+        }
+
+        Ok(uuid)
+    }
+
+    /// Record that an entry has been used, e.g. because its password was copied, by incrementing
+    /// its usage count and setting its last access time to `Database::now`.
+    ///
+    /// As in KeePass, this is not a modification of the entry, so the last modification time is
+    /// left unchanged and merging is not affected. Reading entries never updates these times, so
+    /// this has to be called explicitly. Returns `false` if there is no entry with the given UUID.
+    pub fn mark_used(&mut self, entry_uuid: &Uuid) -> bool {
+        let now = self.now();
+        match self.get_by_uuid_mut(entry_uuid) {
+            Some(NodeRefMut::Entry(entry)) => {
+                entry.increment_usage_at(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Create a new database from a deep copy of a group and everything below it, e.g. to share the
+    /// group as its own file. The database itself is left untouched.
+    ///
+    /// The copied group becomes the root group of the new database, and all copied groups and
+    /// entries keep their UUIDs, so that the databases can be merged later on. The new database
+    /// has the configuration of this database, but fresh metadata named after the group, containing
+    /// only the custom icons that the copied nodes use. Likewise, the attachment pool only contains
+    /// the attachments of the copied entries and their history. Since a database does not hold its
+    /// key, the new database can be saved with any key.
+    pub fn export_group(&self, group_uuid: &Uuid) -> Result<Database, CopyError> {
+        let group = match self.get_by_uuid(group_uuid) {
+            Some(NodeRef::Group(g)) => g,
+            _ => return Err(CopyError::GroupNotFound(*group_uuid)),
+        };
+
+        let mut db = Database {
+            root: group.clone(),
+            clock: self.clock.clone(),
+            public_suffixes: self.public_suffixes.clone(),
+            history_skipped: self.history_skipped,
+            ..Database::new(self.config.clone())
+        };
+        db.set_name(&group.name);
+
+        let icon_uuids = custom_icon_uuids(group);
+        db.meta.custom_icons.icons = self
+            .meta
+            .custom_icons
+            .icons
+            .iter()
+            .filter(|icon| icon_uuids.contains(&icon.uuid))
+            .cloned()
+            .collect();
+
+        let mut pool = Vec::new();
+        let mut new_indices: HashMap<usize, usize> = HashMap::new();
+        for entry in db.root.iter_entries_mut() {
+            let history = entry.history.iter_mut().flat_map(|h| h.entries.iter_mut());
+            for e in history {
+                export_attachments(e, &self.header_attachments, &mut pool, &mut new_indices);
+            }
+            export_attachments(entry, &self.header_attachments, &mut pool, &mut new_indices);
+        }
+        db.header_attachments = pool;
+
+        Ok(db)
+    }
+
+    /// Insert a deep copy of a group of another database and everything below it into the target
+    /// group of this database, e.g. to take over a group that was shared as its own file.
+    ///
+    /// The custom icons and attachments that the copied nodes use are added to this database, except
+    /// for custom icons that already exist here with the same UUID and attachments whose content
+    /// already exists here. The copied groups and entries keep their UUIDs, unless one of them is
+    /// already used by a group or entry of this database: then, depending on `collisions`, either all
+    /// copies receive new UUIDs, or nothing is imported and `CopyError::UuidCollision` lists the
+    /// UUIDs in question.
+    pub fn import_group(
+        &mut self,
+        source: &Database,
+        group_uuid: &Uuid,
+        target_group_uuid: &Uuid,
+        collisions: UuidCollisionMode,
+    ) -> Result<ImportReport, CopyError> {
+        let group = match source.get_by_uuid(group_uuid) {
+            Some(NodeRef::Group(g)) => g,
+            _ => return Err(CopyError::GroupNotFound(*group_uuid)),
+        };
+
+        if !matches!(self.get_by_uuid(target_group_uuid), Some(NodeRef::Group(_))) {
+            return Err(CopyError::GroupNotFound(*target_group_uuid));
+        }
+
+        let local_uuids: HashSet<Uuid> = self
+            .root
+            .iter()
+            .map(|node| match node {
+                NodeRef::Group(g) => g.uuid,
+                NodeRef::Entry(e) => e.uuid,
+            })
+            .collect();
+        let colliding: Vec<Uuid> = group
+            .iter()
+            .map(|node| match node {
+                NodeRef::Group(g) => g.uuid,
+                NodeRef::Entry(e) => e.uuid,
+            })
+            .filter(|uuid| local_uuids.contains(uuid))
+            .collect();
+
+        let mut copy = group.clone();
+        if !colliding.is_empty() {
+            match collisions {
+                UuidCollisionMode::Regenerate => copy.regenerate_uuids(),
+                UuidCollisionMode::Fail => return Err(CopyError::UuidCollision(colliding)),
+            }
+        }
+
+        let mut report = ImportReport::default();
+
+        let icon_uuids = custom_icon_uuids(group);
+        for icon in &source.meta.custom_icons.icons {
+            if icon_uuids.contains(&icon.uuid)
+                && !self.meta.custom_icons.icons.iter().any(|i| i.uuid == icon.uuid)
+            {
+                self.meta.custom_icons.icons.push(icon.clone());
+                report.icons.push(icon.uuid);
+            }
+        }
+
+        let first_attachment = self.header_attachments.len();
+        let mut new_indices: HashMap<usize, usize> = HashMap::new();
+        for entry in copy.iter_entries_mut() {
+            let history = entry.history.iter_mut().flat_map(|h| h.entries.iter_mut());
+            for e in history {
+                export_attachments(
+                    e,
+                    &source.header_attachments,
+                    &mut self.header_attachments,
+                    &mut new_indices,
+                );
+            }
+            export_attachments(
+                entry,
+                &source.header_attachments,
+                &mut self.header_attachments,
+                &mut new_indices,
+            );
+        }
+        report.attachments = (first_attachment..self.header_attachments.len()).collect();
+
+        for node in copy.iter() {
+            match node {
+                NodeRef::Group(g) => report.groups.push(g.uuid),
+                NodeRef::Entry(e) => report.entries.push(e.uuid),
+            }
+        }
+
+        if !self.add_to_group(target_group_uuid, copy) {
+            return Err(CopyError::GroupNotFound(*target_group_uuid));
+        }
+
+        Ok(report)
+    }
+
+    /// Deletes a node (entry or group) from the database by its UUID.
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - The UUID of the node to delete.
+    /// * `log_deletion` - If true, the deletion will be logged in the `deleted_objects` list,
+    ///   which is important for merging databases.
+    ///
+    /// # Returns
+    ///
+    /// `Some(Node)` containing the deleted node if it was found, otherwise `None`.
+    pub fn delete_by_uuid(&mut self, uuid: &Uuid, log_deletion: bool) -> Option<Node> {
+        self.delete_by_uuid_with_parent(uuid, log_deletion)
+            .map(|(node, _)| node)
+    }
+
+    /// Deletes a node like `delete_by_uuid`, and also returns the UUID of the group that the node
+    /// was in, which is the UUID of the root group for nodes directly below it. This is useful to
+    /// log where a node was deleted from, or to put it back there later on.
+    pub fn delete_by_uuid_with_parent(&mut self, uuid: &Uuid, log_deletion: bool) -> Option<(Node, Uuid)> {
+        let (removed_node, parent_uuid) = self.remove_by_uuid_with_parent(uuid)?;
+        if log_deletion {
+            let deletion_time = self.now();
+            self.deleted_objects.objects.push(DeletedObject {
+                uuid: removed_node.uuid(),
+                deletion_time,
+            });
+        }
+        Some((removed_node, parent_uuid))
+    }
+
+    /// Remove a node below the root group from the tree and return it, e.g. to cut it and paste
+    /// it elsewhere with `attach`. Unlike `delete_by_uuid`, this never records a deletion, so the
+    /// node is not removed from other databases when merging.
+    ///
+    /// Returns `None` if there is no such node, or if `uuid` is the UUID of the root group.
+    pub fn detach_by_uuid(&mut self, uuid: &Uuid) -> Option<Node> {
+        self.remove_by_uuid(uuid)
+    }
+
+    /// Add a node, e.g. one that was removed with `detach_by_uuid`, to the group with the UUID
+    /// `parent_uuid` and set the time its location changed to `Database::now`.
+    ///
+    /// Nothing is added if the parent group does not exist, if the parent group is the node itself
+    /// or one of its subgroups, or if the node or any node below it has a UUID that already exists
+    /// in the database, in which case the error lists the UUIDs in question.
+    pub fn attach(&mut self, mut node: Node, parent_uuid: &Uuid) -> Result<(), CopyError> {
+        if let Node::Group(g) = &node {
+            if &g.uuid == parent_uuid || g.iter_groups().any(|g| &g.uuid == parent_uuid) {
+                return Err(CopyError::Cycle(g.uuid));
+            }
+        }
+
+        let local_uuids: HashSet<Uuid> = self.root.iter().map(|node| node.uuid()).collect();
+        let uuids: Vec<Uuid> = match &node {
+            Node::Group(g) => g.iter().map(|node| node.uuid()).collect(),
+            Node::Entry(e) => vec![e.uuid],
+        };
+        let colliding: Vec<Uuid> = uuids
+            .into_iter()
+            .filter(|uuid| local_uuids.contains(uuid))
+            .collect();
+        if !colliding.is_empty() {
+            return Err(CopyError::UuidCollision(colliding));
+        }
+
+        if let Some(now) = self.clock.change_time() {
+            match &mut node {
+                Node::Group(g) => g.times.set_location_changed(now),
+                Node::Entry(e) => e.times.set_location_changed(now),
+            }
+        }
+
+        if !self.add_to_group(parent_uuid, node) {
+            return Err(CopyError::GroupNotFound(*parent_uuid));
+        }
+        Ok(())
+    }
+
+    /// Merge this database with another version of this same database.
+    /// This function will use the UUIDs to detect that entries and groups are
+    /// the same.
+    ///
+    /// The deleted objects of both databases are combined, so that a node that was deleted in
+    /// either database is removed, unless it was modified after its deletion.
+    ///
+    /// Of the metadata, the settings that have a time they were last changed, such as the name, the
+    /// recycle bin and the entry templates group, are taken from the database that changed them
+    /// most recently.
+    ///
+    /// Only the contents of the databases are merged, so they may have different format versions,
+    /// e.g. a KDBX 3.1 database and a copy of it that was upgraded to KDBX 4. This database keeps its
+    /// version and settings, and is saved in its own format. The attachments of both versions are
+    /// kept in the attachment pool, see `header_attachments`, and the attachments of entries that
+    /// are taken from the other database are copied into the pool of this database.
+    #[cfg(feature = "_merge")]
+    pub fn merge(&mut self, other: &Database) -> Result<MergeLog, MergeError> {
+        self.merge_with_strategy(other, MergeStrategy::Timestamp)
+    }
+
+    /// Merge this database with another version of this same database, resolving entries that
+    /// differ between the two with `strategy`. Entries and groups that only exist in one of the
+    /// databases are merged as in `merge`.
+    #[cfg(feature = "_merge")]
+    pub fn merge_with_strategy(
+        &mut self,
+        other: &Database,
+        strategy: MergeStrategy,
+    ) -> Result<MergeLog, MergeError> {
+        let mut log = MergeLog::default();
+
+        // the deletions of the other database need to be known before merging the nodes, so that
+        // deleted nodes are not added again
+        self.deleted_objects.union_with(&other.deleted_objects);
+
+        let indexed = self.is_indexed();
+        self.drop_index();
+
+        // the last merge between the databases, in either direction, is the last state of the nodes
+        // that both of them have seen. Copies that share a replica ID cannot be told apart.
+        let last_sync = match (self.meta.replica_id, other.meta.replica_id) {
+            (Some(ours), Some(theirs)) if ours != theirs => self
+                .meta
+                .merge_history
+                .iter()
+                .filter(|record| record.remote_db_uuid == theirs)
+                .chain(
+                    other
+                        .meta
+                        .merge_history
+                        .iter()
+                        .filter(|record| record.remote_db_uuid == ours),
+                )
+                .map(|record| record.merged_at)
+                .max(),
+            _ => None,
+        };
+
+        let ctx = MergeContext {
+            attachments: &other.header_attachments,
+            strategy,
+            last_sync,
+        };
+        let mut locations = self.node_locations();
+        log.append(&self.merge_group(vec![], &other.root, false, &ctx, &mut locations)?);
+        log.append(&self.merge_deletions()?);
+        self.meta.merge_with(&other.meta);
+        self.record_merge(other, &log);
+
+        if indexed {
+            self.build_index();
+        }
+        Ok(log)
+    }
+
+    /// Open another version of this database from a reader with the open options of `options`, and
+    /// merge it into this database with the strategy of `options`, see `merge_with_strategy`.
+    ///
+    /// The other database is opened with its own key, which does not need to be the key of this
+    /// database, e.g. for a copy that a colleague exported with a different password. Errors while
+    /// opening it are returned as `MergeFromError::Open`, and this database is left unchanged.
+    #[cfg(feature = "_merge")]
+    pub fn merge_from_reader(
+        &mut self,
+        reader: &mut dyn std::io::Read,
+        key: DatabaseKey,
+        options: MergeOptions,
+    ) -> Result<MergeLog, MergeFromError> {
+        let other = Database::open_with_options(reader, key, &options.open_options)?;
+        Ok(self.merge_with_strategy(&other, options.strategy)?)
+    }
+
+    /// Open another version of this database from a file and merge it into this database, see
+    /// `merge_from_reader`
+    #[cfg(feature = "_merge")]
+    pub fn merge_from_path<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+        key: DatabaseKey,
+        options: MergeOptions,
+    ) -> Result<MergeLog, MergeFromError> {
+        let mut file = std::fs::File::open(path).map_err(DatabaseOpenError::from)?;
+        self.merge_from_reader(&mut file, key, options)
+    }
+
+    /// Get the records of the merges into this database, oldest first. They are added by `merge`
+    /// and `merge_with_strategy` and saved with the database. Merges that did not change anything
+    /// are not recorded, so that merging the same databases repeatedly does not grow the history.
+    /// Only the 100 most recent records are kept, and `run_maintenance` removes records older than
+    /// `maintenance_history_days`.
+    pub fn merge_history(&self) -> &[MergeRecord] {
+        &self.meta.merge_history
+    }
+
+    /// Add the changes to entries in a merge log to the merge history
+    #[cfg(feature = "_merge")]
+    fn record_merge(&mut self, other: &Database, log: &MergeLog) {
+        if log.events.is_empty() {
+            return;
+        }
+
+        // a copy of the other database takes a replica ID of its own, so that later merges can tell
+        // the two apart
+        if self.meta.replica_id.is_none() || self.meta.replica_id == other.meta.replica_id {
+            self.meta.replica_id = Some(Uuid::new_v4());
+        }
+
+        let mut record = MergeRecord {
+            merged_at: self.now(),
+            remote_db_uuid: other.meta.replica_id.unwrap_or_default(),
+            ..Default::default()
+        };
+
+        for event in &log.events {
+            let uuids = match event.event_type {
+                MergeEventType::EntryCreated => &mut record.added_entry_uuids,
+                MergeEventType::EntryDeleted => &mut record.deleted_entry_uuids,
+                MergeEventType::EntryUpdated | MergeEventType::EntryLocationUpdated => {
+                    &mut record.modified_entry_uuids
+                }
+                _ => continue,
+            };
+            if !uuids.contains(&event.node_uuid) {
+                uuids.push(event.node_uuid);
+            }
+        }
+
+        self.meta.merge_history.push(record);
+        let excess = self
+            .meta
+            .merge_history
+            .len()
+            .saturating_sub(meta::MERGE_HISTORY_MAX_RECORDS);
+        self.meta.merge_history.drain(..excess);
+    }
+
+    /// Remove the nodes that were deleted after their last modification, and forget the deletions of
+    /// nodes that were modified afterwards
+    #[cfg(feature = "_merge")]
+    fn merge_deletions(&mut self) -> Result<MergeLog, MergeError> {
+        // Utility function to search for a UUID in the VecDeque of deleted objects.
+        let is_in_deleted_queue = |uuid: Uuid, deleted_groups_queue: &VecDeque<DeletedObject>| -> bool {
+            for deleted_object in deleted_groups_queue {
+                // This group still has a child group, but it is not going to be deleted.
+                if deleted_object.uuid == uuid {
+                    return true;
+                }
+            }
+            false
+        };
+
+        let mut log = MergeLog::default();
+
+        let deleted_objects = self.deleted_objects.objects.clone();
+
+        // We start by deleting the entries, since we will only remove groups if they are empty.
+        for deleted_object in &deleted_objects {
+            let entry_location = match self.find_node_location(deleted_object.uuid) {
+                Some(l) => l,
+                None => continue,
+            };
+
+            let parent_group = match self.root.find_group_mut(&entry_location) {
+                Some(g) => g,
+                None => return Err(MergeError::FindGroupError(entry_location)),
+            };
+
+            let entry = match parent_group.find_entry(&[deleted_object.uuid]) {
+                Some(e) => e,
+                // This uuid might refer to a group, which will be handled later.
+                None => continue,
+            };
+
+            let entry_last_modification = match entry.times.get_last_modification() {
+                Some(t) => *t,
+                None => {
+                    log.warnings.push(format!(
+                        "Entry {} did not have a last modification timestamp",
+                        entry.uuid
+                    ));
+                    self.clock.now()
+                }
+            };
+
+            if entry_last_modification < deleted_object.deletion_time {
+                parent_group.remove_node(&deleted_object.uuid)?;
+                log.events.push(MergeEvent {
+                    event_type: MergeEventType::EntryDeleted,
+                    node_uuid: deleted_object.uuid,
+                });
+            }
+        }
+
+        let mut deleted_groups_queue: VecDeque<DeletedObject> = deleted_objects.into();
+
+        while let Some(deleted_object) = deleted_groups_queue.pop_front() {
+            let group_location = match self.find_node_location(deleted_object.uuid) {
+                Some(l) => l,
+                None => continue,
+            };
+
+            let parent_group = match self.root.find_group_mut(&group_location) {
+                Some(g) => g,
+                None => return Err(MergeError::FindGroupError(group_location)),
+            };
+
+            let group = match parent_group.find_group(&[deleted_object.uuid]) {
+                Some(e) => e,
+                None => {
+                    // The node might be an entry that was modified after its deletion.
+                    continue;
+                }
+            };
+
+            // Not deleting a group if it still has entries.
+            if !group.entries().is_empty() {
+                continue;
+            }
+
+            // This group still has a child group that might get deleted in the future, so we delay
+            // decision to delete it or not.
+            if group
+                .groups()
+                .iter()
+                .any(|g| is_in_deleted_queue(g.uuid, &deleted_groups_queue))
+            {
+                deleted_groups_queue.push_back(deleted_object.clone());
+                continue;
+            }
+
+            // This group still has groups that won't be deleted, so we don't delete it.
+            if !group.groups().is_empty() {
+                continue;
+            }
+
+            let group_last_modification = match group.times.get_last_modification() {
+                Some(t) => *t,
+                None => {
+                    log.warnings.push(format!(
+                        "Group {} did not have a last modification timestamp",
+                        group.uuid
+                    ));
+                    self.clock.now()
+                }
+            };
+
+            if group_last_modification < deleted_object.deletion_time {
+                parent_group.remove_node(&deleted_object.uuid)?;
+                log.events.push(MergeEvent {
+                    event_type: MergeEventType::GroupDeleted,
+                    node_uuid: deleted_object.uuid,
+                });
+            }
+        }
+
+        // nodes that are still present were modified after their deletion, or contain such nodes
+        let remaining: HashSet<Uuid> = self
+            .root
+            .iter()
+            .map(|node| match node {
+                NodeRef::Entry(e) => e.uuid,
+                NodeRef::Group(g) => g.uuid,
+            })
+            .collect();
+        self.deleted_objects
+            .objects
+            .retain(|deleted_object| !remaining.contains(&deleted_object.uuid));
+
+        Ok(log)
+    }
+
+    #[cfg(feature = "_merge")]
     pub(crate) fn find_node_location(&self, id: Uuid) -> Option<NodeLocation> {
         for node in &self.root.children {
             match node {
@@ -341,16 +1518,34 @@ impl Database {
         None
     }
 
+    /// Locations of all groups and entries below the root group, as returned by
+    /// `find_node_location`
+    #[cfg(feature = "_merge")]
+    fn node_locations(&self) -> HashMap<Uuid, Rc<NodeLocation>> {
+        let mut locations = HashMap::new();
+        index_node_locations(&self.root, &Rc::new(vec![]), &mut locations);
+        locations
+    }
+
+    /// Merge a group of another database into this database. `locations` are the locations of the
+    /// nodes of this database as returned by `node_locations`, and are kept up to date while the
+    /// nodes are created and moved, so that the tree does not have to be searched for every node.
+    ///
+    /// The attachments of the entries of the other database are added to the attachment pool of this
+    /// database before they are compared, and removed again if the entry is not changed.
     #[cfg(feature = "_merge")]
     fn merge_group(
         &mut self,
         current_group_path: NodeLocation,
         current_group: &Group,
         is_in_deleted_group: bool,
+        ctx: &MergeContext<'_>,
+        locations: &mut HashMap<Uuid, Rc<NodeLocation>>,
     ) -> Result<MergeLog, MergeError> {
         let mut log = MergeLog::default();
+        let mut current_group_path = current_group_path;
 
-        if let Some(destination_group_location) = self.find_node_location(current_group.uuid) {
+        if let Some(destination_group_location) = locations.get(&current_group.uuid).map(|l| l.to_vec()) {
             let mut destination_group_path = destination_group_location.clone();
             destination_group_path.push(current_group.uuid);
             let destination_group = match self.root.find_group_mut(&destination_group_path) {
@@ -359,24 +1554,25 @@ impl Database {
             };
             let group_update_merge_events = destination_group.merge_with(current_group)?;
             log.append(&group_update_merge_events);
+
+            // The group might be at a different location in the destination database if it was
+            // moved there more recently, and its children belong wherever it is in the destination.
+            current_group_path = destination_group_path;
         }
 
         for other_entry in &current_group.entries() {
             // find the existing location
-            let destination_entry_location = self.find_node_location(other_entry.uuid);
+            let destination_entry_location = locations.get(&other_entry.uuid).map(|l| l.to_vec());
 
             // The group already exists in the destination database.
             if let Some(destination_entry_location) = destination_entry_location {
                 let mut existing_entry_location = destination_entry_location.clone();
                 existing_entry_location.push(other_entry.uuid);
 
-                // The entry already exists but is not at the right location. We might have to
-                // relocate it.
-                let mut existing_entry = self.root.find_entry(&existing_entry_location).unwrap().clone();
-
                 // The entry already exists but is not at the right location. We might have to
                 // relocate it.
                 if current_group_path.last() != destination_entry_location.last() && !is_in_deleted_group {
+                    let existing_entry = self.root.find_entry(&existing_entry_location).unwrap();
                     let source_location_changed_time = match other_entry.times.get_location_changed() {
                         Some(t) => *t,
                         None => {
@@ -394,9 +1590,19 @@ impl Database {
                                 "Entry {} did not have a location updated timestamp",
                                 other_entry.uuid
                             ));
-                            Times::now()
+                            self.now()
                         }
                     };
+                    // Both sides moved the entry to different groups since they were last merged, the
+                    // most recent move wins.
+                    if other_entry.times.was_moved_since(ctx.last_sync)
+                        && existing_entry.times.was_moved_since(ctx.last_sync)
+                    {
+                        log.events.push(MergeEvent {
+                            event_type: MergeEventType::EntryLocationConflict,
+                            node_uuid: other_entry.uuid,
+                        });
+                    }
                     if source_location_changed_time > destination_location_changed {
                         log.events.push(MergeEvent {
                             event_type: MergeEventType::EntryLocationUpdated,
@@ -408,47 +1614,59 @@ impl Database {
                             &current_group_path,
                             source_location_changed_time,
                         )?;
+                        locations.insert(other_entry.uuid, Rc::new(current_group_path.clone()));
                         // Update the location of the current entry in case we have to update it
                         // after.
                         existing_entry_location = current_group_path.clone();
                         existing_entry_location.push(other_entry.uuid);
-                        existing_entry
-                            .times
-                            .set_location_changed(source_location_changed_time);
                     }
                 }
 
-                if !existing_entry.has_diverged_from(other_entry) {
+                let pool_size = self.header_attachments.len();
+                let other_entry =
+                    with_attachments_in(other_entry, ctx.attachments, &mut self.header_attachments);
+
+                let existing_entry = match self.root.find_entry(&existing_entry_location) {
+                    Some(e) => e,
+                    None => return Err(MergeError::FindEntryError(existing_entry_location)),
+                };
+
+                if !existing_entry.has_diverged_from(&other_entry, &self.header_attachments) {
+                    self.header_attachments.truncate(pool_size);
                     continue;
                 }
 
                 // The entry already exists and is at the right location, so we can proceed and merge
                 // the two entries.
-                let (merged_entry, entry_merge_log) = existing_entry.merge(other_entry)?;
+                let (merged_entry, entry_merge_log) =
+                    existing_entry.merge(&other_entry, ctx.strategy, &self.header_attachments)?;
                 let merged_entry = match merged_entry {
-                    Some(m) => m,
-                    None => continue,
+                    Some(m) if !existing_entry.eq(&m) => m,
+                    _ => {
+                        self.header_attachments.truncate(pool_size);
+                        continue;
+                    }
                 };
 
-                if existing_entry.eq(&merged_entry) {
-                    continue;
-                }
-
                 let existing_entry = match self.root.find_entry_mut(&existing_entry_location) {
                     Some(e) => e,
                     None => return Err(MergeError::FindEntryError(existing_entry_location)),
                 };
-                *existing_entry = merged_entry.clone();
+                let merged_entry_uuid = merged_entry.uuid;
+                *existing_entry = merged_entry;
 
                 log.events.push(MergeEvent {
                     event_type: MergeEventType::EntryUpdated,
-                    node_uuid: merged_entry.uuid,
+                    node_uuid: merged_entry_uuid,
                 });
                 log.append(&entry_merge_log);
                 continue;
             }
 
-            if self.deleted_objects.contains(other_entry.uuid) {
+            if self
+                .deleted_objects
+                .is_deleted_after(other_entry.uuid, other_entry.times.get_last_modification())
+            {
                 continue;
             }
 
@@ -458,18 +1676,19 @@ impl Database {
             }
 
             // The entry doesn't exist in the destination, we create it
-            let new_entry = other_entry.to_owned().clone();
-
+            let new_entry =
+                with_attachments_in(other_entry, ctx.attachments, &mut self.header_attachments).into_owned();
             let new_entry_parent_group = match self.root.find_group_mut(&current_group_path) {
                 Some(g) => g,
                 None => return Err(MergeError::FindGroupError(current_group_path)),
             };
-            new_entry_parent_group.add_child(new_entry.clone());
+            new_entry_parent_group.push_child(new_entry);
+            locations.insert(other_entry.uuid, Rc::new(current_group_path.clone()));
 
             // TODO should we update the time info for the entry?
             log.events.push(MergeEvent {
                 event_type: MergeEventType::EntryCreated,
-                node_uuid: new_entry.uuid,
+                node_uuid: other_entry.uuid,
             });
         }
 
@@ -478,13 +1697,17 @@ impl Database {
             let other_group_uuid = other_group.uuid;
             new_group_location.push(other_group_uuid);
 
-            if self.deleted_objects.contains(other_group.uuid) || is_in_deleted_group {
-                let new_merge_log = self.merge_group(new_group_location, other_group, true)?;
+            if self
+                .deleted_objects
+                .is_deleted_after(other_group.uuid, other_group.times.get_last_modification())
+                || is_in_deleted_group
+            {
+                let new_merge_log = self.merge_group(new_group_location, other_group, true, ctx, locations)?;
                 log.append(&new_merge_log);
                 continue;
             }
 
-            let destination_group_location = self.find_node_location(other_group.uuid);
+            let destination_group_location = locations.get(&other_group.uuid).map(|l| l.to_vec());
 
             // The group already exists in the destination database.
             if let Some(destination_group_location) = destination_group_location {
@@ -502,7 +1725,7 @@ impl Database {
                                 "Entry {} did not have a location changed timestamp",
                                 existing_group.uuid
                             ));
-                            Times::now()
+                            self.now()
                         }
                     };
                     let other_group_location_changed = match other_group.times.get_location_changed() {
@@ -515,6 +1738,16 @@ impl Database {
                             Times::epoch()
                         }
                     };
+                    // Both sides moved the group to different groups since they were last merged, the
+                    // most recent move wins.
+                    if other_group.times.was_moved_since(ctx.last_sync)
+                        && existing_group.times.was_moved_since(ctx.last_sync)
+                    {
+                        log.events.push(MergeEvent {
+                            event_type: MergeEventType::GroupLocationConflict,
+                            node_uuid: other_group.uuid,
+                        });
+                    }
                     // The other group was moved after the current group, so we have to relocate it.
                     if existing_group_location_changed < other_group_location_changed {
                         self.relocate_node(
@@ -523,14 +1756,21 @@ impl Database {
                             &current_group_path,
                             other_group_location_changed,
                         )?;
+                        // the locations of all nodes below the group have changed as well
+                        *locations = self.node_locations();
 
                         log.events.push(MergeEvent {
                             event_type: MergeEventType::GroupLocationUpdated,
                             node_uuid: other_group.uuid,
                         });
 
-                        let new_merge_log =
-                            self.merge_group(new_group_location, other_group, is_in_deleted_group)?;
+                        let new_merge_log = self.merge_group(
+                            new_group_location,
+                            other_group,
+                            is_in_deleted_group,
+                            ctx,
+                            locations,
+                        )?;
                         log.append(&new_merge_log);
                         continue;
                     }
@@ -538,14 +1778,19 @@ impl Database {
 
                 // The group already exists and is at the right location, so we can proceed and merge
                 // the two groups.
-                let new_merge_log = self.merge_group(new_group_location, other_group, is_in_deleted_group)?;
+                let new_merge_log = self.merge_group(
+                    new_group_location,
+                    other_group,
+                    is_in_deleted_group,
+                    ctx,
+                    locations,
+                )?;
                 log.append(&new_merge_log);
                 continue;
             }
 
             // The group doesn't exist in the destination, we create it
-            let mut new_group = other_group.to_owned().clone();
-            new_group.children = vec![];
+            let new_group = other_group.clone_without_children();
             log.events.push(MergeEvent {
                 event_type: MergeEventType::GroupCreated,
                 node_uuid: new_group.uuid,
@@ -554,41 +1799,209 @@ impl Database {
                 Some(g) => g,
                 None => return Err(MergeError::FindGroupError(current_group_path)),
             };
-            new_group_parent_group.add_child(new_group.clone());
+            new_group_parent_group.push_child(new_group);
+            locations.insert(other_group_uuid, Rc::new(current_group_path.clone()));
+
+            let new_merge_log = self.merge_group(
+                new_group_location,
+                other_group,
+                is_in_deleted_group,
+                ctx,
+                locations,
+            )?;
+            log.append(&new_merge_log);
+        }
+
+        Ok(log)
+    }
+
+    #[cfg(feature = "_merge")]
+    fn relocate_node(
+        &mut self,
+        node_uuid: &Uuid,
+        from: &NodeLocation,
+        to: &NodeLocation,
+        new_location_changed_timestamp: NaiveDateTime,
+    ) -> Result<(), MergeError> {
+        let source_group = match self.root.find_group_mut(from) {
+            Some(g) => g,
+            None => return Err(MergeError::FindGroupError(from.to_vec())),
+        };
+
+        // the node is touched at the time it was moved in the other database, unless it was accessed
+        // later. The groups are not modified, since their modification times are merged from both
+        // databases.
+        let relocate = |times: &mut Times| {
+            times.set_location_changed(new_location_changed_timestamp);
+            if times
+                .get_last_access()
+                .is_none_or(|t| *t < new_location_changed_timestamp)
+            {
+                times.touch(false, new_location_changed_timestamp);
+            }
+        };
+        let mut relocated_node = source_group.remove_node(node_uuid)?;
+        match relocated_node {
+            Node::Group(ref mut g) => relocate(&mut g.times),
+            Node::Entry(ref mut e) => relocate(&mut e.times),
+        };
+
+        let destination_group = match self.root.find_group_mut(to) {
+            Some(g) => g,
+            None => return Err(MergeError::FindGroupError(to.to_vec())),
+        };
+        destination_group.push_child(relocated_node);
+        Ok(())
+    }
+}
+
+/// Add the locations of the nodes below a group at `location` to `locations`, see
+/// `Database::node_locations`. The nodes in a group share the allocation of its location. As in
+/// `Database::find_node_location`, the first node in depth-first order wins if there are nodes with
+/// the same UUID.
+#[cfg(feature = "_merge")]
+fn index_node_locations(
+    group: &Group,
+    location: &Rc<NodeLocation>,
+    locations: &mut HashMap<Uuid, Rc<NodeLocation>>,
+) {
+    for node in &group.children {
+        locations.entry(node.uuid()).or_insert_with(|| location.clone());
+        if let Node::Group(g) = node {
+            let mut group_location = location.to_vec();
+            group_location.push(g.uuid);
+            index_node_locations(g, &Rc::new(group_location), locations);
+        }
+    }
+}
+
+/// How `Database::import_group` handles imported groups and entries with UUIDs that already exist
+/// in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UuidCollisionMode {
+    /// Assign new UUIDs to all imported groups and entries
+    Regenerate,
+
+    /// Import nothing and return `CopyError::UuidCollision`
+    Fail,
+}
+
+/// What was added to a database by `Database::import_group`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// UUIDs of the created groups, starting with the imported group itself
+    pub groups: Vec<Uuid>,
+
+    /// UUIDs of the created entries
+    pub entries: Vec<Uuid>,
+
+    /// UUIDs of the custom icons that were added to the metadata
+    pub icons: Vec<Uuid>,
+
+    /// Indices of the attachments that were added to `Database::header_attachments`
+    pub attachments: Vec<usize>,
+}
+
+/// UUIDs of the custom icons used by a group, its descendants and their history
+fn custom_icon_uuids(group: &Group) -> HashSet<Uuid> {
+    group
+        .iter_groups()
+        .chain(std::iter::once(group))
+        .filter_map(|g| g.custom_icon_uuid)
+        .chain(
+            group
+                .iter_entries()
+                .flat_map(|e| std::iter::once(e).chain(e.history.iter().flat_map(|h| h.get_entries())))
+                .filter_map(|e| e.custom_icon_uuid),
+        )
+        .collect()
+}
+
+/// Copy the attachments of an entry from the pool of a database into the pool of another database,
+/// see `Database::export_group` and `Database::import_group`, and update the references of the
+/// entry to them. Each attachment is copied only once, attachments with the same content as one
+/// in the destination are not copied at all, and references to attachments that are missing are
+/// dropped.
+fn export_attachments(
+    entry: &mut Entry,
+    source: &[HeaderAttachment],
+    destination: &mut Vec<HeaderAttachment>,
+    new_indices: &mut HashMap<usize, usize>,
+) {
+    entry.attachments.retain(|_, index| {
+        let Some(attachment) = source.get(*index) else {
+            return false;
+        };
 
-            let new_merge_log = self.merge_group(new_group_location, other_group, is_in_deleted_group)?;
-            log.append(&new_merge_log);
-        }
+        *index = *new_indices.entry(*index).or_insert_with(|| {
+            match destination.iter().position(|a| a == attachment) {
+                Some(existing) => existing,
+                None => {
+                    destination.push(attachment.clone());
+                    destination.len() - 1
+                }
+            }
+        });
+        true
+    });
+}
 
-        Ok(log)
+/// Get an entry of another database with the references of it and its history changed to the
+/// attachments in `destination`, see `export_attachments`. Entries without attachments are not
+/// copied.
+#[cfg(feature = "_merge")]
+fn with_attachments_in<'e>(
+    entry: &'e Entry,
+    source: &[HeaderAttachment],
+    destination: &mut Vec<HeaderAttachment>,
+) -> Cow<'e, Entry> {
+    let mut history = entry.history.iter().flat_map(|h| &h.entries);
+    if entry.attachments.is_empty() && history.all(|e| e.attachments.is_empty()) {
+        return Cow::Borrowed(entry);
     }
 
-    #[cfg(feature = "_merge")]
-    fn relocate_node(
-        &mut self,
-        node_uuid: &Uuid,
-        from: &NodeLocation,
-        to: &NodeLocation,
-        new_location_changed_timestamp: NaiveDateTime,
-    ) -> Result<(), MergeError> {
-        let source_group = match self.root.find_group_mut(from) {
-            Some(g) => g,
-            None => return Err(MergeError::FindGroupError(from.to_vec())),
-        };
+    let mut entry = entry.clone();
+    let mut new_indices = HashMap::new();
+    for e in entry.history.iter_mut().flat_map(|h| h.entries.iter_mut()) {
+        export_attachments(e, source, destination, &mut new_indices);
+    }
+    export_attachments(&mut entry, source, destination, &mut new_indices);
+    Cow::Owned(entry)
+}
 
-        let mut relocated_node = source_group.remove_node(node_uuid)?;
-        match relocated_node {
-            Node::Group(ref mut g) => g.times.set_location_changed(new_location_changed_timestamp),
-            Node::Entry(ref mut e) => e.times.set_location_changed(new_location_changed_timestamp),
-        };
+/// Settings of a merge that are the same for all groups, see `Database::merge_group`
+#[cfg(feature = "_merge")]
+struct MergeContext<'a> {
+    /// Attachment pool of the other database, which the attachments of its entries refer to
+    attachments: &'a [HeaderAttachment],
 
-        let destination_group = match self.root.find_group_mut(to) {
-            Some(g) => g,
-            None => return Err(MergeError::FindGroupError(to.to_vec())),
-        };
-        destination_group.children.push(relocated_node);
-        Ok(())
-    }
+    strategy: MergeStrategy,
+
+    /// Time of the last merge between the two databases, see `Times::was_moved_since`
+    last_sync: Option<NaiveDateTime>,
+}
+
+/// Phases of saving a database, in the order in which they happen
+#[cfg(feature = "save_kdbx4")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavePhase {
+    /// Deriving the encryption key with the key derivation function
+    KdfDerivation,
+    Compression,
+    Encryption,
+
+    /// Writing the encrypted database to the destination
+    Writing,
+}
+
+/// Progress of saving a database, see `Database::save_with_progress`
+#[cfg(feature = "save_kdbx4")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveProgress {
+    pub phase: SavePhase,
+
+    /// How much of the current phase is complete, from 0.0 to 1.0
+    pub fraction_complete: f32,
 }
 
 /// Timestamps for a Group or Entry
@@ -627,6 +2040,12 @@ impl Times {
         self.times.insert(EXPIRY_TIME_TAG_NAME.to_string(), time);
     }
 
+    /// Whether the node expires and its expiry time is at or before `now`. As in KeePass, the
+    /// expiry time is ignored unless `expires` is set.
+    pub fn is_expired_at(&self, now: NaiveDateTime) -> bool {
+        self.expires && self.get_expiry().is_some_and(|expiry| *expiry <= now)
+    }
+
     pub fn get_last_modification(&self) -> Option<&NaiveDateTime> {
         self.times.get(LAST_MODIFICATION_TIME_TAG_NAME)
     }
@@ -660,6 +2079,30 @@ impl Times {
         self.times.insert(LOCATION_CHANGED_TAG_NAME.to_string(), time);
     }
 
+    /// Whether the node was moved to another group after `since`, or after it was created if that is
+    /// later. New nodes get the same creation and location changed times.
+    #[cfg(feature = "_merge")]
+    pub(crate) fn was_moved_since(&self, since: Option<NaiveDateTime>) -> bool {
+        let location_changed = match self.get_location_changed() {
+            Some(t) => t,
+            None => return false,
+        };
+        match (self.get_creation().copied(), since) {
+            (Some(creation), Some(since)) => *location_changed > creation.max(since),
+            (Some(t), None) | (None, Some(t)) => *location_changed > t,
+            (None, None) => false,
+        }
+    }
+
+    /// Set the last access time, and the last modification time if `modified` is set, to `now`.
+    /// The creation time is never changed.
+    pub fn touch(&mut self, modified: bool, now: NaiveDateTime) {
+        self.set_last_access(now);
+        if modified {
+            self.set_last_modification(now);
+        }
+    }
+
     // Returns the current time, without the nanoseconds since
     // the last leap second.
     pub fn now() -> NaiveDateTime {
@@ -672,8 +2115,12 @@ impl Times {
     }
 
     pub fn new() -> Times {
+        Times::new_at(Times::now())
+    }
+
+    /// Create timestamps for a new node, with all times set to `now` and no expiry
+    pub fn new_at(now: NaiveDateTime) -> Times {
         let mut response = Times::default();
-        let now = Times::now();
         response.set_creation(now);
         response.set_last_modification(now);
         response.set_last_access(now);
@@ -691,6 +2138,49 @@ pub struct CustomData {
     pub items: HashMap<String, CustomDataItem>,
 }
 
+impl CustomData {
+    /// Get the value of a custom data item as a string, if it exists and contains text
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.items.get(key)?.value.as_ref()?.expose_secret()
+    }
+
+    /// Set a custom data item to a string value, updating its modification time
+    pub fn set(&mut self, key: String, value: String) {
+        self.items.insert(
+            key,
+            CustomDataItem {
+                value: Some(Value::Unprotected(value)),
+                last_modification_time: Some(Times::now()),
+            },
+        );
+    }
+
+    /// Remove a custom data item, returning it if it was present
+    pub fn remove(&mut self, key: &str) -> Option<CustomDataItem> {
+        self.items.remove(key)
+    }
+
+    /// Check whether a custom data item with the given key exists
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.items.contains_key(key)
+    }
+
+    /// Iterate over the keys of all custom data items
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.items.keys().map(|k| k.as_str())
+    }
+
+    /// Number of custom data items
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether there are no custom data items
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
 /// Custom data field for an entry or metadata for internal use
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
@@ -707,12 +2197,217 @@ pub struct CustomDataItemDenormalized {
     pub custom_data_item: CustomDataItem,
 }
 
-/// Binary attachments stored in a database inner header
+/// An XML element that is not known to this crate, e.g. one added by a KeePass plugin.
+///
+/// Unknown elements are kept with the Entry, Group or Meta they were found in and written back
+/// unchanged on save, so that opening and saving a database does not lose data.
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+pub struct UnknownElement {
+    /// The tag name of the element
+    pub name: String,
+
+    /// Attributes of the element, sorted by name
+    pub attributes: Vec<(String, String)>,
+
+    /// Text content of the element
+    pub text: Option<String>,
+
+    /// Child elements, in document order
+    pub children: Vec<UnknownElement>,
+}
+
+/// Binary attachments stored in a database inner header
+#[derive(Debug, Default, Clone)]
 pub struct HeaderAttachment {
     pub flags: u8,
-    pub content: Vec<u8>,
+
+    /// Content of the attachment, which is empty for attachments that were not loaded when opening
+    /// the database, see `OpenOptions::defer_binaries`. It is only accessed through `data` and
+    /// `set_data`, so that setting it cannot be overridden by the deferred content.
+    pub(crate) content: Vec<u8>,
+
+    /// Location of the content in the decrypted payload, if it has not been loaded yet
+    pub(crate) deferred: Option<DeferredBinary>,
+
+    /// SHA-256 hash of the content, computed when attachments are first compared by
+    /// `Database::add_attachment`
+    pub(crate) hash: std::sync::OnceLock<[u8; 32]>,
+}
+
+impl HeaderAttachment {
+    pub fn new(flags: u8, content: Vec<u8>) -> Self {
+        HeaderAttachment {
+            flags,
+            content,
+            deferred: None,
+            hash: Default::default(),
+        }
+    }
+
+    /// Get the SHA-256 hash of the content, which identifies attachments with the same content
+    pub(crate) fn content_hash(&self) -> [u8; 32] {
+        *self.hash.get_or_init(|| Sha256::digest(self.data()).into())
+    }
+
+    /// Get the content of the attachment, reading it from the decrypted payload if it has not been
+    /// loaded yet. A deferred KDBX3 attachment that cannot be decompressed reads as empty, use
+    /// `try_data` to tell it apart from an empty attachment.
+    pub fn data(&self) -> &[u8] {
+        self.try_data().unwrap_or_default()
+    }
+
+    /// Get the content of the attachment, or the error decompressing a deferred KDBX3 attachment
+    pub fn try_data(&self) -> Result<&[u8], DecompressionError> {
+        match &self.deferred {
+            Some(deferred) => deferred.data(),
+            None => Ok(&self.content),
+        }
+    }
+
+    /// Replace the content of the attachment, including content that has not been loaded yet
+    pub fn set_data(&mut self, content: Vec<u8>) {
+        self.content = content;
+        self.deferred = None;
+        self.hash = Default::default();
+    }
+
+    /// Whether the content of the attachment has not been loaded yet
+    pub fn is_deferred(&self) -> bool {
+        self.deferred.is_some()
+    }
+
+    /// Load the content of the attachment, so that it no longer refers to the decrypted payload or
+    /// needs to be decompressed. An attachment that cannot be decompressed stays deferred, so that
+    /// its compressed content is still saved.
+    pub fn load(&mut self) -> Result<(), DecompressionError> {
+        if let Some(deferred) = &self.deferred {
+            self.content = deferred.data()?.to_vec();
+            self.deferred = None;
+        }
+        Ok(())
+    }
+
+    /// Take the content of the attachment, copying it out of the decrypted payload if it has not
+    /// been loaded yet
+    pub fn into_content(mut self) -> Result<Vec<u8>, DecompressionError> {
+        self.load()?;
+        Ok(self.content)
+    }
+}
+
+impl PartialEq for HeaderAttachment {
+    fn eq(&self, other: &Self) -> bool {
+        self.flags == other.flags && self.data() == other.data()
+    }
+}
+
+impl Eq for HeaderAttachment {}
+
+#[cfg(feature = "serialization")]
+impl serde::Serialize for HeaderAttachment {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("HeaderAttachment", 2)?;
+        state.serialize_field("flags", &self.flags)?;
+        state.serialize_field("content", self.data())?;
+        state.end()
+    }
+}
+
+/// Handle to the content of an attachment that was not loaded when opening the database
+#[derive(Clone)]
+pub(crate) enum DeferredBinary {
+    /// Location of the content in the decrypted payload of a KDBX4 database. The payload is shared
+    /// between all attachments of the database, and freed once none of them refer to it anymore.
+    Payload {
+        payload: std::sync::Arc<Vec<u8>>,
+        range: std::ops::Range<usize>,
+    },
+
+    /// GZip-compressed content from the XML document of a KDBX3 database, which is decompressed
+    /// when it is first accessed. Clones share the decompressed content.
+    Compressed {
+        compressed: std::sync::Arc<Vec<u8>>,
+        size: u64,
+        max_size: u64,
+        content: std::sync::Arc<std::sync::OnceLock<Result<Vec<u8>, String>>>,
+    },
+}
+
+impl DeferredBinary {
+    /// Defer decompressing GZip-compressed content, given the size of the content as recorded in
+    /// the GZip stream
+    pub(crate) fn compressed(compressed: Vec<u8>, size: u64, max_size: u64) -> Self {
+        DeferredBinary::Compressed {
+            compressed: std::sync::Arc::new(compressed),
+            size,
+            max_size,
+            content: Default::default(),
+        }
+    }
+
+    /// Size of the content, without decompressing it
+    pub(crate) fn size(&self) -> u64 {
+        match self {
+            DeferredBinary::Payload { range, .. } => range.len() as u64,
+            DeferredBinary::Compressed { size, .. } => *size,
+        }
+    }
+
+    /// The GZip-compressed content as it was read, which is saved as is instead of recompressing it
+    #[cfg(feature = "save_kdbx4")]
+    pub(crate) fn gzip(&self) -> Option<&[u8]> {
+        match self {
+            DeferredBinary::Payload { .. } => None,
+            DeferredBinary::Compressed { compressed, .. } => Some(compressed),
+        }
+    }
+
+    pub(crate) fn data(&self) -> Result<&[u8], DecompressionError> {
+        match self {
+            DeferredBinary::Payload { payload, range } => Ok(&payload[range.clone()]),
+            DeferredBinary::Compressed {
+                compressed,
+                max_size,
+                content,
+                ..
+            } => content
+                .get_or_init(|| {
+                    let content = crate::compression::Compression::decompress(
+                        &crate::compression::GZipCompression,
+                        compressed,
+                        *max_size,
+                    )
+                    .map_err(|e| e.to_string())?;
+                    if content.len() as u64 > *max_size {
+                        return Err(format!("larger than the limit of {} bytes", max_size));
+                    }
+                    Ok(content)
+                })
+                .as_deref()
+                .map_err(|e| DecompressionError(e.clone())),
+        }
+    }
+}
+
+impl std::fmt::Debug for DeferredBinary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeferredBinary::Payload { range, .. } => f
+                .debug_struct("DeferredBinary::Payload")
+                .field("range", range)
+                .finish(),
+            DeferredBinary::Compressed {
+                compressed, content, ..
+            } => f
+                .debug_struct("DeferredBinary::Compressed")
+                .field("compressed_size", &compressed.len())
+                .field("decompressed", &content.get().is_some())
+                .finish(),
+        }
+    }
 }
 
 /// Elements that have been previously deleted
@@ -724,12 +2419,41 @@ pub struct DeletedObjects {
 
 impl DeletedObjects {
     pub fn contains(&self, uuid: Uuid) -> bool {
-        for deleted_object in &self.objects {
-            if deleted_object.uuid == uuid {
-                return true;
+        self.get(uuid).is_some()
+    }
+
+    /// Get the deletion record of a node
+    pub fn get(&self, uuid: Uuid) -> Option<&DeletedObject> {
+        self.objects
+            .iter()
+            .find(|deleted_object| deleted_object.uuid == uuid)
+    }
+
+    /// Add the deletion records of another collection, keeping the latest deletion time of nodes
+    /// that are recorded in both
+    #[cfg(feature = "_merge")]
+    pub(crate) fn union_with(&mut self, other: &DeletedObjects) {
+        for deleted_object in &other.objects {
+            match self.objects.iter_mut().find(|d| d.uuid == deleted_object.uuid) {
+                Some(existing) => {
+                    existing.deletion_time = existing.deletion_time.max(deleted_object.deletion_time);
+                }
+                None => self.objects.push(deleted_object.clone()),
+            }
+        }
+    }
+
+    /// Whether a node was deleted after it was last modified. Nodes without a modification time
+    /// count as deleted if there is a deletion record.
+    #[cfg(feature = "_merge")]
+    pub(crate) fn is_deleted_after(&self, uuid: Uuid, last_modification: Option<&NaiveDateTime>) -> bool {
+        match (self.get(uuid), last_modification) {
+            (Some(deleted_object), Some(last_modification)) => {
+                *last_modification <= deleted_object.deletion_time
             }
+            (Some(_), None) => true,
+            (None, _) => false,
         }
-        false
     }
 }
 
@@ -737,7 +2461,10 @@ impl DeletedObjects {
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 pub struct DeletedObject {
+    /// UUID of the deleted group or entry
     pub uuid: Uuid,
+
+    /// Time of the deletion, which is stored in the database with a precision of seconds
     pub deletion_time: NaiveDateTime,
 }
 
@@ -757,38 +2484,610 @@ impl serde::Serialize for Color {
     {
         serializer.serialize_str(&self.to_string())
     }
-}
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('#') || s.len() != 7 {
+            return Err(ParseColorError(s.to_string()));
+        }
+
+        let v =
+            u64::from_str_radix(s.trim_start_matches('#'), 16).map_err(|_e| ParseColorError(s.to_string()))?;
+
+        let r = ((v >> 16) & 0xff) as u8;
+        let g = ((v >> 8) & 0xff) as u8;
+        let b = (v & 0xff) as u8;
+
+        Ok(Self { r, g, b })
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[cfg(test)]
+mod database_tests {
+    use std::fs::File;
+
+    use crate::{
+        db::{AutoType, Entry, FixedTimeProvider, Group, Node, NodeRef, NodeRefMut, Times, Value},
+        error::{CopyError, DatabaseOpenError, TreeError},
+        Database, DatabaseKey,
+    };
+
+    fn find_entry<'a>(db: &'a Database, uuid: &uuid::Uuid) -> Option<&'a Entry> {
+        db.root.iter_entries().find(|e| &e.uuid == uuid)
+    }
+
+    #[test]
+    fn copy_entry() -> Result<(), CopyError> {
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Original".to_string()));
+        let entry_uuid = entry.uuid;
+        db.root.add_child(entry);
+
+        let target = Group::new("Target");
+        let target_uuid = target.uuid;
+        db.root.add_child(target);
+
+        let copy_uuid = db.copy_entry(&entry_uuid, &target_uuid)?;
+        assert_ne!(copy_uuid, entry_uuid);
+        assert!(find_entry(&db, &entry_uuid).is_some());
+        assert!(matches!(db.root.get(&["Target", "Original"]), Some(NodeRef::Entry(e)) if e.uuid == copy_uuid));
+
+        if let Some(NodeRef::Entry(e)) = db.root.get(&["Target", "Original"]) {
+            assert_eq!(e.get_title(), Some("Original"));
+        }
+        for entry in db.root.iter_entries_mut().filter(|e| e.uuid == copy_uuid) {
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected("Copy".to_string()));
+        }
+        assert_eq!(
+            find_entry(&db, &entry_uuid).unwrap().get_title(),
+            Some("Original")
+        );
+        assert_eq!(find_entry(&db, &copy_uuid).unwrap().get_title(), Some("Copy"));
+
+        let missing = uuid::Uuid::new_v4();
+        assert!(
+            matches!(db.copy_entry(&missing, &target_uuid), Err(CopyError::EntryNotFound(u)) if u == missing)
+        );
+        assert!(
+            matches!(db.copy_entry(&entry_uuid, &missing), Err(CopyError::GroupNotFound(u)) if u == missing)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_group() -> Result<(), CopyError> {
+        let mut db = Database::new(Default::default());
+
+        let mut source = Group::new("Source");
+        let source_uuid = source.uuid;
+        let mut child = Group::new("Child");
+        let child_uuid = child.uuid;
+        child.add_child(Entry::new());
+        source.add_child(child);
+        source.add_child(Entry::new());
+        db.root.add_child(source);
+
+        let original_uuids: Vec<_> = db.root.iter_entries().map(|e| e.uuid).collect();
+
+        let root_uuid = db.root.uuid;
+        let copy_uuid = db.copy_group(&source_uuid, &root_uuid)?;
+
+        assert_eq!(db.root.iter_groups().count(), 4);
+        assert_eq!(db.root.iter_entries().count(), 4);
+
+        let copy = db
+            .root
+            .groups()
+            .into_iter()
+            .find(|g| g.uuid == copy_uuid)
+            .unwrap();
+        assert_eq!(copy.name, "Source");
+        assert!(copy.iter_groups().all(|g| g.uuid != child_uuid));
+        assert!(copy.iter_entries().all(|e| !original_uuids.contains(&e.uuid)));
+
+        assert!(matches!(
+            db.copy_group(&source_uuid, &source_uuid),
+            Err(CopyError::Cycle(_))
+        ));
+        assert!(matches!(
+            db.copy_group(&source_uuid, &child_uuid),
+            Err(CopyError::Cycle(_))
+        ));
+
+        Ok(())
+    }
+
+    /// A database with fixed UUIDs and times, which saves to the same bytes for the same seed
+    #[cfg(feature = "save_kdbx4")]
+    fn deterministic_database() -> Database {
+        use crate::config::{DatabaseConfig, DatabaseSettings, KdfConfig};
+        use crate::db::{CustomDataItem, Value};
+
+        let now = "2024-01-01T12:00:00".parse().unwrap();
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 100 },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        db.set_name("Snapshot");
+        db.meta.database_name_changed = Some(now);
+        db.root.uuid = uuid::uuid!("8f6d2b1c-3a4e-4c5d-9e7f-0a1b2c3d4e5f");
+
+        let mut entry = Entry {
+            uuid: uuid::uuid!("1a2b3c4d-5e6f-4a8b-9c0d-1e2f3a4b5c6d"),
+            ..Entry::new()
+        };
+        entry.set("Title", "Mail");
+        entry.set("Password", "hunter2");
+        for name in ["b.txt", "a.txt", "c.txt"] {
+            entry.add_attachment(&mut db, name, name.as_bytes().to_vec());
+        }
+        for key in ["b", "a", "c"] {
+            entry.custom_data.items.insert(
+                key.to_string(),
+                CustomDataItem {
+                    value: Some(Value::Unprotected(key.to_string())),
+                    last_modification_time: Some(now),
+                },
+            );
+        }
+        entry.times = Times::new_at(now);
+        db.root.add_child(entry);
+        db.root.times = Times::new_at(now);
+
+        db
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn save_deterministic() {
+        let db = deterministic_database();
+        let key = DatabaseKey::new().with_password("snapshot");
+        let save = |seed| {
+            let mut buffer = Vec::new();
+            db.save_deterministic(&mut buffer, key.clone(), seed).unwrap();
+            buffer
+        };
+
+        if std::env::var_os("KEEPASS_UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write("tests/resources/test_db_deterministic_seed_42.kdbx", save(42)).unwrap();
+        }
+
+        let snapshot = include_bytes!("../../tests/resources/test_db_deterministic_seed_42.kdbx");
+        assert_eq!(save(42), snapshot);
+        assert_ne!(save(43), snapshot);
+
+        let mut normal = Vec::new();
+        db.save(&mut normal, key.clone()).unwrap();
+        assert_ne!(normal, snapshot);
+
+        let reopened = Database::parse(snapshot, key).unwrap();
+        assert_eq!(reopened.root, db.root);
+    }
+
+    #[test]
+    fn detach_and_attach() -> Result<(), CopyError> {
+        let mut db = Database::new(Default::default());
+        let now = Times::epoch() + chrono::Duration::days(1000);
+        db.set_time_provider(Box::new(FixedTimeProvider(now)));
+
+        let mut source = Group::new("Source");
+        let source_uuid = source.uuid;
+        let mut child = Group::new("Child");
+        let child_uuid = child.uuid;
+        child.add_child(Entry::new());
+        source.add_child(child);
+        let target = Group::new("Target");
+        let target_uuid = target.uuid;
+        db.root.add_child(source);
+        db.root.add_child(target);
+
+        let detached = db.detach_by_uuid(&child_uuid).unwrap();
+        let original = detached.clone();
+        assert!(db.get_by_uuid(&child_uuid).is_none());
+        assert!(db.detach_by_uuid(&child_uuid).is_none());
+        assert!(db.detach_by_uuid(&db.root.uuid.clone()).is_none());
+        assert!(db.deleted_objects.objects.is_empty());
+
+        // the node cannot be attached below itself, next to a copy of itself or to a missing group
+        assert!(matches!(
+            db.attach(detached.clone(), &child_uuid),
+            Err(CopyError::Cycle(uuid)) if uuid == child_uuid
+        ));
+        db.add_to_group(&source_uuid, original.clone());
+        assert!(matches!(
+            db.attach(detached.clone(), &target_uuid),
+            Err(CopyError::UuidCollision(uuids)) if uuids.len() == 2
+        ));
+        db.detach_by_uuid(&child_uuid);
+        let missing = uuid::Uuid::new_v4();
+        assert!(matches!(
+            db.attach(detached.clone(), &missing),
+            Err(CopyError::GroupNotFound(uuid)) if uuid == missing
+        ));
+
+        db.attach(detached, &target_uuid)?;
+        let Some(NodeRef::Group(attached)) = db.get_by_uuid(&child_uuid) else {
+            panic!("the group was not attached");
+        };
+        assert_eq!(attached.times.get_location_changed(), Some(&now));
+        let mut expected = original;
+        if let Node::Group(g) = &mut expected {
+            g.times.set_location_changed(now);
+        }
+        assert_eq!(Node::Group(attached.clone()), expected);
+        assert_eq!(db.root.groups()[1].children.len(), 1);
+        assert!(db.root.groups()[0].children.is_empty());
+        assert!(db.deleted_objects.objects.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn with_root_mut() {
+        let mut db = Database::new(Default::default());
+        let entry = Entry::new();
+        let entry_uuid = entry.uuid;
+
+        let count = db
+            .with_root_mut(|root| {
+                root.add_child(entry);
+                root.add_child(Group::new("Group"));
+                root.children.len()
+            })
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(db.root().children.len(), 2);
+
+        let before = db.clone();
+
+        let res = db.with_root_mut(|root| root.uuid = uuid::Uuid::new_v4());
+        assert!(matches!(res, Err(TreeError::RootUuidChanged { .. })));
+        assert_eq!(db, before);
+
+        let res = db.with_root_mut(|root| {
+            let mut duplicate = Entry::new();
+            duplicate.uuid = entry_uuid;
+            root.add_child(duplicate);
+        });
+        assert!(matches!(res, Err(TreeError::DuplicateUuid(u)) if u == entry_uuid));
+        assert_eq!(db, before);
+
+        let res = db.with_root_mut(|root| {
+            for entry in root.iter_entries_mut() {
+                entry.attachments.insert("missing".to_string(), 0);
+            }
+        });
+        assert!(matches!(res, Err(TreeError::MissingAttachment { index: 0, .. })));
+        assert_eq!(db, before);
+
+        db.root_mut().name = "Renamed".to_string();
+        assert_eq!(db.root().name, "Renamed");
+    }
+
+    #[test]
+    fn time_provider() {
+        let now = Times::epoch() + chrono::Duration::days(365);
+
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(now)));
+        assert_eq!(db.now(), now);
+
+        let mut group = db.new_group("Group");
+        assert_eq!(group.times.get_creation(), Some(&now));
+
+        let entry = db.new_entry();
+        assert_eq!(entry.times.get_last_modification(), Some(&now));
+        let entry_uuid = entry.uuid;
+        group.add_child(entry);
+        db.root.add_child(group);
+
+        db.delete_by_uuid(&entry_uuid, true);
+        assert_eq!(db.deleted_objects.objects.len(), 1);
+        assert_eq!(db.deleted_objects.objects[0].deletion_time, now);
+
+        // the time provider is shared between clones but does not affect equality
+        let other = db.clone();
+        assert_eq!(other.now(), now);
+        assert_eq!(
+            other,
+            Database {
+                clock: Default::default(),
+                ..db
+            }
+        );
+    }
+
+    #[test]
+    fn time_provider_for_nodes() {
+        let created = Times::epoch() + chrono::Duration::days(365);
+        let changed = created + chrono::Duration::days(1);
+
+        // nodes that are already in the database and nodes that are added later use its clock
+        let mut db = Database::new(Default::default());
+        let mut group = Group::new("Group");
+        group.add_child(Entry::new());
+        db.root.add_child(group);
+        db.set_time_provider(Box::new(FixedTimeProvider(changed)));
+        db.root.add_child(Entry::new());
+
+        for entry in db.root.iter_entries_mut() {
+            entry.set("Title", "Changed");
+            assert_eq!(entry.times.get_last_modification(), Some(&changed));
+            entry.increment_usage();
+            assert_eq!(entry.times.get_last_access(), Some(&changed));
+
+            entry.times.expires = true;
+            entry.times.set_expiry(changed);
+            assert!(entry.is_expired());
+            entry.times.set_expiry(changed + chrono::Duration::seconds(1));
+            assert!(!entry.is_expired());
+        }
+        let group = &mut db.root.groups_mut()[0];
+        group.set_notes(Some("Changed"));
+        assert_eq!(group.times.get_last_modification(), Some(&changed));
+
+        // without updates on changes, setters keep the timestamps, but explicit touches do not
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(created)));
+        let entry = db.new_entry();
+        let entry_uuid = entry.uuid;
+        db.root.add_child(entry);
+        db.set_time_provider(Box::new(FixedTimeProvider(changed)));
+        db.set_update_times(false);
+        db.set_name("Unchanged");
+        assert_eq!(db.meta.database_name_changed, None);
+
+        let Some(NodeRefMut::Entry(entry)) = db.get_by_uuid_mut(&entry_uuid) else {
+            panic!("entry not found");
+        };
+        entry.set("Title", "Changed");
+        entry.set_totp(b"secret", Default::default());
+        assert_eq!(entry.times.get_last_modification(), Some(&created));
+        assert!(entry.update_history());
+        assert_eq!(entry.times.get_last_modification(), Some(&changed));
+    }
+
+    #[test]
+    fn adding_children_touches_parent() {
+        let created = Times::epoch() + chrono::Duration::days(365);
+        let changed = created + chrono::Duration::days(1);
+        let later = changed + chrono::Duration::days(1);
+
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(created)));
+        let group = db.new_group("Group");
+        let group_uuid = group.uuid;
+        db.root.add_child(group);
+        db.root.times.set_last_modification(created);
+
+        db.set_time_provider(Box::new(FixedTimeProvider(changed)));
+        db.root.add_child(Entry::new());
+        assert_eq!(db.root.times.get_last_modification(), Some(&changed));
+
+        db.set_time_provider(Box::new(FixedTimeProvider(later)));
+        db.root.add_child_at(0, Entry::new());
+        assert_eq!(db.root.times.get_last_modification(), Some(&later));
+
+        db.set_time_provider(Box::new(FixedTimeProvider(changed)));
+        db.attach(Node::Entry(Entry::new()), &group_uuid).unwrap();
+        let group = &db.root.groups()[0];
+        assert_eq!(group.times.get_last_modification(), Some(&changed));
+
+        // without updates on changes, the parent keeps its timestamps
+        db.set_time_provider(Box::new(FixedTimeProvider(created)));
+        db.set_update_times(false);
+        db.root.add_child(Entry::new());
+        db.attach(Node::Entry(Entry::new()), &group_uuid).unwrap();
+        assert_eq!(db.root.times.get_last_modification(), Some(&later));
+        let group = &db.root.groups()[0];
+        assert_eq!(group.times.get_last_modification(), Some(&changed));
+    }
 
-impl FromStr for Color {
-    type Err = ParseColorError;
+    #[test]
+    fn mark_used() {
+        let created = Times::epoch() + chrono::Duration::days(365);
+        let used = created + chrono::Duration::days(1);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.starts_with('#') || s.len() != 7 {
-            return Err(ParseColorError(s.to_string()));
-        }
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(created)));
+        let entry = db.new_entry();
+        let entry_uuid = entry.uuid;
+        db.root.add_child(entry);
 
-        let v =
-            u64::from_str_radix(s.trim_start_matches('#'), 16).map_err(|_e| ParseColorError(s.to_string()))?;
+        db.set_time_provider(Box::new(FixedTimeProvider(used)));
+        assert!(db.mark_used(&entry_uuid));
+        assert!(db.mark_used(&entry_uuid));
 
-        let r = ((v >> 16) & 0xff) as u8;
-        let g = ((v >> 8) & 0xff) as u8;
-        let b = (v & 0xff) as u8;
+        let entry = find_entry(&db, &entry_uuid).unwrap();
+        assert_eq!(entry.times.usage_count, 2);
+        assert_eq!(entry.times.get_last_access(), Some(&used));
+        assert_eq!(entry.times.get_last_modification(), Some(&created));
 
-        Ok(Self { r, g, b })
+        assert!(!db.mark_used(&uuid::Uuid::new_v4()));
     }
-}
 
-impl std::fmt::Display for Color {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    #[test]
+    fn export_group() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::db::{meta::Icon, History};
+
+        let mut db = Database::new(Default::default());
+        db.set_name("Everything");
+
+        let icon = |data: &[u8]| Icon {
+            uuid: uuid::Uuid::new_v4(),
+            data: data.to_vec(),
+        };
+        let (entry_icon, group_icon, unused_icon) = (icon(b"entry"), icon(b"group"), icon(b"unused"));
+        db.meta.custom_icons.icons = vec![unused_icon.clone(), entry_icon.clone(), group_icon.clone()];
+
+        let mut work_entry = Entry::new();
+        work_entry.add_attachment(&mut db, "report.pdf", b"report".to_vec());
+        work_entry.add_attachment(&mut db, "photo.jpg", b"photo".to_vec());
+        work_entry.custom_icon_uuid = Some(unused_icon.uuid);
+        let mut work = Group::new("Work");
+        work.add_child(work_entry);
+        db.root.add_child(work);
+
+        let mut old_version = Entry::new();
+        old_version.add_attachment(&mut db, "old.txt", b"old".to_vec());
+        let mut family_entry = Entry::new();
+        family_entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Netflix".to_string()));
+        family_entry.add_attachment(&mut db, "photo.jpg", b"photo".to_vec());
+        family_entry.custom_icon_uuid = Some(entry_icon.uuid);
+        let mut history = History::default();
+        history.add_entry(old_version);
+        family_entry.history = Some(history);
+        let family_entry_uuid = family_entry.uuid;
+
+        let mut kids = Group::new("Kids");
+        kids.custom_icon_uuid = Some(group_icon.uuid);
+        let mut family = Group::new("Family");
+        family.add_child(family_entry);
+        family.add_child(kids);
+        let family_uuid = family.uuid;
+        db.root.add_child(family);
+
+        let before = db.clone();
+        let exported = db.export_group(&family_uuid)?;
+        assert_eq!(db, before);
+
+        assert_eq!(exported.root.uuid, family_uuid);
+        assert_eq!(exported.name(), "Family");
+        assert_eq!(
+            exported.meta.custom_icons.icons,
+            vec![entry_icon.clone(), group_icon]
+        );
+        assert_eq!(exported.header_attachments.len(), 2);
+
+        let check = |db: &Database| {
+            let entry = find_entry(db, &family_entry_uuid).unwrap();
+            assert_eq!(entry.get_title(), Some("Netflix"));
+            assert_eq!(entry.get_attachment(db, "photo.jpg"), Some(&b"photo"[..]));
+            let old_version = &entry.history.as_ref().unwrap().get_entries()[0];
+            assert_eq!(old_version.get_attachment(db, "old.txt"), Some(&b"old"[..]));
+            assert_eq!(entry.custom_icon_uuid, Some(entry_icon.uuid));
+            assert!(db.root.get(&["Kids"]).is_some());
+            assert!(db.root.get(&["Work"]).is_none());
+        };
+        check(&exported);
+
+        #[cfg(feature = "save_kdbx4")]
+        {
+            let key = || DatabaseKey::new().with_password("shared");
+            let mut buffer = Vec::new();
+            exported.save(&mut buffer, key())?;
+            let reopened = Database::open(&mut buffer.as_slice(), key())?;
+            check(&reopened);
+            assert_eq!(reopened.meta.custom_icons, exported.meta.custom_icons);
+        }
+
+        assert!(matches!(
+            db.export_group(&uuid::Uuid::new_v4()),
+            Err(CopyError::GroupNotFound(_))
+        ));
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod database_tests {
-    use std::fs::File;
+    #[test]
+    fn import_group() -> Result<(), CopyError> {
+        use crate::db::{meta::Icon, UuidCollisionMode};
+
+        let mut source = Database::new(Default::default());
+        let icon = Icon {
+            uuid: uuid::Uuid::new_v4(),
+            data: b"icon".to_vec(),
+        };
+        source.meta.custom_icons.icons = vec![icon.clone()];
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Netflix".to_string()));
+        entry.add_attachment(&mut source, "photo.jpg", b"photo".to_vec());
+        entry.custom_icon_uuid = Some(icon.uuid);
+        let mut family = Group::new("Family");
+        family.add_child(entry);
+        family.add_child(Group::new("Kids"));
+        let family_uuid = family.uuid;
+        source.root.add_child(family);
+
+        let mut db = Database::new(Default::default());
+        db.header_attachments
+            .push(crate::db::HeaderAttachment::new(1, b"local".to_vec()));
+        let shared = Group::new("Shared");
+        let shared_uuid = shared.uuid;
+        db.root.add_child(shared);
+
+        let first = db.import_group(&source, &family_uuid, &shared_uuid, UuidCollisionMode::Regenerate)?;
+        assert_eq!(first.groups.len(), 2);
+        assert_eq!(first.groups[0], family_uuid);
+        assert_eq!(first.entries.len(), 1);
+        assert_eq!(first.icons, vec![icon.uuid]);
+        assert_eq!(first.attachments, vec![1]);
+
+        let err = db
+            .import_group(&source, &family_uuid, &shared_uuid, UuidCollisionMode::Fail)
+            .unwrap_err();
+        assert!(matches!(&err, CopyError::UuidCollision(uuids) if uuids.len() == 3));
+
+        let second = db.import_group(&source, &family_uuid, &shared_uuid, UuidCollisionMode::Regenerate)?;
+        assert_ne!(second.groups[0], family_uuid);
+        assert!(second.icons.is_empty());
+        assert!(second.attachments.is_empty());
+        assert_eq!(db.meta.custom_icons.icons, vec![icon.clone()]);
+
+        let shared = match db.root.get(&["Shared"]) {
+            Some(NodeRef::Group(g)) => g,
+            _ => panic!("Expected the group Shared"),
+        };
+        let families: Vec<&Group> = shared.iter_groups().filter(|g| g.name == "Family").collect();
+        assert_eq!(families.len(), 2);
+        assert_ne!(families[0].uuid, families[1].uuid);
+
+        let entries: Vec<&Entry> = shared.iter_entries().collect();
+        assert_eq!(entries.len(), 2);
+        assert_ne!(entries[0].uuid, entries[1].uuid);
+        for entry in entries {
+            assert_eq!(entry.get_title(), Some("Netflix"));
+            assert_eq!(entry.get_attachment(&db, "photo.jpg"), Some(&b"photo"[..]));
+            assert_eq!(entry.custom_icon_uuid, Some(icon.uuid));
+        }
+        assert_eq!(db.header_attachments[0].content, b"local");
+
+        assert!(matches!(
+            db.import_group(
+                &source,
+                &family_uuid,
+                &uuid::Uuid::new_v4(),
+                UuidCollisionMode::Fail
+            ),
+            Err(CopyError::GroupNotFound(_))
+        ));
 
-    use crate::{error::DatabaseOpenError, Database, DatabaseKey};
+        Ok(())
+    }
 
     #[test]
     fn test_xml() -> Result<(), DatabaseOpenError> {
@@ -845,4 +3144,914 @@ mod database_tests {
 
         assert_eq!(db, db_loaded);
     }
+
+    #[test]
+    fn entry_templates() {
+        let mut db = Database::new(Default::default());
+        let mut templates = Group::new("Templates");
+        let templates_uuid = templates.uuid;
+
+        let mut template = Entry::new();
+        template
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Server".to_string()));
+        template
+            .fields
+            .insert("Host".to_string(), Value::Unprotected("example.com".to_string()));
+        template.icon_id = Some(30);
+        template.custom_icon_uuid = Some(db.add_custom_icon(vec![1, 2, 3]));
+        template.autotype = Some(AutoType {
+            enabled: true,
+            sequence: Some("{USERNAME}{ENTER}".to_string()),
+            associations: vec![],
+        });
+        template.times.set_creation(Times::epoch());
+        let mut old = template.clone();
+        template
+            .history
+            .get_or_insert_with(Default::default)
+            .add_entry(old.clone());
+        let template_uuid = template.uuid;
+        templates.add_child(template);
+        db.root.add_child(templates);
+
+        let changed = Times::epoch() + chrono::Duration::days(365);
+        db.set_time_provider(Box::new(FixedTimeProvider(changed)));
+        db.set_entry_templates_group(Some(templates_uuid));
+        assert_eq!(db.entry_templates_group(), Some(templates_uuid));
+        assert_eq!(db.meta.entry_templates_group_changed, Some(changed));
+
+        let entry = db.new_entry_from_template(&template_uuid).unwrap();
+        assert_ne!(entry.uuid, template_uuid);
+        assert_eq!(entry.get("Host"), Some("example.com"));
+        assert_eq!(entry.icon_id, Some(30));
+        assert_eq!(entry.custom_icon_uuid, old.custom_icon_uuid);
+        assert_eq!(entry.autotype, old.autotype);
+        assert_eq!(entry.times.get_creation(), Some(&changed));
+        assert_eq!(entry.history, None);
+
+        old.uuid = entry.uuid;
+        old.times = entry.times.clone();
+        assert_eq!(entry, old);
+
+        assert!(db.new_entry_from_template(&templates_uuid).is_none());
+        assert!(db.new_entry_from_template(&uuid::Uuid::new_v4()).is_none());
+
+        #[cfg(feature = "save_kdbx4")]
+        {
+            let mut buffer = Vec::new();
+            db.save(&mut buffer, DatabaseKey::new().with_password("test"))
+                .unwrap();
+            let reopened =
+                Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("test")).unwrap();
+            assert_eq!(reopened.entry_templates_group(), Some(templates_uuid));
+            assert_eq!(reopened.meta.entry_templates_group_changed, Some(changed));
+        }
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn named_database() {
+        let db = Database::new_with_description("Passwords", "Shared passwords");
+        assert_eq!(db.name(), "Passwords");
+        assert_eq!(db.description(), Some("Shared passwords"));
+        assert!(db.meta.database_name_changed.is_some());
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("test"))
+            .unwrap();
+        let reopened =
+            Database::open(&mut buffer.as_slice(), DatabaseKey::new().with_password("test")).unwrap();
+        assert_eq!(reopened.name(), "Passwords");
+        assert_eq!(reopened.description(), Some("Shared passwords"));
+        assert_eq!(reopened.meta.database_name_changed, db.meta.database_name_changed);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn view_state_round_trip() {
+        let key = || DatabaseKey::new().with_password("demopass");
+        let mut db = Database::open(
+            &mut File::open("tests/resources/test_db_with_password.kdbx").unwrap(),
+            key(),
+        )
+        .unwrap();
+
+        let view_state = |db: &Database| {
+            let entries: Vec<_> = db
+                .root
+                .iter_groups()
+                .map(|g| (g.uuid, g.get_last_top_visible_entry()))
+                .collect();
+            (db.last_selected_group(), db.last_top_visible_group(), entries)
+        };
+        let (selected, top_visible, entries) = view_state(&db);
+        assert_eq!(
+            selected,
+            Some(uuid::uuid!("de257826-e496-b240-bf5d-5ca98c1adc19"))
+        );
+        assert_eq!(
+            top_visible,
+            Some(uuid::uuid!("c02c6828-bf87-e44a-88e8-262076debede"))
+        );
+        assert!(entries.iter().any(|(_, entry)| entry.is_some()));
+
+        let reopen = |db: &Database| {
+            let mut buffer = Vec::new();
+            db.save(&mut buffer, key()).unwrap();
+            Database::open(&mut buffer.as_slice(), key()).unwrap()
+        };
+        assert_eq!(view_state(&reopen(&db)), (selected, top_visible, entries));
+
+        // references to nodes that do not exist are kept
+        let deleted = uuid::Uuid::new_v4();
+        db.set_last_selected_group(Some(deleted));
+        db.set_last_top_visible_group(None);
+        let reopened = reopen(&db);
+        assert_eq!(reopened.last_selected_group(), Some(deleted));
+        assert_eq!(reopened.last_top_visible_group(), None);
+    }
+
+    #[test]
+    fn meta_strings() {
+        let created = Times::epoch() + chrono::Duration::days(365);
+        let changed = created + chrono::Duration::days(1);
+
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(changed)));
+        assert_eq!(db.name(), "");
+
+        db.set_name("Passwords");
+        db.set_description(Some("Shared passwords"));
+        db.set_default_username(Some("alice"));
+
+        assert_eq!(db.name(), "Passwords");
+        assert_eq!(db.description(), Some("Shared passwords"));
+        assert_eq!(db.default_username(), Some("alice"));
+        assert_eq!(db.meta.database_name_changed, Some(changed));
+        assert_eq!(db.meta.database_description_changed, Some(changed));
+        assert_eq!(db.meta.default_username_changed, Some(changed));
+
+        assert_eq!(db.new_entry().get_username(), Some("alice"));
+        db.set_default_username(None);
+        assert_eq!(db.new_entry().get_username(), None);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn meta_strings_round_trip() {
+        let mut db = Database::new(Default::default());
+        db.set_name("Passwords");
+        db.set_description(Some("Shared passwords"));
+        db.set_default_username(Some("alice"));
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("testing"),
+        )
+        .unwrap();
+
+        assert_eq!(db_loaded.name(), "Passwords");
+        assert_eq!(db_loaded.description(), Some("Shared passwords"));
+        assert_eq!(db_loaded.default_username(), Some("alice"));
+        assert_eq!(db_loaded.meta, db.meta);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn custom_icon_round_trip() {
+        // a red 4x4 PNG image
+        let png = hex_literal::hex!(
+            "89504e470d0a1a0a0000000d4948445200000004000000040806000000a9f19e7e000000124944415478"
+            "9c63f8cfc0f01f1933902e00003c401fe1e08177b40000000049454e44ae426082"
+        )
+        .to_vec();
+
+        let mut db = Database::new(Default::default());
+        let icon_uuid = db.add_custom_icon(png.clone());
+        assert_eq!(db.add_custom_icon(png.clone()), icon_uuid);
+        assert_eq!(db.meta.custom_icons.icons.len(), 1);
+
+        let mut entry = db.new_entry();
+        entry.custom_icon_uuid = Some(icon_uuid);
+        db.root.add_child(entry);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("testing"),
+        )
+        .unwrap();
+
+        let loaded_uuid = db_loaded.root.entries()[0].custom_icon_uuid.unwrap();
+        assert_eq!(loaded_uuid, icon_uuid);
+        assert_eq!(db_loaded.get_custom_icon(&loaded_uuid), Some(png.as_slice()));
+        assert_eq!(db_loaded.get_custom_icon(&uuid::Uuid::new_v4()), None);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn save_with_progress() {
+        use crate::{
+            config::{DatabaseConfig, DatabaseSettings, DatabaseVersion, InnerCipherConfig, KdfConfig},
+            db::{SavePhase, SaveProgress},
+        };
+
+        for version in [DatabaseVersion::KDB3(1), DatabaseVersion::KDB4(0)] {
+            let mut db = Database::new(DatabaseConfig {
+                version,
+                settings: DatabaseSettings {
+                    kdf: KdfConfig::Aes { rounds: 10 },
+                    inner_cipher: InnerCipherConfig::Salsa20,
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+            // random data does not compress, so writing takes several chunks
+            let mut data = vec![0; 3 * 1024 * 1024];
+            getrandom::fill(&mut data).unwrap();
+            let mut entry = Entry::new();
+            entry.add_attachment(&mut db, "random.bin", data);
+            db.root.add_child(entry);
+
+            let mut events: Vec<SaveProgress> = Vec::new();
+            let mut buffer = Vec::new();
+            db.save_with_progress(
+                &mut buffer,
+                DatabaseKey::new().with_password("testing"),
+                |progress| events.push(progress),
+            )
+            .unwrap();
+
+            let phases: Vec<SavePhase> = events.iter().map(|e| e.phase).collect();
+            let mut distinct_phases = phases.clone();
+            distinct_phases.dedup();
+            assert_eq!(
+                distinct_phases,
+                vec![
+                    SavePhase::KdfDerivation,
+                    SavePhase::Compression,
+                    SavePhase::Encryption,
+                    SavePhase::Writing
+                ]
+            );
+
+            // every phase starts at 0.0 and ends at 1.0, and streaming a KDBX4 database reports its
+            // intermediate progress while compressing
+            let streamed = match db.config.version {
+                DatabaseVersion::KDB4(_) => SavePhase::Compression,
+                _ => SavePhase::Writing,
+            };
+            for phase in distinct_phases {
+                let fractions: Vec<f32> = events
+                    .iter()
+                    .filter(|e| e.phase == phase)
+                    .map(|e| e.fraction_complete)
+                    .collect();
+                assert!(fractions.windows(2).all(|w| w[0] < w[1]));
+                assert_eq!(fractions[0], 0.0);
+                assert_eq!(*fractions.last().unwrap(), 1.0);
+                assert_eq!(fractions.len() > 3, phase == streamed);
+            }
+
+            let db_loaded = Database::open(
+                &mut buffer.as_slice(),
+                DatabaseKey::new().with_password("testing"),
+            )
+            .unwrap();
+            assert_eq!(db_loaded.root, db.root);
+        }
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn parse_limits() {
+        use crate::config::{
+            DatabaseConfig, DatabaseSettings, KdfConfig, OpenOptions, ParseLimit, ParseLimits,
+        };
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        // 16 MiB of zeros compress to a few kilobytes
+        let mut entry = Entry::new();
+        entry.add_attachment(&mut db, "bomb.bin", vec![0; 16 * 1024 * 1024]);
+        db.root.add_child(entry);
+
+        let key = DatabaseKey::new().with_password("testing");
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, key.clone()).unwrap();
+        assert!(buffer.len() < 256 * 1024);
+
+        let open = |limits: ParseLimits| {
+            Database::open_with_options(
+                &mut buffer.as_slice(),
+                key.clone(),
+                &OpenOptions::new().limits(limits),
+            )
+        };
+
+        assert!(matches!(
+            open(ParseLimits {
+                max_decompressed_size: 1024 * 1024,
+                ..Default::default()
+            }),
+            Err(DatabaseOpenError::LimitExceeded(ParseLimit::DecompressedSize))
+        ));
+        assert!(matches!(
+            open(ParseLimits {
+                max_binary_size: 1024 * 1024,
+                ..Default::default()
+            }),
+            Err(DatabaseOpenError::LimitExceeded(ParseLimit::BinarySize))
+        ));
+        assert!(matches!(
+            open(ParseLimits {
+                max_total_binary_size: 1024 * 1024,
+                ..Default::default()
+            }),
+            Err(DatabaseOpenError::LimitExceeded(ParseLimit::TotalBinarySize))
+        ));
+        assert!(matches!(
+            open(ParseLimits {
+                max_entries: 0,
+                ..Default::default()
+            }),
+            Err(DatabaseOpenError::LimitExceeded(ParseLimit::Entries))
+        ));
+
+        let db_loaded = open(ParseLimits::default()).unwrap();
+        assert_eq!(db_loaded.header_attachments[0].content.len(), 16 * 1024 * 1024);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn save_drops_unused_attachments() {
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry.add_attachment(&mut db, "removed.txt", b"removed".to_vec());
+        entry.add_attachment(&mut db, "kept.txt", b"kept".to_vec());
+        entry.update_history();
+        entry.add_attachment(&mut db, "history.txt", b"history".to_vec());
+        entry.update_history();
+        entry.remove_attachment("removed.txt");
+        entry.remove_attachment("history.txt");
+        db.root.add_child(entry);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+        assert_eq!(db.header_attachments.len(), 3);
+
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("testing"),
+        )
+        .unwrap();
+
+        // "removed" is still referenced by the first history entry, "history" by the second one
+        assert_eq!(db_loaded.header_attachments.len(), 3);
+
+        let mut entry = db_loaded.root.iter_entries().next().unwrap().clone();
+        entry.history = None;
+        let mut db = Database::new(Default::default());
+        db.header_attachments = db_loaded.header_attachments;
+        db.root.add_child(entry);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("testing"),
+        )
+        .unwrap();
+
+        assert_eq!(db_loaded.header_attachments.len(), 1);
+        assert_eq!(db_loaded.header_attachments[0].content, b"kept");
+        let entry = db_loaded.root.iter_entries().next().unwrap();
+        assert_eq!(entry.attachment_names(), vec!["kept.txt"]);
+        assert_eq!(entry.attachments.get("kept.txt"), Some(&0));
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn test_custom_data_roundtrip() {
+        use crate::db::{Entry, Group, Node};
+
+        let mut db = Database::new(Default::default());
+
+        let mut entry = Entry::new();
+        entry
+            .custom_data
+            .set("plugin.entry".to_string(), "entry value".to_string());
+        db.root.add_child(entry);
+
+        let mut group = Group::new("Child");
+        group
+            .custom_data
+            .set("plugin.group".to_string(), "group value".to_string());
+        db.root.add_child(group);
+
+        db.root
+            .custom_data
+            .set("plugin.root".to_string(), "root value".to_string());
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("testing"),
+        )
+        .unwrap();
+
+        assert_eq!(db_loaded.root.custom_data.get("plugin.root"), Some("root value"));
+
+        let mut seen = 0;
+        for node in &db_loaded.root.children {
+            match node {
+                Node::Entry(e) => {
+                    assert_eq!(e.custom_data.get("plugin.entry"), Some("entry value"));
+                    assert_eq!(e.custom_data.get("plugin.group"), None);
+                    seen += 1;
+                }
+                Node::Group(g) => {
+                    assert_eq!(g.custom_data.get("plugin.group"), Some("group value"));
+                    assert_eq!(g.custom_data.len(), 1);
+                    seen += 1;
+                }
+            }
+        }
+        assert_eq!(seen, 2);
+        assert_eq!(db, db_loaded);
+    }
+
+    #[cfg(feature = "save_kdbx4")]
+    #[test]
+    fn open_with_recovery() {
+        use crate::{
+            config::{CompressionConfig, DatabaseConfig, DatabaseSettings, KdfConfig},
+            db::RecoveryWarning,
+        };
+        use secstr::SecStr;
+
+        const MIB: usize = 1024 * 1024;
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                compression: CompressionConfig::None,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        db.meta.database_name = Some("Damaged".to_string());
+
+        // the payload is split into three HMAC blocks of 1 MiB, of which the middle one only holds
+        // (part of) the notes of entry B
+        for (title, notes_size, password) in [
+            ("A", 6 * MIB / 10, Some("password of A")),
+            ("B", 3 * MIB / 2, Some("password of B")),
+            ("C", 4 * MIB / 10, Some("password of C")),
+            ("D", 0, None),
+        ] {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+            entry
+                .fields
+                .insert("Notes".to_string(), Value::Unprotected("n".repeat(notes_size)));
+            if let Some(password) = password {
+                entry.fields.insert(
+                    "Password".to_string(),
+                    Value::Protected(SecStr::from(password.to_string())),
+                );
+            }
+            db.root.add_child(entry);
+        }
+
+        let key = DatabaseKey::new().with_password("testing");
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, key.clone()).unwrap();
+        assert!(buffer.len() > 2 * MIB && buffer.len() < 3 * MIB);
+
+        let middle = buffer.len() / 2;
+        for byte in &mut buffer[middle..middle + 16] {
+            *byte ^= 0xff;
+        }
+
+        let res = Database::open(&mut buffer.as_slice(), key.clone());
+        let offset = match res {
+            Err(DatabaseOpenError::Corrupt {
+                block_index: 1,
+                offset,
+            }) => offset,
+            _ => panic!("Expected block 1 to be corrupt, got {:?}", res),
+        };
+        assert!(offset > MIB as u64 && offset < middle as u64);
+
+        let (recovered, warnings) = Database::open_with_recovery(&mut buffer.as_slice(), key);
+        let recovered = recovered.unwrap();
+
+        assert!(matches!(
+            warnings[0],
+            RecoveryWarning::CorruptBlock { block_index: 1, offset: o } if o == offset
+        ));
+        assert!(matches!(warnings[1], RecoveryWarning::XmlDamaged(_)));
+        assert!(matches!(warnings[2], RecoveryWarning::EntryDropped { .. }));
+        assert!(matches!(
+            warnings[3],
+            RecoveryWarning::ProtectedValuesDropped { .. }
+        ));
+        assert_eq!(warnings.len(), 4);
+
+        let titles: Vec<_> = recovered
+            .root
+            .iter_entries()
+            .map(|e| e.get_title().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["A", "C", "D"]);
+
+        let entry = |title: &str| {
+            recovered
+                .root
+                .iter_entries()
+                .find(|e| e.get_title() == Some(title))
+                .unwrap()
+        };
+        assert_eq!(entry("A").get_password(), Some("password of A"));
+        assert_eq!(entry("C").get_password(), None);
+        assert_eq!(entry("C").get("Notes").map(|n| n.len()), Some(4 * MIB / 10));
+        assert_eq!(entry("D").get_password(), None);
+
+        // the metadata precedes the damage and is recovered
+        assert_eq!(recovered.meta.database_name, Some("Damaged".to_string()));
+
+        // a compressed payload cannot be inflated past the damage, so only entry A is recovered.
+        // The notes are random hexadecimal digits that compress to about half their size.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random_notes = |size: usize| -> String {
+            (0..size)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    char::from_digit((state % 16) as u32, 16).unwrap()
+                })
+                .collect()
+        };
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                compression: CompressionConfig::GZip,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        db.meta.database_name = Some("Damaged".to_string());
+        for (title, notes_size) in [("A", MIB), ("B", 3 * MIB), ("C", MIB)] {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+            entry
+                .fields
+                .insert("Notes".to_string(), Value::Unprotected(random_notes(notes_size)));
+            entry.fields.insert(
+                "Password".to_string(),
+                Value::Protected(SecStr::from(format!("password of {}", title))),
+            );
+            db.root.add_child(entry);
+        }
+
+        let key = DatabaseKey::new().with_password("testing");
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, key.clone()).unwrap();
+        assert!(buffer.len() > 2 * MIB && buffer.len() < 3 * MIB);
+
+        let middle = buffer.len() / 2;
+        for byte in &mut buffer[middle..middle + 16] {
+            *byte ^= 0xff;
+        }
+
+        let (recovered, warnings) = Database::open_with_recovery(&mut buffer.as_slice(), key);
+        let recovered = recovered.unwrap();
+
+        assert!(matches!(
+            warnings[0],
+            RecoveryWarning::CorruptBlock { block_index: 1, .. }
+        ));
+        assert!(matches!(
+            warnings[1],
+            RecoveryWarning::TruncatedPayload { recovered_bytes } if recovered_bytes > MIB as u64
+        ));
+        assert!(matches!(warnings[2], RecoveryWarning::XmlDamaged(_)));
+        assert!(matches!(warnings[3], RecoveryWarning::EntryDropped { .. }));
+        assert_eq!(warnings.len(), 4);
+
+        let entries: Vec<_> = recovered.root.iter_entries().collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_title(), Some("A"));
+        assert_eq!(entries[0].get_password(), Some("password of A"));
+        assert_eq!(recovered.meta.database_name, Some("Damaged".to_string()));
+    }
+
+    #[test]
+    fn find_by_url() {
+        use crate::db::{PublicSuffixRules, UrlMatchMode};
+
+        fn find(db: &Database, mode: UrlMatchMode) -> Vec<&str> {
+            let mut titles: Vec<&str> = db
+                .find_by_url("https://login.example.com/path", mode)
+                .into_iter()
+                .map(|e| e.get_title().unwrap())
+                .collect();
+            titles.sort();
+            titles
+        }
+
+        let mut db = Database::new(Default::default());
+        for (title, url, additional_url) in [
+            ("Root domain", "https://example.com", None),
+            ("Login", "https://login.example.com", None),
+            (
+                "Other",
+                "https://other.com",
+                Some("https://example.com/path/login"),
+            ),
+            ("Unrelated", "https://other.com", None),
+            ("Without scheme", "login.example.com/path", None),
+        ] {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+            entry
+                .fields
+                .insert("URL".to_string(), Value::Unprotected(url.to_string()));
+            if let Some(additional_url) = additional_url {
+                entry.fields.insert(
+                    "KP2A_URL_1".to_string(),
+                    Value::Unprotected(additional_url.to_string()),
+                );
+            }
+            db.root.add_child(entry);
+        }
+
+        let mut hidden = Group::new("Hidden");
+        hidden.set_enable_searching(Some(false));
+        let mut entry = Entry::new();
+        entry.fields.insert(
+            "URL".to_string(),
+            Value::Unprotected("https://login.example.com".to_string()),
+        );
+        hidden.add_child(entry);
+        db.root.add_child(hidden);
+
+        // without a public suffix list, the last two labels of the host names have to match
+        assert_eq!(
+            find(&db, UrlMatchMode::Domain),
+            vec!["Login", "Other", "Root domain", "Without scheme"]
+        );
+        assert_eq!(find(&db, UrlMatchMode::Exact), vec!["Without scheme"]);
+        assert_eq!(find(&db, UrlMatchMode::Prefix), vec!["Login", "Without scheme"]);
+
+        assert!(db.find_by_url("not a url", UrlMatchMode::Domain).is_empty());
+
+        db.set_public_suffix_list(Box::new(PublicSuffixRules::parse("com\n")));
+        assert_eq!(
+            find(&db, UrlMatchMode::Domain),
+            vec!["Login", "Other", "Root domain", "Without scheme"]
+        );
+    }
+
+    #[test]
+    fn open_with_kdf() {
+        use std::{
+            convert::TryInto,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+
+        use crate::{
+            config::{BuiltinKdf, KdfParameters, KeyDerivationFunction, OpenOptions},
+            error::{DatabaseIntegrityError, DatabaseKeyError, KdfError},
+        };
+
+        /// Counts the derivations and returns the composite key or an error instead of deriving a
+        /// key, unless it delegates to the built-in implementation
+        #[derive(Default)]
+        struct StubKdf {
+            calls: AtomicUsize,
+            delegate: bool,
+            fail: bool,
+        }
+
+        impl KeyDerivationFunction for StubKdf {
+            fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                match (self.delegate, self.fail) {
+                    (_, true) => Err(KdfError::Unsupported { uuid: params.uuid() }),
+                    (true, false) => BuiltinKdf.derive_key(composite_key, params),
+                    (false, false) => Ok(composite_key.try_into().unwrap()),
+                }
+            }
+        }
+
+        let open = |path: &str, kdf: std::sync::Arc<StubKdf>| {
+            let mut file = std::fs::File::open(path).unwrap();
+            let options = OpenOptions::new().key_derivation(kdf);
+            Database::open_with_options(&mut file, DatabaseKey::new().with_password("demopass"), &options)
+        };
+
+        for path in [
+            "tests/resources/test_db_with_password.kdbx",
+            "tests/resources/test_db_kdbx4_with_password_argon2.kdbx",
+        ] {
+            let kdf = std::sync::Arc::new(StubKdf {
+                delegate: true,
+                ..Default::default()
+            });
+            assert!(open(path, kdf.clone()).is_ok());
+            assert_eq!(kdf.calls.load(Ordering::SeqCst), 1);
+
+            let kdf = std::sync::Arc::new(StubKdf::default());
+            assert!(matches!(
+                open(path, kdf.clone()),
+                Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))
+            ));
+            assert_eq!(kdf.calls.load(Ordering::SeqCst), 1);
+
+            let kdf = std::sync::Arc::new(StubKdf {
+                fail: true,
+                ..Default::default()
+            });
+            assert!(matches!(
+                open(path, kdf),
+                Err(DatabaseOpenError::DatabaseIntegrity(
+                    DatabaseIntegrityError::KeyDerivation(KdfError::Unsupported { .. })
+                ))
+            ));
+        }
+
+        let mut file = std::fs::File::open("tests/resources/test_db_with_password.kdbx").unwrap();
+        let kdf = StubKdf {
+            delegate: true,
+            ..Default::default()
+        };
+        let db =
+            Database::open_with_kdf(&mut file, DatabaseKey::new().with_password("demopass"), &kdf).unwrap();
+        assert_eq!(db.root.name, "sample");
+
+        // a panicking KDF does not affect later opens on the same thread
+        struct PanickingKdf;
+        impl KeyDerivationFunction for PanickingKdf {
+            fn derive_key(&self, _: &[u8], _: &KdfParameters) -> Result<[u8; 32], KdfError> {
+                panic!("KDF failed");
+            }
+        }
+        let res = std::panic::catch_unwind(|| {
+            let mut file = std::fs::File::open("tests/resources/test_db_with_password.kdbx").unwrap();
+            Database::open_with_kdf(
+                &mut file,
+                DatabaseKey::new().with_password("demopass"),
+                &PanickingKdf,
+            )
+        });
+        assert!(res.is_err());
+        let mut file = std::fs::File::open("tests/resources/test_db_with_password.kdbx").unwrap();
+        assert!(Database::open(&mut file, DatabaseKey::new().with_password("demopass")).is_ok());
+    }
+
+    #[test]
+    fn find_entries_for_url() {
+        use crate::db::{url_match::BROWSER_HIDE_ENTRY_KEY, PublicSuffixRules, UrlMatchMode};
+
+        let entry = |title: &str, field: &str, url: &str| {
+            let mut entry = Entry::new();
+            entry.set("Title", title);
+            entry.set(field, url);
+            entry
+        };
+
+        let mut db = Database::new(Default::default());
+        db.set_public_suffix_list(Box::new(PublicSuffixRules::parse("com\norg\n")));
+        db.root
+            .add_child(entry("Root domain", "URL", "https://example.com"));
+        db.root.add_child(entry("App", "URL", "app.example.com"));
+        db.root
+            .add_child(entry("Other port", "URL", "https://app.example.com:8443"));
+        db.root
+            .add_child(entry("Additional", "KP2A_URL_1", "https://app.example.com/login"));
+        db.root
+            .add_child(entry("Unrelated", "URL", "https://example.org"));
+
+        let mut overridden = entry("Override", "URL", "https://example.org");
+        overridden.set_override_url("https://app.example.com");
+        db.root.add_child(overridden);
+
+        let mut hidden = entry("Hidden", "URL", "https://app.example.com");
+        hidden
+            .custom_data
+            .set(BROWSER_HIDE_ENTRY_KEY.to_string(), "true".to_string());
+        db.root.add_child(hidden);
+
+        let mut not_searchable = Group::new("Not searchable");
+        not_searchable.set_enable_searching(Some(false));
+        not_searchable.add_child(entry("Not searchable", "URL", "https://app.example.com"));
+        db.root.add_child(not_searchable);
+
+        // a recycle bin without the setting to disable searching, as written by some clients
+        let mut recycle_bin = Group::new("Recycle Bin");
+        recycle_bin.add_child(entry("Recycled", "URL", "https://app.example.com"));
+        db.meta.recyclebin_uuid = Some(recycle_bin.uuid);
+        db.root.add_child(recycle_bin);
+
+        let find = |mode: UrlMatchMode| -> Vec<&str> {
+            db.find_entries_for_url("https://app.example.com/login?next=/x", mode)
+                .into_iter()
+                .map(|e| e.get_title().unwrap())
+                .collect()
+        };
+
+        assert!(find(UrlMatchMode::Exact).is_empty());
+        assert_eq!(
+            db.find_entries_for_url("https://App.example.com/login", UrlMatchMode::Exact)
+                .len(),
+            1
+        );
+        assert_eq!(find(UrlMatchMode::Host), vec!["App", "Additional", "Override"]);
+        assert_eq!(
+            find(UrlMatchMode::Domain),
+            vec!["Root domain", "App", "Other port", "Additional", "Override"]
+        );
+        assert!(db.find_entries_for_url("", UrlMatchMode::Domain).is_empty());
+    }
+
+    #[test]
+    fn expired_nodes() {
+        let now = Times::epoch() + chrono::Duration::days(365);
+        let past = now - chrono::Duration::days(1);
+        let future = now + chrono::Duration::days(1);
+
+        let mut db = Database::new(Default::default());
+        db.set_time_provider(Box::new(FixedTimeProvider(now)));
+
+        let times = |expires: bool, expiry: chrono::NaiveDateTime| {
+            let mut times = Times::new_at(now);
+            times.expires = expires;
+            times.set_expiry(expiry);
+            times
+        };
+
+        let mut expired_group = Group::new("Expired");
+        expired_group.times = times(true, past);
+        let mut entry_in_expired_group = Entry::new();
+        entry_in_expired_group.times = times(false, past);
+        expired_group.add_child(entry_in_expired_group);
+
+        let mut not_expiring_group = Group::new("Not expiring");
+        not_expiring_group.times = times(false, past);
+        let mut expired_entry = Entry::new();
+        expired_entry.times = times(true, now);
+        let expired_entry_uuid = expired_entry.uuid;
+        not_expiring_group.add_child(expired_entry);
+
+        let mut future_group = Group::new("Future");
+        future_group.times = times(true, future);
+
+        db.root.add_child(expired_group);
+        db.root.add_child(not_expiring_group);
+        db.root.add_child(future_group);
+
+        let expired_groups: Vec<&str> = db.expired_groups().iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(expired_groups, vec!["Expired"]);
+
+        let expired_nodes = db.expired_nodes();
+        assert_eq!(expired_nodes.len(), 2);
+        assert!(matches!(expired_nodes[0], NodeRef::Group(g) if g.name == "Expired"));
+        assert!(matches!(expired_nodes[1], NodeRef::Entry(e) if e.uuid == expired_entry_uuid));
+
+        // the system clock is well past the expiry times
+        assert!(db.root.groups()[0].is_expired());
+        assert!(!db.root.groups()[1].is_expired());
+        assert!(db.root.groups()[1].entries()[0].is_expired());
+        assert!(!db.root.groups()[0].entries()[0].is_expired());
+    }
 }
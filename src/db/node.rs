@@ -1,6 +1,8 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, rc::Rc};
 
-use crate::db::{entry::Entry, group::Group};
+use uuid::Uuid;
+
+use crate::db::{entry::Entry, group::Group, Times};
 
 /// An owned node in the database tree structure which can either be an Entry or Group
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -18,6 +20,20 @@ impl Node {
     pub fn as_mut(&mut self) -> NodeRefMut<'_> {
         self.into()
     }
+
+    pub(crate) fn uuid(&self) -> Uuid {
+        match self {
+            Node::Group(g) => g.uuid,
+            Node::Entry(e) => e.uuid,
+        }
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: &crate::db::clock::Clock) {
+        match self {
+            Node::Group(g) => g.set_clock(clock),
+            Node::Entry(e) => e.set_clock(clock),
+        }
+    }
 }
 
 impl From<Entry> for Node {
@@ -39,6 +55,40 @@ pub enum NodeRef<'a> {
     Entry(&'a Entry),
 }
 
+impl<'a> NodeRef<'a> {
+    /// The UUID of the group or entry
+    pub fn uuid(&self) -> Uuid {
+        match self {
+            NodeRef::Group(g) => g.uuid,
+            NodeRef::Entry(e) => e.uuid,
+        }
+    }
+
+    /// The name of the group or the title of the entry, which is empty if the entry has none
+    pub fn title(&self) -> &'a str {
+        match self {
+            NodeRef::Group(g) => &g.name,
+            NodeRef::Entry(e) => e.get_title().unwrap_or(""),
+        }
+    }
+
+    /// The timestamps of the group or entry
+    pub fn times(&self) -> &'a Times {
+        match self {
+            NodeRef::Group(g) => &g.times,
+            NodeRef::Entry(e) => &e.times,
+        }
+    }
+
+    pub fn is_entry(&self) -> bool {
+        matches!(self, NodeRef::Entry(_))
+    }
+
+    pub fn is_group(&self) -> bool {
+        matches!(self, NodeRef::Group(_))
+    }
+}
+
 impl<'a> std::convert::From<&'a Node> for NodeRef<'a> {
     fn from(n: &'a Node) -> Self {
         match n {
@@ -55,6 +105,32 @@ pub enum NodeRefMut<'a> {
     Entry(&'a mut Entry),
 }
 
+impl NodeRefMut<'_> {
+    /// The UUID of the group or entry
+    pub fn uuid(&self) -> Uuid {
+        match self {
+            NodeRefMut::Group(g) => g.uuid,
+            NodeRefMut::Entry(e) => e.uuid,
+        }
+    }
+
+    /// The name of the group or the title of the entry, which is empty if the entry has none
+    pub fn title(&self) -> &str {
+        match self {
+            NodeRefMut::Group(g) => &g.name,
+            NodeRefMut::Entry(e) => e.get_title().unwrap_or(""),
+        }
+    }
+
+    pub fn is_entry(&self) -> bool {
+        matches!(self, NodeRefMut::Entry(_))
+    }
+
+    pub fn is_group(&self) -> bool {
+        matches!(self, NodeRefMut::Group(_))
+    }
+}
+
 impl<'a> std::convert::From<&'a mut Node> for NodeRefMut<'a> {
     fn from(n: &'a mut Node) -> Self {
         match n {
@@ -88,3 +164,159 @@ impl<'a> Iterator for NodeIter<'a> {
         Some(head)
     }
 }
+
+/// Order in which `Group::iter_with_path` visits the nodes of a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IterOrder {
+    /// Visit a group, then everything below its first child, then everything below its second
+    /// child, and so on
+    DepthFirst,
+
+    /// Visit a group, then its children, then their children, and so on
+    BreadthFirst,
+}
+
+/// An iterator over Group and Entry references together with the names of the groups containing
+/// them, see `Group::iter_with_path`
+pub struct PathIter<'a> {
+    order: PathIterOrder<'a>,
+}
+
+enum PathIterOrder<'a> {
+    /// `path` holds the names of the groups whose children are iterated by `stack`
+    DepthFirst {
+        root: Option<&'a Group>,
+        path: Vec<&'a str>,
+        stack: Vec<std::slice::Iter<'a, Node>>,
+    },
+
+    /// The nodes are queued with the path of their parent, which its children share
+    BreadthFirst {
+        queue: VecDeque<(Rc<Vec<&'a str>>, NodeRef<'a>)>,
+    },
+}
+
+impl<'a> PathIter<'a> {
+    pub(crate) fn new(root: &'a Group, order: IterOrder) -> Self {
+        let order = match order {
+            IterOrder::DepthFirst => PathIterOrder::DepthFirst {
+                root: Some(root),
+                path: Vec::new(),
+                stack: Vec::new(),
+            },
+            IterOrder::BreadthFirst => PathIterOrder::BreadthFirst {
+                queue: VecDeque::from([(Rc::new(Vec::new()), NodeRef::Group(root))]),
+            },
+        };
+        PathIter { order }
+    }
+}
+
+impl<'a> Iterator for PathIter<'a> {
+    type Item = (Vec<&'a str>, NodeRef<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.order {
+            PathIterOrder::DepthFirst { root, path, stack } => {
+                if let Some(root) = root.take() {
+                    path.push(&root.name);
+                    stack.push(root.children.iter());
+                    return Some((Vec::new(), NodeRef::Group(root)));
+                }
+
+                loop {
+                    match stack.last_mut()?.next() {
+                        Some(Node::Entry(e)) => return Some((path.clone(), NodeRef::Entry(e))),
+                        Some(Node::Group(g)) => {
+                            let group_path = path.clone();
+                            path.push(&g.name);
+                            stack.push(g.children.iter());
+                            return Some((group_path, NodeRef::Group(g)));
+                        }
+                        None => {
+                            stack.pop();
+                            path.pop();
+                        }
+                    }
+                }
+            }
+            PathIterOrder::BreadthFirst { queue } => {
+                let (path, node) = queue.pop_front()?;
+
+                if let NodeRef::Group(g) = node {
+                    let mut children_path = Vec::with_capacity(path.len() + 1);
+                    children_path.extend_from_slice(&path);
+                    children_path.push(g.name.as_str());
+
+                    let children_path = Rc::new(children_path);
+                    queue.extend(g.children.iter().map(|n| (children_path.clone(), n.into())));
+                }
+
+                Some((path.to_vec(), node))
+            }
+        }
+    }
+}
+
+/// A depth-first iterator over mutable references to all entries below a group
+pub(crate) struct EntryIterMut<'a> {
+    stack: Vec<std::slice::IterMut<'a, Node>>,
+}
+
+impl<'a> EntryIterMut<'a> {
+    pub(crate) fn new(group: &'a mut Group) -> Self {
+        Self {
+            stack: vec![group.children.iter_mut()],
+        }
+    }
+}
+
+impl<'a> Iterator for EntryIterMut<'a> {
+    type Item = &'a mut Entry;
+
+    fn next(&mut self) -> Option<&'a mut Entry> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(Node::Entry(e)) => return Some(e),
+                Some(Node::Group(g)) => self.stack.push(g.children.iter_mut()),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod node_tests {
+    use crate::db::{Entry, Group, Node, Value};
+
+    #[test]
+    fn accessors() {
+        let group = Group::new("Group");
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Entry".to_string()));
+        let untitled = Entry::new();
+        let uuids = [group.uuid, entry.uuid, untitled.uuid];
+
+        let mut nodes = [Node::from(group), Node::from(entry), Node::from(untitled)];
+
+        for (node, (uuid, title)) in nodes.iter().zip(uuids.iter().zip(["Group", "Entry", ""])) {
+            let node = node.as_ref();
+            assert_eq!(node.uuid(), *uuid);
+            assert_eq!(node.title(), title);
+            assert_eq!(node.is_group(), title == "Group");
+            assert_eq!(node.is_entry(), title != "Group");
+        }
+
+        for (node, (uuid, title)) in nodes.iter_mut().zip(uuids.iter().zip(["Group", "Entry", ""])) {
+            let node = node.as_mut();
+            assert_eq!(node.uuid(), *uuid);
+            assert_eq!(node.title(), title);
+            assert_eq!(node.is_group(), title == "Group");
+            assert_eq!(node.is_entry(), title != "Group");
+        }
+    }
+}
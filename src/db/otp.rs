@@ -5,8 +5,7 @@ use totp_lite::{totp_custom, Sha1, Sha256, Sha512};
 use url::Url;
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
-const DEFAULT_PERIOD: u64 = 30;
-const DEFAULT_DIGITS: u32 = 8;
+use crate::db::otp_uri::{DEFAULT_DIGITS, DEFAULT_PERIOD};
 
 /// Choices of hash algorithm for TOTP
 #[derive(Debug, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
@@ -154,7 +153,7 @@ impl TOTP {
 
     /// Get the current one-time code
     pub fn value_now(&self) -> Result<OTPCode, SystemTimeError> {
-        let time: u64 = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let time: u64 = system_time_now().duration_since(UNIX_EPOCH)?.as_secs();
         Ok(self.value_at(time))
     }
 
@@ -163,6 +162,19 @@ impl TOTP {
     }
 }
 
+/// The current time. On wasm32-unknown-unknown, where `SystemTime::now` panics, it is read from the
+/// JavaScript clock through chrono.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn system_time_now() -> SystemTime {
+    SystemTime::now()
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn system_time_now() -> SystemTime {
+    let millis = chrono::Utc::now().timestamp_millis();
+    UNIX_EPOCH + Duration::from_millis(u64::try_from(millis).unwrap_or_default())
+}
+
 #[cfg(test)]
 mod kdbx4_otp_tests {
     use super::{TOTPAlgorithm, TOTPError, TOTP};
@@ -0,0 +1,357 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use url::Url;
+
+use crate::error::OtpParseError;
+
+/// Validity of a code if an URI does not specify it, in seconds
+pub(crate) const DEFAULT_PERIOD: u64 = 30;
+
+/// Number of digits of a code if an URI does not specify it
+pub(crate) const DEFAULT_DIGITS: u32 = 6;
+
+/// Characters that are escaped in the components of an URI, all but the unreserved ones
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+const OTP_URI_SCHEME: &str = "otpauth://";
+
+/// Hash algorithm of a one time password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl std::str::FromStr for OtpAlgorithm {
+    type Err = OtpParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_uppercase()[..] {
+            "SHA1" => Ok(OtpAlgorithm::Sha1),
+            "SHA256" => Ok(OtpAlgorithm::Sha256),
+            "SHA512" => Ok(OtpAlgorithm::Sha512),
+            _ => Err(OtpParseError::UnsupportedAlgorithm(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtpAlgorithm::Sha1 => write!(f, "SHA1"),
+            OtpAlgorithm::Sha256 => write!(f, "SHA256"),
+            OtpAlgorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+
+/// The components of an `otpauth://totp/...` URI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpParameters {
+    /// Base32 encoded shared secret
+    pub secret: String,
+
+    /// Provider of the account, from the `issuer` parameter or the label prefix
+    pub issuer: Option<String>,
+
+    /// Name of the account, from the label
+    pub account: Option<String>,
+
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+
+    /// Validity of a code, in seconds
+    pub period: u64,
+}
+
+impl std::str::FromStr for OtpParameters {
+    type Err = OtpParseError;
+
+    /// Parse an URI in the Key Uri Format, e.g.
+    /// `otpauth://totp/ACME%20Co:john@example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if !is_otp_uri(s) {
+            return Err(OtpParseError::Malformed("missing otpauth:// scheme".to_string()));
+        }
+        let uri = Url::parse(s).map_err(|e| OtpParseError::Malformed(e.to_string()))?;
+
+        let otp_type = uri.host_str().unwrap_or_default();
+        if !otp_type.eq_ignore_ascii_case("totp") {
+            return Err(OtpParseError::UnsupportedType(otp_type.to_string()));
+        }
+
+        let label = uri
+            .path()
+            .strip_prefix('/')
+            .ok_or_else(|| OtpParseError::Malformed("missing label".to_string()))?;
+        let label = percent_decode_str(label)
+            .decode_utf8()
+            .map_err(|_| OtpParseError::Malformed("invalid UTF-8 in label".to_string()))?;
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim().to_string()),
+            None => (None, label.trim().to_string()),
+        };
+
+        let mut secret = None;
+        let mut issuer = None;
+        let mut algorithm = OtpAlgorithm::Sha1;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+
+        for (key, value) in uri.query_pairs() {
+            let invalid = || OtpParseError::InvalidParameter(key.to_string());
+
+            match &key[..] {
+                "secret" => secret = Some(value.into_owned()),
+                "issuer" => issuer = Some(value.into_owned()),
+                "algorithm" => algorithm = value.parse()?,
+                "digits" => digits = value.parse().ok().filter(|d| *d > 0).ok_or_else(invalid)?,
+                "period" => period = value.parse().ok().filter(|p| *p > 0).ok_or_else(invalid)?,
+                _ => {}
+            }
+        }
+
+        let secret = secret
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| OtpParseError::Malformed("missing secret".to_string()))?;
+
+        Ok(OtpParameters {
+            secret,
+            issuer: issuer.or(label_issuer).filter(|i| !i.is_empty()),
+            account: Some(account).filter(|a| !a.is_empty()),
+            algorithm,
+            digits,
+            period,
+        })
+    }
+}
+
+impl std::fmt::Display for OtpParameters {
+    /// Write the parameters as an URI in the Key Uri Format, with the issuer as a prefix of the
+    /// label and as a parameter, e.g.
+    /// `otpauth://totp/ACME%20Co:john%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}totp/", OTP_URI_SCHEME)?;
+        if let Some(issuer) = &self.issuer {
+            write!(f, "{}:", percent_encode(issuer))?;
+        }
+        write!(
+            f,
+            "{}?secret={}",
+            percent_encode(self.account.as_deref().unwrap_or_default()),
+            percent_encode(&self.secret)
+        )?;
+        if let Some(issuer) = &self.issuer {
+            write!(f, "&issuer={}", percent_encode(issuer))?;
+        }
+        write!(
+            f,
+            "&algorithm={}&digits={}&period={}",
+            self.algorithm, self.digits, self.period
+        )
+    }
+}
+
+/// Options of a TOTP secret stored with `Entry::set_totp`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpOptions {
+    /// Provider of the account, e.g. `ACME Co`
+    pub issuer: Option<String>,
+
+    /// Name of the account, e.g. `john@example.com`
+    pub account: Option<String>,
+
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+
+    /// Validity of a code, in seconds
+    pub period: u64,
+
+    /// Also store the secret in the `TimeOtp-*` fields that KeePass and the KeeOtp2 plugin use
+    pub split_fields: bool,
+}
+
+impl Default for TotpOptions {
+    fn default() -> Self {
+        TotpOptions {
+            issuer: None,
+            account: None,
+            algorithm: OtpAlgorithm::Sha1,
+            digits: DEFAULT_DIGITS,
+            period: DEFAULT_PERIOD,
+            split_fields: false,
+        }
+    }
+}
+
+/// Whether a field value looks like an `otpauth://` URI
+pub(crate) fn is_otp_uri(value: &str) -> bool {
+    value
+        .trim_start()
+        .get(..OTP_URI_SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(OTP_URI_SCHEME))
+}
+
+/// Encode a secret in base32 without padding, as in otpauth URIs
+pub(crate) fn base32_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut out = String::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    out
+}
+
+/// Escape everything but the unreserved characters of an URI component as %XX
+fn percent_encode(s: &str) -> String {
+    utf8_percent_encode(s, COMPONENT).to_string()
+}
+
+#[cfg(test)]
+mod otp_uri_tests {
+    use super::{OtpAlgorithm, OtpParameters};
+    use crate::error::OtpParseError;
+
+    #[test]
+    fn parse_full_uri() -> Result<(), OtpParseError> {
+        let params: OtpParameters = "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60".parse()?;
+
+        assert_eq!(
+            params,
+            OtpParameters {
+                secret: "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ".to_string(),
+                issuer: Some("ACME Co".to_string()),
+                account: Some("john.doe@email.com".to_string()),
+                algorithm: OtpAlgorithm::Sha256,
+                digits: 8,
+                period: 60,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_minimal_uri() -> Result<(), OtpParseError> {
+        let params: OtpParameters = "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP".parse()?;
+
+        assert_eq!(params.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(params.issuer, None);
+        assert_eq!(params.account, Some("alice".to_string()));
+        assert_eq!(params.algorithm, OtpAlgorithm::Sha1);
+        assert_eq!(params.digits, 6);
+        assert_eq!(params.period, 30);
+
+        // the issuer can also be given as a prefix of the label only
+        let params: OtpParameters = "otpauth://totp/KeePassXC:none?secret=JBSWY3DPEHPK3PXP".parse()?;
+        assert_eq!(params.issuer, Some("KeePassXC".to_string()));
+        assert_eq!(params.account, Some("none".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn base32_encode() {
+        // test vectors of RFC 4648, without the padding
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (data, encoded) in vectors {
+            assert_eq!(super::base32_encode(data.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn format_uri() -> Result<(), OtpParseError> {
+        let params = OtpParameters {
+            secret: "JBSWY3DPEHPK3PXP".to_string(),
+            issuer: Some("ACME Co/Zürich".to_string()),
+            account: Some("john doe@example.com".to_string()),
+            algorithm: OtpAlgorithm::Sha512,
+            digits: 8,
+            period: 60,
+        };
+
+        let uri = params.to_string();
+        assert_eq!(
+            uri,
+            "otpauth://totp/ACME%20Co%2FZ%C3%BCrich:john%20doe%40example.com?secret=JBSWY3DPEHPK3PXP\
+             &issuer=ACME%20Co%2FZ%C3%BCrich&algorithm=SHA512&digits=8&period=60"
+        );
+        assert_eq!(uri.parse::<OtpParameters>()?, params);
+
+        let params = OtpParameters {
+            issuer: None,
+            ..params
+        };
+        assert_eq!(params.to_string().parse::<OtpParameters>()?, params);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_bad_uri() {
+        assert!(matches!(
+            "not an uri".parse::<OtpParameters>(),
+            Err(OtpParseError::Malformed(_))
+        ));
+        assert!(matches!(
+            "https://totp/alice?secret=JBSWY3DPEHPK3PXP".parse::<OtpParameters>(),
+            Err(OtpParseError::Malformed(_))
+        ));
+        assert!(matches!(
+            "otpauth://totp/alice?issuer=ACME".parse::<OtpParameters>(),
+            Err(OtpParseError::Malformed(_))
+        ));
+        assert!(matches!(
+            "otpauth://totp/al%FFice?secret=JBSWY3DPEHPK3PXP".parse::<OtpParameters>(),
+            Err(OtpParseError::Malformed(_))
+        ));
+        // a multibyte character where the scheme would end
+        assert!(matches!(
+            "otpauth:/é?secret=JBSWY3DPEHPK3PXP".parse::<OtpParameters>(),
+            Err(OtpParseError::Malformed(_))
+        ));
+        assert!(!super::is_otp_uri("otpauth:/é"));
+        assert!(matches!(
+            "otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter=1".parse::<OtpParameters>(),
+            Err(OtpParseError::UnsupportedType(t)) if t == "hotp"
+        ));
+        assert!(matches!(
+            "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&algorithm=MD5".parse::<OtpParameters>(),
+            Err(OtpParseError::UnsupportedAlgorithm(a)) if a == "MD5"
+        ));
+        let err = "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&digits=six"
+            .parse::<OtpParameters>()
+            .unwrap_err();
+        assert!(matches!(&err, OtpParseError::InvalidParameter(name) if name == "digits"));
+        assert!(!err.to_string().contains("six"));
+        assert!(matches!(
+            "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&period=0".parse::<OtpParameters>(),
+            Err(OtpParseError::InvalidParameter(name)) if name == "period"
+        ));
+    }
+}
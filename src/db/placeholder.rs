@@ -0,0 +1,225 @@
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, NodeRef};
+
+/// How deep placeholders in the values of placeholders are expanded, so that fields that refer to
+/// each other do not expand forever
+const MAX_DEPTH: usize = 10;
+
+/// How many placeholders are expanded in total, so that values that refer to a field several times,
+/// such as `{S:A}{S:A}`, do not grow exponentially with the depth
+const MAX_EXPANSIONS: usize = 1000;
+
+impl Entry {
+    /// Replace the placeholders of this entry in `text`, such as `{USERNAME}` in a URL.
+    ///
+    /// Supported are `{TITLE}`, `{USERNAME}`, `{PASSWORD}`, `{URL}`, `{NOTES}`, `{UUID}` and
+    /// `{S:<field>}` for custom fields, ignoring case as KeePass does. Placeholders in the values
+    /// are expanded as well, up to a fixed depth and number of expansions. Unknown placeholders,
+    /// and field references such as `{REF:U@I:<uuid>}`, which need the database, are kept as they
+    /// are, see `Database::expand_placeholders`.
+    pub fn expand_placeholders(&self, text: &str) -> String {
+        let mut budget = MAX_EXPANSIONS;
+        expand(text, self, None, 0, &mut budget)
+    }
+
+    /// The URL that clients open for this entry: the URL override if it is set and not empty,
+    /// otherwise the 'URL' field, with placeholders expanded. Returns `None` if both are empty.
+    ///
+    /// Field references are not resolved, use `Database::effective_url` for entries of a database.
+    pub fn effective_url(&self) -> Option<String> {
+        self.raw_effective_url().map(|url| self.expand_placeholders(url))
+    }
+
+    fn raw_effective_url(&self) -> Option<&str> {
+        self.get_override_url()
+            .filter(|url| !url.trim().is_empty())
+            .or_else(|| self.get_url().filter(|url| !url.trim().is_empty()))
+    }
+}
+
+impl Database {
+    /// Replace the placeholders of `entry` in `text`, like `Entry::expand_placeholders`, and
+    /// resolve field references to entries of this database.
+    ///
+    /// References have the form `{REF:<field>@I:<uuid>}`, where the field is one of `T` (title),
+    /// `U` (user name), `P` (password), `A` (URL), `N` (notes) or `I` (UUID) and the UUID is written
+    /// as 32 hexadecimal digits, as KeePass does. References that search by other fields, and
+    /// references to entries that do not exist, are kept as they are.
+    pub fn expand_placeholders(&self, entry: &Entry, text: &str) -> String {
+        let mut budget = MAX_EXPANSIONS;
+        expand(text, entry, Some(self), 0, &mut budget)
+    }
+
+    /// The URL that clients open for `entry`, like `Entry::effective_url`, with field references
+    /// to entries of this database resolved
+    pub fn effective_url(&self, entry: &Entry) -> Option<String> {
+        entry
+            .raw_effective_url()
+            .map(|url| self.expand_placeholders(entry, url))
+    }
+}
+
+/// Expand the placeholders in `text`, taking one from `budget` for each placeholder that is expanded.
+/// Placeholders are kept as they are once the budget is exhausted.
+fn expand(text: &str, entry: &Entry, db: Option<&Database>, depth: usize, budget: &mut usize) -> String {
+    if depth > MAX_DEPTH {
+        return text.to_string();
+    }
+
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(end) = rest.find('}') {
+        // a placeholder starts at the last `{` before its `}`, so that `{{TITLE}` keeps the first `{`
+        let Some(start) = rest[..end].rfind('{') else {
+            expanded.push_str(&rest[..=end]);
+            rest = &rest[end + 1..];
+            continue;
+        };
+        expanded.push_str(&rest[..start]);
+
+        let name = &rest[start + 1..end];
+        match resolve(name, entry, db).filter(|_| *budget > 0) {
+            Some((value, source)) => {
+                *budget -= 1;
+                expanded.push_str(&expand(&value, source, db, depth + 1, budget))
+            }
+            None => expanded.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+/// Get the value of a placeholder, and the entry that placeholders in it refer to
+fn resolve<'a>(name: &str, entry: &'a Entry, db: Option<&'a Database>) -> Option<(String, &'a Entry)> {
+    let upper = name.to_uppercase();
+    let value = match upper.as_str() {
+        "TITLE" => entry.get_title(),
+        "USERNAME" => entry.get_username(),
+        "PASSWORD" => entry.get_password(),
+        "URL" => entry.get_url(),
+        "NOTES" => entry.get("Notes"),
+        "UUID" => return Some((entry.uuid.simple().to_string().to_uppercase(), entry)),
+        _ if upper.starts_with("S:") => entry.get(&name[2..]),
+        _ if upper.starts_with("REF:") => return resolve_reference(&upper[4..], db?),
+        _ => return None,
+    };
+
+    Some((value.unwrap_or_default().to_string(), entry))
+}
+
+/// Resolve a field reference such as `U@I:<uuid>`
+fn resolve_reference<'a>(reference: &str, db: &'a Database) -> Option<(String, &'a Entry)> {
+    let (field, uuid) = reference.split_once("@I:")?;
+    let uuid = Uuid::parse_str(uuid).ok()?;
+    let Some(NodeRef::Entry(target)) = db.get_by_uuid(&uuid) else {
+        return None;
+    };
+
+    let placeholder = match field {
+        "T" => "TITLE",
+        "U" => "USERNAME",
+        "P" => "PASSWORD",
+        "A" => "URL",
+        "N" => "NOTES",
+        "I" => "UUID",
+        _ => return None,
+    };
+    resolve(placeholder, target, Some(db))
+}
+
+#[cfg(test)]
+mod placeholder_tests {
+    use crate::db::{Database, Entry, Value};
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry::new();
+        for (key, value) in fields {
+            entry
+                .fields
+                .insert(key.to_string(), Value::Unprotected(value.to_string()));
+        }
+        entry
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let mut entry = entry(&[
+            ("Title", "Example"),
+            ("UserName", "jdoe"),
+            ("URL", "https://example.com/{S:Path}"),
+            ("Path", "login?user={username}"),
+        ]);
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected("secret".as_bytes().into()),
+        );
+
+        assert_eq!(
+            entry.expand_placeholders("{TITLE}: {URL} {PASSWORD} {UNKNOWN} {"),
+            "Example: https://example.com/login?user=jdoe secret {UNKNOWN} {"
+        );
+        assert_eq!(
+            entry.expand_placeholders("{{TITLE}} {a{USERNAME}} }{URL"),
+            "{Example} {ajdoe} }{URL"
+        );
+
+        // fields that refer to each other stop expanding
+        entry
+            .fields
+            .insert("Path".to_string(), Value::Unprotected("{URL}".to_string()));
+        assert!(entry.expand_placeholders("{URL}").ends_with("/{S:Path}"));
+
+        // fields that refer to themselves several times stop expanding as well
+        let mut looping = Entry::new();
+        looping.set("A", "x{S:A}{S:A}{S:A}{S:A}");
+        let expanded = looping.expand_placeholders("{S:A}");
+        assert_eq!(expanded.matches('x').count(), super::MAX_EXPANSIONS);
+    }
+
+    #[test]
+    fn effective_url() {
+        let mut entry = entry(&[("UserName", "jdoe"), ("URL", "https://example.com")]);
+        assert_eq!(entry.effective_url().as_deref(), Some("https://example.com"));
+
+        entry.set_override_url("cmd://firefox -P {USERNAME} {URL}");
+        assert_eq!(
+            entry.effective_url().as_deref(),
+            Some("cmd://firefox -P jdoe https://example.com")
+        );
+
+        entry.override_url = Some(" ".to_string());
+        assert_eq!(entry.effective_url().as_deref(), Some("https://example.com"));
+
+        assert_eq!(Entry::new().effective_url(), None);
+    }
+
+    #[test]
+    fn field_references() {
+        let mut db = Database::new(Default::default());
+        let target = entry(&[("UserName", "jdoe"), ("URL", "https://{USERNAME}.example.com")]);
+        let reference = format!("{{REF:A@I:{}}}", target.uuid.simple());
+        let mut entry = entry(&[("URL", &reference)]);
+        entry.set_override_url(&format!("{{URL}}?user={{ref:u@i:{}}}", target.uuid.simple()));
+        db.root.add_child(target);
+
+        assert_eq!(
+            db.effective_url(&entry).as_deref(),
+            Some("https://jdoe.example.com?user=jdoe")
+        );
+        assert_eq!(
+            entry.effective_url().as_deref(),
+            Some(
+                format!(
+                    "{}?user={{ref:u@i:{}}}",
+                    reference,
+                    db.root.entries()[0].uuid.simple()
+                )
+                .as_str()
+            )
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use uuid::Uuid;
+
+use crate::{
+    config::{BuiltinKdf, OpenOptions},
+    db::Database,
+    error::{DatabaseOpenError, XmlParseError},
+    format::{kdbx4::recover_kdbx4, DatabaseVersion},
+    key::DatabaseKey,
+};
+
+/// Damage found while opening a database with `Database::open_with_recovery`
+#[derive(Debug)]
+pub enum RecoveryWarning {
+    /// A block of the encrypted payload failed its integrity check and was replaced with zeros.
+    /// `offset` is the position of the block in the file.
+    CorruptBlock { block_index: u64, offset: u64 },
+
+    /// The file ends in the middle of the block at `offset`
+    TruncatedBlockStream { offset: u64 },
+
+    /// The payload could only be decompressed up to `recovered_bytes`
+    TruncatedPayload { recovered_bytes: u64 },
+
+    /// The XML document could not be parsed as a whole, so its entries were salvaged one by one.
+    /// Groups, deleted objects and the location of entries are lost.
+    XmlDamaged(XmlParseError),
+
+    /// The metadata could not be recovered and was replaced with the default
+    MetaDropped,
+
+    /// The entry starting at `offset` in the XML document could not be recovered
+    EntryDropped { offset: u64 },
+
+    /// An entry was recovered without its protected values, which could not be decrypted reliably
+    ProtectedValuesDropped { uuid: Uuid },
+
+    /// Nothing could be recovered
+    Unrecoverable(DatabaseOpenError),
+}
+
+impl std::fmt::Display for RecoveryWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecoveryWarning::CorruptBlock { block_index, offset } => write!(
+                f,
+                "Block {} at offset {} failed its integrity check and was zero-filled",
+                block_index, offset
+            ),
+            RecoveryWarning::TruncatedBlockStream { offset } => {
+                write!(f, "The file is truncated in the block at offset {}", offset)
+            }
+            RecoveryWarning::TruncatedPayload { recovered_bytes } => write!(
+                f,
+                "The payload could only be decompressed up to {} bytes",
+                recovered_bytes
+            ),
+            RecoveryWarning::XmlDamaged(e) => write!(f, "The XML document is damaged: {}", e),
+            RecoveryWarning::MetaDropped => write!(f, "The database metadata could not be recovered"),
+            RecoveryWarning::EntryDropped { offset } => write!(
+                f,
+                "The entry at offset {} of the XML document could not be recovered",
+                offset
+            ),
+            RecoveryWarning::ProtectedValuesDropped { uuid } => {
+                write!(f, "The protected values of entry {} could not be recovered", uuid)
+            }
+            RecoveryWarning::Unrecoverable(e) => write!(f, "The database could not be recovered: {}", e),
+        }
+    }
+}
+
+impl Database {
+    /// Open a damaged database, salvaging as much of it as possible.
+    ///
+    /// This is meant for rescuing data from a database that fails to open with `Database::open`,
+    /// and should never be used to open databases in regular operation: the returned database may
+    /// be incomplete, and saving it over the original file loses everything that was not recovered.
+    ///
+    /// For KDBX4 databases, blocks of the payload that fail their integrity check are replaced with
+    /// zeros, and if the XML document cannot be parsed, the metadata and entries are salvaged one by
+    /// one into a new root group. Other versions are opened as usual. The returned warnings
+    /// describe everything that was damaged or dropped; if no database is returned, they end with
+    /// `RecoveryWarning::Unrecoverable`.
+    ///
+    /// Entries after a damaged block can only be salvaged from payloads without compression. A
+    /// gzip-compressed payload, which is the default, cannot be decompressed past the damage, so
+    /// everything after it is lost and `RecoveryWarning::TruncatedPayload` is reported.
+    pub fn open_with_recovery(
+        source: &mut dyn std::io::Read,
+        key: DatabaseKey,
+    ) -> (Option<Database>, Vec<RecoveryWarning>) {
+        let mut warnings = Vec::new();
+
+        let mut data = Vec::new();
+        let res = source
+            .read_to_end(&mut data)
+            .map_err(DatabaseOpenError::from)
+            .and_then(|_| DatabaseVersion::parse(data.as_ref()).map_err(DatabaseOpenError::from))
+            .and_then(|version| match version {
+                DatabaseVersion::KDB4(_) => recover_kdbx4(
+                    data.as_ref(),
+                    &key,
+                    &OpenOptions::default(),
+                    &BuiltinKdf,
+                    &mut warnings,
+                ),
+                _ => Database::parse(data.as_ref(), key),
+            });
+
+        match res {
+            Ok(db) => (Some(db), warnings),
+            Err(e) => {
+                warnings.push(RecoveryWarning::Unrecoverable(e));
+                (None, warnings)
+            }
+        }
+    }
+}
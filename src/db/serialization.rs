@@ -0,0 +1,116 @@
+use std::cell::Cell;
+
+use crate::db::Database;
+
+/// Placeholder that protected values are serialized as, unless they are serialized with
+/// `WithSecrets`
+pub const REDACTED: &str = "[redacted]";
+
+thread_local! {
+    /// Whether protected values are serialized in plain text on this thread
+    static EXPOSE_SECRETS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether protected values should be serialized in plain text, see `WithSecrets`
+pub(crate) fn exposes_secrets() -> bool {
+    EXPOSE_SECRETS.with(|e| e.get())
+}
+
+/// Restores the previous value of `EXPOSE_SECRETS` when dropped, also if serializing panics
+struct ExposeSecretsGuard(bool);
+
+impl Drop for ExposeSecretsGuard {
+    fn drop(&mut self) {
+        EXPOSE_SECRETS.with(|e| e.set(self.0));
+    }
+}
+
+/// Wrapper to serialize a database or a part of it, such as a group or an entry, with the plain
+/// text of its protected values instead of `REDACTED`.
+///
+/// ```
+/// use keepass::{db::WithSecrets, Database};
+///
+/// let db = Database::new(Default::default());
+/// let json = serde_json::to_string(&WithSecrets(&db.root)).unwrap();
+/// ```
+pub struct WithSecrets<'a, T: ?Sized>(pub &'a T);
+
+impl<T: serde::Serialize + ?Sized> serde::Serialize for WithSecrets<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let _guard = ExposeSecretsGuard(EXPOSE_SECRETS.with(|e| e.replace(true)));
+        self.0.serialize(serializer)
+    }
+}
+
+impl Database {
+    /// Get a view of the database that serializes the plain text of protected values, such as
+    /// passwords. By default, they are serialized as `REDACTED`.
+    pub fn serializable_with_secrets(&self) -> WithSecrets<'_, Database> {
+        WithSecrets(self)
+    }
+}
+
+#[cfg(test)]
+mod serialization_tests {
+    use chrono::NaiveDateTime;
+    use secstr::SecStr;
+    use uuid::uuid;
+
+    use super::{WithSecrets, REDACTED};
+    use crate::{
+        db::{Entry, Times, Value},
+        Database,
+    };
+
+    fn database() -> Database {
+        let now = NaiveDateTime::parse_from_str("2024-01-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        let mut db = Database::new(Default::default());
+        db.root.uuid = uuid!("8f6d2b1c-3a4e-4c5d-9e7f-0a1b2c3d4e5f");
+
+        let mut entry = Entry {
+            uuid: uuid!("1a2b3c4d-5e6f-4a8b-9c0d-1e2f3a4b5c6d"),
+            times: Times::new_at(now),
+            ..Entry::new()
+        };
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Mail".to_string()));
+        entry
+            .fields
+            .insert("Password".to_string(), Value::Protected(SecStr::from("hunter2")));
+        db.root.add_child(entry);
+        db.root.times = Times::new_at(now);
+
+        db
+    }
+
+    #[test]
+    fn snapshot() {
+        let snapshot: serde_json::Value =
+            serde_json::from_str(include_str!("../../tests/resources/serialization_snapshot.json")).unwrap();
+
+        assert_eq!(serde_json::to_value(database()).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn protected_values_are_redacted() {
+        let db = database();
+
+        let json = serde_json::to_string(&db).unwrap();
+        assert!(!json.contains("hunter2"));
+        assert!(json.contains(REDACTED));
+
+        let json = serde_json::to_string(&db.serializable_with_secrets()).unwrap();
+        assert!(json.contains("hunter2"));
+        assert!(!json.contains(REDACTED));
+
+        let json = serde_json::to_string(&WithSecrets(&db.root.entries()[0].fields["Password"])).unwrap();
+        assert_eq!(json, "\"hunter2\"");
+
+        // the wrapper only applies while it is serialized
+        let json = serde_json::to_string(&db.root).unwrap();
+        assert!(!json.contains("hunter2"));
+    }
+}
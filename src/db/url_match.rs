@@ -0,0 +1,466 @@
+use std::{collections::HashSet, sync::Arc};
+
+use url::{Host, Url};
+
+use crate::db::Entry;
+
+/// Prefix of the names of fields with additional URLs, as used by KeePass2Android and KeePassXC
+const ADDITIONAL_URL_FIELD_PREFIX: &str = "KP2A_URL";
+
+/// Key of the custom data item with which KeePassXC hides an entry from browser integration
+pub(crate) const BROWSER_HIDE_ENTRY_KEY: &str = "BrowserHideEntry";
+
+/// How the URLs of an entry are compared to a URL in `Database::find_by_url`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlMatchMode {
+    /// The URLs are equal after normalization, e.g. of the case of the host name
+    Exact,
+
+    /// The URLs have the same host name, e.g. `example.com/login` and `http://example.com/`. If
+    /// the URL of the entry has a port other than the default port of its scheme, the port has to
+    /// match as well.
+    Host,
+
+    /// The URLs have the same registered domain, e.g. `login.example.com` and `example.com`.
+    ///
+    /// The registered domain is the public suffix of the host name and the label before it, as
+    /// determined by the public suffix list of the database, see `Database::set_public_suffix_list`.
+    /// Without a public suffix list, the registered domain is made up of the last two labels of the
+    /// host name, which is wrong for suffixes such as `co.uk`. For host names that are public
+    /// suffixes themselves, such as `github.io`, or that have a single label, such as `localhost`,
+    /// only equal host names match. IP addresses only match themselves.
+    Domain,
+
+    /// The URLs have the same scheme, host and port, and the path of the URL of the entry is a
+    /// prefix of the path that is searched for that ends on a `/`, e.g. `https://example.com/app`
+    /// matches `https://example.com/app/login` but not `https://example.com/application`. If the
+    /// URL of the entry has a query string, it has to be equal.
+    Prefix,
+}
+
+impl UrlMatchMode {
+    fn matches(&self, stored: &Url, query: &Url, suffixes: &PublicSuffixes) -> bool {
+        match self {
+            UrlMatchMode::Exact => stored == query,
+            UrlMatchMode::Host => {
+                stored.host() == query.host()
+                    && stored
+                        .port()
+                        .is_none_or(|port| query.port_or_known_default() == Some(port))
+            }
+            UrlMatchMode::Domain => {
+                match (
+                    suffixes.registered_domain(stored),
+                    suffixes.registered_domain(query),
+                ) {
+                    (Some(stored), Some(query)) => stored == query,
+                    _ => stored.host().is_some() && stored.host() == query.host(),
+                }
+            }
+            UrlMatchMode::Prefix => {
+                stored.origin() == query.origin()
+                    && is_path_prefix(stored.path(), query.path())
+                    && stored.query().is_none_or(|q| query.query() == Some(q))
+            }
+        }
+    }
+}
+
+/// Whether `path` starts with `prefix` on a boundary between path segments
+fn is_path_prefix(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Source of the public suffixes of domain names, such as `com`, `co.uk` or `github.io`, under
+/// which anyone can register a domain, see `Database::set_public_suffix_list`
+pub trait PublicSuffixList: Send + Sync {
+    /// Get the public suffix of a lowercase domain name without a trailing dot, e.g. `co.uk` for
+    /// `www.example.co.uk`, or `None` if the suffix is not known
+    fn public_suffix<'a>(&self, domain: &'a str) -> Option<&'a str>;
+}
+
+/// Public suffix list in the format of <https://publicsuffix.org/list/>, with normal rules such as
+/// `co.uk`, wildcard rules such as `*.ck` and exception rules such as `!www.ck`.
+///
+/// The list is not included in this crate, as it changes frequently. Domains that no rule matches
+/// have their top-level domain as public suffix.
+///
+/// ```
+/// use keepass::db::{PublicSuffixList, PublicSuffixRules};
+///
+/// let rules = PublicSuffixRules::parse("// comment\ncom\nco.uk\ngithub.io\n*.ck\n!www.ck\n");
+/// assert_eq!(rules.public_suffix("www.example.co.uk"), Some("co.uk"));
+/// assert_eq!(rules.public_suffix("alice.github.io"), Some("github.io"));
+/// assert_eq!(rules.public_suffix("a.b.ck"), Some("b.ck"));
+/// assert_eq!(rules.public_suffix("www.ck"), Some("ck"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicSuffixRules {
+    rules: HashSet<String>,
+    wildcards: HashSet<String>,
+    exceptions: HashSet<String>,
+}
+
+impl PublicSuffixRules {
+    /// Parse a public suffix list, one rule per line. Empty lines and comments starting with `//`
+    /// are ignored, and so is everything after the first whitespace of a line.
+    pub fn parse(list: &str) -> Self {
+        let mut rules = PublicSuffixRules::default();
+        for line in list.lines() {
+            let Some(rule) = line.split_whitespace().next() else {
+                continue;
+            };
+            if rule.starts_with("//") {
+                continue;
+            }
+
+            let rule = rule.trim_end_matches('.').to_lowercase();
+            if let Some(exception) = rule.strip_prefix('!') {
+                rules.exceptions.insert(exception.to_string());
+            } else if let Some(parent) = rule.strip_prefix("*.") {
+                rules.wildcards.insert(parent.to_string());
+            } else {
+                rules.rules.insert(rule);
+            }
+        }
+        rules
+    }
+}
+
+impl PublicSuffixList for PublicSuffixRules {
+    fn public_suffix<'a>(&self, domain: &'a str) -> Option<&'a str> {
+        // the suffixes of the domain, from the longest to the top-level domain
+        let suffixes: Vec<&str> = std::iter::once(domain)
+            .chain(domain.match_indices('.').map(|(i, _)| &domain[i + 1..]))
+            .collect();
+
+        for (i, suffix) in suffixes.iter().enumerate() {
+            if self.exceptions.contains(*suffix) {
+                return suffixes.get(i + 1).copied();
+            }
+        }
+
+        suffixes
+            .iter()
+            .enumerate()
+            .find_map(|(i, suffix)| {
+                if self.rules.contains(*suffix) {
+                    Some(*suffix)
+                } else {
+                    // `*.ck` makes `b.ck` a public suffix, but only if `b.ck` is a suffix of the domain
+                    let parent = suffixes.get(i + 1)?;
+                    self.wildcards.contains(*parent).then_some(*suffix)
+                }
+            })
+            .or(suffixes.last().copied())
+    }
+}
+
+/// Shared handle to the public suffix list of a database.
+///
+/// Clones of a database share the list, and databases compare equal regardless of it.
+#[derive(Clone, Default)]
+pub(crate) struct PublicSuffixes(Option<Arc<dyn PublicSuffixList>>);
+
+impl PublicSuffixes {
+    pub(crate) fn new(list: Box<dyn PublicSuffixList>) -> Self {
+        PublicSuffixes(Some(Arc::from(list)))
+    }
+
+    /// Get the registered domain of the host of a URL, i.e. its public suffix and the label before
+    /// it, or the IP address. Without a public suffix list, the last label is taken as the public
+    /// suffix. Returns `None` if the host name is a public suffix itself.
+    fn registered_domain(&self, url: &Url) -> Option<String> {
+        let domain = match url.host()? {
+            Host::Domain(domain) => domain.trim_end_matches('.'),
+            Host::Ipv4(ip) => return Some(ip.to_string()),
+            Host::Ipv6(ip) => return Some(ip.to_string()),
+        };
+
+        let suffix = match self.0.as_ref() {
+            Some(list) => list.public_suffix(domain)?,
+            None => &domain[domain.rfind('.').map_or(0, |i| i + 1)..],
+        };
+        let rest = domain.strip_suffix(suffix)?.strip_suffix('.')?;
+        let label_start = rest.rfind('.').map_or(0, |i| i + 1);
+        Some(domain[label_start..].to_string())
+    }
+}
+
+impl std::fmt::Debug for PublicSuffixes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PublicSuffixes")
+    }
+}
+
+impl PartialEq for PublicSuffixes {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for PublicSuffixes {}
+
+impl Entry {
+    /// Parse the 'URL' field for comparing it to other URLs, as in `Database::find_by_url`.
+    ///
+    /// The host name is lowercased and default ports such as 443 for `https` are removed. URLs
+    /// without a scheme, such as `example.com/login`, are parsed as `https://` URLs. Returns `None`
+    /// if the field is missing, empty or not a URL with a host.
+    pub fn normalized_url(&self) -> Option<Url> {
+        parse_url(self.get_url()?)
+    }
+}
+
+/// Parse a URL, assuming `https://` for URLs without a scheme such as `example.com/login`
+pub(crate) fn parse_url(url: &str) -> Option<Url> {
+    let url = url.trim();
+    if url.is_empty() {
+        return None;
+    }
+
+    match Url::parse(url) {
+        Ok(parsed) if parsed.has_host() => Some(parsed),
+        // "example.com:8080" parses with the scheme "example.com", unlike "mailto:alice@example.com"
+        Ok(parsed) if !parsed.path().starts_with(|c: char| c.is_ascii_digit()) => None,
+        Ok(_) | Err(url::ParseError::RelativeUrlWithoutBase) => Url::parse(&format!("https://{}", url))
+            .ok()
+            .filter(|parsed| parsed.has_host()),
+        Err(_) => None,
+    }
+}
+
+/// Whether one of the URLs of an entry matches a URL
+pub(crate) fn entry_matches(entry: &Entry, query: &Url, mode: UrlMatchMode, suffixes: &PublicSuffixes) -> bool {
+    entry_urls(entry).any(|stored| mode.matches(&stored, query, suffixes))
+}
+
+/// Whether an entry is offered to a browser for a URL, see `Database::find_entries_for_url`
+pub(crate) fn entry_matches_for_browser(
+    entry: &Entry,
+    query: &Url,
+    mode: UrlMatchMode,
+    suffixes: &PublicSuffixes,
+) -> bool {
+    if entry.custom_data.get(BROWSER_HIDE_ENTRY_KEY) == Some("true") {
+        return false;
+    }
+
+    entry_urls(entry)
+        .chain(entry.get_override_url().and_then(parse_url))
+        .any(|stored| mode.matches(&stored, query, suffixes))
+}
+
+/// The parsed 'URL' field and additional URL fields of an entry
+fn entry_urls(entry: &Entry) -> impl Iterator<Item = Url> + '_ {
+    entry
+        .fields
+        .keys()
+        .filter(|name| *name == "URL" || name.starts_with(ADDITIONAL_URL_FIELD_PREFIX))
+        .filter_map(move |name| parse_url(entry.get(name)?))
+}
+
+#[cfg(test)]
+mod url_match_tests {
+    use super::{parse_url, PublicSuffixList, PublicSuffixRules, PublicSuffixes, UrlMatchMode};
+    use crate::db::{Entry, Value};
+
+    const RULES: &str = "// test rules\ncom\nde\nuk\nco.uk\nio\ngithub.io\n*.ck\n!www.ck\n";
+
+    #[test]
+    fn test_public_suffix_rules() {
+        let rules = PublicSuffixRules::parse(RULES);
+
+        assert_eq!(rules.public_suffix("example.com"), Some("com"));
+        assert_eq!(rules.public_suffix("www.example.co.uk"), Some("co.uk"));
+        assert_eq!(rules.public_suffix("co.uk"), Some("co.uk"));
+        assert_eq!(rules.public_suffix("alice.github.io"), Some("github.io"));
+        assert_eq!(rules.public_suffix("a.b.ck"), Some("b.ck"));
+        assert_eq!(rules.public_suffix("www.ck"), Some("ck"));
+        // the implicit rule for unknown top-level domains
+        assert_eq!(rules.public_suffix("example.test"), Some("test"));
+        assert_eq!(rules.public_suffix("localhost"), Some("localhost"));
+    }
+
+    #[test]
+    fn test_registered_domain() {
+        let suffixes = PublicSuffixes::new(Box::new(PublicSuffixRules::parse(RULES)));
+        let domain = |url: &str| suffixes.registered_domain(&parse_url(url).unwrap());
+
+        assert_eq!(
+            domain("https://login.example.com/path"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(domain("example.com"), Some("example.com".to_string()));
+        assert_eq!(domain("mail.gmx.de"), Some("gmx.de".to_string()));
+        assert_eq!(domain("gmx.de"), Some("gmx.de".to_string()));
+        assert_eq!(
+            domain("https://www.example.co.uk"),
+            Some("example.co.uk".to_string())
+        );
+        assert_eq!(
+            domain("https://alice.github.io"),
+            Some("alice.github.io".to_string())
+        );
+        assert_eq!(domain("https://github.io"), None);
+        assert_eq!(domain("http://localhost:8080"), None);
+        assert_eq!(
+            domain("http://192.168.1.1/admin"),
+            Some("192.168.1.1".to_string())
+        );
+
+        // without a public suffix list, the last two labels are the registered domain
+        let domain = |url: &str| PublicSuffixes::default().registered_domain(&parse_url(url).unwrap());
+        assert_eq!(domain("login.example.com"), Some("example.com".to_string()));
+        assert_eq!(domain("www.example.co.uk"), Some("co.uk".to_string()));
+        assert_eq!(domain("http://localhost:8080"), None);
+    }
+
+    #[test]
+    fn test_match_modes() {
+        let suffixes = PublicSuffixes::new(Box::new(PublicSuffixRules::parse(RULES)));
+        let matches = |mode: UrlMatchMode, stored: &str, query: &str| {
+            mode.matches(&parse_url(stored).unwrap(), &parse_url(query).unwrap(), &suffixes)
+        };
+
+        assert!(matches(
+            UrlMatchMode::Exact,
+            "https://Example.com",
+            "https://example.com/"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Exact,
+            "https://example.com",
+            "https://example.com/login"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Exact,
+            "http://example.com",
+            "https://example.com"
+        ));
+
+        assert!(matches(
+            UrlMatchMode::Host,
+            "example.com/login",
+            "http://example.com/?next=/x"
+        ));
+        assert!(matches(
+            UrlMatchMode::Host,
+            "https://example.com:8443",
+            "https://example.com:8443/login"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Host,
+            "https://example.com:8443",
+            "https://example.com/login"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Host,
+            "https://example.com",
+            "https://app.example.com"
+        ));
+
+        assert!(matches(
+            UrlMatchMode::Prefix,
+            "https://example.com/app",
+            "https://example.com/app/login"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Prefix,
+            "https://example.com/app",
+            "https://example.com/"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Prefix,
+            "https://example.com/app",
+            "https://example.com/application"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Prefix,
+            "https://example.com",
+            "https://example.com.evil.net/"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Prefix,
+            "https://example.com/app",
+            "http://example.com/app/login"
+        ));
+        assert!(matches(
+            UrlMatchMode::Prefix,
+            "https://example.com/app/",
+            "https://example.com/app/login?next=/"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Prefix,
+            "https://example.com/app?tenant=a",
+            "https://example.com/app?tenant=b"
+        ));
+
+        assert!(matches(
+            UrlMatchMode::Domain,
+            "example.com:8080",
+            "https://www.example.com"
+        ));
+        assert!(!matches(
+            UrlMatchMode::Domain,
+            "https://example.co.uk",
+            "https://other.co.uk"
+        ));
+        assert!(matches(UrlMatchMode::Domain, "gmx.de", "https://mail.gmx.de"));
+        assert!(!matches(
+            UrlMatchMode::Domain,
+            "https://alice.github.io",
+            "https://mallory.github.io"
+        ));
+        assert!(matches(UrlMatchMode::Domain, "github.io", "https://github.io/"));
+
+        // without a public suffix list, the last two labels have to match
+        let no_list = |stored: &str, query: &str| {
+            UrlMatchMode::Domain.matches(
+                &parse_url(stored).unwrap(),
+                &parse_url(query).unwrap(),
+                &PublicSuffixes::default(),
+            )
+        };
+        assert!(no_list("https://example.com", "https://login.example.com/path"));
+        assert!(no_list("example.com:8080", "https://example.com/login"));
+        assert!(!no_list("example.com", "https://example.net"));
+        assert!(no_list("localhost", "http://localhost:8080"));
+        assert!(!no_list("localhost", "http://otherhost"));
+    }
+
+    #[test]
+    fn test_normalized_url() {
+        let normalized = |url: &str| {
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("URL".to_string(), Value::Unprotected(url.to_string()));
+            entry.normalized_url().map(|url| url.to_string())
+        };
+
+        assert_eq!(
+            normalized("HTTPS://Login.Example.COM:443/Path?q=1"),
+            Some("https://login.example.com/Path?q=1".to_string())
+        );
+        assert_eq!(
+            normalized(" Example.com:443 "),
+            Some("https://example.com/".to_string())
+        );
+        assert_eq!(
+            normalized("http://example.com:80/"),
+            Some("http://example.com/".to_string())
+        );
+        assert_eq!(
+            normalized("example.com:8443/login"),
+            Some("https://example.com:8443/login".to_string())
+        );
+        assert_eq!(normalized(""), None);
+        assert_eq!(normalized("not a url"), None);
+        assert_eq!(normalized("mailto:alice@example.com"), None);
+        assert_eq!(Entry::new().normalized_url(), None);
+    }
+}
@@ -0,0 +1,163 @@
+use std::cmp::Ordering;
+
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, Group, Node};
+
+impl Database {
+    /// Get up to `limit` entries that were accessed most recently, e.g. for a list of recently
+    /// used entries, together with the names of the groups that contain them, starting with the
+    /// name of the root group.
+    ///
+    /// Entries that have never been accessed, entries in the recycle bin and history items are not
+    /// included. Entries with the same last access time are sorted by title, then by UUID.
+    pub fn recently_used(&self, limit: usize) -> Vec<(Vec<&str>, &Entry)> {
+        self.usage_sorted(
+            limit,
+            |entry| entry.times.get_last_access().is_some(),
+            |a, b| b.times.get_last_access().cmp(&a.times.get_last_access()),
+        )
+    }
+
+    /// Get up to `limit` entries with the highest usage count, e.g. for a list of the most used
+    /// entries, together with the names of the groups that contain them, starting with the name of
+    /// the root group.
+    ///
+    /// Entries that have never been used, entries in the recycle bin and history items are not
+    /// included. Entries with the same usage count are sorted by title, then by UUID.
+    pub fn most_used(&self, limit: usize) -> Vec<(Vec<&str>, &Entry)> {
+        self.usage_sorted(
+            limit,
+            |entry| entry.times.usage_count > 0,
+            |a, b| b.times.usage_count.cmp(&a.times.usage_count),
+        )
+    }
+
+    fn usage_sorted(
+        &self,
+        limit: usize,
+        include: impl Fn(&Entry) -> bool,
+        order: impl Fn(&Entry, &Entry) -> Ordering,
+    ) -> Vec<(Vec<&str>, &Entry)> {
+        let mut entries = Vec::new();
+        collect_entries(
+            &self.root,
+            self.meta.recyclebin_uuid,
+            &mut vec![self.root.name.as_str()],
+            &include,
+            &mut entries,
+        );
+
+        entries.sort_by(|(_, a), (_, b)| {
+            order(a, b)
+                .then_with(|| a.get_title().cmp(&b.get_title()))
+                .then_with(|| a.uuid.cmp(&b.uuid))
+        });
+        entries.truncate(limit);
+        entries
+    }
+}
+
+/// Collect the entries below `group` for which `include` returns `true` together with their paths,
+/// skipping the recycle bin
+fn collect_entries<'a>(
+    group: &'a Group,
+    recycle_bin: Option<Uuid>,
+    path: &mut Vec<&'a str>,
+    include: &impl Fn(&Entry) -> bool,
+    entries: &mut Vec<(Vec<&'a str>, &'a Entry)>,
+) {
+    for child in &group.children {
+        match child {
+            Node::Entry(e) if include(e) => entries.push((path.clone(), e)),
+            Node::Entry(_) => {}
+            Node::Group(g) if Some(g.uuid) == recycle_bin => {}
+            Node::Group(g) => {
+                path.push(&g.name);
+                collect_entries(g, recycle_bin, path, include, entries);
+                path.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod usage_tests {
+    use chrono::Duration;
+
+    use crate::db::{Database, DeleteMode, Entry, Group, History, NodeRef, Times, LAST_ACCESS_TIME_TAG_NAME};
+
+    fn entry(title: &str, usage_count: usize, days_ago: Option<i64>) -> Entry {
+        let mut entry = Entry::new();
+        entry.set("Title", title);
+        entry.times.usage_count = usage_count;
+        entry.times.times.remove(LAST_ACCESS_TIME_TAG_NAME);
+        if let Some(days_ago) = days_ago {
+            entry
+                .times
+                .set_last_access(Times::epoch() + Duration::days(100 - days_ago));
+        }
+        entry
+    }
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        db.root.name = "Root".to_string();
+        db.root.add_child(entry("Mail", 5, Some(3)));
+        db.root.add_child(entry("Unused", 0, None));
+
+        let mut work = Group::new("Work");
+        work.add_child(entry("VPN", 2, Some(1)));
+        work.add_child(entry("Bank", 5, Some(3)));
+        db.root.add_child(work);
+
+        let mut recycled = entry("Recycled", 10, Some(0));
+        let mut history = History::default();
+        history.add_entry(entry("Old", 20, Some(0)));
+        recycled.history = Some(history);
+        let recycled_uuid = recycled.uuid;
+        db.root.add_child(recycled);
+        db.delete_where(
+            |node| matches!(node, NodeRef::Entry(e) if e.uuid == recycled_uuid),
+            DeleteMode::Recycle,
+        );
+
+        db
+    }
+
+    fn titles<'a>(entries: &[(Vec<&str>, &'a Entry)]) -> Vec<&'a str> {
+        entries.iter().map(|(_, e)| e.get_title().unwrap()).collect()
+    }
+
+    #[test]
+    fn recently_used() {
+        let db = database();
+        let recent = db.recently_used(10);
+        assert_eq!(titles(&recent), ["VPN", "Bank", "Mail"]);
+        assert_eq!(recent[0].0, ["Root", "Work"]);
+        assert_eq!(recent[2].0, ["Root"]);
+
+        assert_eq!(titles(&db.recently_used(2)), ["VPN", "Bank"]);
+        assert!(db.recently_used(0).is_empty());
+    }
+
+    #[test]
+    fn most_used() {
+        let mut db = database();
+        assert_eq!(titles(&db.most_used(10)), ["Bank", "Mail", "VPN"]);
+        assert_eq!(titles(&db.most_used(1)), ["Bank"]);
+
+        // entries with the same usage count and title are sorted by UUID
+        let mut first = entry("Email", 5, None);
+        let mut second = entry("Email", 5, None);
+        if first.uuid > second.uuid {
+            std::mem::swap(&mut first, &mut second);
+        }
+        let uuids = [first.uuid, second.uuid];
+        db.root.add_child(second);
+        db.root.add_child(first);
+
+        let most_used: Vec<_> = db.most_used(3).iter().map(|(_, e)| e.uuid).collect();
+        assert_eq!(most_used[1..], uuids);
+    }
+}
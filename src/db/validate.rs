@@ -0,0 +1,366 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::db::{Database, Entry, IterOrder, NodeRef, NodeRefMut};
+
+/// How serious a problem found by `Database::validate` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The database can be used, but clients may show or handle some data differently
+    Warning,
+
+    /// Operations that rely on the structure of the database, such as merging or looking up nodes
+    /// by UUID, can give wrong results
+    Error,
+}
+
+/// A structural problem of a database, as written by some faulty clients, see `Database::validate`.
+///
+/// All issues can be repaired with `Database::fix_issues`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Several groups or entries have the same UUID. It is fixed by giving all but the first of them,
+    /// in depth-first order, a new UUID.
+    DuplicateUuid { uuid: Uuid, count: usize },
+
+    /// A group or entry, or a history entry of an entry, refers to a custom icon that is not in the
+    /// database. It is fixed by removing the reference.
+    MissingCustomIcon { node: Uuid, icon: Uuid },
+
+    /// An entry or one of its history entries refers to an attachment that is not in the attachment
+    /// pool. It is fixed by removing the attachment from the entry.
+    MissingAttachment { entry: Uuid, index: usize },
+
+    /// The recycle bin in the metadata is not a group of the database. It is fixed by unsetting it.
+    MissingRecycleBin(Uuid),
+
+    /// The group of entry templates in the metadata is not a group of the database. It is fixed by
+    /// unsetting it.
+    MissingTemplatesGroup(Uuid),
+
+    /// A history entry has a different UUID than its entry. It is fixed by setting the UUID of the
+    /// history entry to that of the entry.
+    HistoryUuidMismatch { entry: Uuid, history_uuid: Uuid },
+}
+
+impl ValidationIssue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            ValidationIssue::DuplicateUuid { .. } | ValidationIssue::MissingAttachment { .. } => {
+                Severity::Error
+            }
+            ValidationIssue::MissingCustomIcon { .. }
+            | ValidationIssue::MissingRecycleBin(_)
+            | ValidationIssue::MissingTemplatesGroup(_)
+            | ValidationIssue::HistoryUuidMismatch { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl Database {
+    /// Check the database for structural problems, such as duplicate UUIDs or references to custom
+    /// icons, attachments and groups that do not exist. An empty list means that no problems were
+    /// found.
+    ///
+    /// Unlike `with_root_mut`, which rejects changes that introduce some of these problems, this
+    /// reports the problems of databases as they were written by other clients, see `fix_issues`.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        let mut counts: HashMap<Uuid, usize> = HashMap::new();
+        let mut order = Vec::new();
+        for (_, node) in self.root.iter_with_path(IterOrder::DepthFirst) {
+            let count = counts.entry(node.uuid()).or_default();
+            if *count == 0 {
+                order.push(node.uuid());
+            }
+            *count += 1;
+        }
+        for uuid in order {
+            if counts[&uuid] > 1 {
+                issues.push(ValidationIssue::DuplicateUuid {
+                    uuid,
+                    count: counts[&uuid],
+                });
+            }
+        }
+
+        let icons: HashSet<Uuid> = self.meta.custom_icons.icons.iter().map(|i| i.uuid).collect();
+        for (_, node) in self.root.iter_with_path(IterOrder::DepthFirst) {
+            let mut node_icons = match node {
+                NodeRef::Group(g) => vec![g.custom_icon_uuid],
+                NodeRef::Entry(e) => with_history(e).map(|e| e.custom_icon_uuid).collect(),
+            };
+            node_icons.sort();
+            node_icons.dedup();
+            for icon in node_icons.into_iter().flatten() {
+                if !icons.contains(&icon) {
+                    issues.push(ValidationIssue::MissingCustomIcon {
+                        node: node.uuid(),
+                        icon,
+                    });
+                }
+            }
+
+            let NodeRef::Entry(entry) = node else {
+                continue;
+            };
+
+            let mut missing: Vec<usize> = with_history(entry)
+                .flat_map(|e| e.attachments.values())
+                .filter(|index| **index >= self.header_attachments.len())
+                .copied()
+                .collect();
+            missing.sort_unstable();
+            missing.dedup();
+            for index in missing {
+                issues.push(ValidationIssue::MissingAttachment {
+                    entry: entry.uuid,
+                    index,
+                });
+            }
+
+            for history_entry in entry.history.iter().flat_map(|h| &h.entries) {
+                if history_entry.uuid != entry.uuid {
+                    issues.push(ValidationIssue::HistoryUuidMismatch {
+                        entry: entry.uuid,
+                        history_uuid: history_entry.uuid,
+                    });
+                }
+            }
+        }
+
+        if let Some(uuid) = self
+            .meta
+            .recyclebin_uuid
+            .filter(|u| !u.is_nil() && !self.is_group(u))
+        {
+            issues.push(ValidationIssue::MissingRecycleBin(uuid));
+        }
+        if let Some(uuid) = self
+            .meta
+            .entry_templates_group
+            .filter(|u| !u.is_nil() && !self.is_group(u))
+        {
+            issues.push(ValidationIssue::MissingTemplatesGroup(uuid));
+        }
+
+        issues
+    }
+
+    /// Repair issues found by `validate`. Issues that no longer apply, e.g. because the database was
+    /// changed since it was validated, are ignored.
+    pub fn fix_issues(&mut self, issues: &[ValidationIssue]) {
+        let attachment_count = self.header_attachments.len();
+        let icons: HashSet<Uuid> = self.meta.custom_icons.icons.iter().map(|i| i.uuid).collect();
+
+        for issue in issues {
+            match issue {
+                ValidationIssue::DuplicateUuid { uuid, .. } => {
+                    let mut seen = false;
+                    self.root.visit_mut(|node| {
+                        if node.uuid() != *uuid {
+                            return;
+                        }
+                        if !seen {
+                            seen = true;
+                            return;
+                        }
+
+                        match node {
+                            NodeRefMut::Group(g) => g.uuid = Uuid::new_v4(),
+                            NodeRefMut::Entry(e) => {
+                                e.uuid = Uuid::new_v4();
+                                for history_entry in e.history.iter_mut().flat_map(|h| &mut h.entries) {
+                                    history_entry.uuid = e.uuid;
+                                }
+                            }
+                        }
+                    });
+                }
+                ValidationIssue::MissingCustomIcon { node, icon } => {
+                    if icons.contains(icon) {
+                        continue;
+                    }
+                    self.root.visit_mut(|n| match n {
+                        NodeRefMut::Group(g) if g.uuid == *node && g.custom_icon_uuid == Some(*icon) => {
+                            g.custom_icon_uuid = None
+                        }
+                        NodeRefMut::Entry(e) if e.uuid == *node => for_each_with_history_mut(e, |e| {
+                            if e.custom_icon_uuid == Some(*icon) {
+                                e.custom_icon_uuid = None;
+                            }
+                        }),
+                        _ => {}
+                    });
+                }
+                ValidationIssue::MissingAttachment { entry, index } => {
+                    if *index < attachment_count {
+                        continue;
+                    }
+                    self.root.visit_mut(|n| {
+                        if let NodeRefMut::Entry(e) = n {
+                            if e.uuid == *entry {
+                                for_each_with_history_mut(e, |e| e.attachments.retain(|_, i| i != index));
+                            }
+                        }
+                    });
+                }
+                ValidationIssue::MissingRecycleBin(uuid) => {
+                    if self.meta.recyclebin_uuid == Some(*uuid) && !self.is_group(uuid) {
+                        self.meta.recyclebin_uuid = None;
+                    }
+                }
+                ValidationIssue::MissingTemplatesGroup(uuid) => {
+                    if self.meta.entry_templates_group == Some(*uuid) && !self.is_group(uuid) {
+                        self.meta.entry_templates_group = None;
+                    }
+                }
+                ValidationIssue::HistoryUuidMismatch { entry, history_uuid } => {
+                    self.root.visit_mut(|n| {
+                        if let NodeRefMut::Entry(e) = n {
+                            if e.uuid == *entry {
+                                let uuid = e.uuid;
+                                for history_entry in e.history.iter_mut().flat_map(|h| &mut h.entries) {
+                                    if history_entry.uuid == *history_uuid {
+                                        history_entry.uuid = uuid;
+                                    }
+                                }
+                            }
+                        }
+                    });
+                }
+            }
+        }
+
+        // the locations of nodes whose UUID changed are outdated
+        if self.is_indexed() {
+            self.build_index();
+        }
+    }
+
+    /// Whether `uuid` is a group of the database, as the recycle bin and the templates group must be
+    fn is_group(&self, uuid: &Uuid) -> bool {
+        matches!(self.get_by_uuid(uuid), Some(NodeRef::Group(_)))
+    }
+}
+
+/// An entry followed by its history entries
+fn with_history(entry: &Entry) -> impl Iterator<Item = &Entry> {
+    std::iter::once(entry).chain(entry.history.iter().flat_map(|h| &h.entries))
+}
+
+/// Call a closure for an entry and each of its history entries
+fn for_each_with_history_mut(entry: &mut Entry, mut f: impl FnMut(&mut Entry)) {
+    f(entry);
+    for history_entry in entry.history.iter_mut().flat_map(|h| &mut h.entries) {
+        f(history_entry);
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use uuid::Uuid;
+
+    use super::{Severity, ValidationIssue};
+    use crate::db::{Database, Entry, Group, History};
+
+    #[test]
+    fn valid_database() {
+        let mut db = Database::new(Default::default());
+        let recycle_bin = Group::new("Recycle Bin");
+        db.meta.recyclebin_uuid = Some(recycle_bin.uuid);
+        db.meta.entry_templates_group = Some(Uuid::nil());
+        db.root.add_child(recycle_bin);
+        db.root.add_child(Entry::new());
+
+        assert_eq!(db.validate(), []);
+    }
+
+    #[test]
+    fn detect_and_fix() {
+        let mut db = Database::new(Default::default());
+
+        // duplicate UUIDs
+        let entry = Entry::new();
+        let duplicate = entry.uuid;
+        let mut group = Group::new("Group");
+        group.add_child(entry.clone());
+        db.root.add_child(group);
+        db.root.add_child(entry.clone());
+
+        // dangling references of an entry, partly in its history
+        let mut broken = Entry::new();
+        let broken_uuid = broken.uuid;
+        let icon = Uuid::new_v4();
+        broken.custom_icon_uuid = Some(icon);
+        broken.attachments.insert("missing.txt".to_string(), 3);
+        let mut history_entry = broken.clone();
+        history_entry.uuid = Uuid::new_v4();
+        let history_uuid = history_entry.uuid;
+        let mut history = History::default();
+        history.add_entry(history_entry);
+        broken.history = Some(history);
+        db.root.add_child(broken);
+
+        let mut icon_group = Group::new("Icon");
+        let icon_group_uuid = icon_group.uuid;
+        icon_group.custom_icon_uuid = Some(icon);
+        db.root.add_child(icon_group);
+
+        let recycle_bin = Uuid::new_v4();
+        // an entry cannot be the templates group
+        let templates = broken_uuid;
+        db.meta.recyclebin_uuid = Some(recycle_bin);
+        db.meta.entry_templates_group = Some(templates);
+
+        let issues = db.validate();
+        assert_eq!(
+            issues,
+            [
+                ValidationIssue::DuplicateUuid {
+                    uuid: duplicate,
+                    count: 2
+                },
+                ValidationIssue::MissingCustomIcon {
+                    node: broken_uuid,
+                    icon
+                },
+                ValidationIssue::MissingAttachment {
+                    entry: broken_uuid,
+                    index: 3
+                },
+                ValidationIssue::HistoryUuidMismatch {
+                    entry: broken_uuid,
+                    history_uuid
+                },
+                ValidationIssue::MissingCustomIcon {
+                    node: icon_group_uuid,
+                    icon
+                },
+                ValidationIssue::MissingRecycleBin(recycle_bin),
+                ValidationIssue::MissingTemplatesGroup(templates),
+            ]
+        );
+        assert_eq!(issues[0].severity(), Severity::Error);
+        assert_eq!(issues[1].severity(), Severity::Warning);
+
+        db.fix_issues(&issues);
+        assert_eq!(db.validate(), []);
+
+        // the first node in depth-first order keeps its UUID
+        assert_eq!(db.root.groups()[0].entries()[0].uuid, duplicate);
+        assert_ne!(db.root.entries()[0].uuid, duplicate);
+
+        let broken = db.root.entries()[1];
+        assert_eq!(broken.custom_icon_uuid, None);
+        assert!(broken.attachments.is_empty());
+        let history_entry = &broken.history.as_ref().unwrap().get_entries()[0];
+        assert_eq!(history_entry.uuid, broken_uuid);
+        assert_eq!(history_entry.custom_icon_uuid, None);
+        assert!(history_entry.attachments.is_empty());
+        assert_eq!(db.meta.recyclebin_uuid, None);
+        assert_eq!(db.meta.entry_templates_group, None);
+    }
+}
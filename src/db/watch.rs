@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{db::Database, error::WatchError, key::DatabaseKey};
+
+/// Time without further changes to wait for after a change, so that a database that is saved in
+/// several steps is only reopened once
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches a database file for changes, see `Database::watch`. Watching stops when it is dropped.
+pub struct DatabaseWatcher {
+    watcher: RecommendedWatcher,
+    directory: PathBuf,
+}
+
+impl Drop for DatabaseWatcher {
+    fn drop(&mut self) {
+        // dropping the watcher closes the channel, which ends the thread that reopens the database
+        let _ = self.watcher.unwatch(&self.directory);
+    }
+}
+
+impl Database {
+    /// Watch a database file and call `on_change` with the reopened database when the file changes
+    /// on disk, e.g. because another client saved it.
+    ///
+    /// Changes are debounced: the database is reopened once there were no further changes for
+    /// 500 ms. The directory of the file is watched, so that files that are replaced instead of
+    /// overwritten by a client are noticed as well. If the changed file cannot be opened, e.g.
+    /// because its key was changed, the change is ignored. `on_change` is called on a background
+    /// thread, until the returned `DatabaseWatcher` is dropped.
+    pub fn watch<P: AsRef<Path>, F: Fn(Database) + Send + 'static>(
+        path: P,
+        key: DatabaseKey,
+        on_change: F,
+    ) -> Result<DatabaseWatcher, WatchError> {
+        let path = path.as_ref().canonicalize()?;
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        std::thread::spawn(move || reopen_on_change(&path, &key, &receiver, on_change));
+
+        Ok(DatabaseWatcher { watcher, directory })
+    }
+}
+
+/// Reopen the database after every debounced change, until the watcher is dropped
+fn reopen_on_change(
+    path: &Path,
+    key: &DatabaseKey,
+    receiver: &Receiver<notify::Result<Event>>,
+    on_change: impl Fn(Database),
+) {
+    // reading the file causes access events, which are not changes
+    let is_change = |event: &notify::Result<Event>| {
+        event
+            .as_ref()
+            .is_ok_and(|e| !e.kind.is_access() && e.paths.iter().any(|p| p == path))
+    };
+
+    while let Ok(event) = receiver.recv() {
+        if !is_change(&event) {
+            continue;
+        }
+
+        let mut deadline = Instant::now() + DEBOUNCE;
+        loop {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => {
+                    if is_change(&event) {
+                        deadline = Instant::now() + DEBOUNCE;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let db = File::open(path)
+            .map_err(Into::into)
+            .and_then(|mut file| Database::open(&mut file, key.clone()));
+        if let Ok(db) = db {
+            on_change(db);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "save_kdbx4"))]
+mod watch_tests {
+    use std::{fs::File, sync::mpsc, time::Duration};
+
+    use crate::{
+        config::{DatabaseConfig, DatabaseSettings, KdfConfig},
+        db::{Database, Entry, Value},
+        key::DatabaseKey,
+    };
+
+    #[test]
+    fn reopens_changed_file() {
+        let path = std::env::temp_dir().join(format!("keepass-watch-{}.kdbx", uuid::Uuid::new_v4()));
+        let key = DatabaseKey::new().with_password("test");
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..DatabaseConfig::default()
+        });
+        db.save(&mut File::create(&path).unwrap(), key.clone()).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let watcher = Database::watch(&path, key.clone(), move |db| {
+            let _ = sender.send(db);
+        })
+        .unwrap();
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("New entry".to_string()));
+        db.root.add_child(entry);
+        db.save(&mut File::create(&path).unwrap(), key).unwrap();
+
+        let reopened = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(reopened.root.entries()[0].get_title(), Some("New entry"));
+
+        // the callback is dropped with the thread once the watcher is dropped
+        drop(watcher);
+        assert!(receiver.recv_timeout(Duration::from_secs(10)).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
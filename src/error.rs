@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::config::ParseLimit;
+
 #[cfg(feature = "totp")]
 pub use crate::db::otp::TOTPError;
 
@@ -20,9 +22,28 @@ pub enum DatabaseOpenError {
     #[error(transparent)]
     DatabaseIntegrity(#[from] DatabaseIntegrityError),
 
+    /// A block of the encrypted payload failed its integrity check, i.e. the file has been damaged.
+    /// Opening a database with a wrong key is reported as `DatabaseKeyError::IncorrectKey` instead.
+    /// `offset` is the position of the block in the file for KDBX4 and in the decrypted payload
+    /// for KDBX3.
+    #[error(
+        "The database is corrupt: integrity check failed for block {} at offset {}",
+        block_index,
+        offset
+    )]
+    Corrupt { block_index: u64, offset: u64 },
+
     /// The database version cannot be read by this library
     #[error("Opening this database version is not supported")]
     UnsupportedVersion,
+
+    /// No correct key was provided within the allowed number of attempts
+    #[error("Too many attempts to open the database with an incorrect key")]
+    TooManyAttempts,
+
+    /// The database exceeds one of the `ParseLimits` it was opened with
+    #[error("The database exceeds the parse limit {}", _0)]
+    LimitExceeded(ParseLimit),
 }
 
 /// Errors stemming from corrupted databases
@@ -127,6 +148,9 @@ pub enum DatabaseIntegrityError {
     #[error(transparent)]
     KdfSettings(#[from] KdfConfigError),
 
+    #[error(transparent)]
+    KeyDerivation(#[from] KdfError),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
@@ -138,6 +162,27 @@ pub enum DatabaseSaveError {
     #[error("Saving this database version is not supported")]
     UnsupportedVersion,
 
+    /// The database uses a feature that cannot be stored in the requested format version
+    #[error("Cannot save as {}: {}", version, reason)]
+    IncompatibleVersion {
+        version: crate::format::DatabaseVersion,
+        reason: &'static str,
+    },
+
+    /// The database contains values that are encrypted by `Database::lock`
+    #[error("Cannot save a locked database")]
+    Locked,
+
+    /// The database was opened without the history of its entries, see
+    /// `Database::allow_lossy_save`
+    #[error("Cannot save a database that was opened without history")]
+    HistorySkipped,
+
+    /// An attachment that was deferred when opening the database cannot be decompressed, and the
+    /// format that is saved cannot store its compressed content instead
+    #[error(transparent)]
+    Decompression(#[from] DecompressionError),
+
     /// Error while writing out the inner XML database
     #[error("Error while generating XML")]
     Xml(#[from] xml::writer::Error),
@@ -159,6 +204,153 @@ pub enum DatabaseSaveError {
     Random(#[from] getrandom::Error),
 }
 
+/// Errors when locking or unlocking the protected values of a database in memory
+#[derive(Debug, Error)]
+pub enum LockError {
+    /// The protected values were locked with a different session key
+    #[error("Wrong session key")]
+    WrongSessionKey,
+
+    /// An error getting randomness for the session key occurred
+    #[error(transparent)]
+    Random(#[from] getrandom::Error),
+}
+
+/// Errors when copying entries or groups within a database or between databases
+#[derive(Debug, Error)]
+pub enum CopyError {
+    /// There is no entry with the given UUID in the database
+    #[error("Entry {} not found", _0)]
+    EntryNotFound(uuid::Uuid),
+
+    /// There is no group with the given UUID in the database
+    #[error("Group {} not found", _0)]
+    GroupNotFound(uuid::Uuid),
+
+    /// The target group is the copied group itself or one of its subgroups
+    #[error("Cannot copy group {} into itself or one of its subgroups", _0)]
+    Cycle(uuid::Uuid),
+
+    /// Imported groups or entries have UUIDs that already exist in the database
+    #[error("{} imported nodes have UUIDs that already exist in the database", _0.len())]
+    UuidCollision(Vec<uuid::Uuid>),
+}
+
+/// Inconsistencies in the group tree of a database
+#[derive(Debug, Error)]
+pub enum TreeError {
+    /// The UUID of the root group was changed
+    #[error("The root group UUID was changed from {} to {}", old, new)]
+    RootUuidChanged { old: uuid::Uuid, new: uuid::Uuid },
+
+    /// Two groups or entries in the tree have the same UUID
+    #[error("Duplicate UUID {}", _0)]
+    DuplicateUuid(uuid::Uuid),
+
+    /// An entry refers to an attachment that is not in the attachment pool
+    #[error("Entry {} refers to missing attachment {}", entry, index)]
+    MissingAttachment { entry: uuid::Uuid, index: usize },
+}
+
+/// Errors when starting to watch a database file with `Database::watch`
+#[cfg(feature = "watch")]
+#[derive(Debug, Error)]
+pub enum WatchError {
+    /// The database file could not be found
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The file system could not be watched
+    #[error(transparent)]
+    Notify(#[from] notify::Error),
+}
+
+/// Errors when exporting a database to the import format of another password manager
+#[cfg(feature = "export")]
+#[derive(Debug, Error)]
+pub enum CsvExportError {
+    /// An error occurred while writing the CSV document
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    /// An I/O error occurred while writing the CSV document
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// A field of an entry cannot be exported as text, because the database is locked or the value
+    /// is binary
+    #[error("Field {} of entry {} is not readable as text", field, entry)]
+    UnreadableField { entry: uuid::Uuid, field: String },
+}
+
+/// Errors when importing a database from the export format of another password manager
+#[cfg(feature = "import")]
+#[derive(Debug, Error)]
+pub enum ImportError {
+    /// An I/O error has occurred while reading the export
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// An item of the export is not valid JSON or does not have the expected structure
+    #[error("Invalid item on line {}: {}", line, source)]
+    Json { line: usize, source: serde_json::Error },
+
+    /// An item has a type that cannot be imported
+    #[error("Unknown item type {:?}", _0)]
+    UnknownItemType(String),
+
+    /// The CSV document is malformed, e.g. a row has a different number of fields than the header
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    /// The header of a CSV export has a column that cannot be imported
+    #[error("Unknown column {:?}", _0)]
+    UnknownColumn(String),
+}
+
+/// Errors when constructing a database key from environment variables
+#[derive(Debug, Error)]
+pub enum EnvKeyError {
+    /// The environment variable is not set
+    #[error("Environment variable {} is not set", _0)]
+    VarNotSet(String),
+
+    /// The value of the environment variable is not valid UTF-8
+    #[error("Environment variable {} is not valid UTF-8", _0)]
+    VarInvalidUtf8(String),
+
+    /// The key file named by the environment variable could not be read
+    #[error("Cannot read key file {}: {}", path.display(), source)]
+    KeyFileError {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// Errors when generating passwords or passphrases
+#[derive(Debug, Error)]
+pub enum PasswordGeneratorError {
+    /// There are no words to generate a passphrase from
+    #[error("The wordlist is empty")]
+    EmptyWordlist,
+
+    /// A word appears more than once in the wordlist, which would make it more likely to be picked
+    #[error("The wordlist contains the word {0:?} more than once")]
+    DuplicateWord(String),
+
+    /// All characters are disabled or excluded
+    #[error("No characters to generate a password from")]
+    EmptyCharacterSet,
+
+    /// The minimum numbers of characters cannot be met with the enabled characters and the length
+    #[error("The password generator rules cannot be satisfied")]
+    UnsatisfiableRules,
+
+    /// An error getting randomness occurred
+    #[error(transparent)]
+    Random(#[from] getrandom::Error),
+}
+
 /// Errors related to the database key
 #[derive(Debug, Error)]
 pub enum DatabaseKeyError {
@@ -244,14 +436,33 @@ pub enum CryptographyError {
     Argon2(#[from] argon2::Error),
 }
 
+/// Errors of a `KeyDerivationFunction`
+#[derive(Debug, Error)]
+pub enum KdfError {
+    #[error("Unsupported key derivation function: {}", uuid)]
+    Unsupported { uuid: uuid::Uuid },
+
+    #[error(transparent)]
+    KdfSettings(#[from] KdfConfigError),
+
+    #[error(transparent)]
+    Cryptography(#[from] CryptographyError),
+
+    #[error("Key derivation failed: {}", _0)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
 /// Errors reading from the HMAC block stream
 #[derive(Debug, Error)]
 pub enum BlockStreamError {
     #[error(transparent)]
     Cryptography(#[from] CryptographyError),
 
-    #[error("Block hash mismatch for block {}", block_index)]
-    BlockHashMismatch { block_index: u64 },
+    #[error("Block hash mismatch for block {} at offset {}", block_index, offset)]
+    BlockHashMismatch { block_index: u64, offset: u64 },
+
+    #[error("Block stream ends in the middle of block {}", block_index)]
+    Truncated { block_index: u64 },
 }
 
 /// Errors while parsing a VariantDictionary
@@ -285,6 +496,10 @@ pub enum XmlParseError {
     #[error(transparent)]
     TimestampFormat(#[from] chrono::ParseError),
 
+    /// A timestamp is empty, has an unknown format or is out of range
+    #[error("Invalid timestamp: '{}'", _0)]
+    InvalidTimestamp(String),
+
     #[error(transparent)]
     IntFormat(#[from] std::num::ParseIntError),
 
@@ -303,9 +518,14 @@ pub enum XmlParseError {
     #[error("Decompression error: {}", _0)]
     Compression(#[source] std::io::Error),
 
+    /// A protected value does not decrypt to valid UTF-8, e.g. because the inner stream is out of
+    /// sync with the values
+    #[error("Protected value is not valid UTF-8")]
+    ProtectedValueUtf8(#[source] std::str::Utf8Error),
+
     /// An unexpected XML event occurred, such as opening an unexpected tag, or an error in the
     /// underlying XML reader
-    #[error("Bad XML event: expected {}, got {:?}", expected, event)]
+    #[error("Bad XML event: expected {}, got {}", expected, event)]
     BadEvent {
         expected: &'static str,
         event: crate::xml_db::parse::SimpleXmlEvent,
@@ -314,6 +534,51 @@ pub enum XmlParseError {
     /// The stream of XML events ended when more events were expected
     #[error("Unexpected end of XML document")]
     Eof,
+
+    /// The document exceeds one of the `ParseLimits` it was opened with
+    #[error("Parse limit exceeded: {}", _0)]
+    LimitExceeded(ParseLimit),
+
+    /// An error at a known location in the XML document. The path lists the enclosing elements,
+    /// e.g. `KeePassFile/Root/Group/Entry[12]/String/Value`, with one-based indices for elements
+    /// that follow siblings of the same name.
+    #[error(
+        "{} (at {}, line {}, column {}, byte offset {})",
+        source,
+        path,
+        line,
+        column,
+        offset
+    )]
+    Located {
+        offset: usize,
+        line: u64,
+        column: u64,
+        path: String,
+        source: Box<XmlParseError>,
+    },
+}
+
+/// Errors while parsing an `otpauth://` URI
+#[derive(Debug, Error)]
+pub enum OtpParseError {
+    /// The entry does not contain an `otpauth://` URI
+    #[error("No OTP URI found")]
+    NoUri,
+
+    #[error("Malformed OTP URI: {}", _0)]
+    Malformed(String),
+
+    /// Only time-based one time passwords are supported
+    #[error("Unsupported OTP type: '{}'", _0)]
+    UnsupportedType(String),
+
+    #[error("Unsupported OTP algorithm: '{}'", _0)]
+    UnsupportedAlgorithm(String),
+
+    /// A parameter of the URI has an invalid value, which is left out since it may be secret
+    #[error("Invalid value for OTP parameter '{}'", _0)]
+    InvalidParameter(String),
 }
 
 /// Error parsing a color code
@@ -321,6 +586,25 @@ pub enum XmlParseError {
 #[error("Cannot parse color: '{}'", _0)]
 pub struct ParseColorError(pub String);
 
+/// An attachment that was deferred when opening the database cannot be decompressed, see
+/// `OpenOptions::defer_binaries`
+#[derive(Debug, Clone, Error)]
+#[error("Cannot decompress attachment: {}", _0)]
+pub struct DecompressionError(pub String);
+
+/// There is no group with the given UUID in the database
+#[derive(Debug, Error)]
+#[error("Group {} not found", _0)]
+pub struct GroupNotFoundError(pub uuid::Uuid);
+
+/// An index to insert a child at is greater than the number of children of a group
+#[derive(Debug, Error)]
+#[error("Index {} out of bounds for {} children", index, len)]
+pub struct IndexOutOfBounds {
+    pub index: usize,
+    pub len: usize,
+}
+
 // move error type conversions to a module and exclude them from coverage counting.
 #[cfg(not(tarpaulin_include))]
 mod conversions {
@@ -334,13 +618,24 @@ mod conversions {
 
     impl From<BlockStreamError> for DatabaseOpenError {
         fn from(e: BlockStreamError) -> Self {
-            DatabaseIntegrityError::from(e).into()
+            match e {
+                BlockStreamError::BlockHashMismatch { block_index, offset } => {
+                    DatabaseOpenError::Corrupt { block_index, offset }
+                }
+                _ => DatabaseIntegrityError::from(e).into(),
+            }
         }
     }
 
     impl From<XmlParseError> for DatabaseOpenError {
         fn from(e: XmlParseError) -> Self {
-            DatabaseIntegrityError::from(e).into()
+            match e {
+                XmlParseError::LimitExceeded(limit) => DatabaseOpenError::LimitExceeded(limit),
+                XmlParseError::Located { source, .. } if matches!(*source, XmlParseError::LimitExceeded(_)) => {
+                    DatabaseOpenError::from(*source)
+                }
+                _ => DatabaseIntegrityError::from(e).into(),
+            }
         }
     }
 
@@ -362,6 +657,12 @@ mod conversions {
         }
     }
 
+    impl From<KdfError> for DatabaseOpenError {
+        fn from(e: KdfError) -> Self {
+            DatabaseIntegrityError::from(e).into()
+        }
+    }
+
     impl From<VariantDictionaryError> for DatabaseOpenError {
         fn from(e: VariantDictionaryError) -> Self {
             DatabaseIntegrityError::from(e).into()
@@ -0,0 +1,224 @@
+use crate::{
+    db::{Database, Entry, Group, Node},
+    error::CsvExportError,
+};
+
+/// Columns of the CSV export of KeePass
+const HEADER: [&str; 5] = ["Account", "Login Name", "Password", "Web Site", "Comments"];
+
+/// Name of the column with the group path, see `Database::to_keepasscsv`
+const GROUP_HEADER: &str = "Group";
+
+impl Database {
+    /// Export the entries of the database in the CSV format of the built-in export of KeePass, with
+    /// the columns `Account`, `Login Name`, `Password`, `Web Site` and `Comments`.
+    ///
+    /// If `include_groups` is set, a `Group` column with the path of the group of each entry is
+    /// added in front, with the names of the groups below the root group separated by slashes as in
+    /// `Finance/Banking`. Slashes and backslashes in group names are escaped with a backslash, so
+    /// that a group `A/B` is written as `A\/B`. Entries in the root group have an empty path.
+    ///
+    /// Protected values such as passwords are exported in cleartext, so the database must not be
+    /// locked, see `Database::unlock`: values that cannot be read as text fail the export with
+    /// `CsvExportError::UnreadableField`. The history of entries, custom fields and attachments are
+    /// not exported.
+    pub fn to_keepasscsv(&self, include_groups: bool) -> Result<String, CsvExportError> {
+        let mut writer = csv::WriterBuilder::new()
+            .quote_style(csv::QuoteStyle::Always)
+            .terminator(csv::Terminator::CRLF)
+            .from_writer(Vec::new());
+
+        if include_groups {
+            writer.write_field(GROUP_HEADER)?;
+        }
+        writer.write_record(HEADER)?;
+
+        write_group(&mut writer, &self.root, None, include_groups)?;
+
+        let data = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(String::from_utf8(data).expect("CSV of strings is valid UTF-8"))
+    }
+}
+
+fn write_group(
+    writer: &mut csv::Writer<Vec<u8>>,
+    group: &Group,
+    path: Option<&str>,
+    include_groups: bool,
+) -> Result<(), CsvExportError> {
+    for child in &group.children {
+        match child {
+            Node::Entry(entry) => write_entry(writer, entry, path.unwrap_or_default(), include_groups)?,
+            Node::Group(subgroup) => {
+                let name = subgroup.name.replace('\\', "\\\\").replace('/', "\\/");
+                let subpath = match path {
+                    Some(path) => format!("{}/{}", path, name),
+                    None => name,
+                };
+                write_group(writer, subgroup, Some(&subpath), include_groups)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_entry(
+    writer: &mut csv::Writer<Vec<u8>>,
+    entry: &Entry,
+    path: &str,
+    include_groups: bool,
+) -> Result<(), CsvExportError> {
+    if include_groups {
+        writer.write_field(path)?;
+    }
+
+    let field = |name: &str| match entry.fields.get(name) {
+        Some(value) => value
+            .expose_secret()
+            .ok_or_else(|| CsvExportError::UnreadableField {
+                entry: entry.uuid,
+                field: name.to_string(),
+            }),
+        None => Ok(""),
+    };
+    writer.write_record([
+        field("Title")?,
+        field("UserName")?,
+        field("Password")?,
+        field("URL")?,
+        field("Notes")?,
+    ])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod keepasscsv_tests {
+    use std::fs::File;
+
+    use crate::{
+        db::{Database, Entry, Group, Value},
+        error::CsvExportError,
+        DatabaseKey,
+    };
+
+    fn entry(title: &str, notes: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected("s3cr3t".as_bytes().into()),
+        );
+        entry
+            .fields
+            .insert("Notes".to_string(), Value::Unprotected(notes.to_string()));
+        entry
+    }
+
+    #[test]
+    fn to_keepasscsv() {
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry("Root entry", "Line 1\nLine 2"));
+
+        let mut finance = Group::new("Finance");
+        let mut banking = Group::new("Banking");
+        banking.add_child(entry("Bank, \"main\"", ""));
+        finance.add_child(banking);
+        db.root.add_child(finance);
+
+        assert_eq!(
+            db.to_keepasscsv(false).unwrap(),
+            concat!(
+                "\"Account\",\"Login Name\",\"Password\",\"Web Site\",\"Comments\"\r\n",
+                "\"Root entry\",\"\",\"s3cr3t\",\"\",\"Line 1\nLine 2\"\r\n",
+                "\"Bank, \"\"main\"\"\",\"\",\"s3cr3t\",\"\",\"\"\r\n",
+            )
+        );
+
+        assert_eq!(
+            db.to_keepasscsv(true).unwrap(),
+            concat!(
+                "\"Group\",\"Account\",\"Login Name\",\"Password\",\"Web Site\",\"Comments\"\r\n",
+                "\"\",\"Root entry\",\"\",\"s3cr3t\",\"\",\"Line 1\nLine 2\"\r\n",
+                "\"Finance/Banking\",\"Bank, \"\"main\"\"\",\"\",\"s3cr3t\",\"\",\"\"\r\n",
+            )
+        );
+    }
+
+    #[test]
+    fn group_names_are_escaped() {
+        let mut db = Database::new(Default::default());
+        let mut outer = Group::new("A/B");
+        let mut inner = Group::new("C\\D");
+        inner.add_child(entry("Entry", ""));
+        outer.add_child(inner);
+        db.root.add_child(outer);
+
+        let csv = db.to_keepasscsv(true).unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let row = reader.records().next().unwrap().unwrap();
+        assert_eq!(row.get(0), Some("A\\/B/C\\\\D"));
+    }
+
+    #[test]
+    fn locked_database_is_not_exported() {
+        let mut db = Database::new(Default::default());
+        let entry = entry("Entry", "");
+        let uuid = entry.uuid;
+        db.root.add_child(entry);
+
+        let session_key = db.lock().unwrap().unwrap();
+        match db.to_keepasscsv(false) {
+            Err(CsvExportError::UnreadableField { entry, field }) => {
+                assert_eq!(entry, uuid);
+                assert_eq!(field, "Password");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        db.unlock(&session_key).unwrap();
+        assert!(db.to_keepasscsv(false).unwrap().contains("s3cr3t"));
+    }
+
+    #[test]
+    fn export_database() {
+        let db = Database::open(
+            &mut File::open("tests/resources/test_db_with_password.kdbx").unwrap(),
+            DatabaseKey::new().with_password("demopass"),
+        )
+        .unwrap();
+
+        let csv = db.to_keepasscsv(true).unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "Group",
+                "Account",
+                "Login Name",
+                "Password",
+                "Web Site",
+                "Comments"
+            ]
+        );
+
+        let rows: Vec<csv::StringRecord> = reader.records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows.len(), db.root.iter_entries().count());
+
+        for entry in db.root.iter_entries() {
+            let field = |name: &str| entry.get(name).unwrap_or_default();
+            assert!(
+                rows.iter().any(|row| row.get(1) == Some(field("Title"))
+                    && row.get(2) == Some(field("UserName"))
+                    && row.get(3) == Some(field("Password"))
+                    && row.get(4) == Some(field("URL"))
+                    && row.get(5) == Some(field("Notes"))),
+                "no row for entry {:?}",
+                entry.get_title()
+            );
+        }
+    }
+}
@@ -0,0 +1,3 @@
+//! Exporters for the import formats of other password managers
+
+mod keepasscsv;
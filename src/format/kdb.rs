@@ -1,7 +1,10 @@
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-    crypt::calculate_sha256,
-    db::{Database, Entry, Group, NodeRefMut, Value},
+    config::{
+        CompressionConfig, DatabaseConfig, DatabaseSettings, InnerCipherConfig, KdfConfig, KdfParameters,
+        KeyDerivationFunction, OuterCipherConfig,
+    },
+    crypt::{calculate_sha256, locked::LockedKey},
+    db::{Database, Entry, Group, NodeRefMut, ReadConfig, Value},
     error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
     format::DatabaseVersion,
     key::DatabaseKey,
@@ -87,7 +90,7 @@ fn collapse_tail_groups(branch: &mut Vec<Group>, level: usize, root: &mut Group)
             Some(parent) => parent,
             None => root,
         };
-        parent.add_child(leaf);
+        parent.push_child(leaf);
     }
 }
 
@@ -256,7 +259,7 @@ fn parse_entries(
                     panic!("Follow group_path")
                 };
 
-                group.add_child(entry);
+                group.push_child(entry);
                 entry = Default::default();
                 gid = None;
                 num_entries += 1;
@@ -290,7 +293,11 @@ fn parse_db(header: &KDBHeader, data: &[u8]) -> Result<Group, DatabaseIntegrityE
     Ok(root)
 }
 
-pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
+pub(crate) fn parse_kdb(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    kdf: &dyn KeyDerivationFunction,
+) -> Result<Database, DatabaseOpenError> {
     let header = parse_header(data)?;
     let version = DatabaseVersion::KDB(header.subversion as u16);
 
@@ -300,23 +307,22 @@ pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, D
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
     let key_elements = db_key.get_key_elements()?;
     let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = if key_elements.len() == 1 {
+    let composite_key = LockedKey::new(if key_elements.len() == 1 {
         let key_element: [u8; 32] = key_elements[0].try_into().unwrap();
         GenericArray::from(key_element) // single pass of SHA256, already done before the call to parse()
     } else {
         calculate_sha256(&key_elements)? // second pass of SHA256
-    };
+    });
 
     // KDF is always AES
     let kdf_config = KdfConfig::Aes {
         rounds: header.transform_rounds as u64,
     };
 
-    let transformed_key = kdf_config
-        .get_kdf_seeded(&header.transform_seed)
-        .transform_key(&composite_key)?;
+    let kdf_parameters = KdfParameters::from_config(&kdf_config, &header.transform_seed);
+    let transformed_key = LockedKey::new(kdf.derive_key(&composite_key, &kdf_parameters)?.into());
 
-    let master_key = calculate_sha256(&[&header.master_seed, &transformed_key])?;
+    let master_key = LockedKey::new(calculate_sha256(&[&header.master_seed, &transformed_key])?);
 
     let outer_cipher_config = if header.flags & 2 != 0 {
         OuterCipherConfig::AES256
@@ -343,18 +349,25 @@ pub(crate) fn parse_kdb(data: &[u8], db_key: &DatabaseKey) -> Result<Database, D
 
     let config = DatabaseConfig {
         version,
-        outer_cipher_config,
-        compression_config: CompressionConfig::None,
-        inner_cipher_config: InnerCipherConfig::Plain,
-        kdf_config,
+        settings: DatabaseSettings {
+            cipher: outer_cipher_config,
+            kdf: kdf_config,
+            compression: CompressionConfig::None,
+            inner_cipher: InnerCipherConfig::Plain,
+        },
         public_custom_data: Default::default(),
     };
 
     Ok(Database {
-        config,
+        config: config.clone(),
         header_attachments: Default::default(),
         root: root_group,
         deleted_objects: Default::default(),
         meta: Default::default(),
+        clock: Default::default(),
+        public_suffixes: Default::default(),
+        history_skipped: false,
+        index: Default::default(),
+        read_config: ReadConfig(Some(config)),
     })
 }
@@ -1,7 +1,10 @@
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-    crypt::{calculate_sha256, ciphers::Cipher},
-    db::Database,
+    config::{
+        CompressionConfig, DatabaseConfig, DatabaseSettings, InnerCipherConfig, KdfConfig, KdfParameters,
+        KeyDerivationFunction, OpenOptions, OuterCipherConfig, ParseLimit,
+    },
+    crypt::{calculate_sha256, ciphers::Cipher, locked::LockedKey},
+    db::{Database, HeaderAttachment, HeaderInfo, ReadConfig},
     error::{BlockStreamError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
     format::DatabaseVersion,
     key::DatabaseKey,
@@ -9,7 +12,40 @@ use crate::{
 
 use byteorder::{ByteOrder, LittleEndian};
 
-use std::convert::TryFrom;
+use std::{collections::HashMap, convert::TryFrom};
+
+#[cfg(feature = "save_kdbx4")]
+mod dump;
+
+#[cfg(feature = "save_kdbx4")]
+pub(crate) use crate::format::kdbx3::dump::{check_kdbx3_config, dump_kdbx3};
+
+#[cfg(feature = "save_kdbx4")]
+/// Size for the master seed, transform seed, protected stream key and stream start bytes
+pub const HEADER_SEED_SIZE: usize = 32;
+
+/// Header entry denoting the end of the header
+pub const HEADER_END: u8 = 0;
+/// Header entry denoting a comment
+pub const HEADER_COMMENT: u8 = 1;
+/// A UUID specifying which cipher suite should be used to encrypt the payload
+pub const HEADER_OUTER_ENCRYPTION_ID: u8 = 2;
+/// First byte determines compression of payload
+pub const HEADER_COMPRESSION_ID: u8 = 3;
+/// Master seed for deriving the master key
+pub const HEADER_MASTER_SEED: u8 = 4;
+/// Seed used in deriving the transformed key
+pub const HEADER_TRANSFORM_SEED: u8 = 5;
+/// Number of rounds used in derivation of the transformed key
+pub const HEADER_TRANSFORM_ROUNDS: u8 = 6;
+/// Initialization Vector for decrypting the payload
+pub const HEADER_ENCRYPTION_IV: u8 = 7;
+/// Key for decrypting the inner protected values
+pub const HEADER_PROTECTED_STREAM_KEY: u8 = 8;
+/// First bytes of the decrypted payload, to check for correct decryption
+pub const HEADER_STREAM_START_BYTES: u8 = 9;
+/// Identifier of the cipher suite for the inner protected values
+pub const HEADER_INNER_RANDOM_STREAM_ID: u8 = 10;
 
 #[derive(Debug)]
 struct KDBX3Header {
@@ -62,22 +98,22 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
 
         match entry_type {
             // END - finished parsing header
-            0 => {
+            HEADER_END => {
                 break;
             }
 
             // COMMENT
-            1 => {}
+            HEADER_COMMENT => {}
 
             // CIPHERID - a UUID specifying which cipher suite
             //            should be used to encrypt the payload
-            2 => {
+            HEADER_OUTER_ENCRYPTION_ID => {
                 outer_cipher =
                     Some(OuterCipherConfig::try_from(entry_buffer).map_err(DatabaseIntegrityError::from)?);
             }
 
             // COMPRESSIONFLAGS - first byte determines compression of payload
-            3 => {
+            HEADER_COMPRESSION_ID => {
                 compression = Some(
                     CompressionConfig::try_from(LittleEndian::read_u32(entry_buffer))
                         .map_err(DatabaseIntegrityError::from)?,
@@ -85,26 +121,26 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
             }
 
             // MASTERSEED - Master seed for deriving the master key
-            4 => master_seed = Some(entry_buffer.to_vec()),
+            HEADER_MASTER_SEED => master_seed = Some(entry_buffer.to_vec()),
 
             // TRANSFORMSEED - Seed used in deriving the transformed key
-            5 => transform_seed = Some(entry_buffer.to_vec()),
+            HEADER_TRANSFORM_SEED => transform_seed = Some(entry_buffer.to_vec()),
 
             // TRANSFORMROUNDS - Number of rounds used in derivation of transformed key
-            6 => transform_rounds = Some(LittleEndian::read_u64(entry_buffer)),
+            HEADER_TRANSFORM_ROUNDS => transform_rounds = Some(LittleEndian::read_u64(entry_buffer)),
 
             // ENCRYPTIONIV - Initialization Vector for decrypting the payload
-            7 => outer_iv = Some(entry_buffer.to_vec()),
+            HEADER_ENCRYPTION_IV => outer_iv = Some(entry_buffer.to_vec()),
 
             // PROTECTEDSTREAMKEY - Key for decrypting the inner protected values
-            8 => protected_stream_key = Some(entry_buffer.to_vec()),
+            HEADER_PROTECTED_STREAM_KEY => protected_stream_key = Some(entry_buffer.to_vec()),
 
             // STREAMSTARTBYTES - First bytes of decrypted payload (to check correct decryption)
-            9 => stream_start = Some(entry_buffer.to_vec()),
+            HEADER_STREAM_START_BYTES => stream_start = Some(entry_buffer.to_vec()),
 
             // INNERRANDOMSTREAMID - specifies which cipher suite
             //                       to use for decrypting the inner protected values
-            10 => {
+            HEADER_INNER_RANDOM_STREAM_ID => {
                 inner_cipher = Some(
                     InnerCipherConfig::try_from(LittleEndian::read_u32(entry_buffer))
                         .map_err(DatabaseIntegrityError::from)?,
@@ -156,31 +192,93 @@ fn parse_outer_header(data: &[u8]) -> Result<KDBX3Header, DatabaseOpenError> {
 }
 
 /// Open, decrypt and parse a KeePass database from a source and a password
-pub(crate) fn parse_kdbx3(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
-    let (config, mut inner_decryptor, xml) = decrypt_kdbx3(data, db_key)?;
+pub(crate) fn parse_kdbx3(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+    kdf: &dyn KeyDerivationFunction,
+) -> Result<Database, DatabaseOpenError> {
+    let (config, mut inner_decryptor, xml) = decrypt_kdbx3(data, db_key, options, kdf)?;
 
     // Parse XML data blocks
-    let database_content =
-        crate::xml_db::parse::parse(&xml, &mut *inner_decryptor).map_err(DatabaseIntegrityError::from)?;
+    let database_content = crate::xml_db::parse::parse(&xml, &mut *inner_decryptor, options)?;
 
-    let db = Database {
-        config,
+    let mut db = Database {
+        config: config.clone(),
         header_attachments: Vec::new(),
         root: database_content.root.group,
         deleted_objects: database_content.root.deleted_objects,
         meta: database_content.meta,
+        clock: Default::default(),
+        public_suffixes: Default::default(),
+        history_skipped: options.skip_history,
+        index: Default::default(),
+        read_config: ReadConfig(Some(config)),
     };
 
+    move_meta_binaries_to_pool(&mut db);
+
     Ok(db)
 }
 
+/// KDBX3 stores attachments in the metadata, where entries refer to them by ID. Move them into the
+/// attachment pool that entries refer to by index instead. Attachments that have not been
+/// decompressed yet stay deferred.
+pub(crate) fn move_meta_binaries_to_pool(db: &mut Database) {
+    let mut indices = HashMap::new();
+    for binary in std::mem::take(&mut db.meta.binaries.binaries) {
+        if let Some(id) = binary.identifier.as_ref().and_then(|id| id.parse::<usize>().ok()) {
+            indices.insert(id, db.header_attachments.len());
+        }
+        db.header_attachments.push(HeaderAttachment {
+            flags: 0,
+            content: binary.content,
+            deferred: binary.deferred,
+            hash: Default::default(),
+        });
+    }
+
+    for entry in db.root.iter_entries_mut() {
+        let history = entry.history.iter_mut().flat_map(|h| h.entries.iter_mut());
+        for attachments in std::iter::once(&mut entry.attachments).chain(history.map(|e| &mut e.attachments)) {
+            for index in attachments.values_mut() {
+                if let Some(new_index) = indices.get(index) {
+                    *index = *new_index;
+                }
+            }
+        }
+    }
+}
+
+/// Parse the outer header of a KDBX3 database, see `Database::read_header`
+pub(crate) fn parse_header_info(data: &[u8]) -> Result<HeaderInfo, DatabaseOpenError> {
+    let version = DatabaseVersion::parse(data)?;
+    let header = parse_outer_header(data)?;
+
+    Ok(HeaderInfo {
+        version,
+        outer_cipher_config: header.outer_cipher,
+        compression_config: header.compression,
+        kdf_config: header.kdf_config,
+        master_seed: header.master_seed,
+        encryption_iv: header.outer_iv,
+        kdf_seed: header.transform_seed,
+        inner_cipher_config: Some(header.inner_cipher),
+        protected_stream_key: Some(header.protected_stream_key),
+        stream_start_bytes: Some(header.stream_start),
+        public_custom_data: None,
+    })
+}
+
 /// Open and decrypt a KeePass KDBX3 database from a source and a password
 #[allow(clippy::type_complexity)]
 pub(crate) fn decrypt_kdbx3(
     data: &[u8],
     db_key: &DatabaseKey,
+    options: &OpenOptions,
+    kdf: &dyn KeyDerivationFunction,
 ) -> Result<(DatabaseConfig, Box<dyn Cipher>, Vec<u8>), DatabaseOpenError> {
-    let version = DatabaseVersion::parse(data)?;
+    let version = crate::format::parse_kdbx_version(data, options.version)?;
     let header = parse_outer_header(data)?;
 
     // Derive stream key for decrypting inner protected values and set up decryption context
@@ -189,22 +287,24 @@ pub(crate) fn decrypt_kdbx3(
 
     let inner_decryptor = header
         .inner_cipher
-        .get_cipher(&stream_key)
+        .get_parsing_cipher(&stream_key, options.defer_protected_values)
         .map_err(DatabaseIntegrityError::from)?;
 
     let config = DatabaseConfig {
         version,
-        outer_cipher_config: header.outer_cipher,
-        compression_config: header.compression,
-        inner_cipher_config: header.inner_cipher,
-        kdf_config: header.kdf_config,
+        settings: DatabaseSettings {
+            cipher: header.outer_cipher,
+            kdf: header.kdf_config,
+            compression: header.compression,
+            inner_cipher: header.inner_cipher,
+        },
         public_custom_data: Default::default(),
     };
 
     let mut pos = header.body_start;
 
     // Turn enums into appropriate trait objects
-    let compression = config.compression_config.get_compression();
+    let compression = config.settings.compression.get_compression();
 
     // Rest of file after header is payload
     let payload_encrypted = &data[pos..];
@@ -212,24 +312,28 @@ pub(crate) fn decrypt_kdbx3(
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
     let key_elements = db_key.get_key_elements()?;
     let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = calculate_sha256(&key_elements)?;
+    let composite_key = LockedKey::new(calculate_sha256(&key_elements)?);
 
     // transform the key
-    let transformed_key = config
-        .kdf_config
-        .get_kdf_seeded(&header.transform_seed)
-        .transform_key(&composite_key)?;
+    let kdf_parameters = KdfParameters::from_config(&config.settings.kdf, &header.transform_seed);
+    let transformed_key = LockedKey::new(kdf.derive_key(&composite_key, &kdf_parameters)?.into());
 
-    let master_key = calculate_sha256(&[header.master_seed.as_ref(), &transformed_key])?;
+    let master_key = LockedKey::new(calculate_sha256(&[
+        header.master_seed.as_ref(),
+        &transformed_key,
+    ])?);
 
-    // Decrypt payload
+    // Decrypt payload. KDBX3 has no key check before this point, so invalid padding after decryption
+    // almost always means that the key was wrong.
     let payload = config
-        .outer_cipher_config
+        .settings
+        .cipher
         .get_cipher(&master_key, header.outer_iv.as_ref())?
-        .decrypt(payload_encrypted)?;
+        .decrypt(payload_encrypted)
+        .map_err(|_| DatabaseKeyError::IncorrectKey)?;
 
     // Check if we decrypted correctly
-    if &payload[0..header.stream_start.len()] != header.stream_start.as_slice() {
+    if !payload.starts_with(&header.stream_start) {
         return Err(DatabaseKeyError::IncorrectKey.into());
     }
 
@@ -263,7 +367,11 @@ pub(crate) fn decrypt_kdbx3(
         // Test block hash
         let block_hash_check = calculate_sha256(&[block_buffer_compressed])?;
         if block_hash != block_hash_check.as_slice() {
-            return Err(BlockStreamError::BlockHashMismatch { block_index }.into());
+            return Err(BlockStreamError::BlockHashMismatch {
+                block_index,
+                offset: pos as u64,
+            }
+            .into());
         }
 
         // Decompress block_buffer_compressed
@@ -273,7 +381,302 @@ pub(crate) fn decrypt_kdbx3(
         block_index += 1;
     }
 
-    let xml = compression.decompress(&buf)?;
+    let max_size = options.limits.max_decompressed_size;
+    let xml = compression.decompress(&buf, max_size)?;
+    if xml.len() as u64 > max_size {
+        return Err(DatabaseOpenError::LimitExceeded(ParseLimit::DecompressedSize));
+    }
 
     Ok((config, inner_decryptor, xml))
 }
+
+#[cfg(feature = "save_kdbx4")]
+#[cfg(test)]
+mod kdbx3_tests {
+    use super::*;
+
+    use crate::{
+        config::BuiltinKdf,
+        db::{Entry, NodeRef, Value},
+        error::DatabaseSaveError,
+    };
+
+    fn make_db(kdf_config: KdfConfig) -> Database {
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: kdf_config,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Demo Entry".into()));
+        entry
+            .fields
+            .insert("Password".to_string(), Value::Protected("secret".into()));
+        entry.add_attachment(&mut db, "data.bin", b"i am binary data".to_vec());
+        db.root.add_child(entry);
+
+        db
+    }
+
+    #[test]
+    fn downgrade_roundtrip() -> Result<(), DatabaseSaveError> {
+        let mut db = make_db(KdfConfig::Aes { rounds: 10 });
+        db.set_format_version(DatabaseVersion::KDB3(1))?;
+        assert_eq!(db.config.settings.inner_cipher, InnerCipherConfig::Salsa20);
+
+        let db_key = DatabaseKey::new().with_password("testing");
+        let mut encrypted_db = Vec::new();
+        db.save(&mut encrypted_db, db_key.clone())?;
+
+        assert_eq!(
+            DatabaseVersion::parse(&encrypted_db).unwrap(),
+            DatabaseVersion::KDB3(1)
+        );
+
+        let decrypted_db = parse_kdbx3(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        assert_eq!(decrypted_db.config, db.config);
+        assert_eq!(decrypted_db.root, db.root);
+        assert_eq!(decrypted_db.header_attachments.len(), 1);
+        assert_eq!(decrypted_db.header_attachments[0].content, b"i am binary data");
+        assert!(decrypted_db.meta.binaries.binaries.is_empty());
+
+        if let Some(NodeRef::Entry(e)) = decrypted_db.root.get(&["Demo Entry"]) {
+            assert_eq!(e.get_password(), Some("secret"));
+        } else {
+            panic!("Could not get NodeRef")
+        }
+
+        // timestamps are stored as ISO 8601 strings, which is what older KeePass versions expect
+        let (_, _, xml) = decrypt_kdbx3(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(&format!(
+            "<CreationTime>{}</CreationTime>",
+            db.root.times.get_creation().unwrap().format("%Y-%m-%dT%H:%M:%SZ")
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn downgrade_argon2() {
+        let mut db = make_db(KdfConfig::Argon2 {
+            iterations: 10,
+            memory: 65536,
+            parallelism: 2,
+            version: argon2::Version::Version13,
+        });
+
+        let res = db.set_format_version(DatabaseVersion::KDB3(1));
+        assert!(matches!(res, Err(DatabaseSaveError::IncompatibleVersion { .. })));
+        assert_eq!(db.config.version, DatabaseVersion::KDB4(0));
+
+        // changing the version directly is caught when saving
+        db.config.version = DatabaseVersion::KDB3(1);
+        let res = db.save(&mut Vec::new(), DatabaseKey::new().with_password("testing"));
+        assert!(matches!(res, Err(DatabaseSaveError::IncompatibleVersion { .. })));
+    }
+
+    #[test]
+    fn upgrade_roundtrip() {
+        let db_key = DatabaseKey::new().with_password("samplepassword");
+        let data = std::fs::read("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx").unwrap();
+        let original = parse_kdbx3(&data, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        assert_eq!(original.header_attachments.len(), 1);
+        assert!(original
+            .root
+            .iter_entries()
+            .any(|e| e.attachments.values().any(|i| *i == 0)));
+
+        let mut db = original.clone();
+        assert!(matches!(db.config.settings.kdf, KdfConfig::Aes { .. }));
+        db.set_format_version(DatabaseVersion::KDB4(0)).unwrap();
+        assert_eq!(db.config.settings.kdf, DatabaseSettings::default().kdf);
+        assert_eq!(db.config.settings.inner_cipher, InnerCipherConfig::ChaCha20);
+
+        let mut encrypted_db = Vec::new();
+        db.save(&mut encrypted_db, db_key.clone()).unwrap();
+
+        let decrypted_db = Database::parse(&encrypted_db, db_key).unwrap();
+        assert_eq!(decrypted_db.config.version, DatabaseVersion::KDB4(0));
+        assert_eq!(decrypted_db.config.settings.kdf, db.config.settings.kdf);
+        assert_eq!(decrypted_db.root, original.root);
+        assert_eq!(decrypted_db.header_attachments, original.header_attachments);
+    }
+
+    #[test]
+    fn deferred_compressed_binaries() {
+        use crate::config::{OpenOptions, ParseLimit, ParseLimits};
+
+        let db_key = DatabaseKey::new().with_password("samplepassword");
+        let data = std::fs::read("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx").unwrap();
+        let loaded = Database::parse(&data, db_key.clone()).unwrap();
+        let deferred =
+            Database::parse_with_options(&data, db_key.clone(), &OpenOptions::new().defer_binaries(true))
+                .unwrap();
+
+        let attachment = &deferred.header_attachments[0];
+        assert!(attachment.is_deferred());
+        assert!(attachment.content.is_empty());
+        assert!(!loaded.header_attachments[0].is_deferred());
+        assert_eq!(attachment.data(), loaded.header_attachments[0].content);
+        assert_eq!(deferred.header_attachments, loaded.header_attachments);
+
+        // the decompressed content is shared between clones
+        let clone = attachment.clone();
+        assert!(std::ptr::eq(clone.data(), attachment.data()));
+
+        let mut encrypted_db = Vec::new();
+        deferred.save(&mut encrypted_db, db_key.clone()).unwrap();
+        let reopened = parse_kdbx3(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        assert_eq!(reopened.header_attachments, loaded.header_attachments);
+
+        // the size is checked without decompressing the attachment
+        let limits = ParseLimits {
+            max_binary_size: attachment.data().len() as u64 - 1,
+            ..Default::default()
+        };
+        let res = Database::parse_with_options(
+            &data,
+            db_key,
+            &OpenOptions::new().defer_binaries(true).limits(limits),
+        );
+        assert!(matches!(
+            res,
+            Err(DatabaseOpenError::LimitExceeded(ParseLimit::BinarySize))
+        ));
+    }
+
+    #[test]
+    fn deferred_corrupted_binaries() {
+        use crate::db::DeferredBinary;
+
+        let db_key = DatabaseKey::new().with_password("samplepassword");
+        let data = std::fs::read("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx").unwrap();
+        let options = OpenOptions::new().defer_binaries(true);
+        let mut db = Database::parse_with_options(&data, db_key.clone(), &options).unwrap();
+
+        // damage the compressed data, but keep the size recorded at the end of the GZip stream
+        let attachment = &mut db.header_attachments[0];
+        let deferred = attachment.deferred.take().unwrap();
+        let mut gzip = deferred.gzip().unwrap().to_vec();
+        let middle = gzip.len() / 2;
+        gzip[middle..middle + 64].fill(0xff);
+        attachment.deferred = Some(DeferredBinary::compressed(
+            gzip.clone(),
+            deferred.size(),
+            u64::MAX,
+        ));
+
+        assert!(attachment.try_data().is_err());
+        assert!(attachment.data().is_empty());
+        assert!(attachment.load().is_err());
+        assert!(attachment.is_deferred());
+
+        // the compressed data is saved as it was read instead of as an empty attachment
+        let mut encrypted_db = Vec::new();
+        db.save(&mut encrypted_db, db_key.clone()).unwrap();
+        let reopened = Database::parse_with_options(&encrypted_db, db_key.clone(), &options).unwrap();
+        let reopened_gzip = reopened.header_attachments[0]
+            .deferred
+            .as_ref()
+            .and_then(DeferredBinary::gzip);
+        assert_eq!(reopened_gzip, Some(&gzip[..]));
+
+        // KDBX4 cannot store the compressed data, so saving fails
+        db.set_format_version(DatabaseVersion::KDB4(0)).unwrap();
+        assert!(matches!(
+            db.save(&mut Vec::new(), db_key),
+            Err(DatabaseSaveError::Decompression(_))
+        ));
+    }
+
+    #[test]
+    fn upgrade_moves_meta_binaries() {
+        use crate::{config::OpenOptions, db::BinaryAttachment};
+
+        let db_key = DatabaseKey::new().with_password("samplepassword");
+        let data = std::fs::read("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx").unwrap();
+        let loaded = parse_kdbx3(&data, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        let options = OpenOptions::new().defer_binaries(true);
+        let mut db = parse_kdbx3(&data, &db_key, &options, &BuiltinKdf).unwrap();
+
+        // put the attachment back into the metadata, behind one that no entry refers to
+        let attachment = db.header_attachments.remove(0);
+        db.meta.binaries.binaries = vec![
+            BinaryAttachment::new(Some("1".to_string()), false, b"unused".to_vec()),
+            BinaryAttachment {
+                identifier: Some("0".to_string()),
+                compressed: true,
+                content: attachment.content,
+                deferred: attachment.deferred,
+            },
+        ];
+
+        db.set_format_version(DatabaseVersion::KDB4(0)).unwrap();
+        assert!(db.meta.binaries.binaries.is_empty());
+        assert_eq!(db.header_attachments.len(), 2);
+        assert!(db.header_attachments[1].is_deferred());
+
+        let mut encrypted_db = Vec::new();
+        db.save(&mut encrypted_db, db_key.clone()).unwrap();
+        let reopened = Database::parse(&encrypted_db, db_key).unwrap();
+        assert_eq!(reopened.config.version, DatabaseVersion::KDB4(0));
+
+        let mut compared = 0;
+        for (entry, loaded_entry) in reopened.root.iter_entries().zip(loaded.root.iter_entries()) {
+            for name in loaded_entry.attachment_names() {
+                assert_eq!(
+                    entry.get_attachment(&reopened, name),
+                    loaded_entry.get_attachment(&loaded, name)
+                );
+                compared += 1;
+            }
+        }
+        assert!(compared > 0);
+    }
+
+    #[test]
+    fn resave_larger_than_block_size() {
+        let db_key = DatabaseKey::new().with_password("samplepassword");
+        let data = std::fs::read("tests/resources/test_db_kdb3_with_file_larger_1mb.kdbx").unwrap();
+        let db = parse_kdbx3(&data, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+
+        let mut encrypted_db = Vec::new();
+        db.save(&mut encrypted_db, db_key.clone()).unwrap();
+
+        let decrypted_db = parse_kdbx3(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        assert_eq!(decrypted_db.root, db.root);
+        assert_eq!(decrypted_db.header_attachments, db.header_attachments);
+    }
+
+    #[test]
+    fn fresh_seeds_on_save() -> Result<(), DatabaseSaveError> {
+        let mut db = make_db(KdfConfig::Aes { rounds: 10 });
+        db.set_format_version(DatabaseVersion::KDB3(1))?;
+        let db_key = DatabaseKey::new().with_password("testing");
+
+        let mut first = Vec::new();
+        db.save(&mut first, db_key.clone())?;
+        let mut second = Vec::new();
+        db.save(&mut second, db_key.clone())?;
+
+        let first_header = parse_outer_header(&first).unwrap();
+        let second_header = parse_outer_header(&second).unwrap();
+        assert_ne!(first_header.outer_iv, second_header.outer_iv);
+        assert_ne!(first_header.master_seed, second_header.master_seed);
+        assert_ne!(first_header.transform_seed, second_header.transform_seed);
+        assert_ne!(
+            first_header.protected_stream_key,
+            second_header.protected_stream_key
+        );
+
+        assert!(parse_kdbx3(&first, &db_key, &OpenOptions::default(), &BuiltinKdf).is_ok());
+        assert!(parse_kdbx3(&second, &db_key, &OpenOptions::default(), &BuiltinKdf).is_ok());
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::{
+    config::{DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    crypt::{self, locked::LockedKey},
+    db::{BinaryAttachment, Database, HeaderAttachment, SavePhase, SaveProgress},
+    error::{DatabaseSaveError, DecompressionError},
+    format::{
+        finish_phase,
+        kdbx3::{
+            HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END, HEADER_INNER_RANDOM_STREAM_ID,
+            HEADER_MASTER_SEED, HEADER_OUTER_ENCRYPTION_ID, HEADER_PROTECTED_STREAM_KEY, HEADER_SEED_SIZE,
+            HEADER_STREAM_START_BYTES, HEADER_TRANSFORM_ROUNDS, HEADER_TRANSFORM_SEED,
+        },
+        start_phase, write_with_progress, DatabaseVersion, SaveRng,
+    },
+    key::DatabaseKey,
+};
+
+/// Maximum size of a block in the hashed block stream
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Check that a database configuration only uses features that can be stored in a KDBX3 file
+pub(crate) fn check_kdbx3_config(config: &DatabaseConfig) -> Result<(), DatabaseSaveError> {
+    let incompatible = |reason| DatabaseSaveError::IncompatibleVersion {
+        version: DatabaseVersion::KDB3(1),
+        reason,
+    };
+
+    if !matches!(config.settings.kdf, KdfConfig::Aes { .. }) {
+        return Err(incompatible(
+            "KDBX3 only supports the AES key derivation function",
+        ));
+    }
+
+    if config.settings.cipher == OuterCipherConfig::ChaCha20 {
+        return Err(incompatible("KDBX3 does not support the ChaCha20 outer cipher"));
+    }
+
+    if config.settings.inner_cipher == InnerCipherConfig::ChaCha20 {
+        return Err(incompatible("KDBX3 does not support the ChaCha20 inner cipher"));
+    }
+
+    if config.public_custom_data.is_some() {
+        return Err(incompatible("KDBX3 does not support public custom data"));
+    }
+
+    Ok(())
+}
+
+/// Dump a KeePass database in the KDBX3 format using the key elements
+pub fn dump_kdbx3(
+    db: &Database,
+    db_key: &DatabaseKey,
+    writer: &mut dyn Write,
+    rng: &mut SaveRng,
+    progress: &mut dyn FnMut(SaveProgress),
+) -> Result<(), DatabaseSaveError> {
+    if !matches!(db.config.version, DatabaseVersion::KDB3(_)) {
+        return Err(DatabaseSaveError::UnsupportedVersion);
+    }
+
+    check_kdbx3_config(&db.config)?;
+
+    // generate encryption keys and seeds on the fly when saving
+    let mut master_seed = vec![0; HEADER_SEED_SIZE];
+    rng.fill(&mut master_seed)?;
+
+    let mut outer_iv = vec![0; db.config.settings.cipher.get_iv_size()];
+    rng.fill(&mut outer_iv)?;
+
+    let mut protected_stream_key = vec![0; HEADER_SEED_SIZE];
+    rng.fill(&mut protected_stream_key)?;
+
+    let mut stream_start = vec![0; HEADER_SEED_SIZE];
+    rng.fill(&mut stream_start)?;
+
+    let (kdf, transform_seed) = db.config.settings.kdf.get_kdf_and_seed(rng)?;
+
+    let transform_rounds = match db.config.settings.kdf {
+        KdfConfig::Aes { rounds } => rounds,
+        _ => unreachable!("KDF was checked to be AES"),
+    };
+
+    #[cfg(feature = "challenge_response")]
+    let db_key = db_key.clone().perform_challenge(&transform_seed)?;
+
+    // dump the outer header into a buffer, it is written out together with the payload
+    let mut header_data = Vec::new();
+    db.config.version.dump(&mut header_data)?;
+
+    write_header_field(
+        &mut header_data,
+        HEADER_OUTER_ENCRYPTION_ID,
+        &db.config.settings.cipher.dump(),
+    )?;
+    write_header_field(
+        &mut header_data,
+        HEADER_COMPRESSION_ID,
+        &db.config.settings.compression.dump(),
+    )?;
+    write_header_field(&mut header_data, HEADER_MASTER_SEED, &master_seed)?;
+    write_header_field(&mut header_data, HEADER_TRANSFORM_SEED, &transform_seed)?;
+    write_header_field(
+        &mut header_data,
+        HEADER_TRANSFORM_ROUNDS,
+        &transform_rounds.to_le_bytes(),
+    )?;
+    write_header_field(&mut header_data, HEADER_ENCRYPTION_IV, &outer_iv)?;
+    write_header_field(
+        &mut header_data,
+        HEADER_PROTECTED_STREAM_KEY,
+        &protected_stream_key,
+    )?;
+    write_header_field(&mut header_data, HEADER_STREAM_START_BYTES, &stream_start)?;
+    write_header_field(
+        &mut header_data,
+        HEADER_INNER_RANDOM_STREAM_ID,
+        &db.config.settings.inner_cipher.dump().to_le_bytes(),
+    )?;
+    write_header_field(&mut header_data, HEADER_END, b"\r\n\r\n")?;
+
+    // derive master key from composite key, transform_seed, transform_rounds and master_seed
+    let key_elements = db_key.get_key_elements()?;
+    let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+    let composite_key = LockedKey::new(crypt::calculate_sha256(&key_elements)?);
+    start_phase(SavePhase::KdfDerivation, progress);
+    let transformed_key = LockedKey::new(kdf.transform_key(&composite_key)?);
+    finish_phase(SavePhase::KdfDerivation, progress);
+    let master_key = LockedKey::new(crypt::calculate_sha256(&[&master_seed, &transformed_key])?);
+
+    // Initialize inner encryptor, the inner stream key is hashed just like when reading
+    let stream_key = crypt::calculate_sha256(&[&protected_stream_key])?;
+    let mut inner_cipher = db.config.settings.inner_cipher.get_cipher(&stream_key)?;
+
+    // KDBX3 has no inner header, so attachments are stored in the metadata instead. The IDs of the
+    // pooled attachments are their indices, which is how entries refer to them.
+    // Attachments that were removed from all entries are not written to the file.
+    let mut xml = Vec::new();
+    let attachment_indices = db.attachment_indices();
+    if db.header_attachments.is_empty() {
+        crate::xml_db::dump::dump(db, &mut *inner_cipher, &attachment_indices, &mut xml)?;
+    } else {
+        let mut db = db.clone();
+        let other_binaries = std::mem::take(&mut db.meta.binaries.binaries);
+        // attachments that were not decompressed when opening are written with their compressed
+        // content as it was read, which also keeps the ones that cannot be decompressed intact
+        db.meta.binaries.binaries = db
+            .header_attachments
+            .drain(..)
+            .zip(&attachment_indices)
+            .filter_map(|(attachment, index)| index.map(|_| attachment))
+            .map(
+                |HeaderAttachment {
+                     content, deferred, ..
+                 }| (content, deferred),
+            )
+            .chain(other_binaries.into_iter().map(|b| (b.content, b.deferred)))
+            .enumerate()
+            .map(|(i, (content, deferred))| {
+                let identifier = Some(i.to_string());
+                Ok(match deferred {
+                    Some(deferred) if deferred.gzip().is_some() => BinaryAttachment {
+                        identifier,
+                        compressed: true,
+                        content: Vec::new(),
+                        deferred: Some(deferred),
+                    },
+                    Some(deferred) => BinaryAttachment {
+                        identifier,
+                        compressed: false,
+                        content: deferred.data()?.to_vec(),
+                        deferred: None,
+                    },
+                    None => BinaryAttachment {
+                        identifier,
+                        compressed: false,
+                        content,
+                        deferred: None,
+                    },
+                })
+            })
+            .collect::<Result<_, DecompressionError>>()?;
+        crate::xml_db::dump::dump(&db, &mut *inner_cipher, &attachment_indices, &mut xml)?;
+    }
+
+    start_phase(SavePhase::Compression, progress);
+    let xml_compressed = db.config.settings.compression.get_compression().compress(&xml)?;
+    finish_phase(SavePhase::Compression, progress);
+
+    start_phase(SavePhase::Encryption, progress);
+    let mut payload = stream_start;
+    write_hashed_block_stream(&xml_compressed, &mut payload)?;
+
+    let payload_encrypted = db
+        .config
+        .settings
+        .cipher
+        .get_cipher(&master_key, &outer_iv)?
+        .encrypt(&payload)?;
+    finish_phase(SavePhase::Encryption, progress);
+
+    write_with_progress(writer, &[&header_data, &payload_encrypted], progress)?;
+
+    Ok(())
+}
+
+/// Write an outer header field with a 16-bit length
+fn write_header_field(writer: &mut dyn Write, entry_type: u8, data: &[u8]) -> Result<(), std::io::Error> {
+    writer.write_u8(entry_type)?;
+    writer.write_u16::<LittleEndian>(data.len() as u16)?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// Split data into SHA256-hashed blocks, terminated by an empty block
+fn write_hashed_block_stream(data: &[u8], writer: &mut Vec<u8>) -> Result<(), DatabaseSaveError> {
+    let mut block_index = 0;
+
+    for block in data.chunks(BLOCK_SIZE) {
+        writer.write_u32::<LittleEndian>(block_index)?;
+        writer.write_all(&crypt::calculate_sha256(&[block])?)?;
+        writer.write_u32::<LittleEndian>(block.len() as u32)?;
+        writer.write_all(block)?;
+        block_index += 1;
+    }
+
+    writer.write_u32::<LittleEndian>(block_index)?;
+    writer.write_all(&[0; 32])?;
+    writer.write_u32::<LittleEndian>(0)?;
+
+    Ok(())
+}
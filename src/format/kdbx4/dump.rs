@@ -1,21 +1,23 @@
-use std::io::Write;
+use std::io::{BufWriter, Write};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::{
-    crypt,
-    db::{Database, HeaderAttachment},
+    config::KdfParameters,
+    crypt::{self, ciphers::EncryptWriter, locked::LockedKey},
+    db::{Database, HeaderAttachment, SavePhase, SaveProgress},
     error::DatabaseSaveError,
     format::{
+        finish_phase,
         kdbx4::{
             KDBX4InnerHeader, KDBX4OuterHeader, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END,
             HEADER_KDF_PARAMS, HEADER_MASTER_SEED, HEADER_MASTER_SEED_SIZE, HEADER_OUTER_ENCRYPTION_ID,
             INNER_HEADER_BINARY_ATTACHMENTS, INNER_HEADER_END, INNER_HEADER_RANDOM_STREAM_ID,
             INNER_HEADER_RANDOM_STREAM_KEY,
         },
-        DatabaseVersion,
+        start_phase, DatabaseVersion, ProgressWriter, SaveRng,
     },
-    hmac_block_stream,
+    hmac_block_stream::{self, HmacBlockWriter},
     io::WriteLengthTaggedExt,
     key::DatabaseKey,
     variant_dictionary::VariantDictionary,
@@ -23,11 +25,16 @@ use crate::{
 
 use super::HEADER_PUBLIC_CUSTOM_DATA;
 
+/// Size of the buffer that collects the small writes of the XML serialization
+const PAYLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
 /// Dump a KeePass database using the key elements
 pub fn dump_kdbx4(
     db: &Database,
     db_key: &DatabaseKey,
     writer: &mut dyn Write,
+    rng: &mut SaveRng,
+    progress: &mut dyn FnMut(SaveProgress),
 ) -> Result<(), DatabaseSaveError> {
     if !matches!(db.config.version, DatabaseVersion::KDB4(_)) {
         return Err(DatabaseSaveError::UnsupportedVersion);
@@ -35,15 +42,15 @@ pub fn dump_kdbx4(
 
     // generate encryption keys and seeds on the fly when saving
     let mut master_seed = vec![0; HEADER_MASTER_SEED_SIZE];
-    getrandom::fill(&mut master_seed)?;
+    rng.fill(&mut master_seed)?;
 
-    let mut outer_iv = vec![0; db.config.outer_cipher_config.get_iv_size()];
-    getrandom::fill(&mut outer_iv)?;
+    let mut outer_iv = vec![0; db.config.settings.cipher.get_iv_size()];
+    rng.fill(&mut outer_iv)?;
 
-    let mut inner_random_stream_key = vec![0; db.config.inner_cipher_config.get_key_size()];
-    getrandom::fill(&mut inner_random_stream_key)?;
+    let mut inner_random_stream_key = vec![0; db.config.settings.inner_cipher.get_key_size()];
+    rng.fill(&mut inner_random_stream_key)?;
 
-    let (kdf, kdf_seed) = db.config.kdf_config.get_kdf_and_seed()?;
+    let (kdf, kdf_seed) = db.config.settings.kdf.get_kdf_and_seed(rng)?;
 
     #[cfg(feature = "challenge_response")]
     let db_key = db_key.clone().perform_challenge(&kdf_seed)?;
@@ -52,28 +59,25 @@ pub fn dump_kdbx4(
     let mut header_data = Vec::new();
     KDBX4OuterHeader {
         version: db.config.version.clone(),
-        outer_cipher_config: db.config.outer_cipher_config.clone(),
-        compression_config: db.config.compression_config.clone(),
+        outer_cipher_config: db.config.settings.cipher.clone(),
+        compression_config: db.config.settings.compression.clone(),
         master_seed: master_seed.clone(),
         outer_iv: outer_iv.clone(),
-        kdf_config: db.config.kdf_config.clone(),
-        kdf_seed,
+        kdf_parameters: KdfParameters::from_config(&db.config.settings.kdf, &kdf_seed),
         public_custom_data: db.config.public_custom_data.clone(),
     }
     .dump(&mut header_data)?;
 
     let header_sha256 = crypt::calculate_sha256(&[&header_data])?;
 
-    // write out header and header hash
-    writer.write_all(&header_data)?;
-    writer.write_all(&header_sha256)?;
-
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
+    start_phase(SavePhase::KdfDerivation, progress);
     let key_elements = db_key.get_key_elements()?;
     let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = crypt::calculate_sha256(&key_elements)?;
-    let transformed_key = kdf.transform_key(&composite_key)?;
-    let master_key = crypt::calculate_sha256(&[&master_seed, &transformed_key])?;
+    let composite_key = LockedKey::new(crypt::calculate_sha256(&key_elements)?);
+    let transformed_key = LockedKey::new(kdf.transform_key(&composite_key)?);
+    finish_phase(SavePhase::KdfDerivation, progress);
+    let master_key = LockedKey::new(crypt::calculate_sha256(&[&master_seed, &transformed_key])?);
 
     // verify credentials
     let hmac_key =
@@ -81,47 +85,85 @@ pub fn dump_kdbx4(
     let header_hmac_key = hmac_block_stream::get_hmac_block_key(u64::MAX, &hmac_key)?;
     let header_hmac = crypt::calculate_hmac(&[&header_data], &header_hmac_key)?;
 
-    writer.write_all(&header_hmac)?;
-
-    // Initialize inner encryptor from inner header params
+    let inner_header = KDBX4InnerHeader {
+        inner_random_stream: db.config.settings.inner_cipher.clone(),
+        inner_random_stream_key,
+    };
     let mut inner_cipher = db
         .config
-        .inner_cipher_config
-        .get_cipher(&inner_random_stream_key)?;
+        .settings
+        .inner_cipher
+        .get_cipher(&inner_header.inner_random_stream_key)?;
+
+    // attachments that were removed from all entries are not written to the file
+    let attachment_indices = db.attachment_indices();
+    let attachments: Vec<&HeaderAttachment> = db
+        .header_attachments
+        .iter()
+        .zip(&attachment_indices)
+        .filter_map(|(attachment, index)| index.map(|_| attachment))
+        .collect();
+
+    // write out header, header hash and header HMAC
+    writer.write_all(&header_data)?;
+    writer.write_all(&header_sha256)?;
+    writer.write_all(&header_hmac)?;
 
-    // dump inner header into buffer
-    let mut payload = Vec::new();
-    KDBX4InnerHeader {
-        inner_random_stream: db.config.inner_cipher_config.clone(),
-        inner_random_stream_key,
-    }
-    .dump(&db.header_attachments, &mut payload)?;
+    // stream the inner header and the XML document through compression, encryption and the HMAC
+    // block stream into the writer. The three happen in one pass, whose progress is reported as
+    // that of the compression; encryption and writing complete as the remaining data is flushed.
+    // The size of the XML document is not known before it is written, so the progress is that of
+    // the inner header, whose attachments make up most of a large payload.
+    start_phase(SavePhase::Compression, progress);
+    let block_writer = HmacBlockWriter::new(&mut *writer, hmac_key);
+    let encrypt_writer = EncryptWriter::new(
+        block_writer,
+        db.config.settings.cipher.get_encryptor(&master_key, &outer_iv)?,
+    );
+    let compression_writer = db
+        .config
+        .settings
+        .compression
+        .get_compression_writer(encrypt_writer);
+    let mut payload_writer = BufWriter::with_capacity(
+        PAYLOAD_BUFFER_SIZE,
+        ProgressWriter::new(
+            compression_writer,
+            SavePhase::Compression,
+            inner_header.size(&attachments)?,
+            progress,
+        ),
+    );
+
+    inner_header.dump(&attachments, &mut payload_writer)?;
 
     // after inner header is one XML document
-    crate::xml_db::dump::dump(db, &mut *inner_cipher, &mut payload)?;
+    crate::xml_db::dump::dump(db, &mut *inner_cipher, &attachment_indices, &mut payload_writer)?;
 
-    let payload_compressed = db
-        .config
-        .compression_config
-        .get_compression()
-        .compress(&payload)?;
+    let encrypt_writer = payload_writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .into_inner()
+        .finish()?;
+    finish_phase(SavePhase::Compression, progress);
 
-    let payload_encrypted = db
-        .config
-        .outer_cipher_config
-        .get_cipher(&master_key, &outer_iv)?
-        .encrypt(&payload_compressed)?;
+    start_phase(SavePhase::Encryption, progress);
+    let block_writer = encrypt_writer.finish()?;
+    finish_phase(SavePhase::Encryption, progress);
 
-    let payload_hmac = hmac_block_stream::write_hmac_block_stream(&payload_encrypted, &hmac_key)?;
-    writer.write_all(&payload_hmac)?;
+    start_phase(SavePhase::Writing, progress);
+    block_writer.finish()?;
+    finish_phase(SavePhase::Writing, progress);
 
     Ok(())
 }
 
 impl HeaderAttachment {
-    fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
+    fn dump(&self, writer: &mut dyn Write) -> Result<(), DatabaseSaveError> {
+        let data = self.try_data()?;
+        writer.write_u32::<LittleEndian>((data.len() + 1) as u32)?;
         writer.write_u8(self.flags)?;
-        writer.write_all(&self.content)?;
+        writer.write_all(data)?;
         Ok(())
     }
 }
@@ -142,7 +184,7 @@ impl KDBX4OuterHeader {
         writer.write_u8(HEADER_MASTER_SEED)?;
         writer.write_with_len(&self.master_seed)?;
 
-        let vd: VariantDictionary = self.kdf_config.to_variant_dictionary(&self.kdf_seed);
+        let vd: &VariantDictionary = self.kdf_parameters.variant_dictionary();
         let mut vd_buffer = Vec::new();
         vd.dump(&mut vd_buffer)?;
 
@@ -167,7 +209,7 @@ impl KDBX4OuterHeader {
 impl KDBX4InnerHeader {
     fn dump(
         &self,
-        header_attachments: &[HeaderAttachment],
+        header_attachments: &[&HeaderAttachment],
         writer: &mut dyn Write,
     ) -> Result<(), DatabaseSaveError> {
         writer.write_all(&[INNER_HEADER_RANDOM_STREAM_ID])?;
@@ -179,7 +221,6 @@ impl KDBX4InnerHeader {
 
         for attachment in header_attachments {
             writer.write_u8(INNER_HEADER_BINARY_ATTACHMENTS)?;
-            writer.write_u32::<LittleEndian>((attachment.content.len() + 1) as u32)?;
             attachment.dump(writer)?;
         }
 
@@ -188,4 +229,44 @@ impl KDBX4InnerHeader {
 
         Ok(())
     }
+
+    /// Get the number of bytes that `dump` writes, without writing them
+    fn size(&self, header_attachments: &[&HeaderAttachment]) -> Result<u64, DatabaseSaveError> {
+        // every field has a one byte type and a four byte length
+        const FIELD_OVERHEAD: u64 = 5;
+
+        let mut size = FIELD_OVERHEAD + 4;
+        size += FIELD_OVERHEAD + self.inner_random_stream_key.len() as u64;
+        for attachment in header_attachments {
+            // the content of an attachment is preceded by a byte of flags
+            size += FIELD_OVERHEAD + 1 + attachment.try_data()?.len() as u64;
+        }
+        size += FIELD_OVERHEAD;
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod kdbx4_dump_tests {
+    use super::KDBX4InnerHeader;
+    use crate::{config::InnerCipherConfig, db::HeaderAttachment};
+
+    #[test]
+    fn inner_header_size() {
+        let inner_header = KDBX4InnerHeader {
+            inner_random_stream: InnerCipherConfig::ChaCha20,
+            inner_random_stream_key: vec![7; 64],
+        };
+        let attachments = [
+            HeaderAttachment::new(1, vec![1, 2, 3]),
+            HeaderAttachment::new(0, Vec::new()),
+        ];
+        let attachments: Vec<&HeaderAttachment> = attachments.iter().collect();
+
+        let mut dumped = Vec::new();
+        inner_header.dump(&attachments, &mut dumped).unwrap();
+        assert_eq!(inner_header.size(&attachments).unwrap(), dumped.len() as u64);
+        assert_eq!(inner_header.size(&[]).unwrap(), 83);
+    }
 }
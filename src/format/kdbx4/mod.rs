@@ -3,14 +3,14 @@ mod dump;
 mod parse;
 
 use crate::{
-    config::{CompressionConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+    config::{CompressionConfig, InnerCipherConfig, KdfParameters, OuterCipherConfig},
     format::DatabaseVersion,
     variant_dictionary::VariantDictionary,
 };
 
 #[cfg(feature = "save_kdbx4")]
 pub(crate) use crate::format::kdbx4::dump::dump_kdbx4;
-pub(crate) use crate::format::kdbx4::parse::{decrypt_kdbx4, parse_kdbx4};
+pub(crate) use crate::format::kdbx4::parse::{decrypt_kdbx4, parse_header_info, parse_kdbx4, recover_kdbx4};
 
 #[cfg(feature = "save_kdbx4")]
 /// Size for a master seed in bytes
@@ -48,8 +48,7 @@ struct KDBX4OuterHeader {
     compression_config: CompressionConfig,
     master_seed: Vec<u8>,
     outer_iv: Vec<u8>,
-    kdf_config: KdfConfig,
-    kdf_seed: Vec<u8>,
+    kdf_parameters: KdfParameters,
     public_custom_data: Option<VariantDictionary>,
 }
 
@@ -65,9 +64,13 @@ mod kdbx4_tests {
 
     use crate::format::kdbx4::dump::dump_kdbx4;
     use crate::{
-        config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
+        config::{
+            BuiltinKdf, CompressionConfig, DatabaseConfig, DatabaseSettings, InnerCipherConfig, KdfConfig,
+            KdfParameters, KeyDerivationFunction, OpenOptions, OuterCipherConfig,
+        },
         db::{Database, Entry, Group, HeaderAttachment, NodeRef, Value},
-        format::KDBX4_CURRENT_MINOR_VERSION,
+        error::{DatabaseIntegrityError, KdfError},
+        format::{SaveRng, KDBX4_CURRENT_MINOR_VERSION},
         key::DatabaseKey,
     };
 
@@ -97,13 +100,31 @@ mod kdbx4_tests {
             ));
 
         let mut encrypted_db = Vec::new();
-        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 3);
     }
 
+    #[cfg(feature = "challenge_response")]
+    #[test]
+    fn test_with_challenge_response_only() {
+        let mut db = Database::new(DatabaseConfig::default());
+        db.root.add_child(Entry::new());
+
+        let db_key =
+            DatabaseKey::new().with_challenge_response_key(crate::key::ChallengeResponseKey::LocalChallenge(
+                "0102030405060708090a0b0c0d0e0f1011121314".to_string(),
+            ));
+
+        let mut encrypted_db = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        assert_eq!(decrypted_db.root.children.len(), 1);
+    }
+
     fn test_with_config(config: DatabaseConfig) {
         let mut db = Database::new(config);
 
@@ -134,9 +155,9 @@ mod kdbx4_tests {
         let db_key = DatabaseKey::new().with_password(&password);
 
         let mut encrypted_db = Vec::new();
-        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 3);
 
@@ -185,10 +206,12 @@ mod kdbx4_tests {
                     for kdf_config in &kdf_configs {
                         let config = DatabaseConfig {
                             version: DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION),
-                            outer_cipher_config: outer_cipher_config.clone(),
-                            compression_config: compression_config.clone(),
-                            inner_cipher_config: inner_cipher_config.clone(),
-                            kdf_config: kdf_config.clone(),
+                            settings: DatabaseSettings {
+                                cipher: outer_cipher_config.clone(),
+                                kdf: kdf_config.clone(),
+                                compression: compression_config.clone(),
+                                inner_cipher: inner_cipher_config.clone(),
+                            },
                             public_custom_data: Default::default(),
                         };
 
@@ -201,6 +224,40 @@ mod kdbx4_tests {
         }
     }
 
+    #[test]
+    pub fn test_stream_encryptor() {
+        let key = [7u8; 32];
+
+        let mut plaintext = vec![0u8; 100_000];
+        getrandom::fill(&mut plaintext).unwrap();
+
+        for outer_cipher_config in [
+            OuterCipherConfig::AES256,
+            OuterCipherConfig::Twofish,
+            OuterCipherConfig::ChaCha20,
+        ] {
+            let iv = vec![3u8; outer_cipher_config.get_iv_size()];
+
+            // the payload ends on a block boundary and in the middle of a block
+            for len in [64_000, plaintext.len()] {
+                let expected = outer_cipher_config
+                    .get_cipher(&key, &iv)
+                    .unwrap()
+                    .encrypt(&plaintext[..len])
+                    .unwrap();
+
+                let mut encryptor = outer_cipher_config.get_encryptor(&key, &iv).unwrap();
+                let mut ciphertext = Vec::new();
+                for part in plaintext[..len].chunks(1000 + 7) {
+                    encryptor.update(part, &mut ciphertext);
+                }
+                encryptor.finish(&mut ciphertext);
+
+                assert_eq!(ciphertext, expected, "{:?}", outer_cipher_config);
+            }
+        }
+    }
+
     #[test]
     pub fn header_attachments() {
         let mut root_group = Group::new("Root");
@@ -209,29 +266,25 @@ mod kdbx4_tests {
         let mut db = Database::new(DatabaseConfig::default());
 
         db.header_attachments = vec![
-            HeaderAttachment {
-                flags: 1,
-                content: vec![0x01, 0x02, 0x03, 0x04],
-            },
-            HeaderAttachment {
-                flags: 2,
-                content: vec![0x04, 0x03, 0x02, 0x01],
-            },
+            HeaderAttachment::new(1, vec![0x01, 0x02, 0x03, 0x04]),
+            HeaderAttachment::new(2, vec![0x04, 0x03, 0x02, 0x01]),
         ];
 
         let mut entry = Entry::new();
         entry
             .fields
             .insert("Title".to_string(), Value::Unprotected("Demo entry".to_string()));
+        entry.attachments.insert("first".to_string(), 0);
+        entry.attachments.insert("second".to_string(), 1);
 
         db.root.add_child(entry);
 
         let db_key = DatabaseKey::new().with_password("test");
 
         let mut encrypted_db = Vec::new();
-        dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
+        dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
 
-        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        let decrypted_db = parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 1);
 
@@ -240,4 +293,190 @@ mod kdbx4_tests {
         assert_eq!(header_attachments[0].flags, 1);
         assert_eq!(header_attachments[0].content, [0x01, 0x02, 0x03, 0x04]);
     }
+
+    #[test]
+    pub fn fresh_seeds_on_save() {
+        let db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..DatabaseConfig::default()
+        });
+        let db_key = DatabaseKey::new().with_password("test");
+
+        let mut first = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut first, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let mut second = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut second, &mut SaveRng::System, &mut |_| {}).unwrap();
+
+        // reusing the IV with the same key would weaken the outer encryption
+        let (first_header, _) = parse::parse_outer_header(&first, None).unwrap();
+        let (second_header, _) = parse::parse_outer_header(&second, None).unwrap();
+        assert_ne!(first_header.outer_iv, second_header.outer_iv);
+        assert_ne!(first_header.master_seed, second_header.master_seed);
+        assert_ne!(
+            first_header.kdf_parameters.seed(),
+            second_header.kdf_parameters.seed()
+        );
+
+        assert!(parse_kdbx4(&first, &db_key, &OpenOptions::default(), &BuiltinKdf).is_ok());
+        assert!(parse_kdbx4(&second, &db_key, &OpenOptions::default(), &BuiltinKdf).is_ok());
+    }
+
+    #[test]
+    pub fn argon2id_header() {
+        use hex_literal::hex;
+
+        let kdf_config = KdfConfig::Argon2id {
+            iterations: 2,
+            memory: 65536,
+            parallelism: 2,
+            version: argon2::Version::Version13,
+        };
+        let db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: kdf_config.clone(),
+                ..Default::default()
+            },
+            ..DatabaseConfig::default()
+        });
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut data, &mut SaveRng::System, &mut |_| {}).unwrap();
+
+        // the KDF parameters identify Argon2id and Argon2d by different UUIDs
+        let (_, header_end) = parse::parse_outer_header(&data, None).unwrap();
+        let contains = |uuid: &[u8]| data[..header_end].windows(uuid.len()).any(|w| w == uuid);
+        assert!(contains(&hex!("9e298b1956db4773b23dfc3ec6f0a1e6")));
+        assert!(!contains(&hex!("ef636ddf8c29444b91f7a9a403e30a0c")));
+
+        let reopened = parse_kdbx4(&data, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+        assert_eq!(reopened.config.settings.kdf, kdf_config);
+    }
+
+    #[test]
+    pub fn forced_version() {
+        use crate::{config::KdbxVersion, error::DatabaseOpenError};
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..DatabaseConfig::default()
+        });
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Demo entry".to_string()));
+        db.root.add_child(entry);
+
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut data, &mut SaveRng::System, &mut |_| {}).unwrap();
+
+        // write a major version of 3 and update the header hashes, as a faulty writer would
+        data[10..12].copy_from_slice(&3u16.to_le_bytes());
+        sign_header(&mut data, &db_key, &BuiltinKdf);
+
+        assert_eq!(
+            Database::get_version(&mut data.as_slice()).unwrap(),
+            DatabaseVersion::KDB3(KDBX4_CURRENT_MINOR_VERSION)
+        );
+        assert!(matches!(
+            Database::open(&mut data.as_slice(), db_key.clone()),
+            Err(DatabaseOpenError::DatabaseIntegrity(_))
+        ));
+
+        let opened = Database::open_with_version(&mut data.as_slice(), db_key, KdbxVersion::Kdbx4).unwrap();
+        assert_eq!(
+            opened.config.version,
+            DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION)
+        );
+        assert_eq!(opened.root.entries()[0].get_title(), Some("Demo entry"));
+    }
+
+    /// Update the hash and the HMAC of a modified outer header, transforming the key with `kdf`
+    fn sign_header(data: &mut [u8], db_key: &DatabaseKey, kdf: &dyn KeyDerivationFunction) {
+        use crate::{crypt, hmac_block_stream};
+
+        let (header, header_end) = parse::parse_outer_header(data, None).unwrap();
+        let key_elements = db_key.get_key_elements().unwrap();
+        let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
+        let composite_key = crypt::calculate_sha256(&key_elements).unwrap();
+        let transformed_key = kdf.derive_key(&composite_key, &header.kdf_parameters).unwrap();
+        let hmac_key = crypt::calculate_sha512(&[
+            &header.master_seed,
+            &transformed_key,
+            &hmac_block_stream::HMAC_KEY_END,
+        ])
+        .unwrap();
+        let header_hmac_key = hmac_block_stream::get_hmac_block_key(u64::MAX, &hmac_key).unwrap();
+        let header_sha256 = crypt::calculate_sha256(&[&data[..header_end]]).unwrap();
+        let header_hmac = crypt::calculate_hmac(&[&data[..header_end]], &header_hmac_key).unwrap();
+        data[header_end..header_end + 32].copy_from_slice(&header_sha256);
+        data[header_end + 32..header_end + 64].copy_from_slice(&header_hmac);
+    }
+
+    #[test]
+    pub fn unknown_kdf() {
+        use hex_literal::hex;
+
+        use crate::{crypt::kdf::Kdf, error::DatabaseOpenError};
+
+        const SCRYPT: [u8; 16] = hex!("5d18f8a5ae4b4f3d8f7e3a0c6b2d1e90");
+
+        /// Stands in for a third-party function: AES-KDF with the rounds and the seed of its
+        /// parameters, under another UUID
+        struct ScryptStub;
+
+        impl KeyDerivationFunction for ScryptStub {
+            fn derive_key(&self, composite_key: &[u8], params: &KdfParameters) -> Result<[u8; 32], KdfError> {
+                if params.uuid().as_bytes() != &SCRYPT {
+                    return Err(KdfError::Unsupported { uuid: params.uuid() });
+                }
+                let kdf = crate::crypt::kdf::AesKdf {
+                    seed: params.get_bytes("S").unwrap().to_vec(),
+                    rounds: params.get_u64("R").unwrap(),
+                };
+                let composite_key = cipher::generic_array::GenericArray::from_slice(composite_key);
+                Ok(kdf.transform_key(composite_key)?.into())
+            }
+        }
+
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..DatabaseConfig::default()
+        });
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Demo entry".to_string()));
+        db.root.add_child(entry);
+
+        let db_key = DatabaseKey::new().with_password("test");
+        let mut data = Vec::new();
+        dump_kdbx4(&db, &db_key, &mut data, &mut SaveRng::System, &mut |_| {}).unwrap();
+
+        // replace the UUID of AES-KDF by the one of a function that this crate does not implement
+        let aes = hex!("7c02bb8279a74ac0927d114a00648238");
+        let start = data.windows(aes.len()).position(|w| w == aes).unwrap();
+        data[start..start + 16].copy_from_slice(&SCRYPT);
+        sign_header(&mut data, &db_key, &ScryptStub);
+
+        assert!(matches!(
+            Database::open(&mut data.as_slice(), db_key.clone()),
+            Err(DatabaseOpenError::DatabaseIntegrity(
+                DatabaseIntegrityError::KeyDerivation(KdfError::Unsupported { .. })
+            ))
+        ));
+
+        let opened = Database::open_with_kdf(&mut data.as_slice(), db_key, &ScryptStub).unwrap();
+        assert_eq!(opened.root.entries()[0].get_title(), Some("Demo entry"));
+        assert_eq!(opened.config.settings.kdf, DatabaseSettings::default().kdf);
+    }
 }
@@ -1,12 +1,15 @@
-use std::convert::{TryFrom, TryInto};
+use std::{convert::TryFrom, sync::Arc};
 
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::{
-    config::{CompressionConfig, DatabaseConfig, InnerCipherConfig, KdfConfig, OuterCipherConfig},
-    crypt::{self, ciphers::Cipher},
-    db::{Database, HeaderAttachment},
-    error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
+    config::{
+        CompressionConfig, DatabaseConfig, DatabaseSettings, InnerCipherConfig, KdbxVersion, KdfParameters,
+        KeyDerivationFunction, OpenOptions, OuterCipherConfig, ParseLimit, ParseLimits,
+    },
+    crypt::{self, ciphers::Cipher, locked::LockedKey},
+    db::{Database, DeferredBinary, HeaderAttachment, HeaderInfo, ReadConfig, RecoveryWarning},
+    error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError, KdfConfigError, XmlParseError},
     format::{
         kdbx4::{
             KDBX4OuterHeader, HEADER_COMMENT, HEADER_COMPRESSION_ID, HEADER_ENCRYPTION_IV, HEADER_END,
@@ -28,35 +31,153 @@ impl From<&[u8]> for HeaderAttachment {
         let flags = data[0];
         let content = data[1..].to_vec();
 
-        HeaderAttachment { flags, content }
+        HeaderAttachment::new(flags, content)
     }
 }
 
 /// Open, decrypt and parse a KeePass database from a source and key elements
-pub(crate) fn parse_kdbx4(data: &[u8], db_key: &DatabaseKey) -> Result<Database, DatabaseOpenError> {
-    let (config, header_attachments, mut inner_decryptor, xml) = decrypt_kdbx4(data, db_key)?;
+pub(crate) fn parse_kdbx4(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+    kdf: &dyn KeyDerivationFunction,
+) -> Result<Database, DatabaseOpenError> {
+    let (config, header_attachments, mut inner_decryptor, xml) = decrypt_kdbx4(data, db_key, options, kdf)?;
 
-    let database_content = crate::xml_db::parse::parse(&xml, &mut *inner_decryptor)?;
+    let database_content = crate::xml_db::parse::parse(&xml, &mut *inner_decryptor, options)?;
 
     let db = Database {
-        config,
+        config: config.clone(),
         header_attachments,
         root: database_content.root.group,
         deleted_objects: database_content.root.deleted_objects,
         meta: database_content.meta,
+        clock: Default::default(),
+        public_suffixes: Default::default(),
+        history_skipped: options.skip_history,
+        index: Default::default(),
+        read_config: ReadConfig(Some(config)),
     };
 
     Ok(db)
 }
 
+/// Open a damaged KeePass database, salvaging as much of it as possible and collecting warnings
+/// about the data that could not be recovered
+pub(crate) fn recover_kdbx4(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+    kdf: &dyn KeyDerivationFunction,
+    warnings: &mut Vec<RecoveryWarning>,
+) -> Result<Database, DatabaseOpenError> {
+    let (config, header_attachments, inner_random_stream_key, xml) =
+        decrypt(data, db_key, options, kdf, Some(warnings))?;
+
+    let inner_decryptor = || {
+        config
+            .settings
+            .inner_cipher
+            .get_parsing_cipher(&inner_random_stream_key, options.defer_protected_values)
+    };
+
+    let (meta, root, deleted_objects) =
+        match crate::xml_db::parse::parse(&xml, &mut *inner_decryptor()?, options) {
+            Ok(content) => (content.meta, content.root.group, content.root.deleted_objects),
+            Err(XmlParseError::Located { source, .. })
+                if matches!(*source, XmlParseError::LimitExceeded(_)) =>
+            {
+                return Err(DatabaseOpenError::from(*source))
+            }
+            Err(e) => {
+                // protected values are decrypted with a stream cipher, so everything after the
+                // damaged part of the document needs to be treated as unreliable
+                let damaged_from = match e {
+                    XmlParseError::Located { offset, .. } => offset,
+                    _ => 0,
+                };
+                warnings.push(RecoveryWarning::XmlDamaged(e));
+
+                let (meta, root) = crate::xml_db::parse::salvage(
+                    &xml,
+                    &mut *inner_decryptor()?,
+                    options,
+                    damaged_from,
+                    warnings,
+                );
+                (meta, root, Default::default())
+            }
+        };
+
+    Ok(Database {
+        config: config.clone(),
+        header_attachments,
+        root,
+        deleted_objects,
+        meta,
+        clock: Default::default(),
+        public_suffixes: Default::default(),
+        history_skipped: options.skip_history,
+        index: Default::default(),
+        read_config: ReadConfig(Some(config)),
+    })
+}
+
+/// Parse the outer header of a KDBX4 database, see `Database::read_header`
+pub(crate) fn parse_header_info(data: &[u8]) -> Result<HeaderInfo, DatabaseOpenError> {
+    let (header, _) = parse_outer_header(data, None)?;
+
+    Ok(HeaderInfo {
+        version: header.version,
+        outer_cipher_config: header.outer_cipher_config,
+        compression_config: header.compression_config,
+        kdf_config: header
+            .kdf_parameters
+            .to_kdf_config()?
+            .ok_or(KdfConfigError::InvalidKDFUUID {
+                uuid: header.kdf_parameters.uuid().as_bytes().to_vec(),
+            })?
+            .0,
+        kdf_seed: header.kdf_parameters.seed().to_vec(),
+        master_seed: header.master_seed,
+        encryption_iv: header.outer_iv,
+        inner_cipher_config: None,
+        protected_stream_key: None,
+        stream_start_bytes: None,
+        public_custom_data: header.public_custom_data,
+    })
+}
+
 /// Open and decrypt a KeePass KDBX4 database from a source and key elements
 #[allow(clippy::type_complexity)]
 pub(crate) fn decrypt_kdbx4(
     data: &[u8],
     db_key: &DatabaseKey,
+    options: &OpenOptions,
+    kdf: &dyn KeyDerivationFunction,
 ) -> Result<(DatabaseConfig, Vec<HeaderAttachment>, Box<dyn Cipher>, Vec<u8>), DatabaseOpenError> {
+    let (config, header_attachments, inner_random_stream_key, xml) = decrypt(data, db_key, options, kdf, None)?;
+
+    let inner_decryptor = config
+        .settings
+        .inner_cipher
+        .get_parsing_cipher(&inner_random_stream_key, options.defer_protected_values)?;
+
+    Ok((config, header_attachments, inner_decryptor, xml))
+}
+
+/// Decrypt a KDBX4 database into its configuration, attachments, inner random stream key and XML
+/// document. Damaged blocks and payloads are tolerated if recovery warnings are collected.
+#[allow(clippy::type_complexity)]
+fn decrypt(
+    data: &[u8],
+    db_key: &DatabaseKey,
+    options: &OpenOptions,
+    kdf: &dyn KeyDerivationFunction,
+    mut recovery: Option<&mut Vec<RecoveryWarning>>,
+) -> Result<(DatabaseConfig, Vec<HeaderAttachment>, Vec<u8>, Vec<u8>), DatabaseOpenError> {
     // parse header
-    let (outer_header, inner_header_start) = parse_outer_header(data)?;
+    let (outer_header, inner_header_start) = parse_outer_header(data, options.version)?;
 
     // split file into segments:
     //      header_data         - The outer header data
@@ -74,17 +195,22 @@ pub(crate) fn decrypt_kdbx4(
     }
 
     #[cfg(feature = "challenge_response")]
-    let db_key = db_key.clone().perform_challenge(&outer_header.kdf_seed)?;
+    let db_key = db_key
+        .clone()
+        .perform_challenge(outer_header.kdf_parameters.seed())?;
 
     // derive master key from composite key, transform_seed, transform_rounds and master_seed
     let key_elements = db_key.get_key_elements()?;
     let key_elements: Vec<&[u8]> = key_elements.iter().map(|v| &v[..]).collect();
-    let composite_key = crypt::calculate_sha256(&key_elements)?;
-    let transformed_key = outer_header
-        .kdf_config
-        .get_kdf_seeded(&outer_header.kdf_seed)
-        .transform_key(&composite_key)?;
-    let master_key = crypt::calculate_sha256(&[outer_header.master_seed.as_ref(), &transformed_key])?;
+    let composite_key = LockedKey::new(crypt::calculate_sha256(&key_elements)?);
+    let transformed_key = LockedKey::new(
+        kdf.derive_key(&composite_key, &outer_header.kdf_parameters)?
+            .into(),
+    );
+    let master_key = LockedKey::new(crypt::calculate_sha256(&[
+        outer_header.master_seed.as_ref(),
+        &transformed_key,
+    ])?);
 
     // verify credentials
     let hmac_key = crypt::calculate_sha512(&[
@@ -98,44 +224,85 @@ pub(crate) fn decrypt_kdbx4(
     }
 
     // read encrypted payload from hmac-verified block stream
-    let payload_encrypted = hmac_block_stream::read_hmac_block_stream(hmac_block_stream, &hmac_key)?;
+    let block_stream_offset = (inner_header_start + 64) as u64;
+    let payload_encrypted = match recovery.as_deref_mut() {
+        Some(warnings) => hmac_block_stream::recover_hmac_block_stream(
+            hmac_block_stream,
+            &hmac_key,
+            block_stream_offset,
+            warnings,
+        )?,
+        None => hmac_block_stream::read_hmac_block_stream(hmac_block_stream, &hmac_key, block_stream_offset)?,
+    };
 
     // Decrypt and decompress encrypted payload
     let payload_compressed = outer_header
         .outer_cipher_config
         .get_cipher(&master_key, &outer_header.outer_iv)?
         .decrypt(&payload_encrypted)?;
+    drop(payload_encrypted);
+
+    let max_size = options.limits.max_decompressed_size;
+    let compression = outer_header.compression_config.get_compression();
+    let payload = match recovery {
+        Some(warnings) => {
+            let (payload, err) = compression.decompress_partial(&payload_compressed, max_size);
+            if err.is_some() {
+                warnings.push(RecoveryWarning::TruncatedPayload {
+                    recovered_bytes: payload.len() as u64,
+                });
+            }
+            payload
+        }
+        None => compression.decompress(&payload_compressed, max_size)?,
+    };
+    drop(payload_compressed);
+    if payload.len() as u64 > max_size {
+        return Err(DatabaseOpenError::LimitExceeded(ParseLimit::DecompressedSize));
+    }
 
-    let payload = outer_header
-        .compression_config
-        .get_compression()
-        .decompress(&payload_compressed)?;
-
-    // KDBX4 has inner header, too - parse it
-    let (header_attachments, inner_header, body_start) = parse_inner_header(&payload)?;
+    // KDBX4 has inner header, too - parse it. If binaries are deferred, the payload is kept alive by
+    // the attachments that refer to it instead of copying them out of it.
+    let mut payload = payload;
+    if options.defer_binaries {
+        payload.shrink_to_fit();
+    }
+    let payload = Arc::new(payload);
+    let retained = Some(&payload).filter(|_| options.defer_binaries);
+    let (header_attachments, inner_header, body_start) =
+        parse_inner_header(&payload, retained, &options.limits)?;
 
     // after inner header is one XML document
     let xml = &payload[body_start..];
 
-    // initialize the inner decryptor
-    let inner_decryptor = inner_header
-        .inner_random_stream
-        .get_cipher(&inner_header.inner_random_stream_key)?;
-
     let config = DatabaseConfig {
         version: outer_header.version,
-        outer_cipher_config: outer_header.outer_cipher_config,
-        compression_config: outer_header.compression_config,
-        inner_cipher_config: inner_header.inner_random_stream,
-        kdf_config: outer_header.kdf_config,
+        settings: DatabaseSettings {
+            cipher: outer_header.outer_cipher_config,
+            // databases with another key derivation function are saved with the default one
+            kdf: match outer_header.kdf_parameters.to_kdf_config()? {
+                Some((kdf_config, _)) => kdf_config,
+                None => DatabaseSettings::default().kdf,
+            },
+            compression: outer_header.compression_config,
+            inner_cipher: inner_header.inner_random_stream,
+        },
         public_custom_data: outer_header.public_custom_data,
     };
 
-    Ok((config, header_attachments, inner_decryptor, xml.to_vec()))
+    Ok((
+        config,
+        header_attachments,
+        inner_header.inner_random_stream_key,
+        xml.to_vec(),
+    ))
 }
 
-fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), DatabaseOpenError> {
-    let version = DatabaseVersion::parse(data)?;
+pub(super) fn parse_outer_header(
+    data: &[u8],
+    forced_version: Option<KdbxVersion>,
+) -> Result<(KDBX4OuterHeader, usize), DatabaseOpenError> {
+    let version = crate::format::parse_kdbx_version(data, forced_version)?;
 
     // skip over the version header
     let mut pos = DatabaseVersion::get_version_header_size();
@@ -144,8 +311,7 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
     let mut compression_config: Option<CompressionConfig> = None;
     let mut master_seed: Option<Vec<u8>> = None;
     let mut outer_iv: Option<Vec<u8>> = None;
-    let mut kdf_config: Option<KdfConfig> = None;
-    let mut kdf_seed: Option<Vec<u8>> = None;
+    let mut kdf_parameters: Option<KdfParameters> = None;
     let mut public_custom_data: Option<VariantDictionary> = None;
 
     // parse header
@@ -187,9 +353,7 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
 
             HEADER_KDF_PARAMS => {
                 let vd = VariantDictionary::parse(entry_buffer)?;
-                let (kconf, kseed) = vd.try_into()?;
-                kdf_config = Some(kconf);
-                kdf_seed = Some(kseed)
+                kdf_parameters = Some(KdfParameters::new(vd)?);
             }
 
             HEADER_PUBLIC_CUSTOM_DATA => {
@@ -216,8 +380,10 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
     let compression_config = get_or_err(compression_config, "Compression ID")?;
     let master_seed = get_or_err(master_seed, "Master seed")?;
     let outer_iv = get_or_err(outer_iv, "Outer IV")?;
-    let kdf_config = get_or_err(kdf_config, "Key Derivation Function Parameters")?;
-    let kdf_seed = get_or_err(kdf_seed, "Key Derivation Function Seed")?;
+    let kdf_parameters = get_or_err(kdf_parameters, "Key Derivation Function Parameters")?;
+
+    // other key derivation functions are left to the `KeyDerivationFunction` of the `OpenOptions`
+    kdf_parameters.to_kdf_config()?;
 
     Ok((
         KDBX4OuterHeader {
@@ -226,16 +392,19 @@ fn parse_outer_header(data: &[u8]) -> Result<(KDBX4OuterHeader, usize), Database
             compression_config,
             master_seed,
             outer_iv,
-            kdf_config,
-            kdf_seed,
+            kdf_parameters,
             public_custom_data,
         },
         pos,
     ))
 }
 
+/// Parse the inner header. If the decrypted payload is retained, attachments refer to their location
+/// in it instead of being copied.
 fn parse_inner_header(
     data: &[u8],
+    retained: Option<&Arc<Vec<u8>>>,
+    limits: &ParseLimits,
 ) -> Result<(Vec<HeaderAttachment>, KDBX4InnerHeader, usize), DatabaseOpenError> {
     let mut pos = 0;
 
@@ -243,6 +412,8 @@ fn parse_inner_header(
     let mut inner_random_stream_key = None;
     let mut header_attachments = Vec::new();
 
+    let mut total_attachment_size: u64 = 0;
+
     loop {
         let entry_type = data[pos];
         let entry_length: usize = LittleEndian::read_u32(&data[pos + 1..(pos + 5)]) as usize;
@@ -260,7 +431,28 @@ fn parse_inner_header(
             INNER_HEADER_RANDOM_STREAM_KEY => inner_random_stream_key = Some(entry_buffer.to_vec()),
 
             INNER_HEADER_BINARY_ATTACHMENTS => {
-                let header_attachment = HeaderAttachment::from(entry_buffer);
+                // the first byte holds the flags of the attachment
+                let size = entry_length.saturating_sub(1) as u64;
+                if size > limits.max_binary_size {
+                    return Err(DatabaseOpenError::LimitExceeded(ParseLimit::BinarySize));
+                }
+                total_attachment_size += size;
+                if total_attachment_size > limits.max_total_binary_size {
+                    return Err(DatabaseOpenError::LimitExceeded(ParseLimit::TotalBinarySize));
+                }
+
+                let header_attachment = match retained {
+                    Some(payload) => HeaderAttachment {
+                        flags: entry_buffer[0],
+                        content: Vec::new(),
+                        deferred: Some(DeferredBinary::Payload {
+                            payload: payload.clone(),
+                            range: (pos - entry_length + 1)..pos,
+                        }),
+                        hash: Default::default(),
+                    },
+                    None => HeaderAttachment::from(entry_buffer),
+                };
                 header_attachments.push(header_attachment);
             }
 
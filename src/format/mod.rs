@@ -9,7 +9,13 @@ use std::io::Write;
 use byteorder::WriteBytesExt;
 use byteorder::{ByteOrder, LittleEndian};
 
-use crate::error::DatabaseIntegrityError;
+#[cfg(feature = "save_kdbx4")]
+use crate::db::{SavePhase, SaveProgress};
+use crate::{config::KdbxVersion, error::DatabaseIntegrityError};
+
+/// Size of the chunks in which a saved database is written, to report progress
+#[cfg(feature = "save_kdbx4")]
+const WRITE_CHUNK_SIZE: usize = 1024 * 1024;
 
 const KDBX_IDENTIFIER: [u8; 4] = [0x03, 0xd9, 0xa2, 0x9a];
 
@@ -74,16 +80,18 @@ impl DatabaseVersion {
 
     #[cfg(feature = "save_kdbx4")]
     fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
-        if let DatabaseVersion::KDB4(minor_version) = self {
-            writer.write_all(&crate::format::KDBX_IDENTIFIER)?;
-            writer.write_u32::<LittleEndian>(KEEPASS_LATEST_ID)?;
-            writer.write_u16::<LittleEndian>(*minor_version)?;
-            writer.write_u16::<LittleEndian>(KDBX4_MAJOR_VERSION)?;
-
-            Ok(())
-        } else {
-            panic!("DatabaseVersion::dump only supports dumping KDBX4.");
-        }
+        let (minor_version, major_version) = match self {
+            DatabaseVersion::KDB3(minor_version) => (*minor_version, KDBX3_MAJOR_VERSION),
+            DatabaseVersion::KDB4(minor_version) => (*minor_version, KDBX4_MAJOR_VERSION),
+            _ => panic!("DatabaseVersion::dump only supports dumping KDBX3 and KDBX4."),
+        };
+
+        writer.write_all(&crate::format::KDBX_IDENTIFIER)?;
+        writer.write_u32::<LittleEndian>(KEEPASS_LATEST_ID)?;
+        writer.write_u16::<LittleEndian>(minor_version)?;
+        writer.write_u16::<LittleEndian>(major_version)?;
+
+        Ok(())
     }
 
     pub(crate) fn get_version_header_size() -> usize {
@@ -91,6 +99,29 @@ impl DatabaseVersion {
     }
 }
 
+/// Parse the version of a KDBX database, or only check its identifier if the version is forced with
+/// `OpenOptions::force_version`
+pub(crate) fn parse_kdbx_version(
+    data: &[u8],
+    forced: Option<KdbxVersion>,
+) -> Result<DatabaseVersion, DatabaseIntegrityError> {
+    let Some(forced) = forced else {
+        return DatabaseVersion::parse(data);
+    };
+
+    if data.len() < DatabaseVersion::get_version_header_size() || data[0..4] != KDBX_IDENTIFIER {
+        return Err(DatabaseIntegrityError::InvalidKDBXIdentifier);
+    }
+
+    // keep the minor version if the header agrees with the forced major version
+    Ok(match (forced, DatabaseVersion::parse(data)) {
+        (KdbxVersion::Kdbx31, Ok(DatabaseVersion::KDB3(minor))) => DatabaseVersion::KDB3(minor),
+        (KdbxVersion::Kdbx31, _) => DatabaseVersion::KDB3(1),
+        (KdbxVersion::Kdbx4, Ok(DatabaseVersion::KDB4(minor))) => DatabaseVersion::KDB4(minor),
+        (KdbxVersion::Kdbx4, _) => DatabaseVersion::KDB4(KDBX4_CURRENT_MINOR_VERSION),
+    })
+}
+
 impl std::fmt::Display for DatabaseVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -101,3 +132,141 @@ impl std::fmt::Display for DatabaseVersion {
         }
     }
 }
+
+/// Source of the seeds, keys and IVs of a database that is being saved
+#[cfg(feature = "save_kdbx4")]
+pub(crate) enum SaveRng {
+    /// The random number generator of the operating system
+    System,
+
+    /// A keystream derived from a seed, see `Database::save_deterministic`
+    Seeded(Box<chacha20::ChaCha20>),
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl SaveRng {
+    /// Generate the seeds, keys and IVs from the ChaCha20 keystream of `seed`
+    pub(crate) fn seeded(seed: u64) -> Self {
+        use cipher::KeyIvInit;
+
+        let mut key = [0; 32];
+        key[..8].copy_from_slice(&seed.to_le_bytes());
+        SaveRng::Seeded(Box::new(chacha20::ChaCha20::new(&key.into(), &[0; 12].into())))
+    }
+
+    /// Fill a seed, key or IV with random bytes
+    pub(crate) fn fill(&mut self, buf: &mut [u8]) -> Result<(), getrandom::Error> {
+        use cipher::StreamCipher;
+
+        match self {
+            SaveRng::System => getrandom::fill(buf),
+            SaveRng::Seeded(rng) => {
+                buf.fill(0);
+                rng.apply_keystream(buf);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Report that a phase of saving a database has started
+#[cfg(feature = "save_kdbx4")]
+pub(crate) fn start_phase(phase: SavePhase, progress: &mut dyn FnMut(SaveProgress)) {
+    progress(SaveProgress {
+        phase,
+        fraction_complete: 0.0,
+    });
+}
+
+/// Report that a phase of saving a database is complete
+#[cfg(feature = "save_kdbx4")]
+pub(crate) fn finish_phase(phase: SavePhase, progress: &mut dyn FnMut(SaveProgress)) {
+    progress(SaveProgress {
+        phase,
+        fraction_complete: 1.0,
+    });
+}
+
+/// Write the parts of a saved database in chunks, reporting the fraction of bytes written
+#[cfg(feature = "save_kdbx4")]
+pub(crate) fn write_with_progress(
+    writer: &mut dyn Write,
+    parts: &[&[u8]],
+    progress: &mut dyn FnMut(SaveProgress),
+) -> Result<(), std::io::Error> {
+    let total: usize = parts.iter().map(|part| part.len()).sum();
+    let mut written = 0;
+
+    start_phase(SavePhase::Writing, progress);
+    for chunk in parts.iter().flat_map(|part| part.chunks(WRITE_CHUNK_SIZE)) {
+        writer.write_all(chunk)?;
+        written += chunk.len();
+
+        if written < total {
+            progress(SaveProgress {
+                phase: SavePhase::Writing,
+                fraction_complete: written as f32 / total as f32,
+            });
+        }
+    }
+    finish_phase(SavePhase::Writing, progress);
+
+    Ok(())
+}
+
+/// Writer that reports the fraction of an expected number of bytes that has been written to it, as
+/// progress of a phase of saving
+#[cfg(feature = "save_kdbx4")]
+pub(crate) struct ProgressWriter<'a, W: Write> {
+    inner: W,
+    phase: SavePhase,
+    progress: &'a mut dyn FnMut(SaveProgress),
+    total: u64,
+    written: u64,
+    reported: u64,
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub(crate) fn new(
+        inner: W,
+        phase: SavePhase,
+        total: u64,
+        progress: &'a mut dyn FnMut(SaveProgress),
+    ) -> Self {
+        ProgressWriter {
+            inner,
+            phase,
+            progress,
+            total,
+            written: 0,
+            reported: 0,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.inner.write(buf)?;
+        self.written += size as u64;
+
+        if self.written - self.reported >= WRITE_CHUNK_SIZE as u64 && self.written < self.total {
+            (self.progress)(SaveProgress {
+                phase: self.phase,
+                fraction_complete: self.written as f32 / self.total as f32,
+            });
+            self.reported = self.written;
+        }
+
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
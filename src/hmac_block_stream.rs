@@ -1,15 +1,49 @@
+#[cfg(feature = "save_kdbx4")]
+use std::io::Write;
+
 use byteorder::{ByteOrder, LittleEndian};
 use cipher::generic_array::{typenum::U64, GenericArray};
 use hex_literal::hex;
 
-use crate::error::{BlockStreamError, CryptographyError};
+use crate::{
+    db::RecoveryWarning,
+    error::{BlockStreamError, CryptographyError},
+};
 
 pub const HMAC_KEY_END: [u8; 1] = hex!("01");
 
-/// Read from a HMAC block stream into a raw buffer
+/// Maximum size of a block in the HMAC block stream
+#[cfg(feature = "save_kdbx4")]
+const HMAC_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// Read from a HMAC block stream into a raw buffer. `offset` is the position of the block stream in
+/// the file, which is used for error reporting.
 pub(crate) fn read_hmac_block_stream(
     data: &[u8],
     key: &GenericArray<u8, U64>,
+    offset: u64,
+) -> Result<Vec<u8>, BlockStreamError> {
+    read_blocks(data, key, offset, None)
+}
+
+/// Read from a HMAC block stream into a raw buffer, replacing blocks that fail verification with
+/// zeros and ending early when the stream is truncated
+pub(crate) fn recover_hmac_block_stream(
+    data: &[u8],
+    key: &GenericArray<u8, U64>,
+    offset: u64,
+    warnings: &mut Vec<RecoveryWarning>,
+) -> Result<Vec<u8>, BlockStreamError> {
+    read_blocks(data, key, offset, Some(warnings))
+}
+
+/// Read the blocks of a HMAC block stream. Damaged blocks are an error, unless recovery warnings are
+/// collected.
+fn read_blocks(
+    data: &[u8],
+    key: &GenericArray<u8, U64>,
+    offset: u64,
+    mut recovery: Option<&mut Vec<RecoveryWarning>>,
 ) -> Result<Vec<u8>, BlockStreamError> {
     // keepassxc src/streams/HmacBlockStream.cpp
 
@@ -19,81 +53,141 @@ pub(crate) fn read_hmac_block_stream(
     let mut block_index: u64 = 0;
 
     while pos < data.len() {
-        let hmac = &data[pos..(pos + 32)];
-        let size_bytes = &data[(pos + 32)..(pos + 36)];
-        let size = LittleEndian::read_u32(size_bytes) as usize;
-        let block = &data[(pos + 36)..(pos + 36 + size)];
+        let block_offset = offset + pos as u64;
+
+        // every block is a triplet of (hmac: [u8; 32], size: u32, block: [u8; size])
+        let size = data
+            .get((pos + 32)..(pos + 36))
+            .map(|size_bytes| LittleEndian::read_u32(size_bytes) as usize);
+        let (hmac, size_bytes, block) = match size {
+            Some(size) if pos + 36 + size <= data.len() => (
+                &data[pos..(pos + 32)],
+                &data[(pos + 32)..(pos + 36)],
+                &data[(pos + 36)..(pos + 36 + size)],
+            ),
+            _ => match recovery {
+                Some(warnings) => {
+                    warnings.push(RecoveryWarning::TruncatedBlockStream { offset: block_offset });
+                    break;
+                }
+                None => return Err(BlockStreamError::Truncated { block_index }),
+            },
+        };
 
         // verify block hmac
         let hmac_block_key = get_hmac_block_key(block_index, key)?;
         let mut block_index_buf = [0u8; 8];
         LittleEndian::write_u64(&mut block_index_buf, block_index);
 
-        if hmac
-            != crate::crypt::calculate_hmac(&[&block_index_buf, size_bytes, block], &hmac_block_key)?.as_slice()
-        {
-            return Err(BlockStreamError::BlockHashMismatch { block_index });
+        let verified = hmac
+            == crate::crypt::calculate_hmac(&[&block_index_buf, size_bytes, block], &hmac_block_key)?
+                .as_slice();
+
+        if !verified {
+            match recovery.as_deref_mut() {
+                Some(warnings) => warnings.push(RecoveryWarning::CorruptBlock {
+                    block_index,
+                    offset: block_offset,
+                }),
+                None => {
+                    return Err(BlockStreamError::BlockHashMismatch {
+                        block_index,
+                        offset: block_offset,
+                    })
+                }
+            }
         }
 
-        pos += 36 + size;
+        pos += 36 + block.len();
         block_index += 1;
 
-        if size == 0 {
+        if block.is_empty() {
             break;
         }
 
-        out.extend_from_slice(block);
+        if verified {
+            out.extend_from_slice(block);
+        } else {
+            out.resize(out.len() + block.len(), 0);
+        }
     }
 
     Ok(out)
 }
 
+/// Writer that splits the data written to it into the blocks of a HMAC block stream. `finish` must
+/// be called to write the last block and the end of the stream.
 #[cfg(feature = "save_kdbx4")]
-/// Write a raw buffer as a HMAC block stream
-pub(crate) fn write_hmac_block_stream(
-    data: &[u8],
-    key: &GenericArray<u8, U64>,
-) -> Result<Vec<u8>, CryptographyError> {
-    let mut out = Vec::new();
+pub(crate) struct HmacBlockWriter<W: Write> {
+    inner: W,
+    key: GenericArray<u8, U64>,
+    block: Vec<u8>,
+    block_index: u64,
+}
 
-    let mut pos = 0;
-    let mut block_index = 0;
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> HmacBlockWriter<W> {
+    pub(crate) fn new(inner: W, key: GenericArray<u8, U64>) -> Self {
+        HmacBlockWriter {
+            inner,
+            key,
+            block: Vec::with_capacity(HMAC_BLOCK_SIZE),
+            block_index: 0,
+        }
+    }
 
-    while pos < data.len() {
-        let size = data.len() - pos;
+    /// Write the buffered data and the empty block that ends the stream, returning the inner writer
+    pub(crate) fn finish(mut self) -> std::io::Result<W> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
 
-        let block = &data[pos..(pos + size)];
+        // the end of the HMAC block stream should be an empty block, but with a valid HMAC
+        self.write_block()?;
+
+        Ok(self.inner)
+    }
 
-        let mut size_bytes: Vec<u8> = vec![0; 4];
-        LittleEndian::write_u32(&mut size_bytes, size as u32);
+    fn write_block(&mut self) -> std::io::Result<()> {
+        let mut size_bytes = [0u8; 4];
+        LittleEndian::write_u32(&mut size_bytes, self.block.len() as u32);
 
         // Generate block hmac
-        let hmac_block_key = get_hmac_block_key(block_index, key)?;
         let mut block_index_buf = [0u8; 8];
-        LittleEndian::write_u64(&mut block_index_buf, block_index);
+        LittleEndian::write_u64(&mut block_index_buf, self.block_index);
+        let hmac = get_hmac_block_key(self.block_index, &self.key)
+            .and_then(|hmac_block_key| {
+                crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, &self.block], &hmac_block_key)
+            })
+            .map_err(std::io::Error::other)?;
 
-        let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, block], &hmac_block_key)?;
+        self.inner.write_all(&hmac)?;
+        self.inner.write_all(&size_bytes)?;
+        self.inner.write_all(&self.block)?;
 
-        pos += 36 + size;
-        block_index += 1;
+        self.block.clear();
+        self.block_index += 1;
 
-        out.extend_from_slice(&hmac);
-        out.extend_from_slice(&size_bytes);
-        out.extend_from_slice(block);
+        Ok(())
     }
+}
 
-    // the end of the HMAC block stream should be an empty block, but with a valid HMAC
-    let hmac_block_key = get_hmac_block_key(block_index, key)?;
-    let mut block_index_buf = [0u8; 8];
-    LittleEndian::write_u64(&mut block_index_buf, block_index);
+#[cfg(feature = "save_kdbx4")]
+impl<W: Write> Write for HmacBlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = usize::min(buf.len(), HMAC_BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..size]);
 
-    let size_bytes = vec![0; 4];
-    let hmac = crate::crypt::calculate_hmac(&[&block_index_buf, &size_bytes, &[]], &hmac_block_key)?;
+        if self.block.len() == HMAC_BLOCK_SIZE {
+            self.write_block()?;
+        }
 
-    out.extend_from_slice(&hmac);
-    out.extend_from_slice(&size_bytes);
+        Ok(size)
+    }
 
-    Ok(out)
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 pub(crate) fn get_hmac_block_key(
@@ -0,0 +1,176 @@
+use std::io::Read;
+
+use chrono::NaiveDateTime;
+use url::Url;
+
+use crate::{
+    config::DatabaseConfig,
+    db::{Database, Group, Node, Times},
+    error::ImportError,
+    import::set_field,
+};
+
+/// Columns of the CSV export of LastPass
+const COLUMNS: [&str; 8] = [
+    "url", "username", "password", "totp", "extra", "name", "grouping", "fav",
+];
+
+/// URL that LastPass gives secure notes, which do not have a URL of their own
+const SECURE_NOTE_URL: &str = "http://sn";
+
+/// Tag of the entries of items that are favorites in LastPass
+const FAVORITE_TAG: &str = "Favorite";
+
+impl Database {
+    /// Import the items of a CSV export of LastPass, with the columns `url`, `username`, `password`,
+    /// `totp`, `extra`, `name`, `grouping` and `fav`, into a new database.
+    ///
+    /// Every row becomes an entry with Title, UserName, Password, URL and Notes. The folder of an
+    /// item in `grouping` becomes a path of groups, e.g. `Finance/Banking`, and items without a
+    /// folder are put into the root group. The TOTP secret is stored in the `otp` field as an
+    /// `otpauth://` URI, and favorites are tagged with `Favorite`. Columns may be missing or in any
+    /// order, but unknown columns are rejected.
+    pub fn import_from_lastpass_csv<R: Read>(reader: R) -> Result<Database, ImportError> {
+        let mut reader = csv::Reader::from_reader(reader);
+
+        let headers = reader.headers()?.clone();
+        if let Some(column) = headers.iter().find(|h| !COLUMNS.contains(h)) {
+            return Err(ImportError::UnknownColumn(column.to_string()));
+        }
+
+        let mut db = Database::new(DatabaseConfig::default());
+        let now = db.now();
+
+        for record in reader.records() {
+            let record = record?;
+            let column = |name: &str| {
+                headers
+                    .iter()
+                    .position(|h| h == name)
+                    .and_then(|i| record.get(i))
+                    .filter(|value| !value.is_empty())
+            };
+
+            let name = column("name");
+            let mut entry = db.new_entry();
+            set_field(&mut entry, "Title", name, false);
+            set_field(&mut entry, "UserName", column("username"), false);
+            set_field(&mut entry, "Password", column("password"), true);
+            set_field(
+                &mut entry,
+                "URL",
+                column("url").filter(|url| *url != SECURE_NOTE_URL),
+                false,
+            );
+            set_field(&mut entry, "Notes", column("extra"), false);
+            if let Some(secret) = column("totp") {
+                set_field(&mut entry, "otp", Some(&otp_uri(secret, name)), true);
+            }
+            if column("fav") == Some("1") {
+                entry.tags.push(FAVORITE_TAG.to_string());
+            }
+
+            group_at_path(&mut db.root, column("grouping").unwrap_or_default(), now).add_child(entry);
+        }
+
+        Ok(db)
+    }
+}
+
+/// Turn the TOTP secret of an item into an `otpauth://` URI labelled with the name of the item.
+/// LastPass exports the base32 secret on its own, but values that already are URIs are kept.
+fn otp_uri(secret: &str, name: Option<&str>) -> String {
+    if secret.starts_with("otpauth://") {
+        return secret.to_string();
+    }
+
+    let mut uri = Url::parse("otpauth://totp/").expect("valid URI");
+    uri.set_path(name.unwrap_or("LastPass"));
+    uri.query_pairs_mut()
+        .append_pair("secret", &secret.replace(' ', ""));
+    uri.to_string()
+}
+
+/// Get the group at a slash-separated path below `group`, creating the groups that do not exist yet
+fn group_at_path<'a>(mut group: &'a mut Group, path: &str, now: NaiveDateTime) -> &'a mut Group {
+    for name in path.split('/').map(str::trim).filter(|name| !name.is_empty()) {
+        let index = match group
+            .children
+            .iter()
+            .position(|child| matches!(child, Node::Group(g) if g.name == name))
+        {
+            Some(index) => index,
+            None => {
+                group.add_child(Group {
+                    times: Times::new_at(now),
+                    ..Group::new(name)
+                });
+                group.children.len() - 1
+            }
+        };
+
+        let Node::Group(child) = &mut group.children[index] else {
+            unreachable!("the child at the index is a group");
+        };
+        group = child;
+    }
+    group
+}
+
+#[cfg(test)]
+mod lastpass_tests {
+    use crate::{
+        db::{Database, NodeRef},
+        error::ImportError,
+    };
+
+    #[test]
+    fn import_from_lastpass_csv() -> Result<(), ImportError> {
+        let data = include_bytes!("../../tests/resources/test_export_lastpass.csv");
+        let db = Database::import_from_lastpass_csv(&data[..])?;
+
+        assert_eq!(db.root.iter_entries().count(), 4);
+        assert_eq!(db.root.groups().len(), 2);
+
+        let NodeRef::Entry(mail) = db.root.get(&["Personal", "Mail", "Example Mail"]).unwrap() else {
+            panic!("Expected an entry");
+        };
+        assert_eq!(mail.get_username(), Some("alice@example.com"));
+        assert_eq!(mail.get_password(), Some("correct horse battery staple"));
+        assert_eq!(mail.get_url(), Some("https://mail.example.com/login"));
+        assert_eq!(
+            mail.get("otp"),
+            Some("otpauth://totp/Example%20Mail?secret=JBSWY3DPEHPK3PXP")
+        );
+        assert!(mail.is_protected("otp"));
+        assert_eq!(mail.tags, vec!["Favorite".to_string()]);
+
+        let NodeRef::Entry(bank) = db.root.get(&["Finance", "Example Bank"]).unwrap() else {
+            panic!("Expected an entry");
+        };
+        assert_eq!(bank.get("Notes"), Some("Branch 042\nAccount 1234"));
+        assert!(bank.get("otp").is_none());
+        assert!(bank.tags.is_empty());
+
+        // secure notes do not get the placeholder URL of LastPass
+        let NodeRef::Entry(note) = db.root.get(&["Personal", "Wi-Fi"]).unwrap() else {
+            panic!("Expected an entry");
+        };
+        assert_eq!(note.get_url(), None);
+        assert_eq!(note.get_password(), None);
+
+        // items without a folder end up in the root group
+        assert!(db.root.get(&["Forum"]).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_from_lastpass_csv_errors() {
+        let result = Database::import_from_lastpass_csv(&b"url,username,password,folder\n"[..]);
+        assert!(matches!(result, Err(ImportError::UnknownColumn(c)) if c == "folder"));
+
+        let result = Database::import_from_lastpass_csv(&b"url,username,password\nhttps://example.com\n"[..]);
+        assert!(matches!(result, Err(ImportError::Csv(_))));
+    }
+}
@@ -0,0 +1,20 @@
+//! Importers for the export formats of other password managers
+
+mod lastpass;
+mod onepif;
+
+use secstr::SecStr;
+
+use crate::db::{Entry, Value};
+
+/// Set a field of an imported entry if the export has a value for it
+fn set_field(entry: &mut Entry, key: &str, value: Option<&str>, protected: bool) {
+    if let Some(value) = value {
+        let value = if protected {
+            Value::Protected(SecStr::new(value.as_bytes().to_vec()))
+        } else {
+            Value::Unprotected(value.to_string())
+        };
+        entry.fields.insert(key.to_string(), value);
+    }
+}
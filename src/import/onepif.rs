@@ -0,0 +1,301 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    config::DatabaseConfig,
+    db::{Database, Entry, Group},
+    error::ImportError,
+    import::set_field,
+};
+
+/// Lines starting with this prefix separate the items of a 1PIF file
+const ITEM_SEPARATOR_PREFIX: &str = "***";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(default)]
+    uuid: String,
+    type_name: String,
+    title: Option<String>,
+    location: Option<String>,
+    folder_uuid: Option<String>,
+    #[serde(default)]
+    trashed: bool,
+    #[serde(default)]
+    secure_contents: SecureContents,
+    #[serde(default)]
+    open_contents: OpenContents,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecureContents {
+    #[serde(default)]
+    fields: Vec<Field>,
+    #[serde(default, rename = "URLs")]
+    urls: Vec<ItemUrl>,
+    notes_plain: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Field {
+    designation: Option<String>,
+    value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItemUrl {
+    url: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenContents {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl Item {
+    fn designated_field(&self, designation: &str) -> Option<&str> {
+        self.secure_contents
+            .fields
+            .iter()
+            .find(|f| f.designation.as_deref() == Some(designation))
+            .and_then(|f| f.value.as_deref())
+    }
+
+    fn url(&self) -> Option<&str> {
+        self.location
+            .as_deref()
+            .filter(|l| !l.is_empty())
+            .or_else(|| self.secure_contents.urls.first().map(|u| &u.url[..]))
+    }
+}
+
+impl Database {
+    /// Import the items of a 1Password Interchange Format (1PIF) export, as written by 1Password 4
+    /// and 5, into a new database.
+    ///
+    /// Logins become entries with Title, UserName, Password, URL and Notes, and secure notes become
+    /// entries with Title and Notes. The tags of an item become the tags of its entry, and
+    /// 1Password folders become groups. Items in the trash and saved searches are not imported.
+    /// Items and folders in unknown folders are put into the root group, as is the first folder of
+    /// folders that are nested in each other in a cycle.
+    pub fn import_from_1pif<R: Read>(mut reader: R) -> Result<Database, ImportError> {
+        let mut data = String::new();
+        reader.read_to_string(&mut data)?;
+
+        let mut db = Database::new(DatabaseConfig::default());
+
+        let mut folders: Vec<(Item, Group)> = Vec::new();
+        let mut entries: Vec<(Option<String>, Entry)> = Vec::new();
+
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(ITEM_SEPARATOR_PREFIX) {
+                continue;
+            }
+
+            let item: Item =
+                serde_json::from_str(line).map_err(|source| ImportError::Json { line: i + 1, source })?;
+
+            if item.trashed {
+                continue;
+            }
+
+            match &item.type_name[..] {
+                "system.folder.Regular" => {
+                    let group = db.new_group(item.title.as_deref().unwrap_or_default());
+                    folders.push((item, group));
+                }
+                "system.folder.SavedSearch" => {}
+                "webforms.WebForm" => {
+                    let mut entry = new_entry(&db, &item);
+                    set_field(&mut entry, "UserName", item.designated_field("username"), false);
+                    set_field(&mut entry, "Password", item.designated_field("password"), true);
+                    set_field(&mut entry, "URL", item.url(), false);
+                    entries.push((item.folder_uuid, entry));
+                }
+                "securenotes.SecureNote" => {
+                    let entry = new_entry(&db, &item);
+                    entries.push((item.folder_uuid, entry));
+                }
+                _ => return Err(ImportError::UnknownItemType(item.type_name)),
+            }
+        }
+
+        // items in folders that are not part of the export are put into the root group
+        let folder_uuids: HashSet<String> = folders.iter().map(|(item, _)| item.uuid.clone()).collect();
+        let known_folder = |uuid: Option<String>| uuid.filter(|u| folder_uuids.contains(u));
+
+        // folders that are their own ancestors would never be reached from the root group, so the
+        // first folder of each cycle is put into the root group instead
+        let mut parents: HashMap<String, Option<String>> = folders
+            .iter()
+            .map(|(item, _)| (item.uuid.clone(), known_folder(item.folder_uuid.clone())))
+            .collect();
+        for (item, _) in &folders {
+            let mut visited = HashSet::new();
+            let mut ancestor = parents[&item.uuid].clone();
+            while let Some(uuid) = ancestor {
+                if uuid == item.uuid {
+                    parents.insert(uuid, None);
+                    break;
+                }
+                if !visited.insert(uuid.clone()) {
+                    break;
+                }
+                ancestor = parents[&uuid].clone();
+            }
+        }
+
+        let mut folders_by_parent: HashMap<Option<String>, Vec<(String, Group)>> = HashMap::new();
+        for (item, group) in folders {
+            folders_by_parent
+                .entry(parents[&item.uuid].clone())
+                .or_default()
+                .push((item.uuid, group));
+        }
+
+        let mut entries_by_folder: HashMap<Option<String>, Vec<Entry>> = HashMap::new();
+        for (folder, entry) in entries {
+            entries_by_folder
+                .entry(known_folder(folder))
+                .or_default()
+                .push(entry);
+        }
+
+        fill_group(&mut db.root, None, &mut folders_by_parent, &mut entries_by_folder);
+
+        Ok(db)
+    }
+}
+
+/// Create an entry with the fields that all item types have in common
+fn new_entry(db: &Database, item: &Item) -> Entry {
+    let mut entry = db.new_entry();
+    set_field(&mut entry, "Title", item.title.as_deref(), false);
+    set_field(
+        &mut entry,
+        "Notes",
+        item.secure_contents.notes_plain.as_deref(),
+        false,
+    );
+    entry.tags = item.open_contents.tags.clone();
+    entry
+}
+
+/// Add the groups for the subfolders of a folder and the entries in it, recursively
+fn fill_group(
+    group: &mut Group,
+    folder: Option<String>,
+    folders_by_parent: &mut HashMap<Option<String>, Vec<(String, Group)>>,
+    entries_by_folder: &mut HashMap<Option<String>, Vec<Entry>>,
+) {
+    for (uuid, mut child) in folders_by_parent.remove(&folder).unwrap_or_default() {
+        fill_group(&mut child, Some(uuid), folders_by_parent, entries_by_folder);
+        group.add_child(child);
+    }
+
+    for entry in entries_by_folder.remove(&folder).unwrap_or_default() {
+        group.add_child(entry);
+    }
+}
+
+#[cfg(test)]
+mod onepif_tests {
+    use crate::{
+        db::{Database, NodeRef},
+        error::ImportError,
+    };
+
+    #[test]
+    fn import_from_1pif() -> Result<(), ImportError> {
+        let data = include_bytes!("../../tests/resources/test_export.1pif");
+        let db = Database::import_from_1pif(&data[..])?;
+
+        assert_eq!(db.root.iter_entries().count(), 6);
+
+        let email = db.root.get(&["Personal", "Mail", "Example Mail"]).unwrap();
+        let NodeRef::Entry(email) = email else {
+            panic!("Expected an entry");
+        };
+        assert_eq!(email.get_username(), Some("alice@example.com"));
+        assert_eq!(email.get_password(), Some("correct horse battery staple"));
+        assert_eq!(email.get_url(), Some("https://mail.example.com/login"));
+        assert_eq!(email.tags, vec!["email".to_string(), "personal".to_string()]);
+
+        let bank = db.root.get(&["Finance", "Example Bank"]).unwrap();
+        let NodeRef::Entry(bank) = bank else {
+            panic!("Expected an entry");
+        };
+        assert_eq!(bank.get_username(), Some("alice"));
+        assert_eq!(bank.get_password(), Some("s3cr3t!"));
+        assert_eq!(bank.get_url(), Some("https://bank.example.com"));
+        assert_eq!(bank.get("Notes"), Some("Branch 042"));
+
+        let note = db.root.get(&["Finance", "Safe combination"]).unwrap();
+        let NodeRef::Entry(note) = note else {
+            panic!("Expected an entry");
+        };
+        assert_eq!(note.get("Notes"), Some("12-34-56"));
+        assert_eq!(note.get_password(), None);
+        assert_eq!(note.tags, vec!["home".to_string()]);
+
+        // items without a folder or in an unknown folder end up in the root group
+        assert!(db.root.get(&["Forum"]).is_some());
+        assert!(db.root.get(&["Wi-Fi"]).is_some());
+        assert!(db.root.get(&["Orphan"]).is_some());
+
+        // trashed items are skipped
+        assert!(db.root.get(&["Old account"]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_from_1pif_folder_cycles() -> Result<(), ImportError> {
+        let item = |uuid: &str, type_name: &str, title: &str, folder: &str| {
+            format!(
+                "{{\"uuid\":\"{}\",\"typeName\":\"{}\",\"title\":\"{}\",\"folderUuid\":\"{}\"}}\n",
+                uuid, type_name, title, folder
+            )
+        };
+        let folder = |uuid, title, parent| item(uuid, "system.folder.Regular", title, parent);
+        let note = |title, folder| item(title, "securenotes.SecureNote", title, folder);
+
+        let data = [
+            folder("A", "Self", "A"),
+            folder("B", "First", "C"),
+            folder("C", "Second", "B"),
+            folder("D", "Below", "C"),
+            note("In self", "A"),
+            note("In first", "B"),
+            note("In second", "C"),
+            note("In below", "D"),
+        ]
+        .concat();
+        let db = Database::import_from_1pif(data.as_bytes())?;
+
+        assert_eq!(db.root.iter_entries().count(), 4);
+        assert!(db.root.get(&["Self", "In self"]).is_some());
+        assert!(db.root.get(&["First", "In first"]).is_some());
+        assert!(db.root.get(&["First", "Second", "In second"]).is_some());
+        assert!(db.root.get(&["First", "Second", "Below", "In below"]).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_from_1pif_errors() {
+        let result = Database::import_from_1pif(&b"{\"typeName\": \"wallet.financial.CreditCard\"}"[..]);
+        assert!(matches!(result, Err(ImportError::UnknownItemType(t)) if t == "wallet.financial.CreditCard"));
+
+        let result = Database::import_from_1pif(&b"***5642bee8-a5ff-11dc-8314-0800200c9a66***\n{"[..]);
+        assert!(matches!(result, Err(ImportError::Json { line: 2, .. })));
+    }
+}
@@ -11,7 +11,10 @@ use challenge_response::{
     ChallengeResponse,
 };
 
-use crate::{crypt::calculate_sha256, error::DatabaseKeyError};
+use crate::{
+    crypt::calculate_sha256,
+    error::{DatabaseKeyError, EnvKeyError},
+};
 
 pub type KeyElement = Vec<u8>;
 pub type KeyElements = Vec<KeyElement>;
@@ -218,7 +221,35 @@ impl ChallengeResponseKey {
     }
 }
 
-/// A KeePass key, which might consist of a password and/or a keyfile
+/// Environment variable lookup, `std::env::var` outside of tests
+type EnvLookup<'a> = &'a dyn Fn(&str) -> Result<String, std::env::VarError>;
+
+fn read_env_var(name: &str, lookup: EnvLookup<'_>) -> Result<String, EnvKeyError> {
+    lookup(name).map_err(|e| match e {
+        std::env::VarError::NotPresent => EnvKeyError::VarNotSet(name.to_string()),
+        std::env::VarError::NotUnicode(_) => EnvKeyError::VarInvalidUtf8(name.to_string()),
+    })
+}
+
+/// A KeePass key, which might consist of a password, a keyfile and a challenge-response key.
+///
+/// The parts are added by chaining `with_password`, `with_keyfile` and
+/// `with_challenge_response_key` in any order. They are always combined in the order of KeePass:
+/// password, keyfile, challenge-response.
+///
+/// A database does not record which parts its key consists of, so opening it with a missing or
+/// additional part fails with `DatabaseKeyError::IncorrectKey`, like a wrong password.
+///
+/// ```
+/// use keepass::{Database, DatabaseKey};
+/// use std::fs::File;
+///
+/// let key = DatabaseKey::new()
+///     .with_keyfile(&mut File::open("tests/resources/test_db_kdbx4_with_keyfile_v2.keyx")?)?
+///     .with_password("demopass");
+/// let db = Database::open(&mut File::open("tests/resources/test_db_kdbx4_with_keyfile_v2.kdbx")?, key)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Debug, Clone, Default, PartialEq, Zeroize, ZeroizeOnDrop)]
 pub struct DatabaseKey {
     password: Option<String>,
@@ -278,6 +309,34 @@ impl DatabaseKey {
         Default::default()
     }
 
+    /// Create a key from a password stored in the environment variable `password_var`, e.g. for
+    /// scripts that cannot prompt for a password
+    pub fn from_env(password_var: &str) -> Result<Self, EnvKeyError> {
+        DatabaseKey::from_env_lookup(password_var, None, &|name| std::env::var(name))
+    }
+
+    /// Create a key from a password stored in the environment variable `password_var` and a key file
+    /// whose path is stored in the environment variable `keyfile_var`
+    pub fn from_env_with_keyfile(password_var: &str, keyfile_var: &str) -> Result<Self, EnvKeyError> {
+        DatabaseKey::from_env_lookup(password_var, Some(keyfile_var), &|name| std::env::var(name))
+    }
+
+    fn from_env_lookup(
+        password_var: &str,
+        keyfile_var: Option<&str>,
+        lookup: EnvLookup<'_>,
+    ) -> Result<Self, EnvKeyError> {
+        let key = DatabaseKey::new().with_password(&read_env_var(password_var, lookup)?);
+
+        let Some(keyfile_var) = keyfile_var else {
+            return Ok(key);
+        };
+        let path = std::path::PathBuf::from(read_env_var(keyfile_var, lookup)?);
+        std::fs::File::open(&path)
+            .and_then(|mut keyfile| key.with_keyfile(&mut keyfile))
+            .map_err(|source| EnvKeyError::KeyFileError { path, source })
+    }
+
     pub(crate) fn get_key_elements(&self) -> Result<KeyElements, DatabaseKeyError> {
         let mut out = Vec::new();
 
@@ -289,10 +348,6 @@ impl DatabaseKey {
             out.push(parse_keyfile(f)?);
         }
 
-        if out.is_empty() {
-            return Err(DatabaseKeyError::IncorrectKey);
-        }
-
         #[cfg(feature = "challenge_response")]
         if let Some(result) = &self.challenge_response_result {
             out.push(calculate_sha256(&[result])?.as_slice().to_vec());
@@ -302,6 +357,10 @@ impl DatabaseKey {
             ));
         }
 
+        if out.is_empty() {
+            return Err(DatabaseKeyError::IncorrectKey);
+        }
+
         Ok(out)
     }
 
@@ -321,10 +380,133 @@ impl DatabaseKey {
 #[cfg(test)]
 mod key_tests {
 
-    use crate::error::DatabaseKeyError;
+    use crate::{
+        error::{DatabaseKeyError, DatabaseOpenError, EnvKeyError},
+        Database,
+    };
 
     use super::DatabaseKey;
 
+    /// Look up variables in `vars` instead of the environment, which is shared between the tests
+    fn lookup<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Result<String, std::env::VarError> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+                .ok_or(std::env::VarError::NotPresent)
+        }
+    }
+
+    #[test]
+    fn from_env() {
+        let vars = lookup(&[("PASSWORD", "demopass")]);
+        let key = DatabaseKey::from_env_lookup("PASSWORD", None, &vars).unwrap();
+        assert_eq!(key, DatabaseKey::new().with_password("demopass"));
+
+        let mut file = std::fs::File::open("tests/resources/test_db_with_password.kdbx").unwrap();
+        let db = Database::open(&mut file, key).unwrap();
+        assert_eq!(db.root.name, "sample");
+
+        assert!(matches!(
+            DatabaseKey::from_env_lookup("UNSET", None, &vars),
+            Err(EnvKeyError::VarNotSet(name)) if name == "UNSET"
+        ));
+        let invalid = |_: &str| Err(std::env::VarError::NotUnicode("\u{fffd}".into()));
+        assert!(matches!(
+            DatabaseKey::from_env_lookup("INVALID", None, &invalid),
+            Err(EnvKeyError::VarInvalidUtf8(name)) if name == "INVALID"
+        ));
+
+        // reading a variable that is never set does not race with other tests
+        assert!(matches!(
+            DatabaseKey::from_env("KEEPASS_RS_TEST_FROM_ENV_UNSET"),
+            Err(EnvKeyError::VarNotSet(name)) if name == "KEEPASS_RS_TEST_FROM_ENV_UNSET"
+        ));
+    }
+
+    #[test]
+    fn from_env_with_keyfile() {
+        let vars = lookup(&[
+            ("PASSWORD", "demopass"),
+            ("KEYFILE", "tests/resources/test_db_kdbx4_with_keyfile_v2.keyx"),
+            ("MISSING_KEYFILE", "tests/resources/does_not_exist"),
+        ]);
+        let key = DatabaseKey::from_env_lookup("PASSWORD", Some("KEYFILE"), &vars).unwrap();
+
+        let mut file = std::fs::File::open("tests/resources/test_db_kdbx4_with_keyfile_v2.kdbx").unwrap();
+        let db = Database::open(&mut file, key).unwrap();
+        assert_eq!(db.root.name, "Root");
+
+        assert!(matches!(
+            DatabaseKey::from_env_lookup("PASSWORD", Some("MISSING_KEYFILE"), &vars),
+            Err(EnvKeyError::KeyFileError { .. })
+        ));
+        assert!(matches!(
+            DatabaseKey::from_env_lookup("PASSWORD", Some("UNSET"), &vars),
+            Err(EnvKeyError::VarNotSet(name)) if name == "UNSET"
+        ));
+    }
+
+    #[test]
+    fn keyfile_only() {
+        use std::fs::File;
+
+        let key = DatabaseKey::new()
+            .with_keyfile(&mut File::open("tests/resources/test_key.key").unwrap())
+            .unwrap();
+
+        // without a password, the composite key consists of the keyfile alone, not of the keyfile
+        // and the hash of an empty password
+        let key_elements = key.get_key_elements().unwrap();
+        assert_eq!(key_elements.len(), 1);
+        let empty_password = DatabaseKey::new().with_password("").get_key_elements().unwrap();
+        assert!(!key_elements.contains(&empty_password[0]));
+
+        let mut file = File::open("tests/resources/test_db_kdbx4_with_keyfile.kdbx").unwrap();
+        assert!(Database::open(&mut file, key).is_ok());
+
+        assert!(matches!(
+            DatabaseKey::new().get_key_elements(),
+            Err(DatabaseKeyError::IncorrectKey)
+        ));
+    }
+
+    #[test]
+    fn password_and_keyfile() {
+        use std::fs::File;
+
+        let open = |key: DatabaseKey| {
+            let mut file = File::open("tests/resources/test_db_kdbx4_with_keyfile_v2.kdbx").unwrap();
+            Database::open(&mut file, key)
+        };
+        let keyfile = || File::open("tests/resources/test_db_kdbx4_with_keyfile_v2.keyx").unwrap();
+
+        let password_first = DatabaseKey::new()
+            .with_password("demopass")
+            .with_keyfile(&mut keyfile())
+            .unwrap();
+        let keyfile_first = DatabaseKey::new()
+            .with_keyfile(&mut keyfile())
+            .unwrap()
+            .with_password("demopass");
+        assert_eq!(
+            password_first.get_key_elements().unwrap(),
+            keyfile_first.get_key_elements().unwrap()
+        );
+        assert!(open(password_first).is_ok());
+        assert!(open(keyfile_first).is_ok());
+
+        for partial_key in [
+            DatabaseKey::new().with_password("demopass"),
+            DatabaseKey::new().with_keyfile(&mut keyfile()).unwrap(),
+        ] {
+            assert!(matches!(
+                open(partial_key),
+                Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))
+            ));
+        }
+    }
+
     #[test]
     fn test_key() -> Result<(), DatabaseKeyError> {
         let ke = DatabaseKey::new().with_password("asdf").get_key_elements()?;
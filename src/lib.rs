@@ -6,14 +6,21 @@ pub mod config;
 pub(crate) mod crypt;
 pub mod db;
 pub mod error;
+#[cfg(feature = "export")]
+pub(crate) mod export;
 pub(crate) mod format;
 pub(crate) mod hmac_block_stream;
+#[cfg(feature = "import")]
+pub(crate) mod import;
 #[cfg(feature = "save_kdbx4")]
 mod io;
 mod key;
+pub mod pwgen;
 pub(crate) mod variant_dictionary;
 pub(crate) mod xml_db;
 
+#[cfg(feature = "mlock")]
+pub use self::crypt::locked::memory_locking_available;
 pub use self::db::Database;
 #[cfg(feature = "challenge_response")]
 pub use self::key::ChallengeResponseKey;
@@ -0,0 +1,220 @@
+use crate::{error::PasswordGeneratorError, pwgen::random_index};
+
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const DIGITS: &str = "0123456789";
+const SPECIAL: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+const SPACE: &str = " ";
+
+/// Rules for `generate_password`, following the character set options of the KeePass password
+/// generator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordGeneratorRules {
+    /// Number of characters in the password
+    pub length: usize,
+
+    /// Whether upper-case letters `A-Z` are used
+    pub use_upper: bool,
+
+    /// Whether lower-case letters `a-z` are used
+    pub use_lower: bool,
+
+    /// Whether digits `0-9` are used
+    pub use_digits: bool,
+
+    /// Whether the printable ASCII special characters are used, not including the space
+    pub use_special: bool,
+
+    /// Whether the space is used
+    pub use_space: bool,
+
+    /// Characters that are never used, e.g. to avoid look-alikes such as `l1I0O`
+    pub exclude_chars: String,
+
+    /// Minimum number of upper-case letters, requires `use_upper`
+    pub min_upper: usize,
+
+    /// Minimum number of digits, requires `use_digits`
+    pub min_digits: usize,
+}
+
+impl Default for PasswordGeneratorRules {
+    /// 20 letters and digits, which is the default profile of KeePass
+    fn default() -> Self {
+        Self {
+            length: 20,
+            use_upper: true,
+            use_lower: true,
+            use_digits: true,
+            use_special: false,
+            use_space: false,
+            exclude_chars: String::new(),
+            min_upper: 0,
+            min_digits: 0,
+        }
+    }
+}
+
+/// Generate a random password following `rules`.
+///
+/// The minimum numbers of upper-case letters and digits are picked first, the remaining characters
+/// are picked from all enabled character sets, and the result is shuffled. All randomness comes from
+/// the operating system's CSPRNG.
+///
+/// ```
+/// use keepass::pwgen::{generate_password, PasswordGeneratorRules};
+///
+/// let rules = PasswordGeneratorRules {
+///     length: 16,
+///     use_special: true,
+///     exclude_chars: "l1I0O".to_string(),
+///     min_digits: 2,
+///     ..PasswordGeneratorRules::default()
+/// };
+///
+/// let password = generate_password(&rules)?;
+/// assert_eq!(password.chars().count(), 16);
+/// assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 2);
+/// # Ok::<(), keepass::error::PasswordGeneratorError>(())
+/// ```
+pub fn generate_password(rules: &PasswordGeneratorRules) -> Result<String, PasswordGeneratorError> {
+    let charset = |enabled: bool, chars: &str| -> Vec<char> {
+        if !enabled {
+            return Vec::new();
+        }
+        chars
+            .chars()
+            .filter(|c| !rules.exclude_chars.contains(*c))
+            .collect()
+    };
+
+    let upper = charset(rules.use_upper, UPPER);
+    let digits = charset(rules.use_digits, DIGITS);
+    let all: Vec<char> = [
+        upper.clone(),
+        charset(rules.use_lower, LOWER),
+        digits.clone(),
+        charset(rules.use_special, SPECIAL),
+        charset(rules.use_space, SPACE),
+    ]
+    .concat();
+
+    if all.is_empty() {
+        return Err(PasswordGeneratorError::EmptyCharacterSet);
+    }
+    if (rules.min_upper > 0 && upper.is_empty())
+        || (rules.min_digits > 0 && digits.is_empty())
+        || rules.min_upper + rules.min_digits > rules.length
+    {
+        return Err(PasswordGeneratorError::UnsatisfiableRules);
+    }
+
+    let mut password = Vec::with_capacity(rules.length);
+    for _ in 0..rules.min_upper {
+        password.push(upper[random_index(upper.len())?]);
+    }
+    for _ in 0..rules.min_digits {
+        password.push(digits[random_index(digits.len())?]);
+    }
+    while password.len() < rules.length {
+        password.push(all[random_index(all.len())?]);
+    }
+
+    // Fisher-Yates shuffle, so that the required characters are not at the start
+    for i in (1..password.len()).rev() {
+        password.swap(i, random_index(i + 1)?);
+    }
+
+    Ok(password.into_iter().collect())
+}
+
+#[cfg(test)]
+mod generator_tests {
+    use super::{generate_password, PasswordGeneratorRules};
+    use crate::error::PasswordGeneratorError;
+
+    #[test]
+    fn minimum_character_classes() -> Result<(), PasswordGeneratorError> {
+        let rules = PasswordGeneratorRules {
+            length: 12,
+            use_upper: true,
+            use_lower: true,
+            use_digits: true,
+            use_special: true,
+            use_space: false,
+            exclude_chars: "AEIOU0".to_string(),
+            min_upper: 3,
+            min_digits: 4,
+        };
+
+        for _ in 0..1000 {
+            let password = generate_password(&rules)?;
+            assert_eq!(password.chars().count(), 12);
+            assert!(password.chars().filter(|c| c.is_ascii_uppercase()).count() >= 3);
+            assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 4);
+            assert!(!password
+                .chars()
+                .any(|c| rules.exclude_chars.contains(c) || c == ' '));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn character_sets() -> Result<(), PasswordGeneratorError> {
+        let rules = PasswordGeneratorRules {
+            length: 50,
+            use_upper: false,
+            use_lower: false,
+            use_digits: true,
+            use_space: true,
+            ..PasswordGeneratorRules::default()
+        };
+        let password = generate_password(&rules)?;
+        assert!(password.chars().all(|c| c.is_ascii_digit() || c == ' '));
+
+        let rules = PasswordGeneratorRules {
+            length: 0,
+            ..PasswordGeneratorRules::default()
+        };
+        assert_eq!(generate_password(&rules)?, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let rules = PasswordGeneratorRules {
+            use_upper: false,
+            use_lower: false,
+            use_digits: false,
+            ..PasswordGeneratorRules::default()
+        };
+        assert!(matches!(
+            generate_password(&rules),
+            Err(PasswordGeneratorError::EmptyCharacterSet)
+        ));
+
+        let rules = PasswordGeneratorRules {
+            use_digits: true,
+            exclude_chars: "0123456789".to_string(),
+            min_digits: 1,
+            ..PasswordGeneratorRules::default()
+        };
+        assert!(matches!(
+            generate_password(&rules),
+            Err(PasswordGeneratorError::UnsatisfiableRules)
+        ));
+
+        let rules = PasswordGeneratorRules {
+            length: 4,
+            min_upper: 3,
+            min_digits: 2,
+            ..PasswordGeneratorRules::default()
+        };
+        assert!(matches!(
+            generate_password(&rules),
+            Err(PasswordGeneratorError::UnsatisfiableRules)
+        ));
+    }
+}
@@ -0,0 +1,27 @@
+//! Generators for new passwords and passphrases, and strength estimation for chosen passwords
+
+#[cfg(feature = "password_generator")]
+mod generator;
+mod passphrase;
+mod strength;
+
+#[cfg(feature = "password_generator")]
+pub use crate::pwgen::generator::{generate_password, PasswordGeneratorRules};
+pub use crate::pwgen::{
+    passphrase::Passphrase,
+    strength::{estimate_strength, Strength, StrengthScore},
+};
+
+/// Pick a uniformly distributed index below `n` using the operating system's CSPRNG
+pub(crate) fn random_index(n: usize) -> Result<usize, getrandom::Error> {
+    let n = n as u64;
+
+    // reject values from the incomplete last block of `n` values to avoid a modulo bias
+    let zone = u64::MAX - (u64::MAX % n);
+    loop {
+        let value = getrandom::u64()?;
+        if value < zone {
+            return Ok((value % n) as usize);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use crate::{error::PasswordGeneratorError, pwgen::random_index};
+
+/// A generator for Diceware-style passphrases made of words picked at random from a wordlist
+///
+/// With the `eff_wordlist` feature, `Passphrase::eff_large` uses the bundled EFF large wordlist.
+/// Any other published Diceware list can be loaded with `Passphrase::from_diceware_list`.
+///
+/// ```
+/// use keepass::pwgen::Passphrase;
+///
+/// let wordlist = ["correct", "horse", "battery", "staple"].iter().map(|w| w.to_string()).collect();
+/// let mut generator = Passphrase::new(wordlist);
+/// generator.word_count = 5;
+/// generator.separator = "-".to_string();
+///
+/// let passphrase = generator.generate().unwrap();
+/// assert_eq!(passphrase.split('-').count(), 5);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passphrase {
+    /// Number of words in the passphrase
+    pub word_count: usize,
+
+    /// Separator between the words
+    pub separator: String,
+
+    /// Words to pick from. Every word must appear once, `generate` fails on duplicates.
+    pub wordlist: Vec<String>,
+
+    /// Whether the first letter of every word is upper-cased
+    pub capitalize: bool,
+
+    /// Whether a random digit is appended to one random word
+    pub insert_number: bool,
+}
+
+impl Passphrase {
+    /// Create a generator for six words separated by spaces, which is the usual recommendation for
+    /// a Diceware wordlist of 7776 words. Duplicate words are removed from the wordlist.
+    pub fn new(mut wordlist: Vec<String>) -> Self {
+        let mut seen = HashSet::new();
+        wordlist.retain(|word| seen.insert(word.clone()));
+        Self {
+            word_count: 6,
+            separator: " ".to_string(),
+            wordlist,
+            capitalize: false,
+            insert_number: false,
+        }
+    }
+
+    /// Create a generator for a wordlist in the Diceware format, where every line holds the dice
+    /// rolls for a word followed by the word itself. Lines with only a word are accepted as well,
+    /// and empty lines are skipped.
+    pub fn from_diceware_list(list: &str) -> Self {
+        let wordlist = list
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(|word| word.to_string())
+            .collect();
+        Self::new(wordlist)
+    }
+
+    /// Create a generator for the EFF large wordlist of 7776 words
+    /// (<https://www.eff.org/dice>, licensed under CC BY 3.0 US)
+    #[cfg(feature = "eff_wordlist")]
+    pub fn eff_large() -> Self {
+        Self::from_diceware_list(include_str!("eff_large_wordlist.txt"))
+    }
+
+    /// Generate a new passphrase
+    pub fn generate(&self) -> Result<String, PasswordGeneratorError> {
+        if self.wordlist.is_empty() {
+            return Err(PasswordGeneratorError::EmptyWordlist);
+        }
+        let mut seen = HashSet::new();
+        if let Some(word) = self.wordlist.iter().find(|word| !seen.insert(*word)) {
+            return Err(PasswordGeneratorError::DuplicateWord(word.clone()));
+        }
+
+        let mut words = Vec::with_capacity(self.word_count);
+        for _ in 0..self.word_count {
+            let word = &self.wordlist[random_index(self.wordlist.len())?];
+            words.push(if self.capitalize {
+                capitalize(word)
+            } else {
+                word.clone()
+            });
+        }
+
+        if self.insert_number && !words.is_empty() {
+            let index = random_index(words.len())?;
+            let digit = random_index(10)?;
+            words[index].push_str(&digit.to_string());
+        }
+
+        Ok(words.join(&self.separator))
+    }
+
+    /// Get the entropy of the generated passphrases in bits, assuming that the wordlist is known to
+    /// an attacker
+    pub fn entropy(&self) -> f64 {
+        if self.wordlist.is_empty() || self.word_count == 0 {
+            return 0.0;
+        }
+
+        let mut bits = self.word_count as f64 * (self.wordlist.len() as f64).log2();
+        if self.insert_number {
+            bits += 10f64.log2() + (self.word_count as f64).log2();
+        }
+        bits
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod passphrase_tests {
+    use super::Passphrase;
+    use crate::error::PasswordGeneratorError;
+
+    fn wordlist() -> Vec<String> {
+        [
+            "apple",
+            "banana",
+            "cherry",
+            "damson",
+            "elder",
+            "fig",
+            "grape",
+            "huckleberry",
+        ]
+        .iter()
+        .map(|w| w.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn generate() -> Result<(), PasswordGeneratorError> {
+        let mut generator = Passphrase::new(wordlist());
+        generator.separator = ".".to_string();
+
+        let passphrase = generator.generate()?;
+        let words: Vec<&str> = passphrase.split('.').collect();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|w| wordlist().iter().any(|l| l == w)));
+
+        generator.word_count = 4;
+        generator.capitalize = true;
+        generator.insert_number = true;
+
+        let passphrase = generator.generate()?;
+        let words: Vec<&str> = passphrase.split('.').collect();
+        assert_eq!(words.len(), 4);
+        assert!(words
+            .iter()
+            .all(|w| w.starts_with(|c: char| c.is_ascii_uppercase())));
+        assert_eq!(passphrase.chars().filter(|c| c.is_ascii_digit()).count(), 1);
+
+        generator.wordlist.push("fig".to_string());
+        assert!(matches!(
+            generator.generate(),
+            Err(PasswordGeneratorError::DuplicateWord(word)) if word == "fig"
+        ));
+
+        generator.wordlist.clear();
+        assert!(matches!(
+            generator.generate(),
+            Err(PasswordGeneratorError::EmptyWordlist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_diceware_list() {
+        let generator =
+            Passphrase::from_diceware_list("11111\tabacus\n11112\tabdomen\n\nabide\r\n11114\tabacus\n");
+        assert_eq!(generator.wordlist, vec!["abacus", "abdomen", "abide"]);
+        assert_eq!(generator.word_count, 6);
+    }
+
+    #[cfg(feature = "eff_wordlist")]
+    #[test]
+    fn eff_large() {
+        let generator = Passphrase::eff_large();
+        assert_eq!(generator.wordlist.len(), 7776);
+        assert_eq!(generator.wordlist.first().map(String::as_str), Some("abacus"));
+        assert_eq!(generator.wordlist.last().map(String::as_str), Some("zoom"));
+        assert!(generator.generate().is_ok());
+
+        // six words of a 7776 word list give the usual 77.5 bits
+        assert!((generator.entropy() - 6.0 * 7776f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn entropy() {
+        let generator = Passphrase::new(wordlist());
+        assert_eq!(generator.entropy(), 18.0);
+
+        // duplicate words are removed and do not add entropy
+        let mut generator = Passphrase::new([wordlist(), wordlist()].concat());
+        assert_eq!(generator.wordlist, wordlist());
+        assert_eq!(generator.entropy(), 18.0);
+
+        generator.word_count = 4;
+        generator.insert_number = true;
+        assert!((generator.entropy() - (12.0 + 10f64.log2() + 2.0)).abs() < 1e-9);
+
+        generator.wordlist.clear();
+        assert_eq!(generator.entropy(), 0.0);
+    }
+}
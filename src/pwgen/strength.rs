@@ -0,0 +1,186 @@
+/// Pool sizes of the character classes, see `estimate_strength`
+const LOWERCASE_POOL: u32 = 26;
+const UPPERCASE_POOL: u32 = 26;
+const DIGIT_POOL: u32 = 10;
+const SYMBOL_POOL: u32 = 33;
+const OTHER_POOL: u32 = 100;
+
+/// Coarse rating of a password's strength
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StrengthScore {
+    /// Less than 28 bits of entropy
+    VeryWeak,
+
+    /// 28 to 36 bits of entropy
+    Weak,
+
+    /// 36 to 60 bits of entropy
+    Fair,
+
+    /// 60 to 100 bits of entropy
+    Strong,
+
+    /// 100 bits of entropy or more
+    VeryStrong,
+}
+
+impl StrengthScore {
+    fn from_entropy(entropy: f64) -> Self {
+        if entropy < 28.0 {
+            StrengthScore::VeryWeak
+        } else if entropy < 36.0 {
+            StrengthScore::Weak
+        } else if entropy < 60.0 {
+            StrengthScore::Fair
+        } else if entropy < 100.0 {
+            StrengthScore::Strong
+        } else {
+            StrengthScore::VeryStrong
+        }
+    }
+}
+
+/// Estimated strength of a password
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strength {
+    /// Estimated entropy in bits
+    pub entropy: f64,
+
+    pub score: StrengthScore,
+}
+
+/// Estimate the strength of a password from the classes of characters it uses.
+///
+/// The estimate is deterministic:
+///
+/// 1. The pool size is the sum of the sizes of the character classes that occur in the password:
+///    26 for lowercase ASCII letters, 26 for uppercase ASCII letters, 10 for digits, 33 for other
+///    printable ASCII characters including space, and 100 for all other characters.
+/// 2. Every character adds `log2(pool size)` bits, except for characters that repeat the previous
+///    character (`aaa`) or continue a run of consecutive code points (`abc`, `987`), which add a
+///    single bit.
+/// 3. The score is derived from the entropy with the thresholds documented on `StrengthScore`.
+///
+/// This does not detect dictionary words or keyboard patterns, so it overestimates the strength of
+/// passwords such as `Password1!`.
+///
+/// ```
+/// use keepass::pwgen::{estimate_strength, StrengthScore};
+///
+/// assert_eq!(estimate_strength("aaaaaaaa").score, StrengthScore::VeryWeak);
+/// assert_eq!(estimate_strength("x7#Rq9!bVz").score, StrengthScore::Strong);
+/// ```
+pub fn estimate_strength(password: &str) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+
+    let mut pool = 0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        pool += LOWERCASE_POOL;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        pool += UPPERCASE_POOL;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        pool += DIGIT_POOL;
+    }
+    if chars
+        .iter()
+        .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric() && !c.is_ascii_control())
+    {
+        pool += SYMBOL_POOL;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        pool += OTHER_POOL;
+    }
+
+    let bits_per_char = if pool > 1 { f64::from(pool).log2() } else { 0.0 };
+
+    let mut entropy = 0.0;
+    let mut previous_delta = None;
+    for (i, c) in chars.iter().enumerate() {
+        let delta = i
+            .checked_sub(1)
+            .map(|prev| i64::from(u32::from(*c)) - i64::from(u32::from(chars[prev])));
+
+        let repeats = delta == Some(0);
+        let continues_run = matches!(delta, Some(-1) | Some(1)) && delta == previous_delta;
+
+        entropy += if repeats || continues_run {
+            1.0
+        } else {
+            bits_per_char
+        };
+        previous_delta = delta;
+    }
+
+    Strength {
+        entropy,
+        score: StrengthScore::from_entropy(entropy),
+    }
+}
+
+#[cfg(test)]
+mod strength_tests {
+    use super::{estimate_strength, StrengthScore};
+
+    #[test]
+    fn empty_password() {
+        let strength = estimate_strength("");
+        assert_eq!(strength.entropy, 0.0);
+        assert_eq!(strength.score, StrengthScore::VeryWeak);
+    }
+
+    #[test]
+    fn character_classes() {
+        // 7 lowercase letters without patterns: 7 * log2(26)
+        let strength = estimate_strength("qzmxnwb");
+        assert!((strength.entropy - 7.0 * 26f64.log2()).abs() < 1e-9);
+        assert_eq!(strength.score, StrengthScore::Weak);
+
+        // adding classes increases the pool
+        assert!(estimate_strength("qzMxnwb").entropy > strength.entropy);
+        assert!(estimate_strength("qzmx7wb").entropy > strength.entropy);
+        assert!(estimate_strength("qzmx!wb").entropy > strength.entropy);
+
+        assert_eq!(estimate_strength("qzmxnwbv").score, StrengthScore::Fair);
+        assert_eq!(estimate_strength("x7#Rq9!bVz").score, StrengthScore::Strong);
+        assert_eq!(
+            estimate_strength("x7#Rq9!bVz2$mK4p").score,
+            StrengthScore::VeryStrong
+        );
+    }
+
+    #[test]
+    fn repetitions_and_sequences() {
+        // only the first character adds the full bits
+        let strength = estimate_strength("aaaaaaaaaaaa");
+        assert!((strength.entropy - (26f64.log2() + 11.0)).abs() < 1e-9);
+        assert_eq!(strength.score, StrengthScore::VeryWeak);
+
+        // "a" and "b" add full bits, the rest continues the run
+        let strength = estimate_strength("abcdefghijkl");
+        assert!((strength.entropy - (2.0 * 26f64.log2() + 10.0)).abs() < 1e-9);
+
+        assert_eq!(estimate_strength("987654321").score, StrengthScore::VeryWeak);
+        assert!(estimate_strength("abcdefg").entropy < estimate_strength("qzmxnwb").entropy);
+    }
+
+    #[test]
+    fn unicode() {
+        let strength = estimate_strength("Grüße aus Köln 🔑");
+        assert!(strength.entropy > 0.0);
+        assert!(estimate_strength("ğüşıöç").entropy > estimate_strength("gusioc").entropy);
+
+        // a single character from a large pool
+        let strength = estimate_strength("🔑");
+        assert!((strength.entropy - 100f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn deterministic() {
+        assert_eq!(
+            estimate_strength("correct horse battery staple"),
+            estimate_strength("correct horse battery staple")
+        );
+    }
+}
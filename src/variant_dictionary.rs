@@ -27,7 +27,6 @@ pub struct VariantDictionary {
 }
 
 impl VariantDictionary {
-    #[cfg(feature = "save_kdbx4")]
     pub(crate) fn new() -> Self {
         Self { data: HashMap::new() }
     }
@@ -90,7 +89,10 @@ impl VariantDictionary {
     pub(crate) fn dump(&self, writer: &mut dyn Write) -> Result<(), std::io::Error> {
         writer.write_u16::<LittleEndian>(VARIANT_DICTIONARY_VERSION)?;
 
-        for (field_name, field_value) in &self.data {
+        // sorted, so that the same dictionary is always written the same way
+        let mut fields: Vec<_> = self.data.iter().collect();
+        fields.sort_by_key(|(field_name, _)| *field_name);
+        for (field_name, field_value) in fields {
             match field_value {
                 VariantDictionaryValue::UInt32(value) => {
                     writer.write_u8(U32_TYPE_ID)?;
@@ -153,7 +155,6 @@ impl VariantDictionary {
             .ok_or_else(|| VariantDictionaryError::Mistyped { key: key.to_owned() })
     }
 
-    #[cfg(feature = "save_kdbx4")]
     pub(crate) fn set<T>(&mut self, key: &str, value: T)
     where
         T: Into<VariantDictionaryValue>,
@@ -2,66 +2,91 @@ use base64::{engine::general_purpose as base64_engine, Engine as _};
 use xml::writer::{EventWriter, XmlEvent as WriterEvent};
 
 use crate::{
-    crypt::ciphers::Cipher,
-    db::{AutoType, AutoTypeAssociation, Entry, History, Value},
-    xml_db::dump::{DumpXml, SimpleTag},
+    db::{AutoType, AutoTypeAssociation, Color, Entry, History, Value},
+    xml_db::dump::{DumpContext, DumpXml, SimpleTag},
 };
 
 impl DumpXml for Entry {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("Entry"))?;
 
-        SimpleTag("UUID", &self.uuid).dump_xml(writer, inner_cipher)?;
+        SimpleTag("UUID", &self.uuid).dump_xml(writer, ctx)?;
 
-        SimpleTag("Tags", &self.tags.join(";")).dump_xml(writer, inner_cipher)?;
+        SimpleTag("Tags", &self.tags.join(";")).dump_xml(writer, ctx)?;
 
         for (field_name, field_value) in &self.fields {
             writer.write(WriterEvent::start_element("String"))?;
 
-            SimpleTag("Key", field_name).dump_xml(writer, inner_cipher)?;
-            field_value.dump_xml(writer, inner_cipher)?;
+            SimpleTag("Key", field_name).dump_xml(writer, ctx)?;
+            field_value.dump_xml(writer, ctx)?;
 
             writer.write(WriterEvent::end_element())?; // String
         }
 
-        self.custom_data.dump_xml(writer, inner_cipher)?;
+        let mut attachments: Vec<_> = self.attachments.iter().collect();
+        attachments.sort();
+        for (name, index) in attachments {
+            let index = ctx
+                .attachment_indices
+                .get(*index)
+                .copied()
+                .flatten()
+                .unwrap_or(*index);
+            writer.write(WriterEvent::start_element("Binary"))?;
+
+            SimpleTag("Key", name).dump_xml(writer, ctx)?;
+            writer.write(WriterEvent::start_element("Value").attr("Ref", &index.to_string()))?;
+            writer.write(WriterEvent::end_element())?; // Value
+
+            writer.write(WriterEvent::end_element())?; // Binary
+        }
+
+        self.custom_data.dump_xml(writer, ctx)?;
 
         if let Some(ref value) = self.autotype {
-            value.dump_xml(writer, inner_cipher)?;
+            value.dump_xml(writer, ctx)?;
         }
 
-        self.times.dump_xml(writer, inner_cipher)?;
+        self.times.dump_xml(writer, ctx)?;
 
         if let Some(value) = self.icon_id {
-            SimpleTag("IconID", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("IconID", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.custom_icon_uuid {
-            SimpleTag("CustomIconUUID", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("CustomIconUUID", value).dump_xml(writer, ctx)?;
         }
 
-        if let Some(ref value) = self.foreground_color {
-            SimpleTag("ForegroundColor", value).dump_xml(writer, inner_cipher)?;
+        if let Some(value) = color_text(&self.foreground_color, &self.foreground_color_text) {
+            SimpleTag("ForegroundColor", value).dump_xml(writer, ctx)?;
+        } else if let Some(ref value) = self.foreground_color {
+            SimpleTag("ForegroundColor", value).dump_xml(writer, ctx)?;
         }
 
-        if let Some(ref value) = self.background_color {
-            SimpleTag("BackgroundColor", value).dump_xml(writer, inner_cipher)?;
+        if let Some(value) = color_text(&self.background_color, &self.background_color_text) {
+            SimpleTag("BackgroundColor", value).dump_xml(writer, ctx)?;
+        } else if let Some(ref value) = self.background_color {
+            SimpleTag("BackgroundColor", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.override_url {
-            SimpleTag("OverrideURL", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("OverrideURL", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(value) = self.quality_check {
-            SimpleTag("QualityCheck", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("QualityCheck", value).dump_xml(writer, ctx)?;
+        }
+
+        for element in &self.unknown_elements {
+            element.dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.history {
-            value.dump_xml(writer, inner_cipher)?;
+            value.dump_xml(writer, ctx)?;
         }
 
         writer.write(WriterEvent::end_element())?; // Entry
@@ -70,22 +95,26 @@ impl DumpXml for Entry {
     }
 }
 
+/// Get the text of an entry color as read from a file, unless the color was changed since
+fn color_text<'a>(color: &Option<Color>, text: &'a Option<String>) -> Option<&'a str> {
+    text.as_deref().filter(|text| text.parse().ok() == *color)
+}
+
 impl DumpXml for Value {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         match self {
-            Value::Bytes(b) => {
-                SimpleTag("Value", std::str::from_utf8(b).expect("utf-8")).dump_xml(writer, inner_cipher)
-            }
-            Value::Unprotected(s) => SimpleTag("Value", s).dump_xml(writer, inner_cipher),
-            Value::Protected(p) => {
+            Value::Bytes(b) => SimpleTag("Value", std::str::from_utf8(b).expect("utf-8")).dump_xml(writer, ctx),
+            Value::Unprotected(s) => SimpleTag("Value", s).dump_xml(writer, ctx),
+            Value::Protected(_) | Value::Deferred(_) => {
                 writer.write(WriterEvent::start_element("Value").attr("Protected", "True"))?;
 
-                let encrypted_value = inner_cipher
-                    .encrypt(p.unsecure())
+                let encrypted_value = ctx
+                    .inner_cipher
+                    .encrypt(self.protected().expect("protected").unsecure())
                     .expect("Encrypt with inner cipher");
 
                 let protected_value = base64_engine::STANDARD.encode(&encrypted_value);
@@ -95,6 +124,10 @@ impl DumpXml for Value {
                 writer.write(WriterEvent::end_element())?;
                 Ok(())
             }
+            Value::Locked(_) => Err(xml::writer::Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "cannot write a locked value",
+            ))),
         }
     }
 }
@@ -103,18 +136,18 @@ impl DumpXml for AutoType {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("AutoType"))?;
 
-        SimpleTag("Enabled", self.enabled).dump_xml(writer, inner_cipher)?;
+        SimpleTag("Enabled", self.enabled).dump_xml(writer, ctx)?;
 
         if let Some(ref value) = self.sequence {
-            SimpleTag("DefaultSequence", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("DefaultSequence", value).dump_xml(writer, ctx)?;
         }
 
         for assoc in &self.associations {
-            assoc.dump_xml(writer, inner_cipher)?;
+            assoc.dump_xml(writer, ctx)?;
         }
 
         writer.write(WriterEvent::end_element())?;
@@ -126,16 +159,16 @@ impl DumpXml for AutoTypeAssociation {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("Association"))?;
 
         if let Some(ref value) = self.window {
-            SimpleTag("Window", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("Window", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.sequence {
-            SimpleTag("KeystrokeSequence", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("KeystrokeSequence", value).dump_xml(writer, ctx)?;
         }
 
         writer.write(WriterEvent::end_element())?;
@@ -147,12 +180,12 @@ impl DumpXml for History {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("History"))?;
 
         for entry in &self.entries {
-            entry.dump_xml(writer, inner_cipher)?;
+            entry.dump_xml(writer, ctx)?;
         }
 
         writer.write(WriterEvent::end_element())?;
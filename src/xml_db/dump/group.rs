@@ -1,57 +1,60 @@
 use xml::writer::{EventWriter, XmlEvent as WriterEvent};
 
 use crate::{
-    crypt::ciphers::Cipher,
     db::{Group, Node},
-    xml_db::dump::{DumpXml, SimpleTag},
+    xml_db::dump::{DumpContext, DumpXml, SimpleTag},
 };
 
 impl DumpXml for Group {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("Group"))?;
 
-        SimpleTag("Name", &self.name).dump_xml(writer, inner_cipher)?;
-        SimpleTag("UUID", &self.uuid).dump_xml(writer, inner_cipher)?;
+        SimpleTag("Name", &self.name).dump_xml(writer, ctx)?;
+        SimpleTag("UUID", &self.uuid).dump_xml(writer, ctx)?;
 
         if let Some(ref value) = self.notes {
-            SimpleTag("Notes", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("Notes", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(value) = self.icon_id {
-            SimpleTag("IconID", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("IconID", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.custom_icon_uuid {
-            SimpleTag("CustomIconUUID", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("CustomIconUUID", value).dump_xml(writer, ctx)?;
         }
 
-        self.times.dump_xml(writer, inner_cipher)?;
-        self.custom_data.dump_xml(writer, inner_cipher)?;
+        self.times.dump_xml(writer, ctx)?;
+        self.custom_data.dump_xml(writer, ctx)?;
 
-        SimpleTag("IsExpanded", self.is_expanded).dump_xml(writer, inner_cipher)?;
+        SimpleTag("IsExpanded", self.is_expanded).dump_xml(writer, ctx)?;
 
         if let Some(ref value) = self.default_autotype_sequence {
-            SimpleTag("DefaultAutoTypeSequence", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("DefaultAutoTypeSequence", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.enable_autotype {
-            SimpleTag("EnableAutoType", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("EnableAutoType", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.enable_searching {
-            SimpleTag("EnableSearching", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("EnableSearching", value).dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.last_top_visible_entry {
-            SimpleTag("LastTopVisibleEntry", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("LastTopVisibleEntry", value).dump_xml(writer, ctx)?;
+        }
+
+        for element in &self.unknown_elements {
+            element.dump_xml(writer, ctx)?;
         }
 
         for child in &self.children {
-            child.dump_xml(writer, inner_cipher)?;
+            child.dump_xml(writer, ctx)?;
         }
 
         writer.write(WriterEvent::end_element())?; // Group
@@ -64,11 +67,11 @@ impl DumpXml for Node {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         match self {
-            Node::Group(g) => g.dump_xml(writer, inner_cipher),
-            Node::Entry(e) => e.dump_xml(writer, inner_cipher),
+            Node::Group(g) => g.dump_xml(writer, ctx),
+            Node::Entry(e) => e.dump_xml(writer, ctx),
         }
     }
 }
@@ -13,27 +13,55 @@ use xml::{
 
 use crate::{
     crypt::ciphers::Cipher,
-    db::{Color, CustomData, CustomDataItem, Database, DeletedObject, DeletedObjects, Times},
+    db::{Color, CustomData, CustomDataItem, Database, DeletedObject, DeletedObjects, Times, UnknownElement},
+    format::DatabaseVersion,
     xml_db::get_epoch_baseline,
 };
 
 /// Format a timestamp suitable for an XML database
-pub fn format_xml_timestamp(timestamp: &chrono::NaiveDateTime) -> String {
+fn format_xml_timestamp(timestamp: &chrono::NaiveDateTime, ctx: &DumpContext<'_>) -> String {
+    if ctx.iso_timestamps {
+        return timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    }
+
     let timestamp = timestamp.and_utc().timestamp() - get_epoch_baseline().and_utc().timestamp();
     let timestamp_bytes = i64::to_le_bytes(timestamp);
     base64_engine::STANDARD.encode(timestamp_bytes)
 }
 
+/// Write the XML document of a database.
+///
+/// Items that are kept in hash maps, such as times, custom data and the attachments of entries, are
+/// written in sorted order, so that saving the same database twice writes the same XML.
+/// `attachment_indices` maps the attachment pool to the indices the attachments are written at.
 pub(crate) fn dump(
     db: &Database,
     inner_cipher: &mut dyn Cipher,
+    attachment_indices: &[Option<usize>],
     writer: &mut dyn Write,
 ) -> Result<(), xml::writer::Error> {
     let mut xml_writer = EmitterConfig::new().perform_indent(false).create_writer(writer);
 
-    db.dump_xml(&mut xml_writer, inner_cipher)?;
+    db.dump_xml(
+        &mut xml_writer,
+        &mut DumpContext {
+            inner_cipher,
+            attachment_indices,
+            iso_timestamps: matches!(db.config.version, DatabaseVersion::KDB3(_)),
+        },
+    )
+}
+
+/// State that is passed through the `DumpXml` implementations of a document
+pub(crate) struct DumpContext<'a> {
+    /// Inner stream cipher for encrypting protected values, in document order
+    pub(crate) inner_cipher: &'a mut dyn Cipher,
+
+    /// Index each attachment in the pool is written at, as entries refer to attachments by index
+    pub(crate) attachment_indices: &'a [Option<usize>],
 
-    Ok(())
+    /// Whether timestamps are written as ISO 8601 strings, as KDBX3 readers expect, instead of base64
+    pub(crate) iso_timestamps: bool,
 }
 
 /// A trait that denotes an inner KeePass database object can be stored into an XML database.
@@ -44,7 +72,7 @@ pub(crate) trait DumpXml {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error>;
 
     fn normalize_empty_elements(&self) -> bool {
@@ -56,9 +84,9 @@ impl DumpXml for &chrono::NaiveDateTime {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
-        writer.write(WriterEvent::characters(&format_xml_timestamp(self)))
+        writer.write(WriterEvent::characters(&format_xml_timestamp(self, ctx)))
     }
 }
 
@@ -66,7 +94,7 @@ impl DumpXml for bool {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(if *self { "True" } else { "False" }))
     }
@@ -76,7 +104,7 @@ impl DumpXml for usize {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(&format!("{}", self)))
     }
@@ -86,7 +114,7 @@ impl DumpXml for isize {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(&format!("{}", self)))
     }
@@ -96,7 +124,7 @@ impl DumpXml for &str {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(self))
     }
@@ -110,7 +138,7 @@ impl DumpXml for &String {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::characters(self))
     }
@@ -124,7 +152,7 @@ impl DumpXml for &Uuid {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         let b64 = base64_engine::STANDARD.encode(self.as_bytes());
         writer.write(WriterEvent::Characters(&b64))
@@ -135,7 +163,7 @@ impl DumpXml for &Color {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        _inner_cipher: &mut dyn Cipher,
+        _ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::Characters(&self.to_string()))
     }
@@ -148,11 +176,11 @@ impl<S: AsRef<str>, D: DumpXml> DumpXml for SimpleTag<S, D> {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element(self.0.as_ref()))?;
         if !self.1.normalize_empty_elements() {
-            self.1.dump_xml(writer, inner_cipher)?;
+            self.1.dump_xml(writer, ctx)?;
         }
         writer.write(WriterEvent::end_element())?;
         Ok(())
@@ -163,17 +191,17 @@ impl DumpXml for Database {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("KeePassFile"))?;
 
-        self.meta.dump_xml(writer, inner_cipher)?;
+        self.meta.dump_xml(writer, ctx)?;
 
         writer.write(WriterEvent::start_element("Root"))?;
 
-        self.root.dump_xml(writer, inner_cipher)?;
+        self.root.dump_xml(writer, ctx)?;
 
-        self.deleted_objects.dump_xml(writer, inner_cipher)?;
+        self.deleted_objects.dump_xml(writer, ctx)?;
 
         writer.write(WriterEvent::end_element())?; // Root
 
@@ -187,15 +215,17 @@ impl DumpXml for Times {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("Times"))?;
-        for (time_name, time) in &self.times {
-            SimpleTag(time_name, time).dump_xml(writer, inner_cipher)?;
+        let mut times: Vec<_> = self.times.iter().collect();
+        times.sort();
+        for (time_name, time) in times {
+            SimpleTag(time_name, time).dump_xml(writer, ctx)?;
         }
 
-        SimpleTag("Expires", self.expires).dump_xml(writer, inner_cipher)?;
-        SimpleTag("UsageCount", self.usage_count).dump_xml(writer, inner_cipher)?;
+        SimpleTag("Expires", self.expires).dump_xml(writer, ctx)?;
+        SimpleTag("UsageCount", self.usage_count).dump_xml(writer, ctx)?;
 
         writer.write(WriterEvent::end_element())?;
 
@@ -207,15 +237,17 @@ impl DumpXml for CustomData {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("CustomData"))?;
 
-        for (key, item) in &self.items {
+        let mut items: Vec<_> = self.items.iter().collect();
+        items.sort_by_key(|(key, _)| *key);
+        for (key, item) in items {
             writer.write(WriterEvent::start_element("Item"))?;
 
-            SimpleTag("Key", key).dump_xml(writer, inner_cipher)?;
-            item.dump_xml(writer, inner_cipher)?;
+            SimpleTag("Key", key).dump_xml(writer, ctx)?;
+            item.dump_xml(writer, ctx)?;
 
             writer.write(WriterEvent::end_element())?;
         }
@@ -230,14 +262,14 @@ impl DumpXml for CustomDataItem {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         if let Some(ref value) = self.value {
-            value.dump_xml(writer, inner_cipher)?;
+            value.dump_xml(writer, ctx)?;
         }
 
         if let Some(ref value) = self.last_modification_time {
-            SimpleTag("LastModificationTime", value).dump_xml(writer, inner_cipher)?;
+            SimpleTag("LastModificationTime", value).dump_xml(writer, ctx)?;
         }
 
         Ok(())
@@ -248,12 +280,12 @@ impl DumpXml for DeletedObjects {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("DeletedObjects"))?;
 
         for object in &self.objects {
-            object.dump_xml(writer, inner_cipher)?;
+            object.dump_xml(writer, ctx)?;
         }
 
         writer.write(WriterEvent::end_element())?;
@@ -265,12 +297,37 @@ impl DumpXml for DeletedObject {
     fn dump_xml<E: std::io::Write>(
         &self,
         writer: &mut EventWriter<E>,
-        inner_cipher: &mut dyn Cipher,
+        ctx: &mut DumpContext<'_>,
     ) -> Result<(), xml::writer::Error> {
         writer.write(WriterEvent::start_element("DeletedObject"))?;
 
-        SimpleTag("UUID", &self.uuid).dump_xml(writer, inner_cipher)?;
-        SimpleTag("DeletionTime", &self.deletion_time).dump_xml(writer, inner_cipher)?;
+        SimpleTag("UUID", &self.uuid).dump_xml(writer, ctx)?;
+        SimpleTag("DeletionTime", &self.deletion_time).dump_xml(writer, ctx)?;
+
+        writer.write(WriterEvent::end_element())?;
+        Ok(())
+    }
+}
+
+impl DumpXml for UnknownElement {
+    fn dump_xml<E: std::io::Write>(
+        &self,
+        writer: &mut EventWriter<E>,
+        ctx: &mut DumpContext<'_>,
+    ) -> Result<(), xml::writer::Error> {
+        let mut start = WriterEvent::start_element(self.name.as_str());
+        for (name, value) in &self.attributes {
+            start = start.attr(name.as_str(), value);
+        }
+        writer.write(start)?;
+
+        if let Some(ref text) = self.text {
+            writer.write(WriterEvent::characters(text))?;
+        }
+
+        for child in &self.children {
+            child.dump_xml(writer, ctx)?;
+        }
 
         writer.write(WriterEvent::end_element())?;
         Ok(())
@@ -17,16 +17,16 @@ mod tests {
     use uuid::uuid;
 
     use crate::{
-        config::{DatabaseConfig, InnerCipherConfig},
+        config::{BuiltinKdf, DatabaseConfig, InnerCipherConfig, OpenOptions},
         db::{
             entry::History,
-            meta::{BinaryAttachments, CustomIcons, Icon, MemoryProtection},
+            meta::{BinaryAttachments, CustomIcons, Icon, MemoryProtection, MergeRecord},
             AutoType, AutoTypeAssociation, BinaryAttachment, CustomData, CustomDataItem, Database,
-            DeletedObject, Entry, Group, Meta, Node, Times, Value,
+            DeletedObject, Entry, Group, Meta, Node, Times, UnknownElement, Value,
         },
-        format::kdbx4,
+        format::{kdbx4, SaveRng},
         key::DatabaseKey,
-        xml_db::dump::DumpXml,
+        xml_db::dump::{DumpContext, DumpXml},
     };
 
     fn make_key() -> DatabaseKey {
@@ -56,6 +56,8 @@ mod tests {
             "Password".to_string(),
             Value::Protected(std::str::from_utf8(b"klmno").unwrap().into()),
         );
+        entry.attachments.insert("key.pem".to_string(), 0);
+        entry.attachments.insert("notes.txt".to_string(), 3);
         entry.tags.push("test".to_string());
         entry.tags.push("keepass-rs".to_string());
         entry.times.expires = true;
@@ -111,8 +113,9 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let decrypted_db =
+            kdbx4::parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 1);
 
@@ -124,12 +127,87 @@ mod tests {
         assert_eq!(decrypted_entry, &entry);
     }
 
+    #[test]
+    pub fn test_entry_colors() {
+        let mut entry = Entry::new();
+        entry.set_foreground_color(Some("#FF0000".parse().unwrap()));
+        entry.background_color_text = Some("not-a-color".to_string());
+        entry.set_override_url("cmd://firefox {URL}");
+
+        let mut db = Database::new(DatabaseConfig::default());
+        db.root.add_child(entry.clone());
+
+        let db_key = make_key();
+
+        let mut encrypted_db = Vec::new();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let decrypted_db =
+            kdbx4::parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+
+        let decrypted_entry = match &decrypted_db.root.children[0] {
+            Node::Entry(e) => e,
+            Node::Group(_) => panic!("Was expecting an entry as the only child."),
+        };
+
+        assert_eq!(
+            decrypted_entry.get_foreground_color().map(|c| c.to_string()),
+            Some("#ff0000".to_string())
+        );
+        assert_eq!(decrypted_entry.get_background_color(), None);
+        assert_eq!(
+            decrypted_entry.background_color_text.as_deref(),
+            Some("not-a-color")
+        );
+        assert_eq!(decrypted_entry.get_override_url(), Some("cmd://firefox {URL}"));
+        assert_eq!(decrypted_entry, &entry);
+    }
+
+    #[test]
+    pub fn test_entry_colors_keep_case() {
+        let xml = concat!(
+            "<KeePassFile><Root><Group><UUID>AAECAwQFBgcICQoLDA0ODw==</UUID><Name>Root</Name>",
+            "<Entry><UUID>EBESExQVFhcYGRobHB0eHw==</UUID>",
+            "<ForegroundColor>#FF0000</ForegroundColor><BackgroundColor>#00ff7f</BackgroundColor></Entry>",
+            "<Entry><UUID>ICEiIyQlJicoKSorLC0uLw==</UUID>",
+            "<ForegroundColor>#C0FFEE</ForegroundColor><BackgroundColor>#C0FFEE</BackgroundColor></Entry>",
+            "</Group></Root></KeePassFile>",
+        );
+
+        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+        let content =
+            crate::xml_db::parse::parse(xml.as_bytes(), &mut *inner_cipher, &OpenOptions::default()).unwrap();
+
+        let mut db = Database::new(DatabaseConfig::default());
+        db.root = content.root.group;
+
+        // a changed color is written in the format of `Color`
+        if let Node::Entry(entry) = &mut db.root.children[1] {
+            entry.set_background_color(Some("#1C1357".parse().unwrap()));
+        }
+
+        let mut dumped = Vec::new();
+        crate::xml_db::dump::dump(&db, &mut *inner_cipher, &[], &mut dumped).unwrap();
+        let dumped = String::from_utf8(dumped).unwrap();
+
+        for expected in [
+            "<ForegroundColor>#FF0000</ForegroundColor><BackgroundColor>#00ff7f</BackgroundColor>",
+            "<ForegroundColor>#C0FFEE</ForegroundColor><BackgroundColor>#1c1357</BackgroundColor>",
+        ] {
+            assert!(dumped.contains(expected), "{} missing from {}", expected, dumped);
+        }
+    }
+
     #[test]
     pub fn test_group() {
         let group = Group::new("");
         let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
         let mut writer = xml::EventWriter::new(Vec::new());
-        group.dump_xml(&mut writer, &mut *inner_cipher).unwrap();
+        let mut ctx = DumpContext {
+            inner_cipher: &mut *inner_cipher,
+            attachment_indices: &[],
+            iso_timestamps: false,
+        };
+        group.dump_xml(&mut writer, &mut ctx).unwrap();
         let xml = writer.into_inner();
         assert!(String::from_utf8(xml).unwrap().contains("<Name />"));
 
@@ -177,8 +255,9 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let decrypted_db =
+            kdbx4::parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db.root.children.len(), 2);
 
@@ -239,16 +318,19 @@ mod tests {
                         identifier: Some("1".to_string()),
                         compressed: false,
                         content: b"i am binary data".to_vec(),
+                        ..Default::default()
                     },
                     BinaryAttachment {
                         identifier: Some("2".to_string()),
                         compressed: true,
                         content: b"i am compressed binary data".to_vec(),
+                        ..Default::default()
                     },
                     BinaryAttachment {
                         identifier: None,
                         compressed: true,
                         content: b"i am compressed binary data without an identifier".to_vec(),
+                        ..Default::default()
                     },
                 ],
             },
@@ -277,6 +359,34 @@ mod tests {
                     ),
                 ]),
             },
+            unknown_elements: vec![UnknownElement {
+                name: "PluginSettings".to_string(),
+                attributes: vec![("Version".to_string(), "2".to_string())],
+                text: None,
+                children: vec![UnknownElement {
+                    name: "Theme".to_string(),
+                    text: Some("dark".to_string()),
+                    ..Default::default()
+                }],
+            }],
+            merge_history: vec![
+                MergeRecord {
+                    merged_at: "2000-12-31T12:35:04".parse().unwrap(),
+                    remote_db_uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d9"),
+                    added_entry_uuids: vec![uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d700")],
+                    deleted_entry_uuids: vec![],
+                    modified_entry_uuids: vec![
+                        uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d701"),
+                        uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d702"),
+                    ],
+                },
+                MergeRecord {
+                    merged_at: "2000-12-31T12:35:05".parse().unwrap(),
+                    remote_db_uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d9"),
+                    ..Default::default()
+                },
+            ],
+            replica_id: Some(uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7da")),
         };
 
         db.meta = meta.clone();
@@ -284,8 +394,9 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let decrypted_db =
+            kdbx4::parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db.meta, meta);
     }
@@ -307,9 +418,47 @@ mod tests {
         let db_key = make_key();
 
         let mut encrypted_db = Vec::new();
-        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db).unwrap();
-        let decrypted_db = kdbx4::parse_kdbx4(&encrypted_db, &db_key).unwrap();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let decrypted_db =
+            kdbx4::parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
 
         assert_eq!(decrypted_db, db);
     }
+
+    #[test]
+    fn test_unknown_elements() {
+        let xml = include_bytes!("../../tests/resources/inner_xml_with_unknown_elements.xml");
+
+        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+        let content =
+            crate::xml_db::parse::parse(&xml[..], &mut *inner_cipher, &OpenOptions::default()).unwrap();
+
+        let mut db = Database::new(DatabaseConfig::default());
+        db.meta = content.meta;
+        db.root = content.root.group;
+
+        let mut dumped = Vec::new();
+        crate::xml_db::dump::dump(&db, &mut *inner_cipher, &[], &mut dumped).unwrap();
+        let dumped = String::from_utf8(dumped).unwrap();
+
+        for expected in [
+            "<KeeTheme Enabled=\"True\" Version=\"1.2\"><Colors><Background>#202020</Background><Foreground>#E0E0E0</Foreground></Colors></KeeTheme></Meta>",
+            "<KeePassRPCGroupSettings>HideFromBrowser</KeePassRPCGroupSettings>",
+            "<KeePassRPC Priority=\"1\"><FormField Id=\"username\" Type=\"text\" /></KeePassRPC>",
+            "<OldPluginField>kept in history</OldPluginField>",
+            "<NestedPluginTag />",
+        ] {
+            assert!(dumped.contains(expected), "{} missing from {}", expected, dumped);
+        }
+
+        // unknown elements survive a full encrypted round trip as well
+        let db_key = make_key();
+        let mut encrypted_db = Vec::new();
+        kdbx4::dump_kdbx4(&db, &db_key, &mut encrypted_db, &mut SaveRng::System, &mut |_| {}).unwrap();
+        let decrypted_db =
+            kdbx4::parse_kdbx4(&encrypted_db, &db_key, &OpenOptions::default(), &BuiltinKdf).unwrap();
+
+        assert_eq!(decrypted_db.meta, db.meta);
+        assert_eq!(decrypted_db.root, db.root);
+    }
 }
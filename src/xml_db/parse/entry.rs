@@ -1,21 +1,22 @@
-use std::iter::Peekable;
-
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use secstr::SecStr;
 use uuid::Uuid;
+use zeroize::Zeroize;
 
 use crate::{
-    crypt::ciphers::Cipher,
-    db::{AutoType, AutoTypeAssociation, Color, Entry, History, Times, Value},
-    xml_db::parse::{bad_event, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
+    db::{AutoType, AutoTypeAssociation, Color, Entry, History, Times, UnknownElement, Value},
+    xml_db::parse::{
+        bad_event, CustomData, FromXml, IgnoreSubfield, ParseContext, SimpleEventReader, SimpleTag,
+        SimpleXmlEvent, XmlParseError,
+    },
 };
 
 impl FromXml for Entry {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Entry") {
@@ -28,59 +29,70 @@ impl FromXml for Entry {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "UUID" => {
-                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
+                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, ctx)?.value;
                     }
                     "Tags" => {
-                        if let Some(tags) = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value
-                        {
+                        if let Some(tags) = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value {
                             out.tags = tags.split([';', ',']).map(|x| x.to_owned()).collect();
                         }
                     }
                     "String" => {
-                        let field = StringField::from_xml(iterator, inner_cipher)?;
+                        let field = StringField::from_xml(iterator, ctx)?;
                         if let Some(value) = field.value {
                             out.fields.insert(field.key, value);
                         }
                     }
                     "CustomData" => {
-                        out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
+                        out.custom_data = CustomData::from_xml(iterator, ctx)?;
                     }
                     "Binary" => {
-                        let _field = BinaryField::from_xml(iterator, inner_cipher)?;
-                        // TODO reference into a binary field from the Meta. Might only appear in
-                        // kdbx3
+                        let field = BinaryField::from_xml(iterator, ctx)?;
+                        // a reference that is not an attachment ID cannot be resolved, and is left
+                        // out unless parsing strictly
+                        match field.identifier.parse() {
+                            Ok(index) => {
+                                out.attachments.insert(field.key, index);
+                            }
+                            Err(e) if ctx.options.strict => return Err(e.into()),
+                            Err(_) => {}
+                        }
                     }
                     "AutoType" => {
-                        out.autotype = Some(AutoType::from_xml(iterator, inner_cipher)?);
+                        out.autotype = Some(AutoType::from_xml(iterator, ctx)?);
                     }
                     "Times" => {
-                        out.times = Times::from_xml(iterator, inner_cipher)?;
+                        out.times = Times::from_xml(iterator, ctx)?;
                     }
                     "IconID" => {
-                        out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, ctx)?.value;
                     }
                     "CustomIconUUID" => {
-                        out.custom_icon_uuid =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.custom_icon_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "ForegroundColor" => {
-                        out.foreground_color =
-                            SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
+                        let value = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
+                        (out.foreground_color, out.foreground_color_text) = parse_color(value);
                     }
                     "BackgroundColor" => {
-                        out.background_color =
-                            SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
+                        let value = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
+                        (out.background_color, out.background_color_text) = parse_color(value);
                     }
                     "OverrideURL" => {
-                        out.override_url = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.override_url = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "QualityCheck" => {
-                        out.quality_check = SimpleTag::<Option<bool>>::from_xml(iterator, inner_cipher)?.value;
+                        out.quality_check = SimpleTag::<Option<bool>>::from_xml(iterator, ctx)?.value;
+                    }
+                    "History" if ctx.options.skip_history => {
+                        SkippedHistory::from_xml(iterator, ctx)?;
                     }
                     "History" => {
-                        out.history = Some(History::from_xml(iterator, inner_cipher)?);
+                        out.history = Some(History::from_xml(iterator, ctx)?);
+                    }
+                    _ => {
+                        out.unknown_elements
+                            .push(UnknownElement::from_xml(iterator, ctx)?);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Entry" => break,
                 _ => return Err(bad_event("start tag or close entry", event.clone())),
@@ -94,6 +106,19 @@ impl FromXml for Entry {
     }
 }
 
+/// Parse an entry color, keeping the original text if it is not a valid `#RRGGBB` value or is formatted
+/// differently, e.g. in uppercase, so that it can be written back unchanged.
+fn parse_color(value: Option<String>) -> (Option<Color>, Option<String>) {
+    match value {
+        Some(text) => match text.parse::<Color>() {
+            Ok(color) if color.to_string() == text => (Some(color), None),
+            Ok(color) => (Some(color), Some(text)),
+            Err(_) => (None, Some(text)),
+        },
+        None => (None, None),
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct StringField {
     key: String,
@@ -103,9 +128,9 @@ pub(crate) struct StringField {
 impl FromXml for StringField {
     type Parses = StringField;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "String") {
@@ -118,15 +143,15 @@ impl FromXml for StringField {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Key" => {
-                        out.key = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
+                        out.key = SimpleTag::<String>::from_xml(iterator, ctx)?.value;
                     }
                     "Value" => {
-                        let value = Value::from_xml(iterator, inner_cipher)?;
+                        let value = Value::from_xml(iterator, ctx)?;
                         if !value.is_empty() {
                             out.value = Some(value)
                         }
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "String" => break,
                 _ => return Err(bad_event("start tag or close String", event.clone())),
@@ -141,7 +166,6 @@ impl FromXml for StringField {
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub(crate) struct BinaryField {
     pub key: String,
     pub identifier: String,
@@ -150,16 +174,16 @@ pub(crate) struct BinaryField {
 impl FromXml for BinaryField {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Binary") {
             return Err(bad_event("Open Binary tag", open_tag));
         }
 
-        let key = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
+        let key = SimpleTag::<String>::from_xml(iterator, ctx)?.value;
 
         let value_event = iterator.next().ok_or(XmlParseError::Eof)?;
         let identifier = match value_event {
@@ -185,9 +209,9 @@ impl FromXml for BinaryField {
 impl FromXml for Value {
     type Parses = Value;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
 
@@ -198,13 +222,31 @@ impl FromXml for Value {
                     .map(|v| v.to_lowercase().parse::<bool>())
                     .unwrap_or(Ok(false))?;
 
-                let content = Option::<String>::from_xml(iterator, inner_cipher)?.unwrap_or(String::new());
+                let content = Option::<String>::from_xml(iterator, ctx)?.unwrap_or(String::new());
 
-                let value = if protected {
+                let value = if protected && ctx.desynced {
+                    // the keystream is out of sync, so decrypting would only give garbage. The
+                    // ciphertext is kept as a placeholder until `salvage` strips the value.
+                    Value::Protected(SecStr::new(base64_engine::STANDARD.decode(&content)?))
+                } else if protected {
                     let buf = base64_engine::STANDARD.decode(&content)?;
-                    let buf_decrypted = inner_cipher.decrypt(&buf)?;
-                    let value = String::from_utf8_lossy(&buf_decrypted).to_string();
-                    Value::Protected(SecStr::from(value))
+                    let deferred = if ctx.options.defer_protected_values {
+                        ctx.inner_cipher.defer(&buf)
+                    } else {
+                        None
+                    };
+                    match deferred {
+                        Some(deferred) => Value::Deferred(deferred),
+                        None => {
+                            // decrypt in the memory of the SecStr, so that there is no other copy of
+                            // the plaintext
+                            let mut secret = SecStr::new(buf);
+                            ctx.inner_cipher.decrypt_in_place(secret.unsecure_mut())?;
+                            std::str::from_utf8(secret.unsecure())
+                                .map_err(XmlParseError::ProtectedValueUtf8)?;
+                            Value::Protected(secret)
+                        }
+                    }
                 } else {
                     Value::Unprotected(content)
                 };
@@ -224,9 +266,9 @@ impl FromXml for Value {
 impl FromXml for AutoType {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "AutoType") {
@@ -239,20 +281,20 @@ impl FromXml for AutoType {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Enabled" => {
-                        out.enabled = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.enabled = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "DefaultSequence" => {
-                        out.sequence = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.sequence = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "DataTransferObfuscation" => {
-                        let _value = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        let _value = SimpleTag::<Option<usize>>::from_xml(iterator, ctx)?.value;
                         // TODO probably not needed?
                     }
                     "Association" => {
-                        let ata = AutoTypeAssociation::from_xml(iterator, inner_cipher)?;
+                        let ata = AutoTypeAssociation::from_xml(iterator, ctx)?;
                         out.associations.push(ata);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "AutoType" => break,
                 _ => return Err(bad_event("start tag or close AutoType", event.clone())),
@@ -269,9 +311,9 @@ impl FromXml for AutoType {
 impl FromXml for AutoTypeAssociation {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Association") {
@@ -284,12 +326,12 @@ impl FromXml for AutoTypeAssociation {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Window" => {
-                        out.window = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.window = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "KeystrokeSequence" => {
-                        out.sequence = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.sequence = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Association" => break,
                 _ => return Err(bad_event("start tag or close Association", event.clone())),
@@ -303,12 +345,63 @@ impl FromXml for AutoTypeAssociation {
     }
 }
 
+/// A `History` element that is skipped without keeping its entries, see `OpenOptions::skip_history`.
+///
+/// Protected values are still decrypted and dropped, since all protected values of the document
+/// share the position in the inner stream cipher.
+struct SkippedHistory;
+
+impl FromXml for SkippedHistory {
+    type Parses = ();
+
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
+    ) -> Result<Self::Parses, XmlParseError> {
+        let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
+        if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "History") {
+            return Err(bad_event("Open History tag", open_tag));
+        }
+
+        let mut depth = 0usize;
+        let mut in_protected_value = false;
+        for event in iterator.by_ref() {
+            match event {
+                SimpleXmlEvent::Start(tag, attributes) => {
+                    depth += 1;
+                    in_protected_value = tag == "Value"
+                        && attributes
+                            .get("Protected")
+                            .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+                }
+                SimpleXmlEvent::Characters(content) if in_protected_value => {
+                    let mut buf = base64_engine::STANDARD.decode(&content)?;
+                    ctx.inner_cipher.decrypt_in_place(&mut buf)?;
+                    buf.zeroize();
+                }
+                SimpleXmlEvent::Characters(_) => {}
+                SimpleXmlEvent::End(_) => {
+                    in_protected_value = false;
+                    // matching closing tags are ensured by XmlReader
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                SimpleXmlEvent::Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl FromXml for History {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "History") {
@@ -321,10 +414,10 @@ impl FromXml for History {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Entry" => {
-                        let entry = Entry::from_xml(iterator, inner_cipher)?;
+                        let entry = Entry::from_xml(iterator, ctx)?;
                         entries.push(entry);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "History" => break,
                 _ => return Err(bad_event("start tag or close History", event.clone())),
@@ -1,16 +1,18 @@
 use uuid::Uuid;
 
 use crate::{
-    db::{CustomData, Entry, Group, Times},
-    xml_db::parse::{bad_event, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
+    db::{CustomData, Entry, Group, Times, UnknownElement},
+    xml_db::parse::{
+        bad_event, FromXml, ParseContext, SimpleEventReader, SimpleTag, SimpleXmlEvent, XmlParseError,
+    },
 };
 
 impl FromXml for Group {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = super::SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, super::XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Group") {
@@ -23,57 +25,56 @@ impl FromXml for Group {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "UUID" => {
-                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
+                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, ctx)?.value;
                     }
                     "Name" => {
-                        out.name = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?
+                        out.name = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?
                             .value
                             .unwrap_or_default();
                     }
                     "Notes" => {
-                        out.notes = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.notes = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "IconID" => {
-                        out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        out.icon_id = SimpleTag::<Option<usize>>::from_xml(iterator, ctx)?.value;
                     }
                     "CustomIconUUID" => {
-                        out.custom_icon_uuid =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.custom_icon_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "Times" => {
-                        out.times = Times::from_xml(iterator, inner_cipher)?;
+                        out.times = Times::from_xml(iterator, ctx)?;
                     }
                     "IsExpanded" => {
-                        out.is_expanded = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.is_expanded = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "DefaultAutoTypeSequence" => {
                         out.default_autotype_sequence =
-                            SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "EnableAutoType" => {
-                        out.enable_autotype =
-                            SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.enable_autotype = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "EnableSearching" => {
-                        out.enable_searching =
-                            SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.enable_searching = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "LastTopVisibleEntry" => {
-                        out.last_top_visible_entry =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.last_top_visible_entry = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "Entry" => {
-                        let entry = Entry::from_xml(iterator, inner_cipher)?;
-                        out.add_child(entry);
+                        let entry = Entry::from_xml(iterator, ctx)?;
+                        out.push_child(entry);
                     }
                     "Group" => {
-                        let group = Group::from_xml(iterator, inner_cipher)?;
-                        out.add_child(group);
+                        let group = Group::from_xml(iterator, ctx)?;
+                        out.push_child(group);
                     }
                     "CustomData" => {
-                        out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
+                        out.custom_data = CustomData::from_xml(iterator, ctx)?;
+                    }
+                    _ => {
+                        out.unknown_elements
+                            .push(UnknownElement::from_xml(iterator, ctx)?);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Group" => break,
                 _ => return Err(bad_event("start tag or close Group", event.clone())),
@@ -1,22 +1,30 @@
 use base64::{engine::general_purpose as base64_engine, Engine as _};
+use byteorder::{ByteOrder, LittleEndian};
 use chrono::NaiveDateTime;
 use uuid::Uuid;
 
 use crate::{
     compression::{Compression, GZipCompression},
+    config::ParseLimit,
     db::{
-        meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-        Color,
+        meta::{
+            BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, MergeRecord, Meta,
+            MERGE_HISTORY_KEY, REPLICA_ID_KEY,
+        },
+        Color, DeferredBinary, UnknownElement,
+    },
+    xml_db::parse::{
+        bad_event, CustomData, FromXml, IgnoreSubfield, ParseContext, SimpleEventReader, SimpleTag,
+        SimpleXmlEvent, XmlParseError,
     },
-    xml_db::parse::{bad_event, CustomData, FromXml, IgnoreSubfield, SimpleTag, SimpleXmlEvent, XmlParseError},
 };
 
 impl FromXml for Meta {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = crate::xml_db::parse::SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, crate::xml_db::parse::XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Meta") {
@@ -29,106 +37,98 @@ impl FromXml for Meta {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Generator" => {
-                        out.generator = SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.generator = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "DatabaseName" => {
-                        out.database_name =
-                            SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.database_name = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "DatabaseNameChanged" => {
                         out.database_name_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "DatabaseDescription" => {
-                        out.database_description =
-                            SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.database_description = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "DatabaseDescriptionChanged" => {
                         out.database_description_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "DefaultUserName" => {
-                        out.default_username =
-                            SimpleTag::<Option<String>>::from_xml(iterator, inner_cipher)?.value;
+                        out.default_username = SimpleTag::<Option<String>>::from_xml(iterator, ctx)?.value;
                     }
                     "DefaultUserNameChanged" => {
                         out.default_username_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "MaintenanceHistoryDays" => {
                         out.maintenance_history_days =
-                            SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<isize>>::from_xml(iterator, ctx)?.value;
                     }
                     "Color" => {
-                        out.color = SimpleTag::<Option<Color>>::from_xml(iterator, inner_cipher)?.value;
+                        out.color = SimpleTag::<Option<Color>>::from_xml(iterator, ctx)?.value;
                     }
                     "MasterKeyChanged" => {
                         out.master_key_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "MasterKeyChangeRec" => {
-                        out.master_key_change_rec =
-                            SimpleTag::<Option<isize>>::from_xml(iterator, inner_cipher)?.value;
+                        out.master_key_change_rec = SimpleTag::<Option<isize>>::from_xml(iterator, ctx)?.value;
                     }
                     "MasterKeyChangeForce" => {
                         out.master_key_change_force =
-                            SimpleTag::<Option<isize>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<isize>>::from_xml(iterator, ctx)?.value;
                     }
                     "MemoryProtection" => {
-                        out.memory_protection = Some(MemoryProtection::from_xml(iterator, inner_cipher)?);
+                        out.memory_protection = Some(MemoryProtection::from_xml(iterator, ctx)?);
                     }
                     "CustomIcons" => {
-                        out.custom_icons = CustomIcons::from_xml(iterator, inner_cipher)?;
+                        out.custom_icons = CustomIcons::from_xml(iterator, ctx)?;
                     }
                     "RecycleBinEnabled" => {
-                        out.recyclebin_enabled =
-                            SimpleTag::<Option<bool>>::from_xml(iterator, inner_cipher)?.value;
+                        out.recyclebin_enabled = SimpleTag::<Option<bool>>::from_xml(iterator, ctx)?.value;
                     }
                     "RecycleBinUUID" => {
-                        out.recyclebin_uuid =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.recyclebin_uuid = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "RecycleBinChanged" => {
                         out.recyclebin_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "EntryTemplatesGroup" => {
-                        out.entry_templates_group =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.entry_templates_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "EntryTemplatesGroupChanged" => {
                         out.entry_templates_group_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "LastSelectedGroup" => {
-                        out.last_selected_group =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.last_selected_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "LastTopVisibleGroup" => {
-                        out.last_top_visible_group =
-                            SimpleTag::<Option<Uuid>>::from_xml(iterator, inner_cipher)?.value;
+                        out.last_top_visible_group = SimpleTag::<Option<Uuid>>::from_xml(iterator, ctx)?.value;
                     }
                     "HistoryMaxItems" => {
-                        out.history_max_items =
-                            SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        out.history_max_items = SimpleTag::<Option<isize>>::from_xml(iterator, ctx)?.value;
                     }
                     "HistoryMaxSize" => {
-                        out.history_max_size =
-                            SimpleTag::<Option<usize>>::from_xml(iterator, inner_cipher)?.value;
+                        out.history_max_size = SimpleTag::<Option<isize>>::from_xml(iterator, ctx)?.value;
                     }
                     "SettingsChanged" => {
                         out.settings_changed =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     "Binaries" => {
-                        out.binaries = BinaryAttachments::from_xml(iterator, inner_cipher)?;
+                        out.binaries = BinaryAttachments::from_xml(iterator, ctx)?;
                         // TODO figure out where this is needed. Is it only in KDBX3? How to
                         // migrate to KDBX4?
                     }
                     "CustomData" => {
-                        out.custom_data = CustomData::from_xml(iterator, inner_cipher)?;
+                        out.custom_data = CustomData::from_xml(iterator, ctx)?;
+                    }
+                    _ => {
+                        out.unknown_elements
+                            .push(UnknownElement::from_xml(iterator, ctx)?);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Meta" => break,
                 _ => return Err(bad_event("start tag or close Meta", event.clone())),
@@ -138,6 +138,24 @@ impl FromXml for Meta {
         // no need to check for the correct closing tag - checked by XmlReader
         let _close_tag = iterator.next().ok_or(XmlParseError::Eof)?;
 
+        // a merge history that cannot be read is kept as a custom data item
+        if let Some(history) = out
+            .custom_data
+            .get(MERGE_HISTORY_KEY)
+            .and_then(MergeRecord::parse_history)
+        {
+            out.merge_history = history;
+            out.custom_data.remove(MERGE_HISTORY_KEY);
+        }
+        if let Some(replica_id) = out
+            .custom_data
+            .get(REPLICA_ID_KEY)
+            .and_then(|id| Uuid::parse_str(id).ok())
+        {
+            out.replica_id = Some(replica_id);
+            out.custom_data.remove(REPLICA_ID_KEY);
+        }
+
         Ok(out)
     }
 }
@@ -145,9 +163,9 @@ impl FromXml for Meta {
 impl FromXml for MemoryProtection {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "MemoryProtection") {
@@ -160,21 +178,21 @@ impl FromXml for MemoryProtection {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "ProtectTitle" => {
-                        out.protect_title = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.protect_title = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "ProtectUserName" => {
-                        out.protect_username = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.protect_username = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "ProtectPassword" => {
-                        out.protect_password = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.protect_password = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "ProtectURL" => {
-                        out.protect_url = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.protect_url = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "ProtectNotes" => {
-                        out.protect_notes = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.protect_notes = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "MemoryProtection" => break,
                 _ => return Err(bad_event("start tag or close MemoryProtection", event.clone())),
@@ -191,9 +209,9 @@ impl FromXml for MemoryProtection {
 impl FromXml for BinaryAttachments {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Binaries") {
@@ -202,14 +220,24 @@ impl FromXml for BinaryAttachments {
 
         let mut out = Self::default();
 
+        let max_total_size = ctx.options.limits.max_total_binary_size;
+        let mut total_size: u64 = 0;
+
         while let Some(event) = iterator.peek() {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Binary" => {
-                        let binary = BinaryAttachment::from_xml(iterator, inner_cipher)?;
+                        let binary = BinaryAttachment::from_xml(iterator, ctx)?;
+                        total_size += binary
+                            .deferred
+                            .as_ref()
+                            .map_or(binary.content.len() as u64, DeferredBinary::size);
+                        if total_size > max_total_size {
+                            return Err(XmlParseError::LimitExceeded(ParseLimit::TotalBinarySize));
+                        }
                         out.binaries.push(binary);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Binaries" => break,
                 _ => return Err(bad_event("start tag or close Binaries", event.clone())),
@@ -226,9 +254,9 @@ impl FromXml for BinaryAttachments {
 impl FromXml for BinaryAttachment {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
 
@@ -250,16 +278,32 @@ impl FromXml for BinaryAttachment {
             return Err(bad_event("Open Binary tag", open_tag));
         };
 
-        let data = String::from_xml(iterator, inner_cipher)?;
+        let data = String::from_xml(iterator, ctx)?;
         let buf = base64_engine::STANDARD.decode(&data)?;
 
         out.identifier = identifier;
         out.compressed = compressed;
-        out.content = if compressed {
-            Compression::decompress(&GZipCompression, &buf).map_err(XmlParseError::Compression)?
+        let max_size = ctx.options.limits.max_binary_size;
+        if compressed && ctx.options.defer_binaries {
+            // a GZip stream ends with the size of the decompressed data modulo 2^32
+            let size = buf
+                .len()
+                .checked_sub(4)
+                .map_or(0, |start| u64::from(LittleEndian::read_u32(&buf[start..])));
+            if size > max_size {
+                return Err(XmlParseError::LimitExceeded(ParseLimit::BinarySize));
+            }
+            out.deferred = Some(DeferredBinary::compressed(buf, size, max_size));
         } else {
-            buf
-        };
+            out.content = if compressed {
+                Compression::decompress(&GZipCompression, &buf, max_size).map_err(XmlParseError::Compression)?
+            } else {
+                buf
+            };
+            if out.content.len() as u64 > max_size {
+                return Err(XmlParseError::LimitExceeded(ParseLimit::BinarySize));
+            }
+        }
 
         // no need to check for the correct closing tag - checked by XmlReader
         let _close_tag = iterator.next().ok_or(XmlParseError::Eof)?;
@@ -271,9 +315,9 @@ impl FromXml for BinaryAttachment {
 impl FromXml for CustomIcons {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "CustomIcons") {
@@ -286,10 +330,10 @@ impl FromXml for CustomIcons {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Icon" => {
-                        let icon = Icon::from_xml(iterator, inner_cipher)?;
+                        let icon = Icon::from_xml(iterator, ctx)?;
                         out.icons.push(icon);
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "CustomIcons" => break,
                 _ => return Err(bad_event("start tag or close CustomIcons", event.clone())),
@@ -306,9 +350,9 @@ impl FromXml for CustomIcons {
 impl FromXml for Icon {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Icon") {
@@ -321,14 +365,14 @@ impl FromXml for Icon {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "UUID" => {
-                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
+                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, ctx)?.value;
                     }
                     "Data" => {
-                        let data = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
+                        let data = SimpleTag::<String>::from_xml(iterator, ctx)?.value;
                         let buf = base64_engine::STANDARD.decode(&data)?;
                         out.data = buf;
                     }
-                    _ => IgnoreSubfield::from_xml(iterator, inner_cipher)?,
+                    _ => IgnoreSubfield::from_xml(iterator, ctx)?,
                 },
                 SimpleXmlEvent::End(name) if name == "Icon" => break,
                 _ => return Err(bad_event("start tag or close Icon", event.clone())),
@@ -347,7 +391,10 @@ mod parse_meta_test {
 
     use crate::{
         db::meta::{BinaryAttachment, BinaryAttachments, CustomIcons, Icon, MemoryProtection, Meta},
-        xml_db::parse::{parse_test::parse_test_xml, XmlParseError},
+        xml_db::parse::{
+            parse_test::{parse_test_xml, parse_test_xml_strict},
+            XmlParseError,
+        },
     };
 
     use uuid::{uuid, Uuid};
@@ -423,9 +470,12 @@ mod parse_meta_test {
         let value = parse_test_xml::<BinaryAttachment>("<Binary></TestTag>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
-        let value = parse_test_xml::<BinaryAttachment>("<Binary></Binary>");
+        let value = parse_test_xml_strict::<BinaryAttachment>("<Binary></Binary>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
+        let value = parse_test_xml::<BinaryAttachment>("<Binary></Binary>")?;
+        assert!(value.content.is_empty());
+
         let value = parse_test_xml::<BinaryAttachment>("<Binary><UnkownChildTag/></Binary>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
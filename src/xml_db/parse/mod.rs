@@ -1,53 +1,84 @@
 mod entry;
 mod group;
 mod meta;
+mod salvage;
 
-use std::{collections::HashMap, iter::Peekable};
+use std::{cell::RefCell, collections::HashMap, convert::TryInto, rc::Rc};
 
 use base64::{engine::general_purpose as base64_engine, Engine as _};
 use chrono::NaiveDateTime;
 use uuid::Uuid;
-use xml::{name::OwnedName, reader::XmlEvent, EventReader};
+use xml::{
+    common::{Position, TextPosition},
+    name::OwnedName,
+    reader::XmlEvent,
+    EventReader,
+};
 
 use crate::{
+    config::{OpenOptions, ParseLimit, ParseLimits},
     crypt::ciphers::Cipher,
     db::{
         Color, CustomData, CustomDataItem, CustomDataItemDenormalized, DeletedObject, DeletedObjects, Group,
-        Meta, Times, Value,
+        Meta, Times, UnknownElement, Value,
     },
     error::XmlParseError,
     xml_db::get_epoch_baseline,
 };
 
+pub(crate) use salvage::salvage;
+
 /// Parse a KeePass timestamp string
 pub fn parse_xml_timestamp(t: &str) -> Result<chrono::NaiveDateTime, XmlParseError> {
-    match chrono::NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%SZ") {
-        // Prior to KDBX4 file format, timestamps were stored as ISO 8601 strings
-        Ok(ndt) => Ok(ndt),
-        // If we don't have a valid ISO 8601 string, assume we have found a Base64 encoded int.
-        _ => {
-            let v = base64_engine::STANDARD.decode(t)?;
-
-            // Cast the decoded base64 Vec into the array expected by i64::from_le_bytes
-            let mut a: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
-            a.copy_from_slice(&v[0..8]);
-            let ndt = get_epoch_baseline() + chrono::Duration::seconds(i64::from_le_bytes(a));
-            Ok(ndt)
-        }
+    let t = t.trim();
+
+    // Prior to KDBX4 file format, timestamps were stored as ISO 8601 strings. Some clients leave
+    // out the time zone or add fractional seconds or a UTC offset.
+    if let Ok(ndt) = NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%SZ") {
+        return Ok(ndt);
+    }
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(t) {
+        return Ok(dt.naive_utc());
+    }
+    if let Ok(ndt) = NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Ok(ndt);
     }
+
+    // If we don't have a valid ISO 8601 string, assume we have found a Base64 encoded int.
+    let invalid = || XmlParseError::InvalidTimestamp(t.to_string());
+
+    let v = base64_engine::STANDARD.decode(t).map_err(|_| invalid())?;
+    let a: [u8; 8] = v.as_slice().try_into().map_err(|_| invalid())?;
+
+    chrono::Duration::try_seconds(i64::from_le_bytes(a))
+        .and_then(|offset| get_epoch_baseline().checked_add_signed(offset))
+        .ok_or_else(invalid)
+}
+
+/// State that is passed through the `FromXml` parsers of a document
+pub(crate) struct ParseContext<'a> {
+    /// Inner stream cipher for decrypting protected values, in document order
+    pub(crate) inner_cipher: &'a mut dyn Cipher,
+
+    /// Options of the database that is being opened
+    pub(crate) options: &'a OpenOptions,
+
+    /// Whether the inner stream cipher is out of sync after damaged data, see `salvage`. Protected
+    /// values are then read without decrypting them.
+    pub(crate) desynced: bool,
 }
 
 /// Trait that denotes that a KeePass object can be parsed from a stream of `SimpleXmlEvent`.
 ///
 /// The parser implementation should consume everything from the start tag of an object to the end
-/// tag, both inclusive, and use the `Peekable::peek` method to decide when to call into
+/// tag, both inclusive, and use the `SimpleEventReader::peek` method to decide when to call into
 /// sub-parsers.
 pub(crate) trait FromXml {
     type Parses;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError>;
 }
 
@@ -62,47 +93,277 @@ pub enum SimpleXmlEvent {
     Err(xml::reader::Error),
 }
 
+impl std::fmt::Display for SimpleXmlEvent {
+    /// Describe the event without reproducing attribute values or text content in full, so that
+    /// error messages never contain protected values
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimpleXmlEvent::Start(name, _) => write!(f, "start of <{}>", name),
+            SimpleXmlEvent::End(name) => write!(f, "end of <{}>", name),
+            SimpleXmlEvent::Characters(c) => write!(f, "text of length {}", c.chars().count()),
+            SimpleXmlEvent::Err(e) => write!(f, "{}", e),
+        }
+    }
+}
+
 pub(crate) fn bad_event(expected: &'static str, event: SimpleXmlEvent) -> XmlParseError {
     XmlParseError::BadEvent { expected, event }
 }
 
-pub(crate) fn parse(xml: &[u8], inner_cipher: &mut dyn Cipher) -> Result<KeePassXml, XmlParseError> {
-    parse_from_bytes::<KeePassXml>(xml, inner_cipher)
+/// Parse a complete KeePass XML document.
+///
+/// Errors are wrapped in `XmlParseError::Located` to report where in the document parsing failed.
+pub(crate) fn parse(
+    xml: &[u8],
+    inner_cipher: &mut dyn Cipher,
+    options: &OpenOptions,
+) -> Result<KeePassXml, XmlParseError> {
+    let mut reader = SimpleEventReader::new(xml, options.limits);
+    let location = reader.location.clone();
+
+    let mut ctx = ParseContext {
+        inner_cipher,
+        options,
+        desynced: false,
+    };
+    let res = KeePassXml::from_xml(&mut reader, &mut ctx);
+
+    let location = location.borrow();
+    match (res, location.limit_exceeded) {
+        (_, Some(limit)) => Err(location.locate(xml, XmlParseError::LimitExceeded(limit))),
+        (Ok(content), None) => Ok(content),
+        (Err(e), None) => Err(location.locate(xml, e)),
+    }
 }
 
+#[cfg(test)]
 pub(crate) fn parse_from_bytes<P: FromXml>(
     xml: &[u8],
     inner_cipher: &mut dyn Cipher,
+    options: &OpenOptions,
 ) -> Result<<P as FromXml>::Parses, XmlParseError> {
-    let mut reader = EventReader::new(xml)
-        .into_iter()
-        .filter_map(|e| {
+    let mut reader = SimpleEventReader::new(xml, options.limits);
+
+    P::from_xml(
+        &mut reader,
+        &mut ParseContext {
+            inner_cipher,
+            options,
+            desynced: false,
+        },
+    )
+}
+
+/// Position of the last event consumed from the XML document
+#[derive(Debug, Default)]
+struct EventLocation {
+    /// Zero-based line and column of the start of the event
+    row: u64,
+    column: u64,
+
+    /// Open elements, with the number of same-named siblings preceding them
+    path: Vec<(String, usize)>,
+
+    /// Number of children seen so far by name, for each open element and the document itself
+    children: Vec<HashMap<String, usize>>,
+
+    /// Whether the element at the end of `path` was closed by the last event
+    closed: bool,
+
+    /// Number of entries seen so far
+    entries: usize,
+
+    /// The limit that stopped reading the document, if any
+    limit_exceeded: Option<ParseLimit>,
+}
+
+impl EventLocation {
+    fn update(&mut self, event: &SimpleXmlEvent, position: TextPosition) {
+        if self.closed {
+            self.path.pop();
+            self.children.pop();
+            self.closed = false;
+        }
+
+        self.row = position.row;
+        self.column = position.column;
+
+        match event {
+            SimpleXmlEvent::Start(name, _) => {
+                if self.children.is_empty() {
+                    self.children.push(HashMap::new());
+                }
+                let count = self.children.last_mut().unwrap().entry(name.clone()).or_insert(0);
+                let preceding = *count;
+                *count += 1;
+
+                self.path.push((name.clone(), preceding));
+                self.children.push(HashMap::new());
+
+                if name == "Entry" {
+                    self.entries += 1;
+                }
+            }
+            SimpleXmlEvent::End(_) => self.closed = true,
+            _ => {}
+        }
+    }
+
+    /// Element path such as `KeePassFile/Root/Group/Entry[3]/String[2]/Value`, where elements
+    /// that follow siblings of the same name carry their one-based index
+    fn path(&self) -> String {
+        self.path
+            .iter()
+            .map(|(name, preceding)| {
+                if *preceding > 0 {
+                    format!("{}[{}]", name, preceding + 1)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Byte offset of the position in the document
+    fn offset(&self, xml: &[u8]) -> usize {
+        let line_start = xml
+            .split_inclusive(|b| *b == b'\n')
+            .take(self.row as usize)
+            .map(|line| line.len())
+            .sum::<usize>();
+
+        let line = &xml[line_start..];
+        let line = match std::str::from_utf8(line) {
+            Ok(line) => line,
+            Err(e) => std::str::from_utf8(&line[..e.valid_up_to()]).unwrap(),
+        };
+
+        line_start
+            + line
+                .char_indices()
+                .nth(self.column as usize)
+                .map(|(i, _)| i)
+                .unwrap_or(line.len())
+    }
+
+    /// Check the document read so far against the limits
+    fn check_limits(&self, limits: &ParseLimits) -> Option<ParseLimit> {
+        if self.path.len() > limits.max_nesting_depth {
+            Some(ParseLimit::NestingDepth)
+        } else if self.entries > limits.max_entries {
+            Some(ParseLimit::Entries)
+        } else {
+            None
+        }
+    }
+
+    fn locate(&self, xml: &[u8], error: XmlParseError) -> XmlParseError {
+        XmlParseError::Located {
+            offset: self.offset(xml),
+            line: self.row + 1,
+            column: self.column + 1,
+            path: self.path(),
+            source: Box::new(error),
+        }
+    }
+}
+
+/// Iterator over the `SimpleXmlEvent`s of a document with one event of lookahead, which keeps track
+/// of the location of the last consumed event. Peeking at an event does not move the location, so
+/// that errors point at the event that was being parsed. The iterator ends early when the document
+/// exceeds the `ParseLimits`.
+pub(crate) struct SimpleEventReader<'a> {
+    reader: EventReader<&'a [u8]>,
+    location: Rc<RefCell<EventLocation>>,
+    limits: ParseLimits,
+    finished: bool,
+
+    /// The next event and its position, if it was peeked at
+    peeked: Option<Option<(SimpleXmlEvent, TextPosition)>>,
+}
+
+impl<'a> SimpleEventReader<'a> {
+    fn new(xml: &'a [u8], limits: ParseLimits) -> Self {
+        SimpleEventReader {
+            reader: EventReader::new(xml),
+            location: Rc::new(RefCell::new(EventLocation::default())),
+            limits,
+            finished: false,
+            peeked: None,
+        }
+    }
+
+    /// Get the next event without consuming it
+    pub(crate) fn peek(&mut self) -> Option<&SimpleXmlEvent> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read());
+        }
+        self.peeked
+            .as_ref()
+            .and_then(|peeked| peeked.as_ref())
+            .map(|(event, _)| event)
+    }
+
+    /// Read the next event from the document along with its position
+    fn read(&mut self) -> Option<(SimpleXmlEvent, TextPosition)> {
+        while !self.finished {
             // simplify iterator by ignoring unneeded events and flattening the structure
-            match e {
+            let event = match self.reader.next() {
                 Ok(XmlEvent::StartElement {
                     name: OwnedName { local_name, .. },
                     attributes,
                     ..
-                }) => Some(SimpleXmlEvent::Start(
+                }) => SimpleXmlEvent::Start(
                     local_name,
                     attributes
                         .into_iter()
                         .map(|a| (a.name.local_name, a.value))
                         .collect(),
-                )),
+                ),
                 Ok(XmlEvent::EndElement {
                     name: OwnedName { local_name, .. },
-                }) => Some(SimpleXmlEvent::End(local_name)),
-                Ok(XmlEvent::Characters(c)) => Some(SimpleXmlEvent::Characters(c)),
-                Err(e) => Some(SimpleXmlEvent::Err(e)),
+                }) => SimpleXmlEvent::End(local_name),
+                Ok(XmlEvent::Characters(c)) => SimpleXmlEvent::Characters(c),
+                Ok(XmlEvent::EndDocument) => {
+                    self.finished = true;
+                    continue;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    SimpleXmlEvent::Err(e)
+                }
 
                 // ignore whitespace, comments, ...
-                _ => None,
-            }
-        })
-        .peekable();
+                _ => continue,
+            };
+
+            return Some((event, self.reader.position()));
+        }
 
-    P::from_xml(&mut reader, inner_cipher)
+        None
+    }
+}
+
+impl Iterator for SimpleEventReader<'_> {
+    type Item = SimpleXmlEvent;
+
+    fn next(&mut self) -> Option<SimpleXmlEvent> {
+        let (event, position) = match self.peeked.take() {
+            Some(peeked) => peeked?,
+            None => self.read()?,
+        };
+
+        let mut location = self.location.borrow_mut();
+        location.update(&event, position);
+        if let Some(limit) = location.check_limits(&self.limits) {
+            location.limit_exceeded = Some(limit);
+            self.finished = true;
+            return None;
+        }
+
+        Some(event)
+    }
 }
 
 /// Helper trait for converting `SimpleXmlEvent::Characters` into types that can be parsed from
@@ -112,18 +373,33 @@ pub(crate) fn parse_from_bytes<P: FromXml>(
 /// the types to account for how they are represented in the XML documents (e.g. bool, NaiveDateTime)
 trait FromXmlCharacters: Sized {
     fn from_xml_characters(s: &str) -> Result<Self, XmlParseError>;
+
+    /// Value to use for an empty element or text that cannot be parsed, unless parsing strictly.
+    /// Types without a fallback always fail on such input.
+    fn fallback() -> Option<Self> {
+        None
+    }
 }
 
 impl<T: FromXmlCharacters> FromXml for T {
     type Parses = T;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        _inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
+        let tolerated = || if ctx.options.strict { None } else { T::fallback() };
+
+        if let Some(SimpleXmlEvent::End(_)) = iterator.peek() {
+            // leave the closing tag of the empty element to the caller
+            if let Some(value) = tolerated() {
+                return Ok(value);
+            }
+        }
+
         let event = iterator.next().ok_or(XmlParseError::Eof)?;
         if let SimpleXmlEvent::Characters(text) = event {
-            T::from_xml_characters(&text)
+            T::from_xml_characters(&text).or_else(|e| tolerated().ok_or(e))
         } else {
             Err(bad_event("text containing a value", event))
         }
@@ -133,15 +409,20 @@ impl<T: FromXmlCharacters> FromXml for T {
 impl<T: FromXmlCharacters> FromXml for Option<T> {
     type Parses = Option<T>;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        _inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let event = iterator.peek().ok_or(XmlParseError::Eof)?;
         if let SimpleXmlEvent::Characters(_) = event {
             // now that we know that characters are upcoming, proceed the iterator.
             if let SimpleXmlEvent::Characters(text) = iterator.next().ok_or(XmlParseError::Eof)? {
-                return Ok(Some(T::from_xml_characters(&text)?));
+                return match T::from_xml_characters(&text) {
+                    Ok(value) => Ok(Some(value)),
+                    // values that have a fallback are treated as missing instead
+                    Err(_) if !ctx.options.strict && T::fallback().is_some() => Ok(None),
+                    Err(e) => Err(e),
+                };
             }
         }
         Ok(None)
@@ -170,12 +451,20 @@ impl FromXmlCharacters for String {
     fn from_xml_characters(s: &str) -> Result<Self, XmlParseError> {
         Ok(s.to_string())
     }
+
+    fn fallback() -> Option<Self> {
+        Some(String::new())
+    }
 }
 
 impl FromXmlCharacters for NaiveDateTime {
     fn from_xml_characters(s: &str) -> Result<Self, XmlParseError> {
         parse_xml_timestamp(s)
     }
+
+    fn fallback() -> Option<Self> {
+        Some(Times::epoch())
+    }
 }
 
 impl FromXmlCharacters for Uuid {
@@ -202,13 +491,13 @@ struct SimpleTag<V> {
 impl<V: FromXml> FromXml for SimpleTag<V> {
     type Parses = SimpleTag<<V as FromXml>::Parses>;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if let SimpleXmlEvent::Start(name, _) = open_tag {
-            let value = V::from_xml(iterator, inner_cipher)?;
+            let value = V::from_xml(iterator, ctx)?;
 
             let close_tag = iterator.next().ok_or(XmlParseError::Eof)?;
             if !matches!(close_tag, SimpleXmlEvent::End(ref tag) if tag == &name) {
@@ -231,9 +520,9 @@ pub(crate) struct KeePassXml {
 impl FromXml for KeePassXml {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "KeePassFile") {
@@ -246,10 +535,10 @@ impl FromXml for KeePassXml {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Meta" => {
-                        out.meta = Meta::from_xml(iterator, inner_cipher)?;
+                        out.meta = Meta::from_xml(iterator, ctx)?;
                     }
                     "Root" => {
-                        out.root = Root::from_xml(iterator, inner_cipher)?;
+                        out.root = Root::from_xml(iterator, ctx)?;
                     }
                     _ => return Err(bad_event("valid Root child", event.clone())),
                 },
@@ -268,9 +557,9 @@ impl FromXml for KeePassXml {
 impl FromXml for Times {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Times") {
@@ -283,15 +572,24 @@ impl FromXml for Times {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Expires" => {
-                        out.expires = SimpleTag::<bool>::from_xml(iterator, inner_cipher)?.value;
+                        out.expires = SimpleTag::<bool>::from_xml(iterator, ctx)?.value;
                     }
                     "UsageCount" => {
-                        out.usage_count = SimpleTag::<usize>::from_xml(iterator, inner_cipher)?.value;
+                        out.usage_count = SimpleTag::<usize>::from_xml(iterator, ctx)?.value;
                     }
 
                     _ => {
-                        let time = SimpleTag::<NaiveDateTime>::from_xml(iterator, inner_cipher)?;
-                        out.times.insert(time.name, time.value);
+                        // unparseable timestamps are left out unless parsing strictly
+                        let time = SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?;
+                        match time.value {
+                            Some(value) => {
+                                out.times.insert(time.name, value);
+                            }
+                            None if ctx.options.strict => {
+                                return Err(XmlParseError::InvalidTimestamp(String::new()))
+                            }
+                            None => {}
+                        }
                     }
                 },
                 SimpleXmlEvent::End(name) if name == "Times" => break,
@@ -315,9 +613,9 @@ pub(crate) struct Root {
 impl FromXml for Root {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Root") {
@@ -330,10 +628,10 @@ impl FromXml for Root {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Group" => {
-                        out.group = Group::from_xml(iterator, inner_cipher)?;
+                        out.group = Group::from_xml(iterator, ctx)?;
                     }
                     "DeletedObjects" => {
-                        out.deleted_objects = DeletedObjects::from_xml(iterator, inner_cipher)?;
+                        out.deleted_objects = DeletedObjects::from_xml(iterator, ctx)?;
                     }
                     _ => return Err(bad_event("valid Root child", event.clone())),
                 },
@@ -352,9 +650,9 @@ impl FromXml for Root {
 impl FromXml for DeletedObjects {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "DeletedObjects") {
@@ -366,7 +664,7 @@ impl FromXml for DeletedObjects {
         while let Some(event) = iterator.peek() {
             match event {
                 SimpleXmlEvent::Start(name, _) if name == "DeletedObject" => {
-                    let object = DeletedObject::from_xml(iterator, inner_cipher)?;
+                    let object = DeletedObject::from_xml(iterator, ctx)?;
                     out.objects.push(object);
                 }
                 SimpleXmlEvent::End(name) if name == "DeletedObjects" => break,
@@ -384,9 +682,9 @@ impl FromXml for DeletedObjects {
 impl FromXml for DeletedObject {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "DeletedObject") {
@@ -399,10 +697,10 @@ impl FromXml for DeletedObject {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "UUID" => {
-                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, inner_cipher)?.value;
+                        out.uuid = SimpleTag::<Uuid>::from_xml(iterator, ctx)?.value;
                     }
                     "DeletionTime" => {
-                        out.deletion_time = SimpleTag::<NaiveDateTime>::from_xml(iterator, inner_cipher)?.value;
+                        out.deletion_time = SimpleTag::<NaiveDateTime>::from_xml(iterator, ctx)?.value;
                     }
                     _ => return Err(bad_event("valid DeletedObject child", event.clone())),
                 },
@@ -421,9 +719,9 @@ impl FromXml for DeletedObject {
 impl FromXml for CustomData {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "CustomData") {
@@ -436,7 +734,7 @@ impl FromXml for CustomData {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Item" => {
-                        let item = CustomDataItemDenormalized::from_xml(iterator, inner_cipher)?;
+                        let item = CustomDataItemDenormalized::from_xml(iterator, ctx)?;
                         out.items.insert(
                             item.key.to_string(),
                             CustomDataItem {
@@ -462,9 +760,9 @@ impl FromXml for CustomData {
 impl FromXml for CustomDataItemDenormalized {
     type Parses = Self;
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut std::iter::Peekable<I>,
-        inner_cipher: &mut dyn crate::crypt::ciphers::Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if !matches!(open_tag, SimpleXmlEvent::Start(ref tag, _) if tag == "Item") {
@@ -477,14 +775,14 @@ impl FromXml for CustomDataItemDenormalized {
             match event {
                 SimpleXmlEvent::Start(name, _) => match &name[..] {
                     "Key" => {
-                        out.key = SimpleTag::<String>::from_xml(iterator, inner_cipher)?.value;
+                        out.key = SimpleTag::<String>::from_xml(iterator, ctx)?.value;
                     }
                     "Value" => {
-                        out.custom_data_item.value = Some(Value::from_xml(iterator, inner_cipher)?);
+                        out.custom_data_item.value = Some(Value::from_xml(iterator, ctx)?);
                     }
                     "LastModificationTime" => {
                         out.custom_data_item.last_modification_time =
-                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, inner_cipher)?.value;
+                            SimpleTag::<Option<NaiveDateTime>>::from_xml(iterator, ctx)?.value;
                     }
                     _ => return Err(bad_event("valid Item child", event.clone())),
                 },
@@ -506,9 +804,9 @@ pub(crate) struct IgnoreSubfield;
 impl FromXml for IgnoreSubfield {
     type Parses = ();
 
-    fn from_xml<I: Iterator<Item = SimpleXmlEvent>>(
-        iterator: &mut Peekable<I>,
-        _inner_cipher: &mut dyn Cipher,
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        _ctx: &mut ParseContext<'_>,
     ) -> Result<Self::Parses, XmlParseError> {
         let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
         if let SimpleXmlEvent::Start(_, _) = open_tag {
@@ -537,10 +835,59 @@ impl FromXml for IgnoreSubfield {
     }
 }
 
+/// Parse an element that is not known to this crate, keeping its whole sub-tree.
+impl FromXml for UnknownElement {
+    type Parses = Self;
+
+    fn from_xml(
+        iterator: &mut SimpleEventReader<'_>,
+        _ctx: &mut ParseContext<'_>,
+    ) -> Result<Self::Parses, XmlParseError> {
+        parse_unknown_element(iterator)
+    }
+}
+
+/// Parse an unknown element and its sub-tree, which needs no context since nothing is decrypted
+fn parse_unknown_element(iterator: &mut SimpleEventReader<'_>) -> Result<UnknownElement, XmlParseError> {
+    let open_tag = iterator.next().ok_or(XmlParseError::Eof)?;
+    let mut out = if let SimpleXmlEvent::Start(name, attributes) = open_tag {
+        let mut attributes: Vec<(String, String)> = attributes.into_iter().collect();
+        attributes.sort();
+
+        UnknownElement {
+            name,
+            attributes,
+            ..Default::default()
+        }
+    } else {
+        return Err(bad_event("Open tag (to be preserved)", open_tag));
+    };
+
+    while let Some(event) = iterator.peek() {
+        match event {
+            SimpleXmlEvent::Start(_, _) => {
+                out.children.push(parse_unknown_element(iterator)?);
+            }
+            SimpleXmlEvent::Characters(_) => {
+                if let Some(SimpleXmlEvent::Characters(text)) = iterator.next() {
+                    out.text.get_or_insert_with(String::new).push_str(&text);
+                }
+            }
+            SimpleXmlEvent::End(_) => break,
+            SimpleXmlEvent::Err(e) => return Err(e.clone().into()),
+        }
+    }
+
+    // no need to check for the correct closing tag - checked by XmlReader
+    let _close_tag = iterator.next().ok_or(XmlParseError::Eof)?;
+
+    Ok(out)
+}
+
 #[cfg(test)]
 mod parse_test {
     use crate::{
-        config::InnerCipherConfig,
+        config::{InnerCipherConfig, OpenOptions, ParseLimit, ParseLimits},
         crypt::ciphers::PlainCipher,
         db::{
             AutoType, AutoTypeAssociation, CustomData, CustomDataItemDenormalized, Entry, History, Times, Value,
@@ -548,10 +895,19 @@ mod parse_test {
         xml_db::parse::{entry::StringField, DeletedObject, DeletedObjects, IgnoreSubfield, Root},
     };
 
-    use super::{entry::BinaryField, parse, parse_from_bytes, FromXml, KeePassXml, SimpleTag, XmlParseError};
+    use super::{
+        bad_event, entry::BinaryField, parse, parse_from_bytes, FromXml, KeePassXml, SimpleTag, SimpleXmlEvent,
+        XmlParseError,
+    };
 
     pub(crate) fn parse_test_xml<P: FromXml>(xml: &str) -> Result<<P as FromXml>::Parses, XmlParseError> {
-        parse_from_bytes::<P>(xml.as_bytes(), &mut PlainCipher)
+        parse_from_bytes::<P>(xml.as_bytes(), &mut PlainCipher, &OpenOptions::default())
+    }
+
+    pub(crate) fn parse_test_xml_strict<P: FromXml>(
+        xml: &str,
+    ) -> Result<<P as FromXml>::Parses, XmlParseError> {
+        parse_from_bytes::<P>(xml.as_bytes(), &mut PlainCipher, &OpenOptions::new().strict(true))
     }
 
     #[test]
@@ -560,7 +916,190 @@ mod parse_test {
 
         let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
 
-        let _database_content = parse(&xml[..], &mut *inner_cipher)?;
+        let _database_content = parse(&xml[..], &mut *inner_cipher, &OpenOptions::default())?;
+
+        Ok(())
+    }
+
+    fn parse_fixture(xml: &[u8], strict: bool) -> Result<KeePassXml, XmlParseError> {
+        let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+        parse(xml, &mut *inner_cipher, &OpenOptions::new().strict(strict))
+    }
+
+    #[test]
+    fn test_malformed_timestamps() -> Result<(), XmlParseError> {
+        let xml = include_bytes!("../../../tests/resources/inner_xml_malformed_timestamps.xml");
+
+        assert!(matches!(
+            parse_fixture(xml, true),
+            Err(XmlParseError::Located { source, .. }) if matches!(*source, XmlParseError::InvalidTimestamp(_))
+        ));
+
+        let content = parse_fixture(xml, false)?;
+        assert_eq!(content.meta.database_name_changed, None);
+        assert_eq!(
+            content.meta.master_key_changed,
+            Some("0001-01-01T00:00:00".parse().unwrap())
+        );
+
+        // ISO 8601 variants and base64 timestamps can be mixed
+        let times = &content.root.group.times;
+        assert_eq!(
+            times.get_creation(),
+            Some(&"0001-01-01T00:00:00".parse().unwrap())
+        );
+        assert_eq!(
+            times.get_last_modification(),
+            Some(&"2015-03-05T12:00:00".parse().unwrap())
+        );
+        assert_eq!(
+            times.get_last_access(),
+            Some(&"2015-03-05T10:00:00.123".parse().unwrap())
+        );
+        assert!(times.get_expiry().is_some());
+
+        // unparseable timestamps are treated as missing
+        let entry = content.root.group.entries()[0];
+        assert_eq!(entry.times.get_creation(), None);
+        assert_eq!(entry.times.get_last_modification(), None);
+        assert_eq!(entry.times.get_last_access(), None);
+        assert_eq!(entry.times.get_expiry(), None);
+        assert_eq!(
+            entry.times.get_location_changed(),
+            Some(&"2015-03-05T12:00:00".parse().unwrap())
+        );
+
+        assert_eq!(
+            content.root.deleted_objects.objects[0].deletion_time,
+            Times::epoch()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_location() {
+        let xml = include_bytes!("../../../tests/resources/inner_xml_corrupted_value.xml");
+
+        let err = parse_fixture(xml, false).unwrap_err();
+        let message = err.to_string();
+
+        let XmlParseError::Located {
+            offset,
+            line,
+            path,
+            source,
+            ..
+        } = err
+        else {
+            panic!("Expected a located error, got {:?}", err);
+        };
+
+        assert_eq!(path, "KeePassFile/Root/Group/Group[3]/Entry[2]/String[3]/Value");
+        assert_eq!(line, 40);
+        // the text of the value was consumed last, the closing tag was not peeked at yet
+        let text_offset = xml.windows(7).position(|w| w == b"hunter2").unwrap();
+        assert_eq!(offset, text_offset);
+        assert!(matches!(*source, XmlParseError::Base64(_)));
+
+        assert!(message.contains(&path));
+        assert!(!message.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_error_message_omits_text() {
+        let event = SimpleXmlEvent::Characters("hunter2".to_string());
+        let message = bad_event("start of String", event).to_string();
+        assert_eq!(
+            message,
+            "Bad XML event: expected start of String, got text of length 7"
+        );
+    }
+
+    #[test]
+    fn test_nesting_depth_limit() {
+        // without the limit, parsing the nested groups recursively would overflow the stack
+        let depth = 100_000;
+        let xml = format!(
+            "<KeePassFile><Root>{}{}</Root></KeePassFile>",
+            "<Group>".repeat(depth),
+            "</Group>".repeat(depth)
+        );
+
+        let err = parse_fixture(xml.as_bytes(), false).unwrap_err();
+        let XmlParseError::Located { path, source, .. } = err else {
+            panic!("Expected a located error, got {:?}", err);
+        };
+        assert!(matches!(
+            *source,
+            XmlParseError::LimitExceeded(ParseLimit::NestingDepth)
+        ));
+        assert_eq!(
+            path.split('/').count(),
+            ParseLimits::default().max_nesting_depth + 1
+        );
+    }
+
+    #[test]
+    fn test_entries_limit() -> Result<(), XmlParseError> {
+        let xml = include_bytes!("../../../tests/resources/inner_xml_corrupted_value.xml");
+        let xml = String::from_utf8_lossy(xml).replace("hunter2*not-base64", "aHVudGVyMg==");
+
+        let parse_with_max_entries = |max_entries| {
+            let options = OpenOptions::new().limits(ParseLimits {
+                max_entries,
+                ..Default::default()
+            });
+            let mut inner_cipher = InnerCipherConfig::Plain.get_cipher(&[]).unwrap();
+            parse(xml.as_bytes(), &mut *inner_cipher, &options)
+        };
+
+        parse_with_max_entries(2)?;
+
+        let err = parse_with_max_entries(1).unwrap_err();
+        assert!(matches!(
+            err,
+            XmlParseError::Located { path, source, .. }
+                if path == "KeePassFile/Root/Group/Group[3]/Entry[2]"
+                    && matches!(*source, XmlParseError::LimitExceeded(ParseLimit::Entries))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_times() -> Result<(), XmlParseError> {
+        let xml = include_bytes!("../../../tests/resources/inner_xml_missing_times.xml");
+
+        for strict in [true, false] {
+            let content = parse_fixture(xml, strict)?;
+            assert_eq!(content.root.group.times.get_creation(), None);
+            assert_eq!(content.root.group.entries()[0].get_title(), Some("No times"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_values() -> Result<(), XmlParseError> {
+        let xml = include_bytes!("../../../tests/resources/inner_xml_empty_values.xml");
+
+        assert!(matches!(
+            parse_fixture(xml, true),
+            Err(XmlParseError::Located { source, .. }) if matches!(*source, XmlParseError::BadEvent { .. })
+        ));
+
+        let content = parse_fixture(xml, false)?;
+        assert_eq!(content.root.group.name, "");
+
+        let entry = content.root.group.entries()[0];
+        assert_eq!(entry.get_title(), Some("Empty values"));
+        // empty fields read the same as missing ones
+        assert_eq!(entry.get("Notes"), None);
+        assert_eq!(entry.get_password(), None);
+        assert_eq!(entry.get(""), Some("value without a key"));
+
+        assert!(content.meta.custom_data.items.contains_key(""));
 
         Ok(())
     }
@@ -579,9 +1118,12 @@ mod parse_test {
         assert_eq!(value.name, "TestTag");
         assert_eq!(value.value, "Test-Valueeven more test data");
 
-        let value = parse_test_xml::<SimpleTag<String>>("<TestTag attribute=\"SomeValue\"></TestTag>");
+        let value = parse_test_xml_strict::<SimpleTag<String>>("<TestTag attribute=\"SomeValue\"></TestTag>");
         assert!(value.is_err());
 
+        let value = parse_test_xml::<SimpleTag<String>>("<TestTag attribute=\"SomeValue\"></TestTag>")?;
+        assert_eq!(value.value, "");
+
         // Option<String> tag
         let value = parse_test_xml::<SimpleTag<Option<String>>>(
             "<TestTag attribute=\"SomeValue\">Test-Value</TestTag>",
@@ -618,7 +1160,7 @@ mod parse_test {
         let value = parse_test_xml::<SimpleTag<String>>("<OpenTag>Data</CloseTag>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
-        let value = parse_test_xml::<SimpleTag<String>>("<TestTag></TestTag>");
+        let value = parse_test_xml_strict::<SimpleTag<String>>("<TestTag></TestTag>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
         let value = parse_test_xml::<SimpleTag<String>>("<TestTag>");
@@ -746,11 +1288,18 @@ mod parse_test {
         let value = parse_test_xml::<CustomDataItemDenormalized>("<Item><UnkownChildTag/></Item>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
-        let value = parse_test_xml::<CustomDataItemDenormalized>(
+        let value = parse_test_xml_strict::<CustomDataItemDenormalized>(
             "<Item><Key></Key><Value>EmptyKey</Value><LastModificationTime>1234</LastModificationTime></Item>",
         );
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
+        // an empty key and an unparseable timestamp are tolerated unless parsing strictly
+        let value = parse_test_xml::<CustomDataItemDenormalized>(
+            "<Item><Key></Key><Value>EmptyKey</Value><LastModificationTime>1234</LastModificationTime></Item>",
+        )?;
+        assert_eq!(value.key, "");
+        assert_eq!(value.custom_data_item.last_modification_time, None);
+
         Ok(())
     }
 
@@ -800,9 +1349,12 @@ mod parse_test {
         );
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
-        let value = parse_test_xml::<BinaryField>("<Binary><Key></Key><Value Ref=\"asdf\"/></Binary>");
+        let value = parse_test_xml_strict::<BinaryField>("<Binary><Key></Key><Value Ref=\"asdf\"/></Binary>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
+        let value = parse_test_xml::<BinaryField>("<Binary><Key></Key><Value Ref=\"asdf\"/></Binary>")?;
+        assert_eq!(value.key, "");
+
         let value = parse_test_xml::<BinaryField>("<Binary><Key>mykey</Key><Value/></Binary>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
@@ -823,6 +1375,38 @@ mod parse_test {
         let value = parse_test_xml::<Entry>("<WrongTag></WrongTag>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
+        // references to attachments that are not numeric are left out unless parsing strictly
+        let xml = "<Entry><Binary><Key>a</Key><Value Ref=\"0\"/></Binary>\
+                   <Binary><Key>b</Key><Value Ref=\"asdf\"/></Binary></Entry>";
+        let value = parse_test_xml::<Entry>(xml)?;
+        assert_eq!(value.attachment_names(), vec!["a"]);
+
+        let value = parse_test_xml_strict::<Entry>(xml);
+        assert!(matches!(value, Err(XmlParseError::IntFormat(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_colors() -> Result<(), XmlParseError> {
+        // colors as written by KeePass and KeePassXC
+        let value = parse_test_xml::<Entry>(
+            "<Entry><ForegroundColor>#FF0000</ForegroundColor><BackgroundColor>#00ff7f</BackgroundColor></Entry>",
+        )?;
+        assert_eq!(value.foreground_color, Some("#FF0000".parse().unwrap()));
+        assert_eq!(value.background_color, Some("#00FF7F".parse().unwrap()));
+        assert_eq!(value.foreground_color_text, Some("#FF0000".to_string()));
+        assert_eq!(value.background_color_text, None);
+
+        let value =
+            parse_test_xml::<Entry>("<Entry><ForegroundColor/><BackgroundColor></BackgroundColor></Entry>")?;
+        assert_eq!(value.foreground_color, None);
+        assert_eq!(value.background_color, None);
+
+        let value = parse_test_xml::<Entry>("<Entry><BackgroundColor>Red</BackgroundColor></Entry>")?;
+        assert_eq!(value.background_color, None);
+        assert_eq!(value.background_color_text, Some("Red".to_string()));
+
         Ok(())
     }
 
@@ -860,6 +1444,13 @@ mod parse_test {
         let value = parse_test_xml::<Value>("<Value><StrangeTag>Data</StrangeTag></Value>");
         assert!(matches!(value, Err(XmlParseError::BadEvent { .. })));
 
+        let value = parse_test_xml::<Value>("<Value Protected=\"True\">c2VjcmV0</Value>")?;
+        assert!(matches!(&value, Value::Protected(p) if p.unsecure() == b"secret"));
+
+        // protected values that are not valid UTF-8 are not replaced with placeholder characters
+        let value = parse_test_xml::<Value>("<Value Protected=\"True\">//4=</Value>");
+        assert!(matches!(value, Err(XmlParseError::ProtectedValueUtf8(_))));
+
         Ok(())
     }
 
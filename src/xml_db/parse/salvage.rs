@@ -0,0 +1,165 @@
+use std::ops::Range;
+
+use crate::{
+    config::OpenOptions,
+    crypt::ciphers::Cipher,
+    db::{Entry, Group, Meta, RecoveryWarning, Value},
+    error::XmlParseError,
+    xml_db::parse::{FromXml, ParseContext, SimpleEventReader},
+};
+
+/// Name of the group that salvaged entries are collected in
+const RECOVERED_GROUP_NAME: &str = "Recovered";
+
+/// Salvage the metadata and entries of a damaged XML document.
+///
+/// The document is not parsed as a whole. Instead, the `<Meta>` element and every top-level
+/// `<Entry>` element are located by their tags and parsed one by one, in document order so that
+/// protected values consume the inner stream cipher as in a regular parse. Entries that cannot be
+/// parsed are dropped. Entries after `damaged_from` or after a dropped entry keep their fields, but
+/// lose their protected values, since the inner stream cipher can no longer be trusted to be in sync.
+/// The group structure is not recovered: all entries are put into a new root group.
+pub(crate) fn salvage(
+    xml: &[u8],
+    inner_cipher: &mut dyn Cipher,
+    options: &OpenOptions,
+    damaged_from: usize,
+    warnings: &mut Vec<RecoveryWarning>,
+) -> (Meta, Group) {
+    let mut ctx = ParseContext {
+        inner_cipher,
+        options,
+        desynced: false,
+    };
+    let meta = element_range(xml, b"<Meta>", b"</Meta>")
+        .and_then(|range| parse_fragment::<Meta>(&xml[range], &mut ctx).ok())
+        .unwrap_or_else(|| {
+            warnings.push(RecoveryWarning::MetaDropped);
+            Meta::default()
+        });
+
+    let mut root = Group::new(RECOVERED_GROUP_NAME);
+    let mut desynced = false;
+    for range in entry_ranges(xml) {
+        desynced |= range.end > damaged_from;
+        ctx.desynced = desynced;
+
+        let offset = range.start as u64;
+        let mut entry = match parse_fragment::<Entry>(&xml[range], &mut ctx) {
+            Ok(entry) => entry,
+            Err(_) => {
+                warnings.push(RecoveryWarning::EntryDropped { offset });
+                desynced = true;
+                continue;
+            }
+        };
+
+        if desynced && strip_protected_values(&mut entry) {
+            warnings.push(RecoveryWarning::ProtectedValuesDropped { uuid: entry.uuid });
+        }
+
+        root.push_child(entry);
+    }
+
+    (meta, root)
+}
+
+fn parse_fragment<P: FromXml>(
+    xml: &[u8],
+    ctx: &mut ParseContext<'_>,
+) -> Result<<P as FromXml>::Parses, XmlParseError> {
+    P::from_xml(&mut SimpleEventReader::new(xml, ctx.options.limits), ctx)
+}
+
+/// Remove the protected values of an entry and its history, returning whether there were any
+fn strip_protected_values(entry: &mut Entry) -> bool {
+    let fields = entry.fields.len();
+    entry
+        .fields
+        .retain(|_, value| !matches!(value, Value::Protected(_) | Value::Deferred(_)));
+    let mut stripped = entry.fields.len() != fields;
+
+    if let Some(history) = entry.history.as_mut() {
+        for entry in history.entries.iter_mut() {
+            stripped |= strip_protected_values(entry);
+        }
+    }
+
+    stripped
+}
+
+/// Range of the first element between the given tags, both inclusive
+fn element_range(xml: &[u8], start_tag: &[u8], end_tag: &[u8]) -> Option<Range<usize>> {
+    let start = find(xml, start_tag, 0)?;
+    let end = find(xml, end_tag, start)? + end_tag.len();
+    Some(start..end)
+}
+
+fn find(xml: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    xml.get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|pos| from + pos)
+}
+
+/// Ranges of the top-level `<Entry>` elements, i.e. entries that are not part of a history. An entry
+/// that is not closed before the next one starts ends where the next one starts.
+fn entry_ranges(xml: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+
+    let mut current: Option<usize> = None;
+    let mut history_depth = 0usize;
+
+    let mut pos = 0;
+    while let Some(tag) = find(xml, b"<", pos) {
+        let rest = &xml[tag..];
+        pos = tag + 1;
+
+        if rest.starts_with(b"<Entry>") {
+            if history_depth == 0 {
+                if let Some(start) = current {
+                    ranges.push(start..tag);
+                }
+                current = Some(tag);
+            }
+        } else if rest.starts_with(b"</Entry>") {
+            if history_depth == 0 {
+                if let Some(start) = current.take() {
+                    ranges.push(start..(tag + b"</Entry>".len()));
+                }
+            }
+        } else if rest.starts_with(b"<History>") {
+            if current.is_some() {
+                history_depth += 1;
+            }
+        } else if rest.starts_with(b"</History>") {
+            history_depth = history_depth.saturating_sub(1);
+        }
+    }
+
+    if let Some(start) = current {
+        ranges.push(start..xml.len());
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod salvage_tests {
+    use super::entry_ranges;
+
+    #[test]
+    fn test_entry_ranges() {
+        let xml = b"<Group><Entry>a<History><Entry>b</Entry></History></Entry><Entry>c<Entry>d</Entry></Group>";
+        let ranges: Vec<&[u8]> = entry_ranges(xml).into_iter().map(|r| &xml[r]).collect();
+
+        assert_eq!(
+            ranges,
+            vec![
+                &b"<Entry>a<History><Entry>b</Entry></History></Entry>"[..],
+                &b"<Entry>c"[..],
+                &b"<Entry>d</Entry>"[..],
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(all(feature = "tokio", feature = "save_kdbx4"))]
+mod async_tests {
+    use std::sync::{Arc, Mutex};
+
+    use keepass::{
+        config::{DatabaseConfig, DatabaseSettings, KdfConfig},
+        db::{Database, Entry, Value},
+        error::{DatabaseOpenError, DatabaseSaveError},
+        DatabaseKey,
+    };
+
+    fn key() -> DatabaseKey {
+        DatabaseKey::new().with_password("testing")
+    }
+
+    fn create_database() -> Database {
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Argon2 {
+                    iterations: 4,
+                    memory: 64 * 1024 * 1024,
+                    parallelism: 1,
+                    version: argon2::Version::Version13,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Async entry".to_string()));
+        db.root.add_child(entry);
+        db
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn save_and_open_async() -> Result<(), Box<dyn std::error::Error>> {
+        let db = create_database();
+
+        let mut buffer = Vec::new();
+        db.save_async(&mut buffer, key()).await?;
+
+        let db_loaded = Database::open_async(buffer.as_slice(), key()).await?;
+        assert_eq!(db_loaded, db);
+
+        let res = Database::open_async(buffer.as_slice(), DatabaseKey::new().with_password("wrong")).await;
+        assert!(matches!(res, Err(DatabaseOpenError::Key(_))));
+
+        Ok(())
+    }
+
+    /// The key derivation runs on the blocking thread pool, so that other tasks on the
+    /// single-threaded runtime keep running while the database is opened
+    #[tokio::test]
+    async fn open_async_does_not_block_runtime() -> Result<(), Box<dyn std::error::Error>> {
+        let mut buffer = Vec::new();
+        create_database().save(&mut buffer, key())?;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let other = tokio::spawn({
+            let events = events.clone();
+            async move { events.lock().unwrap().push("other task") }
+        });
+
+        // the spawned task can only run once this task yields, which it does while waiting for the
+        // key derivation. If the key derivation blocked the runtime, the database would be opened
+        // before the other task got to run.
+        Database::open_async(buffer.as_slice(), key()).await?;
+        events.lock().unwrap().push("opened");
+        other.await?;
+
+        assert_eq!(*events.lock().unwrap(), vec!["other task", "opened"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn save_async_needs_multi_threaded_runtime() {
+        let mut buffer = Vec::new();
+        let res = create_database().save_async(&mut buffer, key()).await;
+        assert!(matches!(res, Err(DatabaseSaveError::Io(e)) if e.kind() == std::io::ErrorKind::Unsupported));
+        assert!(buffer.is_empty());
+    }
+}
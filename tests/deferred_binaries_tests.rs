@@ -0,0 +1,132 @@
+#[cfg(feature = "save_kdbx4")]
+mod deferred_binaries_tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use keepass::{
+        config::{DatabaseConfig, DatabaseSettings, KdfConfig, OpenOptions},
+        db::{Database, Entry, Value},
+        DatabaseKey,
+    };
+
+    /// Allocator that keeps track of the total amount of memory allocated
+    struct CountingAllocator;
+
+    static TOTAL_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                TOTAL_ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+        }
+
+        // buffers are resized in place where possible, so only growing them allocates memory
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new_ptr = System.realloc(ptr, layout, new_size);
+            if !new_ptr.is_null() {
+                TOTAL_ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::SeqCst);
+            }
+            new_ptr
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    const ATTACHMENT_SIZE: usize = 4 * 1024 * 1024;
+    const ATTACHMENT_COUNT: usize = 4;
+
+    fn open_counting(data: &[u8], key: DatabaseKey, options: &OpenOptions) -> (Database, usize) {
+        let before = TOTAL_ALLOCATED.load(Ordering::SeqCst);
+        let db = Database::parse_with_options(data, key, options).unwrap();
+        (db, TOTAL_ALLOCATED.load(Ordering::SeqCst) - before)
+    }
+
+    /// Deferring the attachments saves a copy of each of them when opening, but the database is
+    /// otherwise the same and is saved the same way
+    #[test]
+    fn defer_binaries() -> Result<(), Box<dyn std::error::Error>> {
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        for i in 0..ATTACHMENT_COUNT {
+            let mut content = vec![0; ATTACHMENT_SIZE];
+            getrandom::fill(&mut content)?;
+
+            let mut entry = Entry::new();
+            entry
+                .fields
+                .insert("Title".to_string(), Value::Unprotected(format!("Entry {i}")));
+            entry.add_attachment(&mut db, &format!("attachment_{i}.bin"), content);
+            db.root.add_child(entry);
+        }
+
+        let key = DatabaseKey::new().with_password("testing");
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, key.clone())?;
+
+        let (loaded, loaded_allocated) = open_counting(&buffer, key.clone(), &OpenOptions::new());
+        let (deferred, deferred_allocated) =
+            open_counting(&buffer, key.clone(), &OpenOptions::new().defer_binaries(true));
+
+        let attachments_size = ATTACHMENT_SIZE * ATTACHMENT_COUNT;
+        assert!(
+            deferred_allocated + attachments_size <= loaded_allocated,
+            "opening allocated {} bytes with deferred attachments and {} bytes without",
+            deferred_allocated,
+            loaded_allocated
+        );
+
+        assert!(deferred.header_attachments.iter().all(|a| a.is_deferred()));
+        assert!(!loaded.header_attachments.iter().any(|a| a.is_deferred()));
+        assert_eq!(deferred.header_attachments, db.header_attachments);
+        assert_eq!(deferred.root, loaded.root);
+
+        for entry in deferred.root.iter_entries() {
+            let name = entry.attachment_names()[0];
+            let expected = db.root.iter_entries().find(|e| e.uuid == entry.uuid).unwrap();
+            assert_eq!(
+                entry.get_attachment(&deferred, name),
+                expected.get_attachment(&db, name)
+            );
+        }
+
+        let mut resaved = Vec::new();
+        deferred.save(&mut resaved, key.clone())?;
+        let reopened = Database::open(&mut resaved.as_slice(), key.clone())?;
+        assert_eq!(reopened.header_attachments, db.header_attachments);
+        assert_eq!(reopened.root, db.root);
+
+        let mut attachment = deferred.header_attachments[0].clone();
+        attachment.load()?;
+        assert!(!attachment.is_deferred());
+        assert_eq!(attachment.data(), db.header_attachments[0].data());
+
+        // setting the content replaces the deferred content
+        let mut attachment = deferred.header_attachments[1].clone();
+        attachment.set_data(b"replaced".to_vec());
+        assert!(!attachment.is_deferred());
+        let mut modified = deferred.clone();
+        modified.header_attachments[1] = attachment;
+        let mut resaved = Vec::new();
+        modified.save(&mut resaved, key.clone())?;
+        let reopened = Database::open(&mut resaved.as_slice(), key)?;
+        assert_eq!(reopened.header_attachments[1].data(), b"replaced");
+
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
+#[cfg(feature = "save_kdbx4")]
+use keepass::db::{DeletedObject, FixedTimeProvider};
 use keepass::{
-    db::{Entry, Group, Node, NodeRefMut, Value},
+    db::{DeleteMode, Entry, Group, Node, NodeRef, NodeRefMut, Value},
     Database, DatabaseKey,
 };
 use std::fs::File;
@@ -39,17 +41,26 @@ fn test_deletion() {
     }
 
     // Verify it's gone from the group
-    let g1_ref = db.root.children.iter().find(|n| match n {
-        Node::Group(g) => g.uuid == g1_uuid,
-        _ => false,
-    }).unwrap();
-    if let Node::Group(g) = g1_ref {
-        let g2_ref = g.children.iter().find(|n| match n {
-            Node::Group(g_inner) => g_inner.name == "G2",
+    let g1_ref = db
+        .root
+        .children
+        .iter()
+        .find(|n| match n {
+            Node::Group(g) => g.uuid == g1_uuid,
             _ => false,
-        }).unwrap();
+        })
+        .unwrap();
+    if let Node::Group(g) = g1_ref {
+        let g2_ref = g
+            .children
+            .iter()
+            .find(|n| match n {
+                Node::Group(g_inner) => g_inner.name == "G2",
+                _ => false,
+            })
+            .unwrap();
         if let Node::Group(g2_inner) = g2_ref {
-             assert_eq!(g2_inner.children.len(), 0);
+            assert_eq!(g2_inner.children.len(), 0);
         } else {
             panic!("Expected G2 group");
         }
@@ -57,7 +68,6 @@ fn test_deletion() {
         panic!("Expected G1 group");
     }
 
-
     // Verify it's in deleted_objects
     assert_eq!(db.deleted_objects.objects.len(), 1);
     assert_eq!(db.deleted_objects.objects[0].uuid, e2_uuid);
@@ -91,6 +101,35 @@ fn test_deletion() {
     assert_eq!(db.deleted_objects.objects.len(), 1);
 }
 
+#[test]
+fn test_delete_with_parent() {
+    let mut db = Database::new(Default::default());
+
+    let mut group = Group::new("Group");
+    let entry = Entry::new();
+    let entry_uuid = entry.uuid;
+    group.add_child(entry);
+    let group_uuid = group.uuid;
+    db.root.add_child(group);
+
+    let top_level = Entry::new();
+    let top_level_uuid = top_level.uuid;
+    db.root.add_child(top_level);
+
+    let (node, parent) = db.delete_by_uuid_with_parent(&entry_uuid, true).unwrap();
+    assert_eq!(node.as_ref().uuid(), entry_uuid);
+    assert_eq!(parent, group_uuid);
+    assert_eq!(db.deleted_objects.objects.len(), 1);
+
+    let (node, parent) = db.delete_by_uuid_with_parent(&top_level_uuid, false).unwrap();
+    assert_eq!(node.as_ref().uuid(), top_level_uuid);
+    assert_eq!(parent, db.root.uuid);
+    assert_eq!(db.deleted_objects.objects.len(), 1);
+
+    assert!(db.delete_by_uuid_with_parent(&entry_uuid, true).is_none());
+    let root_uuid = db.root.uuid;
+    assert!(db.delete_by_uuid_with_parent(&root_uuid, true).is_none());
+}
 
 #[test]
 #[cfg(feature = "save_kdbx4")]
@@ -101,10 +140,9 @@ fn test_delete_entry_and_persist() {
     let mut db = Database::new(Default::default());
     let mut group = Group::new("Group");
     let mut entry = Entry::new();
-    entry.fields.insert(
-        "Title".to_string(),
-        Value::Unprotected("My Entry".to_string()),
-    );
+    entry
+        .fields
+        .insert("Title".to_string(), Value::Unprotected("My Entry".to_string()));
     let entry_uuid = entry.uuid;
     group.add_child(entry);
     db.root.add_child(group);
@@ -117,8 +155,7 @@ fn test_delete_entry_and_persist() {
 
     // 2. Save the initial database to a temporary file
     let key = DatabaseKey::new().with_password("password");
-    db.save(&mut File::create(&path).unwrap(), key.clone())
-        .unwrap();
+    db.save(&mut File::create(&path).unwrap(), key.clone()).unwrap();
 
     // 3. Re-open and verify that the entry was saved
     let mut db_reopened = Database::open(&mut File::open(&path).unwrap(), key.clone()).unwrap();
@@ -159,6 +196,35 @@ fn test_delete_entry_and_persist() {
     std::fs::remove_file(&path).unwrap();
 }
 
+#[test]
+#[cfg(feature = "save_kdbx4")]
+fn test_deletion_time_persists() {
+    let deleted_at = "2024-03-01T12:34:56".parse().unwrap();
+    let mut db = Database::new(Default::default());
+    db.set_time_provider(Box::new(FixedTimeProvider(deleted_at)));
+
+    let entry = Entry::new();
+    let entry_uuid = entry.uuid;
+    db.root.add_child(entry);
+    db.delete_by_uuid(&entry_uuid, true).unwrap();
+    assert_eq!(
+        db.deleted_objects.objects,
+        [DeletedObject {
+            uuid: entry_uuid,
+            deletion_time: deleted_at,
+        }]
+    );
+
+    let key = DatabaseKey::new().with_password("password");
+    let mut buffer = Vec::new();
+    db.save(&mut buffer, key.clone()).unwrap();
+    let db_reopened = Database::open(&mut buffer.as_slice(), key).unwrap();
+
+    let deleted = db_reopened.deleted_objects.get(entry_uuid).unwrap();
+    assert_eq!(deleted.deletion_time, deleted_at);
+    assert_eq!(db_reopened.deleted_objects, db.deleted_objects);
+}
+
 #[test]
 #[cfg(feature = "save_kdbx4")]
 fn test_delete_group_and_persist() {
@@ -178,8 +244,7 @@ fn test_delete_group_and_persist() {
 
     // 2. Save the initial database to a temporary file
     let key = DatabaseKey::new().with_password("password");
-    db.save(&mut File::create(&path).unwrap(), key.clone())
-        .unwrap();
+    db.save(&mut File::create(&path).unwrap(), key.clone()).unwrap();
 
     // 3. Re-open and verify that the group was saved
     let mut db_reopened = Database::open(&mut File::open(&path).unwrap(), key.clone()).unwrap();
@@ -228,10 +293,9 @@ fn test_delete_with_merge() {
     let mut master_db = Database::new(Default::default());
     let mut group = Group::new("Group");
     let mut entry = Entry::new();
-    entry.fields.insert(
-        "Title".to_string(),
-        Value::Unprotected("My Entry".to_string()),
-    );
+    entry
+        .fields
+        .insert("Title".to_string(), Value::Unprotected("My Entry".to_string()));
     let entry_uuid = entry.uuid;
     group.add_child(entry);
     master_db.root.add_child(group);
@@ -279,3 +343,96 @@ fn test_delete_with_merge() {
     std::fs::remove_file(&master_path).unwrap();
     std::fs::remove_file(&replica_path).unwrap();
 }
+
+fn is_deprecated(node: &NodeRef) -> bool {
+    matches!(node, NodeRef::Entry(e) if e.tags.iter().any(|t| t == "deprecated"))
+}
+
+#[test]
+fn test_delete_where_permanent() {
+    // without a recycle bin, recycling deletes permanently
+    for (mode, recycle_bin_enabled, logged) in [
+        (DeleteMode::Permanent, true, true),
+        (DeleteMode::PermanentUnlogged, true, false),
+        (DeleteMode::Recycle, false, true),
+    ] {
+        let mut db = Database::new(Default::default());
+        db.meta.recyclebin_enabled = Some(recycle_bin_enabled);
+        let mut group = Group::new("Group");
+        for parent in [&mut db.root, &mut group] {
+            let mut deprecated = Entry::new();
+            deprecated.tags.push("deprecated".to_string());
+            parent.add_child(deprecated);
+        }
+        group.add_child(Entry::new());
+        db.root.add_child(group);
+
+        let deleted = db.delete_where(is_deprecated, mode);
+        assert_eq!(deleted.len(), 2);
+        assert!(!db.root.iter().any(|node| is_deprecated(&node)));
+        assert_eq!(db.root.iter().count(), 3);
+        assert!(db.meta.recyclebin_uuid.is_none());
+
+        let deleted_objects: Vec<Uuid> = db.deleted_objects.objects.iter().map(|o| o.uuid).collect();
+        if logged {
+            let deleted_uuids: Vec<Uuid> = deleted.iter().map(|node| node.as_ref().uuid()).collect();
+            assert_eq!(deleted_objects, deleted_uuids);
+        } else {
+            assert!(deleted_objects.is_empty());
+        }
+    }
+}
+
+#[test]
+fn test_delete_where_recycle() {
+    let mut db = Database::new(Default::default());
+    let mut group = Group::new("Group");
+    let group_uuid = group.uuid;
+    for parent in [&mut db.root, &mut group] {
+        let mut deprecated = Entry::new();
+        deprecated.tags.push("deprecated".to_string());
+        parent.add_child(deprecated);
+    }
+    group.add_child(Entry::new());
+    db.root.add_child(group);
+
+    let deleted = db.delete_where(is_deprecated, DeleteMode::Recycle);
+    assert_eq!(deleted.len(), 2);
+    assert!(db.deleted_objects.objects.is_empty());
+
+    let recycle_bin_uuid = db.meta.recyclebin_uuid.unwrap();
+    let recycle_bin = match db.get_by_uuid(&recycle_bin_uuid) {
+        Some(NodeRef::Group(g)) => g.clone(),
+        _ => panic!("Expected the recycle bin to be created"),
+    };
+    assert_eq!(recycle_bin.entries().len(), 2);
+    assert!(recycle_bin
+        .entries()
+        .iter()
+        .all(|e| e.times.get_location_changed().is_some()));
+
+    // neither the recycle bin nor the groups containing it are recycled, and nodes that already
+    // are in the recycle bin are deleted permanently
+    let recycle_bin = db.delete_by_uuid(&recycle_bin_uuid, false).unwrap();
+    match db.get_by_uuid_mut(&group_uuid) {
+        Some(NodeRefMut::Group(g)) => g.add_child(recycle_bin),
+        _ => panic!("Expected the group"),
+    }
+    let deleted = db.delete_where(|_| true, DeleteMode::Recycle);
+    assert_eq!(deleted.len(), 3);
+    assert_eq!(db.deleted_objects.objects.len(), 2);
+    assert!(db.get_by_uuid(&group_uuid).is_some());
+    match db.get_by_uuid(&recycle_bin_uuid) {
+        Some(NodeRef::Group(g)) => assert_eq!(g.entries().len(), 1),
+        _ => panic!("Expected the recycle bin"),
+    }
+
+    // the recycle bin itself can be deleted permanently
+    let deleted = db.delete_where(
+        |node| matches!(node, NodeRef::Group(g) if g.uuid == recycle_bin_uuid),
+        DeleteMode::PermanentUnlogged,
+    );
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(db.deleted_objects.objects.len(), 2);
+    assert_eq!(db.root.iter().count(), 2);
+}
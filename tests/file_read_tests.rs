@@ -1,7 +1,10 @@
 mod file_read_tests {
     use keepass::{
-        db::{Database, NodeRef},
-        error::{DatabaseIntegrityError, DatabaseOpenError},
+        config::{
+            CompressionConfig, DatabaseVersion, InnerCipherConfig, KdfConfig, OpenOptions, OuterCipherConfig,
+        },
+        db::{Database, NodeRef, Value},
+        error::{DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError},
         DatabaseKey,
     };
     use uuid::uuid;
@@ -448,6 +451,162 @@ mod file_read_tests {
         Ok(())
     }
 
+    #[test]
+    fn open_kdbx3_with_wrong_password() {
+        let path = Path::new("tests/resources/test_db_with_password.kdbx");
+        let res = Database::open(
+            &mut File::open(path).unwrap(),
+            DatabaseKey::new().with_password("wrongpass"),
+        );
+        assert!(matches!(
+            res,
+            Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))
+        ));
+    }
+
+    #[test]
+    fn open_kdbx4_with_wrong_password() {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_argon2.kdbx");
+        let res = Database::open(
+            &mut File::open(path).unwrap(),
+            DatabaseKey::new().with_password("wrongpass"),
+        );
+        assert!(matches!(
+            res,
+            Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))
+        ));
+    }
+
+    #[test]
+    fn open_kdbx4_with_damaged_block() {
+        let mut data = std::fs::read("tests/resources/test_db_kdbx4_with_password_argon2.kdbx").unwrap();
+
+        // the file ends with an empty block (32 bytes HMAC + 4 bytes size), so this is the last byte
+        // of the first block
+        let pos = data.len() - 37;
+        data[pos] ^= 0xff;
+
+        let res = Database::parse(&data, DatabaseKey::new().with_password("demopass"));
+        assert!(matches!(
+            res,
+            Err(DatabaseOpenError::Corrupt { block_index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn open_with_retry() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_with_password.kdbx");
+        let passwords = ["wrong", "still wrong", "demopass"];
+
+        let mut attempts = Vec::new();
+        let db = Database::open_with_retry(
+            &mut File::open(path)?,
+            |attempt| {
+                attempts.push(attempt);
+                Some(DatabaseKey::new().with_password(passwords[attempt - 1]))
+            },
+            3,
+        )?;
+
+        assert_eq!(attempts, vec![1, 2, 3]);
+        assert_eq!(db.root.name, "sample");
+
+        let res = Database::open_with_retry(
+            &mut File::open(path)?,
+            |attempt| {
+                passwords[..2]
+                    .get(attempt - 1)
+                    .map(|p| DatabaseKey::new().with_password(p))
+            },
+            5,
+        );
+        assert!(matches!(res, Err(DatabaseOpenError::TooManyAttempts)));
+
+        let res = Database::open_with_retry(
+            &mut File::open(path)?,
+            |_| Some(DatabaseKey::new().with_password("wrong")),
+            2,
+        );
+        assert!(matches!(res, Err(DatabaseOpenError::TooManyAttempts)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cipher_and_kdf_of_opened_database() -> Result<(), DatabaseOpenError> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_argon2.kdbx");
+        let db = Database::open(
+            &mut File::open(path)?,
+            DatabaseKey::new().with_password("demopass"),
+        )?;
+
+        assert_eq!(db.cipher(), &OuterCipherConfig::AES256);
+        assert_eq!(
+            db.kdf(),
+            &KdfConfig::Argon2 {
+                iterations: 1,
+                memory: 128 * 1024 * 1024,
+                parallelism: 8,
+                version: argon2::Version::Version13,
+            }
+        );
+        assert_eq!(db.format_version(), &DatabaseVersion::KDB4(0));
+
+        // changes for the next save do not affect the settings as read
+        let mut db = db;
+        db.config.settings.cipher = OuterCipherConfig::ChaCha20;
+        db.config.settings.kdf = KdfConfig::Aes { rounds: 100 };
+        assert_eq!(db.cipher(), &OuterCipherConfig::AES256);
+        assert!(matches!(db.kdf(), KdfConfig::Argon2 { iterations: 1, .. }));
+        assert_eq!(db.settings().cipher, OuterCipherConfig::ChaCha20);
+
+        let path = Path::new("tests/resources/test_db_with_password.kdbx");
+        let db = Database::open(
+            &mut File::open(path)?,
+            DatabaseKey::new().with_password("demopass"),
+        )?;
+
+        assert_eq!(db.kdf(), &KdfConfig::Aes { rounds: 6000 });
+        assert_eq!(db.format_version(), &DatabaseVersion::KDB3(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn settings_of_opened_database() -> Result<(), Box<dyn std::error::Error>> {
+        let path = Path::new("tests/resources/test_db_kdbx4_with_password_argon2_chacha20.kdbx");
+        let mut db = Database::open(
+            &mut File::open(path)?,
+            DatabaseKey::new().with_password("demopass"),
+        )?;
+
+        let settings = db.settings();
+        assert_eq!(settings.cipher, OuterCipherConfig::ChaCha20);
+        assert!(matches!(settings.kdf, KdfConfig::Argon2 { .. }));
+        assert_eq!(settings.compression, CompressionConfig::GZip);
+        assert_eq!(settings.inner_cipher, InnerCipherConfig::ChaCha20);
+
+        let settings = db.settings_mut();
+        settings.cipher = OuterCipherConfig::Twofish;
+        settings.kdf = KdfConfig::Aes { rounds: 100 };
+        assert_eq!(db.settings().cipher, OuterCipherConfig::Twofish);
+        assert_eq!(db.cipher(), &OuterCipherConfig::ChaCha20);
+
+        #[cfg(feature = "save_kdbx4")]
+        {
+            let mut buffer = Vec::new();
+            db.save(&mut buffer, DatabaseKey::new().with_password("demopass"))?;
+            let db = Database::open(
+                &mut buffer.as_slice(),
+                DatabaseKey::new().with_password("demopass"),
+            )?;
+            assert_eq!(db.settings().cipher, OuterCipherConfig::Twofish);
+            assert_eq!(db.settings().kdf, KdfConfig::Aes { rounds: 100 });
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_get_version() -> Result<(), DatabaseIntegrityError> {
         let path = Path::new("tests/resources/test_db_with_password.kdbx");
@@ -460,4 +619,100 @@ mod file_read_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn open_skip_history() -> Result<(), Box<dyn std::error::Error>> {
+        for path in [
+            "tests/resources/test_db_with_password.kdbx",
+            "tests/resources/test_db_kdbx4_with_password_aes.kdbx",
+        ] {
+            let key = DatabaseKey::new().with_password("demopass");
+            let db = Database::open(&mut File::open(path)?, key.clone())?;
+            let db_without_history = Database::open_with_options(
+                &mut File::open(path)?,
+                key.clone(),
+                &OpenOptions::new().skip_history(true),
+            )?;
+
+            assert!(db.root.iter_entries().any(|e| e.history.is_some()));
+            assert!(db_without_history
+                .root
+                .iter_entries()
+                .all(|e| e.history.is_none()));
+            assert!(db_without_history.is_history_skipped());
+
+            // protected values after the skipped history are still decrypted correctly
+            let mut db_history_removed = db.clone();
+            for entry in db_history_removed.root.iter_entries_mut() {
+                entry.history = None;
+            }
+            assert_eq!(db_without_history.root, db_history_removed.root);
+
+            #[cfg(feature = "save_kdbx4")]
+            {
+                use keepass::error::DatabaseSaveError;
+
+                let mut db_without_history = db_without_history;
+                let mut buffer = Vec::new();
+                assert!(matches!(
+                    db_without_history.save(&mut buffer, key.clone()),
+                    Err(DatabaseSaveError::HistorySkipped)
+                ));
+
+                db_without_history.allow_lossy_save();
+                db_without_history.save(&mut buffer, key.clone())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_lazy() -> Result<(), Box<dyn std::error::Error>> {
+        for path in [
+            "tests/resources/test_db_with_password.kdbx",
+            "tests/resources/test_db_kdbx4_with_password_argon2_chacha20.kdbx",
+        ] {
+            let key = DatabaseKey::new().with_password("demopass");
+            let db = Database::open(&mut File::open(path)?, key.clone())?;
+            let lazy = Database::open_lazy(&mut File::open(path)?, key.clone())?;
+
+            let deferred = |db: &Database| {
+                db.root
+                    .iter_entries()
+                    .flat_map(|e| e.fields.values())
+                    .filter_map(|v| match v {
+                        Value::Deferred(d) => Some(d.is_decrypted()),
+                        _ => None,
+                    })
+                    .collect::<Vec<bool>>()
+            };
+            assert!(!deferred(&lazy).is_empty());
+            assert!(deferred(&lazy).iter().all(|decrypted| !decrypted));
+            assert!(deferred(&db).is_empty());
+
+            // accessing a value only decrypts that value
+            let entry = lazy
+                .root
+                .iter_entries()
+                .find(|e| matches!(e.fields.get("Password"), Some(Value::Deferred(_))))
+                .unwrap();
+            let eager_entry = db.root.iter_entries().find(|e| e.uuid == entry.uuid).unwrap();
+            assert_eq!(entry.get_password(), eager_entry.get_password());
+            assert_eq!(deferred(&lazy).iter().filter(|decrypted| **decrypted).count(), 1);
+
+            assert_eq!(lazy.root, db.root);
+
+            #[cfg(feature = "save_kdbx4")]
+            {
+                let lazy = Database::open_lazy(&mut File::open(path)?, key.clone())?;
+                let mut buffer = Vec::new();
+                lazy.save(&mut buffer, key.clone())?;
+                let reopened = Database::open(&mut buffer.as_slice(), key.clone())?;
+                assert_eq!(reopened.root, db.root);
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,100 @@
+#[cfg(feature = "save_kdbx4")]
+mod streaming_save_tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        io::Write,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use keepass::{
+        config::{DatabaseConfig, DatabaseSettings, KdfConfig},
+        db::{Database, Entry},
+        DatabaseKey,
+    };
+
+    /// Allocator that keeps track of the peak amount of allocated memory
+    struct CountingAllocator;
+
+    static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+                PEAK.fetch_max(allocated, Ordering::SeqCst);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    const ATTACHMENT_SIZE: usize = 8 * 1024 * 1024;
+    const ATTACHMENT_COUNT: usize = 4;
+
+    /// Writer that discards the data written to it
+    struct Discard;
+
+    impl Write for Discard {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Saving streams the payload into the writer instead of buffering it, so the memory needed to
+    /// save a database with large attachments stays well below the size of the attachments
+    #[test]
+    fn save_with_bounded_memory() -> Result<(), Box<dyn std::error::Error>> {
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Aes { rounds: 10 },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        for i in 0..ATTACHMENT_COUNT {
+            let mut content = vec![0; ATTACHMENT_SIZE];
+            getrandom::fill(&mut content)?;
+
+            let mut entry = Entry::new();
+            entry.add_attachment(&mut db, &format!("attachment_{i}.bin"), content);
+            db.root.add_child(entry);
+        }
+
+        let key = DatabaseKey::new().with_password("testing");
+
+        let before = ALLOCATED.load(Ordering::SeqCst);
+        PEAK.store(before, Ordering::SeqCst);
+
+        db.save(&mut Discard, key.clone())?;
+
+        let peak = PEAK.load(Ordering::SeqCst) - before;
+        assert!(
+            peak < ATTACHMENT_SIZE,
+            "saving allocated up to {peak} bytes for {} bytes of attachments",
+            ATTACHMENT_SIZE * ATTACHMENT_COUNT
+        );
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, key.clone())?;
+
+        let db_loaded = Database::open(&mut buffer.as_slice(), key)?;
+        assert_eq!(db_loaded.header_attachments, db.header_attachments);
+        assert_eq!(db_loaded.root, db.root);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+#[cfg(all(target_arch = "wasm32", target_os = "unknown", feature = "save_kdbx4"))]
+mod wasm_tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use keepass::{
+        config::{DatabaseConfig, DatabaseSettings, KdfConfig},
+        db::{Database, Value},
+        DatabaseKey,
+    };
+
+    /// Create, save and reopen a database in memory, which needs randomness and the current time
+    /// from JavaScript
+    #[wasm_bindgen_test]
+    fn save_and_open_in_memory() {
+        let mut db = Database::new(DatabaseConfig {
+            settings: DatabaseSettings {
+                kdf: KdfConfig::Argon2id {
+                    iterations: 2,
+                    memory: 1024 * 1024,
+                    parallelism: 2,
+                    version: argon2::Version::Version13,
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        db.set_name("Browser");
+
+        let mut entry = db.new_entry();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Example".to_string()));
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected("s3cr3t".as_bytes().into()),
+        );
+        db.root.add_child(entry);
+
+        let mut buffer = Vec::new();
+        db.save(&mut buffer, DatabaseKey::new().with_password("testing"))
+            .unwrap();
+
+        let db_loaded = Database::open(
+            &mut buffer.as_slice(),
+            DatabaseKey::new().with_password("testing"),
+        )
+        .unwrap();
+        assert_eq!(db_loaded, db);
+        assert_eq!(
+            db_loaded.root.iter_entries().next().unwrap().get_password(),
+            Some("s3cr3t")
+        );
+    }
+}